@@ -0,0 +1,60 @@
+use actix_web::{web, HttpResponse};
+use scylla::client::session::Session;
+use crate::comments::service::CommentsService;
+use crate::error::AppError;
+use crate::models::comment::{CommentListQuery, NewComment, UpdateComment};
+use crate::utils::jwt::AuthenticatedUser;
+use crate::utils::pagination::clamp_page_size;
+
+pub async fn create_comment(
+    session: web::Data<Session>,
+    user: AuthenticatedUser,
+    post_id: web::Path<String>,
+    comment: web::Json<NewComment>,
+) -> Result<HttpResponse, AppError> {
+    let service = CommentsService::new(session);
+    let comment = service.create_comment(&post_id, &user.user_id, comment.into_inner()).await?;
+    Ok(HttpResponse::Created().json(comment))
+}
+
+pub async fn list_comments(
+    session: web::Data<Session>,
+    user: AuthenticatedUser,
+    post_id: web::Path<String>,
+    query: web::Query<CommentListQuery>,
+) -> Result<HttpResponse, AppError> {
+    let service = CommentsService::new(session);
+    let limit = clamp_page_size(query.limit);
+    let page = service.list_comments(
+        &post_id,
+        &user.user_id,
+        query.parent_id.as_deref(),
+        query.line,
+        query.cursor.as_deref(),
+        limit,
+    ).await?;
+    Ok(HttpResponse::Ok().json(page))
+}
+
+pub async fn update_comment(
+    session: web::Data<Session>,
+    user: AuthenticatedUser,
+    path: web::Path<(String, String)>,
+    update: web::Json<UpdateComment>,
+) -> Result<HttpResponse, AppError> {
+    let (post_id, comment_id) = path.into_inner();
+    let service = CommentsService::new(session);
+    let comment = service.update_comment(&post_id, &comment_id, &user.user_id, update.into_inner()).await?;
+    Ok(HttpResponse::Ok().json(comment))
+}
+
+pub async fn delete_comment(
+    session: web::Data<Session>,
+    user: AuthenticatedUser,
+    path: web::Path<(String, String)>,
+) -> Result<HttpResponse, AppError> {
+    let (post_id, comment_id) = path.into_inner();
+    let service = CommentsService::new(session);
+    service.delete_comment(&post_id, &comment_id, &user.user_id).await?;
+    Ok(HttpResponse::NoContent().finish())
+}
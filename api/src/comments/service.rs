@@ -0,0 +1,384 @@
+use actix_web::http::StatusCode;
+use actix_web::web;
+use chrono::Utc;
+use scylla::client::session::Session;
+use scylla::value::{CqlTimestamp, CqlTimeuuid};
+use std::marker::PhantomData;
+use uuid::Uuid;
+use crate::error::AppError;
+use crate::models::comment::{Comment, LineRange, NewComment, UpdateComment};
+use crate::models::post::Post;
+use crate::posts::service::PostsService;
+use crate::utils::db_client::DbClient;
+use crate::utils::delivery::new_event_id;
+use crate::utils::pagination::{parse_timeuuid, Page};
+
+pub const MAX_COMMENT_LENGTH: usize = 5000;
+/// Filtering by line reads at most this many comments for one page.
+const LINE_FILTER_SCAN_LIMIT: usize = 500;
+
+/// `parent_id` of top-level comments; the column is part of the primary key
+/// and cannot be null.
+const ROOT: Uuid = Uuid::nil();
+
+type CommentRow = (Uuid, CqlTimeuuid, Uuid, String, Option<i32>, Option<i32>, CqlTimestamp, CqlTimestamp, Option<bool>);
+
+const COMMENT_COLUMNS: &str = "parent_id, comment_id, user_id, content, line_start, line_end, created_at, updated_at, is_deleted";
+
+fn parse_uuid(id: &str, what: &str) -> Result<Uuid, AppError> {
+    Uuid::parse_str(id).map_err(|e| AppError(format!("Invalid {} ID: {}", what, e), StatusCode::BAD_REQUEST))
+}
+
+fn parse_comment_id(id: &str) -> Result<CqlTimeuuid, AppError> {
+    parse_timeuuid(id).map_err(|_| AppError(format!("Invalid comment ID: {:?}", id), StatusCode::BAD_REQUEST))
+}
+
+fn validate_content(content: &str) -> Result<String, AppError> {
+    let content = content.trim();
+    if content.is_empty() {
+        return Err(AppError("Comment cannot be empty".to_string(), StatusCode::BAD_REQUEST));
+    }
+    if content.chars().count() > MAX_COMMENT_LENGTH {
+        return Err(AppError(format!("Comment must be at most {} characters", MAX_COMMENT_LENGTH), StatusCode::BAD_REQUEST));
+    }
+    Ok(content.to_string())
+}
+
+/// Gathers one page of comments from consecutive slices of a thread,
+/// keeping only those on `line` when a line filter is set.
+struct PageScan {
+    line: Option<i32>,
+    limit: usize,
+    rows: Vec<CommentRow>,
+    scanned: usize,
+    after: Option<CqlTimeuuid>,
+    has_more: bool,
+}
+
+impl PageScan {
+    fn new(line: Option<i32>, limit: usize, after: Option<CqlTimeuuid>) -> Self {
+        Self { line, limit, rows: Vec::new(), scanned: 0, after, has_more: false }
+    }
+
+    /// Takes the next slice, read after `self.after` with `LIMIT limit`, and
+    /// says whether another one is needed.
+    fn push(&mut self, batch: Vec<CommentRow>) -> bool {
+        let batch_len = batch.len();
+        self.scanned += batch_len;
+        self.after = batch.last().map(|row| row.1).or(self.after);
+
+        let line = self.line;
+        self.rows.extend(batch.into_iter().filter(|(_, _, _, _, line_start, line_end, _, _, _)| match line {
+            Some(line) => line_start.zip(*line_end).is_some_and(|(start, end)| LineRange { start, end }.contains(line)),
+            None => true,
+        }));
+
+        if self.rows.len() > self.limit {
+            self.has_more = true;
+            return false;
+        }
+        if batch_len < self.limit {
+            return false;
+        }
+        if self.rows.len() >= self.limit || self.scanned >= LINE_FILTER_SCAN_LIMIT {
+            self.has_more = true;
+            return false;
+        }
+        true
+    }
+
+    /// The page and the cursor for the next one. A page cut short of its
+    /// last slice resumes after its last comment, so nothing it left out is
+    /// skipped; otherwise the next page starts after everything scanned.
+    fn finish(mut self) -> (Vec<CommentRow>, Option<CqlTimeuuid>) {
+        if !self.has_more {
+            return (self.rows, None);
+        }
+        let next = if self.rows.len() > self.limit {
+            self.rows.truncate(self.limit);
+            self.rows.last().map(|row| row.1)
+        } else {
+            self.after
+        };
+        (self.rows, next)
+    }
+}
+
+/// Threaded comments on posts. All comments of a post share one partition,
+/// clustered by parent then time, so a thread level is a single slice.
+pub struct CommentsService {
+    session: web::Data<Session>,
+}
+
+impl CommentsService {
+    pub fn new(session: web::Data<Session>) -> Self {
+        Self { session }
+    }
+
+    async fn get_row(&self, post_id: Uuid, comment_id: CqlTimeuuid) -> Result<CommentRow, AppError> {
+        let db_client = DbClient::<Post> {
+            session: &self.session,
+            _phantom: PhantomData,
+        };
+
+        let results = db_client.query::<CommentRow, _>(
+            &format!("SELECT {} FROM post_comments WHERE post_id = ? AND comment_id = ? ALLOW FILTERING", COMMENT_COLUMNS),
+            Some((post_id, comment_id))
+        ).await?;
+
+        results.into_iter().next()
+            .ok_or_else(|| AppError("Comment not found".to_string(), StatusCode::NOT_FOUND))
+    }
+
+    /// Attaches authors to rows with one batched user lookup.
+    async fn to_comments(&self, post_id: Uuid, rows: Vec<CommentRow>) -> Result<Vec<Comment>, AppError> {
+        let author_ids: Vec<Uuid> = rows.iter().map(|row| row.2).collect();
+        let authors = PostsService::new(self.session.clone()).get_authors(&author_ids).await?;
+
+        Ok(rows
+            .into_iter()
+            .filter_map(|(parent_id, comment_id, user_id, content, line_start, line_end, created_at, updated_at, is_deleted)| {
+                let author = authors.get(&user_id.to_string()).cloned()?;
+                Some(Comment {
+                    id: Uuid::from_bytes(*comment_id.as_bytes()).to_string(),
+                    post_id: post_id.to_string(),
+                    parent_id: (parent_id != ROOT).then(|| parent_id.to_string()),
+                    author,
+                    content,
+                    lines: line_start.zip(line_end).map(|(start, end)| LineRange { start, end }),
+                    created_at: created_at.0 / 1000,
+                    updated_at: updated_at.0 / 1000,
+                    is_edited: updated_at.0 != created_at.0,
+                    is_deleted: is_deleted.unwrap_or(false),
+                })
+            })
+            .collect())
+    }
+
+    async fn get_comment(&self, post_id: Uuid, comment_id: CqlTimeuuid) -> Result<Comment, AppError> {
+        let row = self.get_row(post_id, comment_id).await?;
+        self.to_comments(post_id, vec![row]).await?
+            .pop()
+            .ok_or_else(|| AppError("Comment not found".to_string(), StatusCode::NOT_FOUND))
+    }
+
+    /// Loads a post `user_id` may see: any published post, or their own draft.
+    async fn visible_post(&self, post_id: &str, user_id: &str) -> Result<Post, AppError> {
+        let post = PostsService::new(self.session.clone()).get_post_by_id(post_id, None).await?.post;
+        if !post.is_published && post.user_id != user_id {
+            return Err(AppError("Post not found".to_string(), StatusCode::NOT_FOUND));
+        }
+        Ok(post)
+    }
+
+    pub async fn create_comment(&self, post_id: &str, user_id: &str, new_comment: NewComment) -> Result<Comment, AppError> {
+        let post_uuid = parse_uuid(post_id, "post")?;
+        let user_uuid = parse_uuid(user_id, "user")?;
+        let content = validate_content(&new_comment.content)?;
+
+        let post = self.visible_post(post_id, user_id).await?;
+
+        let parent_id = match new_comment.parent_id.as_deref() {
+            Some(parent_id) => {
+                let (_, parent_comment_id, _, _, _, _, _, _, is_deleted) = self.get_row(post_uuid, parse_comment_id(parent_id)?).await?;
+                if is_deleted.unwrap_or(false) {
+                    return Err(AppError("Cannot reply to a deleted comment".to_string(), StatusCode::BAD_REQUEST));
+                }
+                Uuid::from_bytes(*parent_comment_id.as_bytes())
+            }
+            None => ROOT,
+        };
+
+        let lines = match new_comment.lines {
+            Some(_) if parent_id != ROOT => {
+                return Err(AppError("Only top-level comments can be anchored to lines".to_string(), StatusCode::BAD_REQUEST));
+            }
+            Some(lines) => Some(lines.validate(post.code.as_deref()).map_err(|e| AppError(e, StatusCode::BAD_REQUEST))?),
+            None => None,
+        };
+
+        let comment_id = CqlTimeuuid::from_bytes(*new_event_id().as_bytes());
+        let now = CqlTimestamp(Utc::now().timestamp_millis());
+
+        let db_client = DbClient::<Post> {
+            session: &self.session,
+            _phantom: PhantomData,
+        };
+
+        db_client.insert(
+            "INSERT INTO post_comments (post_id, parent_id, comment_id, user_id, content, line_start, line_end, created_at, updated_at, is_deleted) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+            (post_uuid, parent_id, comment_id, user_uuid, &content, lines.map(|l| l.start), lines.map(|l| l.end), now, now, false)
+        ).await?;
+        db_client.insert(
+            "UPDATE post_comment_counts SET comments = comments + 1 WHERE post_id = ?",
+            (post_uuid,)
+        ).await?;
+
+        self.get_comment(post_uuid, comment_id).await
+    }
+
+    /// One page of a thread level, oldest first: the top-level comments when
+    /// `parent_id` is `None`, otherwise the direct replies to that comment.
+    pub async fn list_comments(
+        &self,
+        post_id: &str,
+        viewer_id: &str,
+        parent_id: Option<&str>,
+        line: Option<i32>,
+        cursor: Option<&str>,
+        limit: i32,
+    ) -> Result<Page<Comment>, AppError> {
+        let post_uuid = parse_uuid(post_id, "post")?;
+        self.visible_post(post_id, viewer_id).await?;
+        let parent = match parent_id {
+            Some(parent_id) => Uuid::from_bytes(*parse_comment_id(parent_id)?.as_bytes()),
+            None => ROOT,
+        };
+        let after = cursor.map(parse_timeuuid).transpose()?;
+
+        let db_client = DbClient::<Post> {
+            session: &self.session,
+            _phantom: PhantomData,
+        };
+
+        let mut scan = PageScan::new(line, limit as usize, after);
+        loop {
+            let batch = match scan.after {
+                Some(after) => db_client.query::<CommentRow, _>(
+                    &format!("SELECT {} FROM post_comments WHERE post_id = ? AND parent_id = ? AND comment_id > ? LIMIT ?", COMMENT_COLUMNS),
+                    Some((post_uuid, parent, after, limit))
+                ).await?,
+                None => db_client.query::<CommentRow, _>(
+                    &format!("SELECT {} FROM post_comments WHERE post_id = ? AND parent_id = ? LIMIT ?", COMMENT_COLUMNS),
+                    Some((post_uuid, parent, limit))
+                ).await?,
+            };
+            if !scan.push(batch) {
+                break;
+            }
+        }
+
+        let (rows, next) = scan.finish();
+        Ok(Page {
+            items: self.to_comments(post_uuid, rows).await?,
+            next_cursor: next.map(|id| Uuid::from_bytes(*id.as_bytes()).to_string()),
+        })
+    }
+
+    pub async fn update_comment(&self, post_id: &str, comment_id: &str, user_id: &str, update: UpdateComment) -> Result<Comment, AppError> {
+        let post_uuid = parse_uuid(post_id, "post")?;
+        let comment_timeuuid = parse_comment_id(comment_id)?;
+        let content = validate_content(&update.content)?;
+
+        let (parent_id, _, author_id, _, _, _, _, _, is_deleted) = self.get_row(post_uuid, comment_timeuuid).await?;
+        if author_id.to_string() != user_id {
+            return Err(AppError("You can only edit your own comments".to_string(), StatusCode::FORBIDDEN));
+        }
+        if is_deleted.unwrap_or(false) {
+            return Err(AppError("Comment has been deleted".to_string(), StatusCode::GONE));
+        }
+
+        let db_client = DbClient::<Post> {
+            session: &self.session,
+            _phantom: PhantomData,
+        };
+
+        db_client.insert(
+            "UPDATE post_comments SET content = ?, updated_at = ? WHERE post_id = ? AND parent_id = ? AND comment_id = ?",
+            (&content, CqlTimestamp(Utc::now().timestamp_millis()), post_uuid, parent_id, comment_timeuuid)
+        ).await?;
+
+        self.get_comment(post_uuid, comment_timeuuid).await
+    }
+
+    /// Deletes a comment; allowed for its author and the post's author. The
+    /// row stays behind without its content so replies keep their thread.
+    pub async fn delete_comment(&self, post_id: &str, comment_id: &str, user_id: &str) -> Result<(), AppError> {
+        let post_uuid = parse_uuid(post_id, "post")?;
+        let comment_timeuuid = parse_comment_id(comment_id)?;
+
+        let (parent_id, _, author_id, _, _, _, _, _, is_deleted) = self.get_row(post_uuid, comment_timeuuid).await?;
+        if is_deleted.unwrap_or(false) {
+            return Ok(());
+        }
+        if author_id.to_string() != user_id {
+            let post = PostsService::new(self.session.clone()).get_post_by_id(post_id, None).await?.post;
+            if post.user_id != user_id {
+                return Err(AppError("Not authorized to delete this comment".to_string(), StatusCode::FORBIDDEN));
+            }
+        }
+
+        let db_client = DbClient::<Post> {
+            session: &self.session,
+            _phantom: PhantomData,
+        };
+
+        // Only the delete that flips the flag moves the count, so repeated or
+        // concurrent deletes of one comment take it off once.
+        let deleted = db_client.apply(
+            "UPDATE post_comments SET content = '', is_deleted = true WHERE post_id = ? AND parent_id = ? AND comment_id = ? IF is_deleted = false",
+            (post_uuid, parent_id, comment_timeuuid)
+        ).await?;
+        if !deleted {
+            return Ok(());
+        }
+        db_client.insert(
+            "UPDATE post_comment_counts SET comments = comments - 1 WHERE post_id = ?",
+            (post_uuid,)
+        ).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use uuid::{NoContext, Timestamp};
+
+    fn row(seconds: u64, lines: Option<(i32, i32)>) -> CommentRow {
+        let id = Uuid::new_v1(Timestamp::from_unix(NoContext, seconds, 0), &[1, 2, 3, 4, 5, 6]);
+        let now = CqlTimestamp(0);
+        (ROOT, CqlTimeuuid::from_bytes(*id.as_bytes()), Uuid::nil(), String::new(), lines.map(|l| l.0), lines.map(|l| l.1), now, now, None)
+    }
+
+    #[test]
+    fn line_filter_resumes_after_the_last_comment_returned() {
+        let mut scan = PageScan::new(Some(5), 3, None);
+        assert!(scan.push(vec![row(1, Some((5, 5))), row(2, None), row(3, Some((4, 6)))]));
+        // The second slice brings three more matches, one more than fits.
+        assert!(!scan.push(vec![row(4, Some((5, 5))), row(5, Some((1, 9))), row(6, Some((5, 5)))]));
+
+        let (rows, next) = scan.finish();
+        let ids: Vec<CqlTimeuuid> = rows.iter().map(|row| row.1).collect();
+        assert_eq!(ids, vec![row(1, None).1, row(3, None).1, row(4, None).1]);
+        assert_eq!(next, Some(row(4, None).1));
+    }
+
+    #[test]
+    fn line_filter_truncates_a_short_last_slice() {
+        let mut scan = PageScan::new(Some(5), 3, None);
+        assert!(scan.push(vec![row(1, Some((5, 5))), row(2, None), row(3, Some((4, 6)))]));
+        // The thread ends in a short slice whose two matches overflow the page.
+        assert!(!scan.push(vec![row(4, Some((5, 5))), row(5, Some((1, 9)))]));
+
+        let (rows, next) = scan.finish();
+        let ids: Vec<CqlTimeuuid> = rows.iter().map(|row| row.1).collect();
+        assert_eq!(ids, vec![row(1, None).1, row(3, None).1, row(4, None).1]);
+        assert_eq!(next, Some(row(4, None).1));
+    }
+
+    #[test]
+    fn scan_limit_resumes_after_everything_scanned() {
+        let mut scan = PageScan::new(Some(5), 100, None);
+        let mut seconds = 0;
+        while seconds < LINE_FILTER_SCAN_LIMIT as u64 {
+            let batch = (0..100).map(|_| { seconds += 1; row(seconds, None) }).collect();
+            if !scan.push(batch) {
+                break;
+            }
+        }
+
+        let (rows, next) = scan.finish();
+        assert!(rows.is_empty());
+        assert_eq!(next, Some(row(seconds, None).1));
+    }
+}
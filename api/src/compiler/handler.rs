@@ -1,50 +1,55 @@
-use actix_web::{web, HttpResponse, Responder};
-use std::sync::Arc;
-use tokio::sync::Mutex;
+use actix_web::{rt, web, Error, HttpRequest, HttpResponse, Responder};
+use actix_ws::Message;
+use futures_util::StreamExt as _;
+use log::{debug, error, info, warn};
+use scylla::client::session::Session;
+use serde::Deserialize;
+use std::time::Instant;
+use tokio::sync::mpsc;
 
-use crate::compiler::models::{CompileRequest, SupportedLanguage};
-use crate::compiler::service::CompilerService;
+use crate::compiler::models::{CompileRequest, CompileResponse, OutputChunk, RunClientEvent, RunServerEvent};
+use crate::compiler::pool::{CompilerPool, PoolError};
+use crate::compiler::service::RunStream;
 use crate::error::AppError;
+use crate::utils::jwt::{AuthenticatedUser, Authenticator};
+use crate::utils::presence::HEARTBEAT_INTERVAL;
+use crate::utils::protocol::{self, encode_event, ErrorCode};
+use crate::utils::websocket::{heartbeat, send_app_error, send_error};
 
-lazy_static::lazy_static! {
-    static ref COMPILER_SERVICE: Arc<Mutex<Option<CompilerService>>> = Arc::new(Mutex::new(None));
-}
-
-
-
-async fn get_compiler_service() -> Result<CompilerService, AppError> {
-    let mut service_guard = COMPILER_SERVICE.lock().await;
-    
-    if service_guard.is_none() {
-        *service_guard = Some(CompilerService::new()?);
-    }
-    
-    CompilerService::new()
-}
-
-
+/// Output chunks buffered between a running program and its socket. When
+/// the client reads slowly the program blocks on its output, not the server
+/// on memory.
+const OUTPUT_BUFFER: usize = 64;
+/// `run.stdin` events buffered for a program that is not reading them.
+const INPUT_BUFFER: usize = 64;
 
 pub async fn compile_code(
+    pool: web::Data<CompilerPool>,
+    user: AuthenticatedUser,
     request: web::Json<CompileRequest>,
-) -> Result<impl Responder, AppError> {
-    let compiler_service = get_compiler_service().await?;
-    let result = compiler_service.compile_and_run(request.into_inner()).await?;
+) -> Result<impl Responder, PoolError> {
+    let result = pool.run(&user.user_id, request.into_inner(), None, |_| {}).await?;
     
     Ok(HttpResponse::Ok().json(result))
 }
 
-pub async fn get_supported_languages() -> Result<impl Responder, AppError> {
-    let compiler_service = get_compiler_service().await?;
-    let languages = compiler_service.get_supported_languages();
+pub async fn get_supported_languages(pool: web::Data<CompilerPool>) -> Result<impl Responder, AppError> {
+    let languages = pool.supported_languages();
     
     Ok(HttpResponse::Ok().json(languages))
 }
 
+pub async fn get_metrics(pool: web::Data<CompilerPool>) -> Result<impl Responder, AppError> {
+    Ok(HttpResponse::Ok().json(pool.metrics()))
+}
+
 
 
 pub async fn run_code_legacy(
+    pool: web::Data<CompilerPool>,
+    user: AuthenticatedUser,
     request: web::Json<serde_json::Value>,
-) -> Result<impl Responder, AppError> {
+) -> Result<impl Responder, PoolError> {
     let legacy_request = request.into_inner();
     
     let language = legacy_request.get("language")
@@ -68,8 +73,7 @@ pub async fn run_code_legacy(
         timeout: Some(10),
     };
     
-    let compiler_service = get_compiler_service().await?;
-    let result = compiler_service.compile_and_run(compile_request).await?;
+    let result = pool.run(&user.user_id, compile_request, None, |_| {}).await?;
     
     let legacy_response = serde_json::json!({
         "success": result.success,
@@ -81,10 +85,192 @@ pub async fn run_code_legacy(
     Ok(HttpResponse::Ok().json(legacy_response))
 }
 
+#[derive(Deserialize, Debug)]
+pub struct RunSocketQuery {
+    token: String,
+}
+
+/// What a run's task reports back to its socket.
+enum RunUpdate {
+    Queued(usize),
+    Done(Result<CompileResponse, PoolError>),
+}
+
+async fn send_run_event(session: &mut actix_ws::Session, event: &RunServerEvent, ref_id: Option<&str>) -> bool {
+    match session.text(encode_event(event, ref_id)).await {
+        Ok(()) => true,
+        Err(e) => {
+            error!("Failed to send run event to socket: {}", e);
+            false
+        }
+    }
+}
+
+/// Hands a `run.stdin` event to the running program without waiting, or
+/// says why it cannot take it.
+fn forward_stdin(stdin: Option<&mpsc::Sender<String>>, data: String) -> Result<(), &'static str> {
+    match stdin {
+        Some(input) => input.try_send(data).map_err(|e| match e {
+            mpsc::error::TrySendError::Full(_) => "The program is not reading its input fast enough",
+            mpsc::error::TrySendError::Closed(_) => "The program's input is closed",
+        }),
+        None => Err("No program is reading input"),
+    }
+}
+
+/// Run socket. `run.start` compiles and starts a program; its output comes
+/// back as `run.output` events while it runs, `run.stdin` feeds it input and
+/// `run.finished` carries the result. One program runs at a time, and
+/// closing the socket stops it.
+pub async fn run_socket(
+    req: HttpRequest,
+    stream: web::Payload,
+    query: web::Query<RunSocketQuery>,
+    dbsession: web::Data<Session>,
+    authenticator: web::Data<Authenticator>,
+    pool: web::Data<CompilerPool>,
+) -> Result<HttpResponse, Error> {
+    let (res, mut session, mut stream) = actix_ws::handle(&req, stream)?;
+
+    let user = authenticator.authenticate(&dbsession, &query.token).await?;
+    let user_id = user.user_id;
+    info!("Run socket opened for user {}", user_id);
+
+    rt::spawn(async move {
+        let (output_tx, mut output_rx) = mpsc::channel::<OutputChunk>(OUTPUT_BUFFER);
+        let (update_tx, mut update_rx) = mpsc::unbounded_channel::<RunUpdate>();
+        let mut stdin_tx: Option<mpsc::Sender<String>> = None;
+        // The running program's task and the `ref` of the `run.start` that began it.
+        let mut running: Option<(rt::task::JoinHandle<()>, Option<String>)> = None;
+
+        let mut heartbeat_interval = tokio::time::interval(HEARTBEAT_INTERVAL);
+        let mut last_heartbeat = Instant::now();
+
+        loop {
+            tokio::select! {
+                msg = stream.next() => {
+                    let Some(msg) = msg else {
+                        info!("Run socket stream ended for user {}", user_id);
+                        break;
+                    };
+                    last_heartbeat = Instant::now();
+                    match msg {
+                        Ok(Message::Text(text)) => {
+                            let envelope = match protocol::decode_event::<RunClientEvent>(&text) {
+                                Ok(envelope) => envelope,
+                                Err((code, message)) => {
+                                    warn!("Rejected run event from user {}: {}", user_id, message);
+                                    send_error(&mut session, code, &message, None).await;
+                                    continue;
+                                }
+                            };
+                            let ref_id = envelope.ref_id;
+                            match envelope.event {
+                                RunClientEvent::Start(request) => {
+                                    if running.is_some() {
+                                        send_error(&mut session, ErrorCode::BadRequest, "A program is already running", ref_id.as_deref()).await;
+                                        continue;
+                                    }
+                                    debug!("User {} started a {} run", user_id, request.language);
+                                    let (input_tx, input_rx) = mpsc::channel(INPUT_BUFFER);
+                                    stdin_tx = Some(input_tx);
+                                    let run_stream = RunStream { output: output_tx.clone(), input: input_rx };
+                                    let (pool, user_id, update_tx) = (pool.clone(), user_id.clone(), update_tx.clone());
+                                    let task = rt::spawn(async move {
+                                        let result = pool.run(&user_id, request, Some(run_stream), |position| {
+                                            let _ = update_tx.send(RunUpdate::Queued(position));
+                                        }).await;
+                                        let _ = update_tx.send(RunUpdate::Done(result));
+                                    });
+                                    running = Some((task, ref_id));
+                                }
+                                RunClientEvent::Stdin { data } => {
+                                    if let Err(message) = forward_stdin(stdin_tx.as_ref(), data) {
+                                        send_error(&mut session, ErrorCode::BadRequest, message, ref_id.as_deref()).await;
+                                    }
+                                }
+                                RunClientEvent::StdinClose => stdin_tx = None,
+                                RunClientEvent::Ping => {
+                                    if !send_run_event(&mut session, &RunServerEvent::Pong, ref_id.as_deref()).await {
+                                        break;
+                                    }
+                                }
+                            }
+                        }
+                        Ok(Message::Ping(bytes)) => {
+                            if let Err(e) = session.pong(&bytes).await {
+                                error!("Failed to answer ping from user {}: {}", user_id, e);
+                                break;
+                            }
+                        }
+                        Ok(Message::Close(reason)) => {
+                            info!("Run socket closed by user {}: {:?}", user_id, reason);
+                            break;
+                        }
+                        Err(e) => {
+                            error!("Run socket error for user {}: {}", user_id, e);
+                            break;
+                        }
+                        _ => {}
+                    }
+                }
+
+                Some(chunk) = output_rx.recv() => {
+                    if !send_run_event(&mut session, &RunServerEvent::Output(chunk), None).await {
+                        break;
+                    }
+                }
+
+                Some(update) = update_rx.recv() => {
+                    let open = match update {
+                        RunUpdate::Queued(position) => {
+                            let ref_id = running.as_ref().and_then(|(_, ref_id)| ref_id.as_deref());
+                            send_run_event(&mut session, &RunServerEvent::Queued { position }, ref_id).await
+                        }
+                        RunUpdate::Done(result) => {
+                            let ref_id = running.take().and_then(|(_, ref_id)| ref_id);
+                            stdin_tx = None;
+                            // Output read before the program exited goes out before its result.
+                            while let Ok(chunk) = output_rx.try_recv() {
+                                send_run_event(&mut session, &RunServerEvent::Output(chunk), None).await;
+                            }
+                            match result {
+                                Ok(response) => send_run_event(&mut session, &RunServerEvent::Finished(response), ref_id.as_deref()).await,
+                                Err(e) => {
+                                    send_app_error(&mut session, &AppError::from(e), ref_id.as_deref()).await;
+                                    true
+                                }
+                            }
+                        }
+                    };
+                    if !open {
+                        break;
+                    }
+                }
+
+                _ = heartbeat_interval.tick() => {
+                    if !heartbeat(&mut session, last_heartbeat, &user_id).await {
+                        break;
+                    }
+                }
+            }
+        }
+
+        // Dropping the run kills the program.
+        if let Some((task, _)) = running {
+            info!("Stopping the running program of user {}", user_id);
+            task.abort();
+        }
+    });
+
+    Ok(res)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::compiler::models::CompileRequest;
+    use crate::compiler::service::CompilerService;
 
     #[tokio::test]
     async fn test_python_hello_world() {
@@ -164,6 +350,19 @@ print(f"Hello, {name}!")
         assert!(result.success);
         assert!(result.output.as_ref().unwrap().contains("Hello, Alice!"));
     }
+
+    #[test]
+    fn rejects_stdin_the_program_cannot_take() {
+        assert_eq!(forward_stdin(None, "1\n".to_string()), Err("No program is reading input"));
+
+        let (input_tx, mut input_rx) = mpsc::channel(1);
+        assert_eq!(forward_stdin(Some(&input_tx), "1\n".to_string()), Ok(()));
+        assert_eq!(forward_stdin(Some(&input_tx), "2\n".to_string()), Err("The program is not reading its input fast enough"));
+        assert_eq!(input_rx.try_recv().ok().as_deref(), Some("1\n"));
+
+        drop(input_rx);
+        assert_eq!(forward_stdin(Some(&input_tx), "3\n".to_string()), Err("The program's input is closed"));
+    }
 }
 
 
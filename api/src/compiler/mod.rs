@@ -1,4 +1,6 @@
 pub mod handler;
 pub mod service;
 pub mod models;
-pub mod security; 
\ No newline at end of file
+pub mod security;
+pub mod sandbox;
+pub mod pool;
\ No newline at end of file
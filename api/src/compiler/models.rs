@@ -15,6 +15,89 @@ pub struct CompileResponse {
     pub output: Option<String>,
     pub error: Option<String>,
     pub execution_time: u64, // in milliseconds
+    /// The program printed more than the output limit; `output` holds the
+    /// start of it and the program was stopped.
+    pub output_truncated: bool,
+    /// The run was stopped for going past its time limit.
+    pub timed_out: bool,
+    /// How the program ended, or the compiler when compilation failed: its
+    /// exit code, or the signal that killed it (9 when it was stopped, 11
+    /// for a segmentation fault). Both are unset when it never ran.
+    pub exit_code: Option<i32>,
+    pub signal: Option<i32>,
+    /// Milliseconds spent waiting for a free worker before the run started.
+    pub queue_time: u64,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputStream {
+    Stdout,
+    Stderr,
+}
+
+/// A piece of a running program's output, sent as soon as it is read.
+#[derive(Debug, Serialize)]
+pub struct OutputChunk {
+    pub stream: OutputStream,
+    pub data: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TimingSummary {
+    pub total_ms: u64,
+    pub average_ms: u64,
+    pub max_ms: u64,
+}
+
+/// A snapshot of the compiler pool: its limits, current load, and how long
+/// finished runs waited in the queue compared to how long they ran.
+#[derive(Debug, Serialize)]
+pub struct PoolMetrics {
+    pub workers: usize,
+    pub max_queue: usize,
+    pub max_per_user: usize,
+    pub running: usize,
+    pub queued: usize,
+    pub completed: u64,
+    /// Runs that got a worker but could not be carried out, e.g. for an
+    /// unsupported language or an unavailable sandbox.
+    pub failed: u64,
+    pub rejected_queue_full: u64,
+    pub rejected_user_limit: u64,
+    pub queue_wait: TimingSummary,
+    pub run: TimingSummary,
+}
+
+/// Events a client sends on the run socket, in the envelope of
+/// `utils::protocol`.
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", content = "data")]
+pub enum RunClientEvent {
+    /// Compiles and starts a program; `input` is written to its stdin first.
+    #[serde(rename = "run.start")]
+    Start(CompileRequest),
+    #[serde(rename = "run.stdin")]
+    Stdin { data: String },
+    /// Closes the program's stdin, so reads see end of file.
+    #[serde(rename = "run.stdin_close")]
+    StdinClose,
+    #[serde(rename = "ping")]
+    Ping,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", content = "data")]
+pub enum RunServerEvent {
+    /// Every worker is busy; the run is `position` in line.
+    #[serde(rename = "run.queued")]
+    Queued { position: usize },
+    #[serde(rename = "run.output")]
+    Output(OutputChunk),
+    #[serde(rename = "run.finished")]
+    Finished(CompileResponse),
+    #[serde(rename = "pong")]
+    Pong,
 }
 
 #[derive(Debug, Clone)]
@@ -91,6 +174,19 @@ impl LanguageConfig {
         languages
     }
 
+    /// The compiler key for a language as users write it, e.g. on a post:
+    /// "C++" and "cpp" both run as "cpp", "js" and "node.js" as "javascript".
+    pub fn key_for(language: &str) -> Option<&'static str> {
+        match language.trim().to_lowercase().as_str() {
+            "cpp" | "c++" | "cxx" => Some("cpp"),
+            "c" => Some("c"),
+            "python" | "python3" | "py" => Some("python"),
+            "java" => Some("java"),
+            "javascript" | "js" | "node" | "node.js" | "nodejs" => Some("javascript"),
+            _ => None,
+        }
+    }
+
     pub fn to_supported_language(key: &str, config: &LanguageConfig) -> SupportedLanguage {
         SupportedLanguage {
             name: match key {
@@ -107,4 +203,59 @@ impl LanguageConfig {
             supports_input: config.supports_input,
         }
     }
-} 
+}
+
+/// Whether a program's output is what its author expected. Line endings and
+/// trailing whitespace are ignored, since editors and runtimes disagree on them.
+pub fn output_matches(expected: &str, actual: &str) -> bool {
+    let lines = |text: &str| -> Vec<String> {
+        let mut lines: Vec<String> = text.lines().map(|line| line.trim_end().to_string()).collect();
+        while lines.last().is_some_and(|line| line.is_empty()) {
+            lines.pop();
+        }
+        lines
+    };
+    lines(expected) == lines(actual)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolves_language_aliases() {
+        assert_eq!(LanguageConfig::key_for("C++"), Some("cpp"));
+        assert_eq!(LanguageConfig::key_for(" node.js "), Some("javascript"));
+        assert_eq!(LanguageConfig::key_for("py"), Some("python"));
+        assert_eq!(LanguageConfig::key_for("rust"), None);
+        for key in LanguageConfig::get_supported_languages().keys() {
+            assert_eq!(LanguageConfig::key_for(key), Some(key.as_str()));
+        }
+    }
+
+    #[test]
+    fn decodes_run_events() {
+        let start = crate::utils::protocol::decode_event::<RunClientEvent>(
+            r#"{"v":1,"ref":"r1","type":"run.start","data":{"language":"python","code":"print(input())"}}"#
+        ).unwrap();
+        assert_eq!(start.ref_id.as_deref(), Some("r1"));
+        assert!(matches!(start.event, RunClientEvent::Start(CompileRequest { ref language, input: None, .. }) if language == "python"));
+
+        let stdin = crate::utils::protocol::decode_event::<RunClientEvent>(r#"{"v":1,"type":"run.stdin","data":{"data":"hi\n"}}"#).unwrap();
+        assert!(matches!(stdin.event, RunClientEvent::Stdin { ref data } if data == "hi\n"));
+
+        let output = RunServerEvent::Output(OutputChunk { stream: OutputStream::Stderr, data: "oops".to_string() });
+        assert_eq!(
+            crate::utils::protocol::encode_event(&output, None),
+            r#"{"v":1,"type":"run.output","data":{"stream":"stderr","data":"oops"}}"#
+        );
+    }
+
+    #[test]
+    fn compares_output_loosely() {
+        assert!(output_matches("Hello\nWorld\n", "Hello  \r\nWorld"));
+        assert!(output_matches("", "\n\n"));
+        assert!(!output_matches("Hello", "hello"));
+        assert!(!output_matches("1\n2", "1\n\n2"));
+    }
+}
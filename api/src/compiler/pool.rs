@@ -0,0 +1,342 @@
+use actix_web::http::StatusCode;
+use actix_web::{HttpResponse, ResponseError};
+use serde_json::json;
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::Instant;
+use tokio::sync::Semaphore;
+
+use crate::compiler::models::{CompileRequest, CompileResponse, PoolMetrics, SupportedLanguage, TimingSummary};
+use crate::compiler::sandbox::parse_env;
+use crate::compiler::service::{CompilerService, RunStream};
+use crate::error::AppError;
+
+/// Seconds a client turned away for load is told to wait before retrying.
+const RETRY_AFTER_SECS: u64 = 2;
+
+#[derive(Debug, Clone)]
+pub struct PoolConfig {
+    /// Runs executing at once.
+    pub workers: usize,
+    /// Runs waiting for a worker before new ones are turned away.
+    pub max_queue: usize,
+    /// Runs one user may have queued or executing at once.
+    pub max_per_user: usize,
+}
+
+impl Default for PoolConfig {
+    fn default() -> Self {
+        Self {
+            workers: std::thread::available_parallelism().map(|n| n.get()).unwrap_or(2),
+            max_queue: 32,
+            max_per_user: 2,
+        }
+    }
+}
+
+impl PoolConfig {
+    /// The defaults, adjusted by `COMPILER_WORKERS`, `COMPILER_MAX_QUEUE` and
+    /// `COMPILER_MAX_PER_USER`.
+    pub fn from_env() -> Result<Self, AppError> {
+        let defaults = Self::default();
+        Ok(Self {
+            workers: parse_env("COMPILER_WORKERS")?.unwrap_or(defaults.workers).max(1),
+            max_queue: parse_env("COMPILER_MAX_QUEUE")?.unwrap_or(defaults.max_queue),
+            max_per_user: parse_env("COMPILER_MAX_PER_USER")?.unwrap_or(defaults.max_per_user).max(1),
+        })
+    }
+}
+
+#[derive(Debug)]
+pub enum PoolError {
+    /// Every worker is busy and the queue is full; `position` is where the
+    /// run would have stood in it.
+    QueueFull { position: usize },
+    /// The user already has `limit` runs queued or executing.
+    UserLimit { limit: usize },
+    Failed(AppError),
+}
+
+impl Display for PoolError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PoolError::QueueFull { position } => write!(f, "The compiler is busy: {} runs are already waiting", position - 1),
+            PoolError::UserLimit { limit } => write!(f, "You can have at most {} programs running or waiting at once", limit),
+            PoolError::Failed(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl ResponseError for PoolError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            PoolError::QueueFull { .. } | PoolError::UserLimit { .. } => StatusCode::TOO_MANY_REQUESTS,
+            PoolError::Failed(e) => e.1,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        match self {
+            PoolError::QueueFull { position } => HttpResponse::TooManyRequests()
+                .insert_header(("Retry-After", RETRY_AFTER_SECS.to_string()))
+                .json(json!({
+                    "error": self.to_string(),
+                    "queue_position": position,
+                })),
+            PoolError::UserLimit { .. } => HttpResponse::TooManyRequests()
+                .insert_header(("Retry-After", RETRY_AFTER_SECS.to_string()))
+                .json(json!({
+                    "error": self.to_string(),
+                })),
+            PoolError::Failed(e) => e.error_response(),
+        }
+    }
+}
+
+impl From<AppError> for PoolError {
+    fn from(e: AppError) -> Self {
+        PoolError::Failed(e)
+    }
+}
+
+impl From<PoolError> for AppError {
+    fn from(e: PoolError) -> Self {
+        match e {
+            PoolError::Failed(e) => e,
+            busy => AppError(busy.to_string(), StatusCode::TOO_MANY_REQUESTS),
+        }
+    }
+}
+
+/// Running total and maximum of a duration, in milliseconds.
+#[derive(Default)]
+struct Timing {
+    total_ms: AtomicU64,
+    max_ms: AtomicU64,
+}
+
+impl Timing {
+    fn record(&self, ms: u64) {
+        self.total_ms.fetch_add(ms, Ordering::Relaxed);
+        self.max_ms.fetch_max(ms, Ordering::Relaxed);
+    }
+
+    fn summary(&self, count: u64) -> TimingSummary {
+        let total_ms = self.total_ms.load(Ordering::Relaxed);
+        TimingSummary {
+            total_ms,
+            average_ms: total_ms.checked_div(count).unwrap_or(0),
+            max_ms: self.max_ms.load(Ordering::Relaxed),
+        }
+    }
+}
+
+#[derive(Default)]
+struct Metrics {
+    completed: AtomicU64,
+    failed: AtomicU64,
+    rejected_queue_full: AtomicU64,
+    rejected_user_limit: AtomicU64,
+    queue_wait: Timing,
+    run: Timing,
+}
+
+/// Gives a user's slot back when their run ends, however it ends.
+struct UserSlot<'a> {
+    users: &'a Mutex<HashMap<String, usize>>,
+    user_id: String,
+}
+
+impl Drop for UserSlot<'_> {
+    fn drop(&mut self) {
+        let mut users = self.users.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(count) = users.get_mut(&self.user_id) {
+            *count -= 1;
+            if *count == 0 {
+                users.remove(&self.user_id);
+            }
+        }
+    }
+}
+
+/// Leaves the queue when dropped: once a worker is free, or when the caller
+/// gives up waiting.
+struct QueueSlot<'a>(&'a AtomicUsize);
+
+impl Drop for QueueSlot<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// The one compiler everything runs through. At most `workers` runs execute
+/// at once; the rest wait in first-come order, up to `max_queue` of them,
+/// and each user is held to `max_per_user` runs queued or executing.
+pub struct CompilerPool {
+    service: CompilerService,
+    config: PoolConfig,
+    workers: Semaphore,
+    queued: AtomicUsize,
+    users: Mutex<HashMap<String, usize>>,
+    metrics: Metrics,
+}
+
+impl CompilerPool {
+    pub fn new(service: CompilerService, config: PoolConfig) -> Self {
+        Self {
+            service,
+            workers: Semaphore::new(config.workers),
+            config,
+            queued: AtomicUsize::new(0),
+            users: Mutex::new(HashMap::new()),
+            metrics: Metrics::default(),
+        }
+    }
+
+    pub fn from_env() -> Result<Self, AppError> {
+        Ok(Self::new(CompilerService::new()?, PoolConfig::from_env()?))
+    }
+
+    pub fn supported_languages(&self) -> Vec<SupportedLanguage> {
+        self.service.get_supported_languages()
+    }
+
+    fn reserve_user(&self, user_id: &str) -> Result<UserSlot<'_>, PoolError> {
+        let mut users = self.users.lock().unwrap_or_else(|e| e.into_inner());
+        let count = users.entry(user_id.to_string()).or_insert(0);
+        if *count >= self.config.max_per_user {
+            self.metrics.rejected_user_limit.fetch_add(1, Ordering::Relaxed);
+            return Err(PoolError::UserLimit { limit: self.config.max_per_user });
+        }
+        *count += 1;
+        Ok(UserSlot { users: &self.users, user_id: user_id.to_string() })
+    }
+
+    /// Compiles and runs `request` for `user_id` once a worker is free,
+    /// streaming it when `stream` is given. `on_queued` hears the run's place
+    /// in the queue when it has to wait. Dropping the future gives up the
+    /// run's place, or stops it if it already started.
+    pub async fn run(
+        &self,
+        user_id: &str,
+        request: CompileRequest,
+        stream: Option<RunStream>,
+        on_queued: impl FnOnce(usize),
+    ) -> Result<CompileResponse, PoolError> {
+        let _user = self.reserve_user(user_id)?;
+        let queued_at = Instant::now();
+
+        let _worker = match self.workers.try_acquire() {
+            Ok(worker) => worker,
+            Err(_) => {
+                let position = self.queued.fetch_add(1, Ordering::SeqCst) + 1;
+                let _waiting = QueueSlot(&self.queued);
+                if position > self.config.max_queue {
+                    self.metrics.rejected_queue_full.fetch_add(1, Ordering::Relaxed);
+                    return Err(PoolError::QueueFull { position });
+                }
+                on_queued(position);
+                self.workers.acquire().await
+                    .map_err(|_| AppError("The compiler is shutting down".to_string(), StatusCode::SERVICE_UNAVAILABLE))?
+            }
+        };
+        let queue_time = queued_at.elapsed().as_millis() as u64;
+
+        let result = match stream {
+            Some(stream) => self.service.compile_and_stream(request, stream).await,
+            None => self.service.compile_and_run(request).await,
+        };
+        let mut response = result.inspect_err(|_| {
+            self.metrics.failed.fetch_add(1, Ordering::Relaxed);
+        })?;
+        response.queue_time = queue_time;
+
+        self.metrics.completed.fetch_add(1, Ordering::Relaxed);
+        self.metrics.queue_wait.record(queue_time);
+        self.metrics.run.record(response.execution_time);
+        Ok(response)
+    }
+
+    pub fn metrics(&self) -> PoolMetrics {
+        let completed = self.metrics.completed.load(Ordering::Relaxed);
+        PoolMetrics {
+            workers: self.config.workers,
+            max_queue: self.config.max_queue,
+            max_per_user: self.config.max_per_user,
+            running: self.config.workers - self.workers.available_permits(),
+            queued: self.queued.load(Ordering::SeqCst).min(self.config.max_queue),
+            completed,
+            failed: self.metrics.failed.load(Ordering::Relaxed),
+            rejected_queue_full: self.metrics.rejected_queue_full.load(Ordering::Relaxed),
+            rejected_user_limit: self.metrics.rejected_user_limit.load(Ordering::Relaxed),
+            queue_wait: self.metrics.queue_wait.summary(completed),
+            run: self.metrics.run.summary(completed),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    fn request() -> CompileRequest {
+        CompileRequest {
+            language: "python".to_string(),
+            code: "print('hi')".to_string(),
+            input: None,
+            timeout: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn queues_then_turns_runs_away() {
+        let pool = Arc::new(CompilerPool::new(
+            CompilerService::new().unwrap(),
+            PoolConfig { workers: 1, max_queue: 1, max_per_user: 1 },
+        ));
+        // Hold the only worker so every run has to queue.
+        let worker = pool.workers.acquire().await.unwrap();
+
+        let (position_tx, position_rx) = tokio::sync::oneshot::channel();
+        let waiting = tokio::spawn({
+            let pool = pool.clone();
+            async move {
+                pool.run("alice", request(), None, |position| {
+                    let _ = position_tx.send(position);
+                }).await
+            }
+        });
+        assert_eq!(position_rx.await.unwrap(), 1);
+
+        let again = pool.run("alice", request(), None, |_| {}).await.unwrap_err();
+        assert!(matches!(again, PoolError::UserLimit { limit: 1 }));
+        let full = pool.run("bob", request(), None, |_| {}).await.unwrap_err();
+        assert!(matches!(full, PoolError::QueueFull { position: 2 }));
+        assert_eq!(full.status_code(), StatusCode::TOO_MANY_REQUESTS);
+
+        // Giving up a place frees it for someone else.
+        waiting.abort();
+        let _ = waiting.await;
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        let metrics = pool.metrics();
+        assert_eq!((metrics.queued, metrics.running), (0, 1));
+        assert_eq!((metrics.rejected_queue_full, metrics.rejected_user_limit), (1, 1));
+        assert!(pool.reserve_user("alice").is_ok());
+        drop(worker);
+    }
+
+    #[tokio::test]
+    async fn counts_runs_that_fail() {
+        let pool = CompilerPool::new(CompilerService::new().unwrap(), PoolConfig::default());
+        let cobol = CompileRequest { language: "cobol".to_string(), ..request() };
+
+        let error = pool.run("alice", cobol, None, |_| {}).await.unwrap_err();
+        assert_eq!(error.status_code(), StatusCode::BAD_REQUEST);
+        let metrics = pool.metrics();
+        assert_eq!((metrics.completed, metrics.failed), (0, 1));
+    }
+}
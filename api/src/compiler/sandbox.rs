@@ -0,0 +1,399 @@
+use crate::compiler::security::SecurityConfig;
+use crate::error::AppError;
+use actix_web::http::StatusCode;
+use std::env;
+use std::path::{Path, PathBuf};
+use std::process::ExitStatus;
+use std::time::Duration;
+use tokio::process::{Child, Command};
+
+/// Where the workspace is mounted inside the sandbox. Commands refer to their
+/// files relative to it, so the same arguments work with the sandbox off.
+pub const SANDBOX_WORKDIR: &str = "/workspace";
+
+/// Syscalls untrusted code has no business making, in nsjail's Kafel
+/// language. They fail with EPERM rather than killing the process, so
+/// runtimes that probe for them keep working. `clone` may start threads and
+/// processes but not new namespaces; `clone3` reports ENOSYS so libc falls
+/// back to `clone`, whose flags can be checked.
+const SECCOMP_POLICY: &str = "\
+ERRNO(1) {
+  ptrace, process_vm_readv, process_vm_writev, mount, umount2, pivot_root, chroot,
+  setns, unshare, reboot, kexec_load, kexec_file_load, init_module, finit_module,
+  delete_module, swapon, swapoff, bpf, perf_event_open, keyctl, add_key, request_key,
+  userfaultfd, acct, quotactl, syslog, settimeofday, clock_settime, sethostname,
+  setdomainname, iopl, ioperm, personality, open_by_handle_at, name_to_handle_at,
+  clone { (clone_flags & 0x7e020000) != 0 }
+}
+ERRNO(38) { clone3 }
+DEFAULT ALLOW";
+
+/// Toolchain directories mounted read-only into the jail by default; the
+/// JDK lives under `/usr/lib/jvm` and is reached through `/etc/alternatives`.
+/// Ones missing on the host are skipped.
+const TOOLCHAIN_DIRS: &[&str] = &[
+    "/usr", "/bin", "/lib", "/lib32", "/lib64", "/etc/alternatives", "/etc/ld.so.cache",
+    "/dev/null", "/dev/zero", "/dev/random", "/dev/urandom",
+];
+
+/// How untrusted code is isolated. Every compiler and program runs under
+/// [nsjail](https://github.com/google/nsjail) in fresh user, mount, PID, IPC,
+/// UTS and network namespaces: its root is the empty `root_dir` with only
+/// the toolchain directories mounted read-only, it can write only to its own
+/// workspace and a small `/tmp`, has no network, runs under a seccomp filter,
+/// and is held to rlimits on CPU time, memory, processes, open files and file
+/// size.
+///
+/// Inside the jail the code runs as `uid`/`gid`, mapped to `host_uid`/
+/// `host_gid` on the host. Those must be an otherwise unused account: code is
+/// refused rather than run as the API's own user, which can read its secrets.
+#[derive(Debug, Clone)]
+pub struct SandboxConfig {
+    /// `false` runs commands directly. Only for local development.
+    pub enabled: bool,
+    pub nsjail_path: PathBuf,
+    /// An empty directory the API owns, used as the jail's `/`. nsjail adds
+    /// the mount points it needs.
+    pub root_dir: PathBuf,
+    /// Host directories (or files) mounted read-only at the same path.
+    pub toolchain_dirs: Vec<PathBuf>,
+    pub uid: u32,
+    pub gid: u32,
+    pub host_uid: Option<u32>,
+    pub host_gid: Option<u32>,
+    pub cpu_time_secs: u64,
+    pub address_space_mb: u64,
+    pub max_processes: u64,
+    pub max_file_size_mb: u64,
+    pub max_open_files: u64,
+    pub tmpfs_size_mb: u64,
+}
+
+impl Default for SandboxConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            nsjail_path: PathBuf::from("/usr/local/bin/nsjail"),
+            root_dir: env::temp_dir().join("kerek_compiler").join("root"),
+            toolchain_dirs: TOOLCHAIN_DIRS.iter().map(PathBuf::from).collect(),
+            uid: 65534,
+            gid: 65534,
+            host_uid: None,
+            host_gid: None,
+            cpu_time_secs: 10,
+            // The JVM reserves far more address space than it touches.
+            address_space_mb: 2048,
+            max_processes: 64,
+            max_file_size_mb: 16,
+            max_open_files: 64,
+            tmpfs_size_mb: 64,
+        }
+    }
+}
+
+impl SandboxConfig {
+    /// The defaults, adjusted by `COMPILER_SANDBOX` ("off" to disable),
+    /// `NSJAIL_PATH`, `SANDBOX_TOOLCHAIN_DIRS` (extra read-only mounts,
+    /// separated by `:`), `SANDBOX_HOST_UID`, `SANDBOX_HOST_GID`,
+    /// `SANDBOX_CPU_TIME_SECS`, `SANDBOX_ADDRESS_SPACE_MB` and
+    /// `SANDBOX_MAX_PROCESSES`.
+    pub fn from_env() -> Result<Self, AppError> {
+        let defaults = Self::default();
+        let mut toolchain_dirs = defaults.toolchain_dirs;
+        if let Some(extra) = env::var_os("SANDBOX_TOOLCHAIN_DIRS") {
+            toolchain_dirs.extend(env::split_paths(&extra).filter(|dir| dir.is_absolute()));
+        }
+        Ok(Self {
+            enabled: !matches!(env::var("COMPILER_SANDBOX").as_deref(), Ok("off")),
+            nsjail_path: env::var("NSJAIL_PATH").map(PathBuf::from).unwrap_or(defaults.nsjail_path),
+            toolchain_dirs,
+            host_uid: parse_env("SANDBOX_HOST_UID")?,
+            host_gid: parse_env("SANDBOX_HOST_GID")?,
+            cpu_time_secs: parse_env("SANDBOX_CPU_TIME_SECS")?.unwrap_or(defaults.cpu_time_secs),
+            address_space_mb: parse_env("SANDBOX_ADDRESS_SPACE_MB")?.unwrap_or(defaults.address_space_mb),
+            max_processes: parse_env("SANDBOX_MAX_PROCESSES")?.unwrap_or(defaults.max_processes),
+            ..defaults
+        })
+    }
+
+    /// Fails unless code can actually be isolated, so a missing nsjail or a
+    /// jail that would run as the API's own user disables code execution
+    /// instead of running it unconfined.
+    pub fn check(&self) -> Result<(), AppError> {
+        if !self.enabled {
+            log::warn!("COMPILER_SANDBOX=off: untrusted code runs without isolation");
+            return Ok(());
+        }
+        if !cfg!(target_os = "linux") {
+            return Err(AppError("Code execution needs the Linux sandbox".to_string(), StatusCode::SERVICE_UNAVAILABLE));
+        }
+        if !self.nsjail_path.is_file() {
+            return Err(AppError(
+                format!("Code execution is unavailable: sandbox not found at {}", self.nsjail_path.display()),
+                StatusCode::SERVICE_UNAVAILABLE,
+            ));
+        }
+        #[cfg(unix)]
+        {
+            // SAFETY: geteuid and getegid cannot fail and touch no memory.
+            let (server_uid, server_gid) = unsafe { (libc::geteuid(), libc::getegid()) };
+            if self.host_uid.is_none_or(|uid| uid == server_uid) || self.host_gid.is_none_or(|gid| gid == server_gid) {
+                return Err(AppError(
+                    "Code execution is unavailable: SANDBOX_HOST_UID and SANDBOX_HOST_GID must name an account other than the server's".to_string(),
+                    StatusCode::SERVICE_UNAVAILABLE,
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Creates the jail's empty root, readable by the jailed user.
+    pub fn prepare_root(&self) -> std::io::Result<()> {
+        if !self.enabled {
+            return Ok(());
+        }
+        std::fs::create_dir_all(&self.root_dir)?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(&self.root_dir, std::fs::Permissions::from_mode(0o755))?;
+        }
+        Ok(())
+    }
+
+    /// Makes a fresh workspace private to the jailed user, so no other run
+    /// and no other host account can look into it.
+    pub fn prepare_workspace(&self, workspace: &Path) -> std::io::Result<()> {
+        #[cfg(unix)]
+        if self.enabled {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(workspace, std::fs::Permissions::from_mode(0o700))?;
+            std::os::unix::fs::chown(workspace, self.host_uid, self.host_gid)?;
+        }
+        #[cfg(not(unix))]
+        let _ = workspace;
+        Ok(())
+    }
+
+    /// A command that runs `program` (and its arguments) in the sandbox with
+    /// `workspace` as its working directory, killed after `time_limit`.
+    pub fn command(&self, workspace: &Path, time_limit: Duration, program: &[String]) -> Command {
+        let mut command = if self.enabled {
+            let mut command = Command::new(&self.nsjail_path);
+            command.args(self.nsjail_args(workspace, time_limit)).arg("--").args(program);
+            command
+        } else {
+            let mut command = Command::new(&program[0]);
+            command
+                .args(&program[1..])
+                .current_dir(workspace)
+                .env_clear()
+                .envs(SecurityConfig::sanitize_environment());
+            command
+        };
+        command.kill_on_drop(true);
+        #[cfg(unix)]
+        command.process_group(0);
+        command
+    }
+
+    /// How a step ended: its exit code, or the signal that killed it. nsjail
+    /// reports a jailed program killed by signal N as exit code 128 + N, the
+    /// way shells do, so only codes naming a real signal are read that way.
+    /// A jailed program that itself exits with such a code still looks killed.
+    pub fn termination(&self, status: ExitStatus) -> (Option<i32>, Option<i32>) {
+        #[cfg(unix)]
+        {
+            use std::os::unix::process::ExitStatusExt;
+            if let Some(signal) = status.signal() {
+                return (None, Some(signal));
+            }
+        }
+        match status.code() {
+            Some(code) if self.enabled && (129..=128 + max_signal()).contains(&code) => (None, Some(code - 128)),
+            code => (code, None),
+        }
+    }
+
+    fn nsjail_args(&self, workspace: &Path, time_limit: Duration) -> Vec<String> {
+        let id_mapping = |inside: u32, outside: Option<u32>| match outside {
+            Some(outside) => format!("{}:{}:1", inside, outside),
+            None => inside.to_string(),
+        };
+
+        let mut args: Vec<String> = [
+            "--mode", "o",
+            "--really_quiet",
+            "--hostname", "sandbox",
+            "--iface_no_lo",
+            "--max_cpus", "1",
+            "--rlimit_core", "0",
+        ]
+        .into_iter()
+        .map(str::to_string)
+        .collect();
+
+        args.extend(["--chroot".to_string(), self.root_dir.display().to_string()]);
+        for dir in self.toolchain_dirs.iter().filter(|dir| dir.exists()) {
+            args.extend(["--bindmount_ro".to_string(), dir.display().to_string()]);
+        }
+        args.extend([
+            "--user".to_string(), id_mapping(self.uid, self.host_uid),
+            "--group".to_string(), id_mapping(self.gid, self.host_gid),
+            // A second past the caller's own timeout, as a backstop.
+            "--time_limit".to_string(), (time_limit.as_secs() + 1).to_string(),
+            "--rlimit_cpu".to_string(), self.cpu_time_secs.to_string(),
+            "--rlimit_as".to_string(), self.address_space_mb.to_string(),
+            "--rlimit_nproc".to_string(), self.max_processes.to_string(),
+            "--rlimit_fsize".to_string(), self.max_file_size_mb.to_string(),
+            "--rlimit_nofile".to_string(), self.max_open_files.to_string(),
+            "--bindmount".to_string(), format!("{}:{}", workspace.display(), SANDBOX_WORKDIR),
+            "--mount".to_string(), format!("none:/tmp:tmpfs:size={}", self.tmpfs_size_mb * 1024 * 1024),
+            "--cwd".to_string(), SANDBOX_WORKDIR.to_string(),
+            "--seccomp_string".to_string(), SECCOMP_POLICY.to_string(),
+        ]);
+        for (name, value) in SecurityConfig::sanitize_environment() {
+            args.push("--env".to_string());
+            args.push(format!("{}={}", name, value));
+        }
+        args
+    }
+}
+
+/// The highest signal number, so exit codes past `128 + max_signal()` are
+/// read as plain exit codes.
+fn max_signal() -> i32 {
+    #[cfg(target_os = "linux")]
+    {
+        libc::SIGRTMAX()
+    }
+    #[cfg(not(target_os = "linux"))]
+    {
+        31
+    }
+}
+
+/// The process group of a running step. Every step leads a group of its own,
+/// so killing the group also reaches whatever the program started; dropping
+/// this does that, which covers callers that go away mid-run.
+///
+/// The group is killed once, right after its leader exits, to take
+/// stragglers with it; its id cannot be handed out again while any of them
+/// is alive. After that the guard is spent, so a later drop cannot signal an
+/// unrelated group that reused the id.
+pub struct ProcessGroup(Option<i32>);
+
+impl ProcessGroup {
+    pub fn of(child: &Child) -> Self {
+        Self(child.id().and_then(|pid| i32::try_from(pid).ok()))
+    }
+
+    pub fn kill(&mut self) {
+        let pgid = self.0.take();
+        #[cfg(unix)]
+        if let Some(pgid) = pgid {
+            // SAFETY: killpg has no memory effects; a group that is already
+            // gone just yields ESRCH.
+            unsafe {
+                libc::killpg(pgid, libc::SIGKILL);
+            }
+        }
+        #[cfg(not(unix))]
+        let _ = pgid;
+    }
+}
+
+impl Drop for ProcessGroup {
+    fn drop(&mut self) {
+        self.kill();
+    }
+}
+
+pub(crate) fn parse_env<T: std::str::FromStr>(name: &str) -> Result<Option<T>, AppError> {
+    match env::var(name) {
+        Ok(value) => value
+            .parse()
+            .map(Some)
+            .map_err(|_| AppError(format!("{} must be a number", name), StatusCode::INTERNAL_SERVER_ERROR)),
+        Err(_) => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn flag<'a>(args: &'a [String], name: &str) -> Option<&'a str> {
+        args.iter().position(|arg| arg == name).map(|i| args[i + 1].as_str())
+    }
+
+    fn flags<'a>(args: &'a [String], name: &str) -> Vec<&'a str> {
+        args.windows(2).filter(|pair| pair[0] == name).map(|pair| pair[1].as_str()).collect()
+    }
+
+    #[test]
+    fn jails_with_limits_and_workspace() {
+        let config = SandboxConfig {
+            root_dir: PathBuf::from("/dev/shm/kerek/root"),
+            toolchain_dirs: vec![PathBuf::from("/usr"), PathBuf::from("/nonexistent/jdk")],
+            host_uid: Some(1500),
+            host_gid: Some(1500),
+            ..SandboxConfig::default()
+        };
+        let args = config.nsjail_args(Path::new("/dev/shm/kerek/abc"), Duration::from_secs(10));
+
+        assert_eq!(flag(&args, "--chroot"), Some("/dev/shm/kerek/root"));
+        assert_eq!(flags(&args, "--bindmount_ro"), vec!["/usr"]);
+        assert_eq!(flag(&args, "--user"), Some("65534:1500:1"));
+        assert_eq!(flag(&args, "--group"), Some("65534:1500:1"));
+        assert_eq!(flag(&args, "--time_limit"), Some("11"));
+        assert_eq!(flag(&args, "--rlimit_nproc"), Some("64"));
+        assert_eq!(flags(&args, "--bindmount"), vec!["/dev/shm/kerek/abc:/workspace"]);
+        assert_eq!(flag(&args, "--cwd"), Some(SANDBOX_WORKDIR));
+        assert!(flag(&args, "--seccomp_string").is_some_and(|policy| policy.contains("ptrace")));
+        // Writable mounts are opted into one by one; nothing else is.
+        assert!(!args.iter().any(|arg| arg == "--rw"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn reads_signals_through_the_jail() {
+        use std::os::unix::process::ExitStatusExt;
+        let jailed = SandboxConfig::default();
+        assert_eq!(jailed.termination(ExitStatus::from_raw(137 << 8)), (None, Some(9)));
+        assert_eq!(jailed.termination(ExitStatus::from_raw(3 << 8)), (Some(3), None));
+        assert_eq!(jailed.termination(ExitStatus::from_raw(128 << 8)), (Some(128), None));
+        assert_eq!(jailed.termination(ExitStatus::from_raw(255 << 8)), (Some(255), None));
+
+        let direct = SandboxConfig { enabled: false, ..SandboxConfig::default() };
+        assert_eq!(direct.termination(ExitStatus::from_raw(11)), (None, Some(11)));
+        assert_eq!(direct.termination(ExitStatus::from_raw(137 << 8)), (Some(137), None));
+    }
+
+    #[test]
+    fn refuses_to_run_without_nsjail() {
+        let config = SandboxConfig { nsjail_path: PathBuf::from("/nonexistent/nsjail"), ..SandboxConfig::default() };
+        let error = config.check().unwrap_err();
+        assert_eq!(error.1, StatusCode::SERVICE_UNAVAILABLE);
+
+        let disabled = SandboxConfig { enabled: false, ..config };
+        assert!(disabled.check().is_ok());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn refuses_to_run_as_the_server_user() {
+        let nsjail_path = std::env::current_exe().unwrap();
+        // SAFETY: see `check`.
+        let (uid, gid) = unsafe { (libc::geteuid(), libc::getegid()) };
+
+        let unmapped = SandboxConfig { nsjail_path, ..SandboxConfig::default() };
+        assert_eq!(unmapped.check().unwrap_err().1, StatusCode::SERVICE_UNAVAILABLE);
+
+        let same_user = SandboxConfig { host_uid: Some(uid), host_gid: Some(gid.wrapping_add(1)), ..unmapped.clone() };
+        assert_eq!(same_user.check().unwrap_err().1, StatusCode::SERVICE_UNAVAILABLE);
+
+        let other_user = SandboxConfig { host_uid: Some(uid.wrapping_add(1)), host_gid: Some(gid.wrapping_add(1)), ..unmapped };
+        assert!(other_user.check().is_ok());
+    }
+}
@@ -1,70 +1,211 @@
 use std::collections::HashMap;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::process::ExitStatus;
+use std::sync::Arc;
 use std::time::{Duration, Instant};
 use tokio::fs;
-use tokio::io::AsyncWriteExt;
-use tokio::process::Command;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt};
+use tokio::sync::{mpsc, Notify};
+use tokio::task::JoinHandle;
 use tokio::time::timeout;
 use uuid::Uuid;
 use std::process::Stdio;
-use crate::compiler::models::{CompileRequest, CompileResponse, LanguageConfig};
+use crate::compiler::models::{CompileRequest, CompileResponse, LanguageConfig, OutputChunk, OutputStream};
+use crate::compiler::sandbox::{ProcessGroup, SandboxConfig};
 use crate::compiler::security::SecurityConfig;
 use crate::error::AppError;
 use actix_web::http::StatusCode;
 
+/// Keeps the JVM inside the sandbox's address space and process limits:
+/// a bounded heap, and the serial collector instead of a thread per core.
+const JVM_OPTIONS: &[&str] = &["-Xmx256m", "-XX:+UseSerialGC", "-XX:CompressedClassSpaceSize=64m", "-XX:ReservedCodeCacheSize=64m"];
+
+/// How long a finished step's pipes are still read. Only a process that left
+/// the step's group can hold them open past its end.
+const PIPE_GRACE: Duration = Duration::from_secs(1);
+
+/// The live side of a streamed run: where the program's output goes as it
+/// is printed, and where more stdin comes from until the sender is dropped.
+pub struct RunStream {
+    pub output: mpsc::Sender<OutputChunk>,
+    pub input: mpsc::Receiver<String>,
+}
+
+/// A finished step. Each output stream is cut off at the output limit, and a
+/// step that prints past it is killed and marked `truncated`; one that runs
+/// out of time is killed and marked `timed_out`.
+struct StepOutput {
+    status: ExitStatus,
+    stdout: String,
+    stderr: String,
+    truncated: bool,
+    timed_out: bool,
+}
+
+/// What the program printed and, if it failed, why. When compilation fails
+/// there is no output and the rest describes the compiler.
+struct RunOutcome {
+    output: Option<String>,
+    error: Option<String>,
+    truncated: bool,
+    timed_out: bool,
+    exit_code: Option<i32>,
+    signal: Option<i32>,
+}
+
+/// Reads one output pipe of a step, keeping up to `limit` bytes and passing
+/// them on to `chunks` as they arrive. Past the limit it stops reading and
+/// signals `overflow` so the step gets killed.
+async fn capture<R: AsyncRead + Unpin>(
+    mut pipe: R,
+    stream: OutputStream,
+    limit: usize,
+    chunks: Option<mpsc::Sender<OutputChunk>>,
+    overflow: Arc<Notify>,
+) -> (Vec<u8>, bool) {
+    let mut captured = Vec::new();
+    // Bytes read but not yet sent: a chunk may end inside a UTF-8 character.
+    let mut unsent = Vec::new();
+    let mut buffer = [0u8; 8192];
+    let mut truncated = false;
+
+    loop {
+        let read = match pipe.read(&mut buffer).await {
+            Ok(0) | Err(_) => break,
+            Ok(read) => read,
+        };
+        let kept = &buffer[..read.min(limit - captured.len())];
+        captured.extend_from_slice(kept);
+        truncated = read > kept.len();
+
+        if let Some(chunks) = &chunks {
+            unsent.extend_from_slice(kept);
+            let complete = match std::str::from_utf8(&unsent) {
+                Err(e) if e.error_len().is_none() && !truncated => e.valid_up_to(),
+                _ => unsent.len(),
+            };
+            if complete > 0 {
+                let data = String::from_utf8_lossy(&unsent[..complete]).into_owned();
+                unsent.drain(..complete);
+                let _ = chunks.send(OutputChunk { stream, data }).await;
+            }
+        }
+
+        if truncated {
+            overflow.notify_one();
+            break;
+        }
+    }
+
+    if let Some(chunks) = chunks.filter(|_| !unsent.is_empty()) {
+        let _ = chunks.send(OutputChunk { stream, data: String::from_utf8_lossy(&unsent).into_owned() }).await;
+    }
+    (captured, truncated)
+}
+
+/// Waits for a [`capture`] to reach the end of its pipe, up to `PIPE_GRACE`.
+async fn collect(mut capture: JoinHandle<(Vec<u8>, bool)>) -> (Vec<u8>, bool) {
+    match timeout(PIPE_GRACE, &mut capture).await {
+        Ok(captured) => captured.unwrap_or_default(),
+        Err(_) => {
+            capture.abort();
+            Default::default()
+        }
+    }
+}
+
 pub struct CompilerService {
     languages: HashMap<String, LanguageConfig>,
     temp_dir: PathBuf,
     security_config: SecurityConfig,
+    sandbox: SandboxConfig,
 }
 
 impl CompilerService {
     pub fn new() -> Result<Self, AppError> {
         let languages = LanguageConfig::get_supported_languages();
         
-        let temp_dir = std::env::temp_dir().join("kerek_compiler");
+        // Workspaces go on tmpfs where there is one, so nothing a program
+        // writes reaches the disk.
+        let shm = Path::new("/dev/shm");
+        let temp_dir = if shm.is_dir() { shm.to_path_buf() } else { std::env::temp_dir() }.join("kerek_compiler");
         std::fs::create_dir_all(&temp_dir)
             .map_err(|e| AppError(format!("Failed to create temp directory: {}", e), StatusCode::INTERNAL_SERVER_ERROR))?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(&temp_dir, std::fs::Permissions::from_mode(0o700))
+                .map_err(|e| AppError(format!("Failed to create temp directory: {}", e), StatusCode::INTERNAL_SERVER_ERROR))?;
+        }
+
+        let sandbox = SandboxConfig { root_dir: temp_dir.join("root"), ..SandboxConfig::from_env()? };
+        sandbox.prepare_root()
+            .map_err(|e| AppError(format!("Failed to create sandbox root: {}", e), StatusCode::INTERNAL_SERVER_ERROR))?;
 
         Ok(Self {
             languages,
             temp_dir,
             security_config: SecurityConfig::default(),
+            sandbox,
         })
     }
 
     pub async fn compile_and_run(&self, request: CompileRequest) -> Result<CompileResponse, AppError> {
+        self.run(request, None).await
+    }
+
+    /// [`compile_and_run`](Self::compile_and_run), also sending the program's
+    /// output to `stream` while it runs and feeding it stdin from there.
+    pub async fn compile_and_stream(&self, request: CompileRequest, stream: RunStream) -> Result<CompileResponse, AppError> {
+        self.run(request, Some(stream)).await
+    }
+
+    async fn run(&self, request: CompileRequest, stream: Option<RunStream>) -> Result<CompileResponse, AppError> {
         let start_time = Instant::now();
         
         self.security_config.validate_code(&request.code)?;
-        
         let language_config = self.languages.get(&request.language)
             .ok_or_else(|| AppError(format!("Language '{}' is not supported", request.language), StatusCode::BAD_REQUEST))?;
 
+        // Bad requests are rejected as such even where the sandbox is missing.
+        self.sandbox.check()?;
+
         let workspace_id = Uuid::new_v4().to_string();
         let workspace_dir = self.temp_dir.join(&workspace_id);
         
         fs::create_dir_all(&workspace_dir).await
             .map_err(|e| AppError(format!("Failed to create workspace: {}", e), StatusCode::INTERNAL_SERVER_ERROR))?;
+        self.sandbox.prepare_workspace(&workspace_dir)
+            .map_err(|e| AppError(format!("Failed to create workspace: {}", e), StatusCode::INTERNAL_SERVER_ERROR))?;
 
-        let result = self.execute_code(&request, language_config, &workspace_dir).await;
+        let result = self.execute_code(&request, language_config, &workspace_dir, stream).await;
         
         let _ = fs::remove_dir_all(&workspace_dir).await;
 
         let execution_time = start_time.elapsed().as_millis() as u64;
         
         match result {
-            Ok(output) => Ok(CompileResponse {
-                success: true,
-                output: Some(output),
-                error: None,
+            Ok(outcome) => Ok(CompileResponse {
+                success: outcome.error.is_none(),
+                output: outcome.output,
+                error: outcome.error,
                 execution_time,
+                output_truncated: outcome.truncated,
+                timed_out: outcome.timed_out,
+                exit_code: outcome.exit_code,
+                signal: outcome.signal,
+                queue_time: 0,
             }),
             Err(error) => Ok(CompileResponse {
                 success: false,
                 output: None,
                 error: Some(error),
                 execution_time,
+                output_truncated: false,
+                timed_out: false,
+                exit_code: None,
+                signal: None,
+                queue_time: 0,
             }),
         }
     }
@@ -73,8 +214,9 @@ impl CompilerService {
         &self,
         request: &CompileRequest,
         config: &LanguageConfig,
-        workspace_dir: &PathBuf,
-    ) -> Result<String, String> {
+        workspace_dir: &Path,
+        stream: Option<RunStream>,
+    ) -> Result<RunOutcome, String> {
         let timeout_duration = Duration::from_secs(
             request.timeout
                 .unwrap_or(self.security_config.get_execution_timeout())
@@ -82,221 +224,229 @@ impl CompilerService {
         );
 
         match request.language.as_str() {
-            "cpp" | "c" => self.execute_compiled_language(request, config, workspace_dir, timeout_duration).await,
-            "java" => self.execute_java(request, workspace_dir, timeout_duration).await,
-            "python" | "javascript" => self.execute_interpreted_language(request, config, workspace_dir, timeout_duration).await,
+            "cpp" | "c" => self.execute_compiled_language(request, config, workspace_dir, stream, timeout_duration).await,
+            "java" => self.execute_java(request, workspace_dir, stream, timeout_duration).await,
+            "python" | "javascript" => self.execute_interpreted_language(request, config, workspace_dir, stream, timeout_duration).await,
             _ => Err(format!("Language '{}' execution not implemented", request.language)),
         }
     }
 
+    /// Runs one step (a compiler or the program) in the sandbox, feeding it
+    /// `input` and then whatever arrives on `stream`. The step's whole process
+    /// group is killed when it runs out of time, when it prints too much, once
+    /// it exits, and if this future is dropped before any of that.
+    async fn run_step(
+        &self,
+        workspace_dir: &Path,
+        program: &[String],
+        input: Option<&str>,
+        stream: Option<RunStream>,
+        timeout_duration: Duration,
+    ) -> std::io::Result<StepOutput> {
+        let mut command = self.sandbox.command(workspace_dir, timeout_duration, program);
+        command
+            .stdin(if input.is_some() || stream.is_some() { Stdio::piped() } else { Stdio::null() })
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+
+        let mut child = command.spawn()?;
+        let mut group = ProcessGroup::of(&child);
+        let (chunks, more_input) = match stream {
+            Some(RunStream { output, input }) => (Some(output), Some(input)),
+            None => (None, None),
+        };
+
+        if let Some(mut stdin) = child.stdin.take() {
+            // Written alongside the run, so a program that prints before it
+            // reads cannot deadlock against a full pipe.
+            let input = input.map(str::to_string);
+            tokio::spawn(async move {
+                if let Some(input) = input {
+                    if stdin.write_all(input.as_bytes()).await.is_err() {
+                        return;
+                    }
+                }
+                if let Some(mut more_input) = more_input {
+                    while let Some(data) = more_input.recv().await {
+                        if stdin.write_all(data.as_bytes()).await.is_err() {
+                            return;
+                        }
+                    }
+                }
+                let _ = stdin.shutdown().await;
+            });
+        }
+
+        let (Some(stdout), Some(stderr)) = (child.stdout.take(), child.stderr.take()) else {
+            return Err(std::io::Error::other("output pipes were not opened"));
+        };
+        let limit = self.security_config.max_output_size;
+        let overflow = Arc::new(Notify::new());
+        let stdout = tokio::spawn(capture(stdout, OutputStream::Stdout, limit, chunks.clone(), overflow.clone()));
+        let stderr = tokio::spawn(capture(stderr, OutputStream::Stderr, limit, chunks, overflow.clone()));
+
+        let exited = timeout(timeout_duration, async {
+            tokio::select! {
+                status = child.wait() => Some(status),
+                _ = overflow.notified() => None,
+            }
+        }).await;
+        let timed_out = exited.is_err();
+        let status = match exited {
+            Ok(Some(status)) => status?,
+            _ => {
+                group.kill();
+                // In case the program moved itself out of its group.
+                let _ = child.start_kill();
+                child.wait().await?
+            }
+        };
+        // Whatever the program left behind goes with it, which also closes
+        // the pipes it shared.
+        group.kill();
+
+        let (stdout, stdout_truncated) = collect(stdout).await;
+        let (stderr, stderr_truncated) = collect(stderr).await;
+        Ok(StepOutput {
+            status,
+            stdout: String::from_utf8_lossy(&stdout).into_owned(),
+            stderr: String::from_utf8_lossy(&stderr).into_owned(),
+            truncated: stdout_truncated || stderr_truncated,
+            timed_out,
+        })
+    }
+
+    /// Turns the program's step into the run's result.
+    fn outcome(&self, step: StepOutput, timeout_error: &str, runtime_error: &str) -> RunOutcome {
+        let error = if step.timed_out {
+            Some(timeout_error.to_string())
+        } else if step.truncated {
+            Some(format!("Output exceeded the limit of {} bytes", self.security_config.max_output_size))
+        } else if !step.status.success() {
+            Some(format!("{}: {}", runtime_error, step.stderr))
+        } else {
+            None
+        };
+        let (exit_code, signal) = self.sandbox.termination(step.status);
+        RunOutcome {
+            output: Some(step.stdout),
+            error,
+            truncated: step.truncated,
+            timed_out: step.timed_out,
+            exit_code,
+            signal,
+        }
+    }
+
+    /// The run's result when a compile step failed; `None` when it succeeded.
+    fn compile_failure(&self, step: StepOutput, timeout_error: &str, compile_error: &str) -> Option<RunOutcome> {
+        let error = if step.timed_out {
+            timeout_error.to_string()
+        } else if !step.status.success() {
+            format!("{}: {}", compile_error, step.stderr)
+        } else {
+            return None;
+        };
+        let (exit_code, signal) = self.sandbox.termination(step.status);
+        Some(RunOutcome {
+            output: None,
+            error: Some(error),
+            truncated: false,
+            timed_out: step.timed_out,
+            exit_code,
+            signal,
+        })
+    }
+
     async fn execute_compiled_language(
         &self,
         request: &CompileRequest,
         config: &LanguageConfig,
-        workspace_dir: &PathBuf,
+        workspace_dir: &Path,
+        stream: Option<RunStream>,
         timeout_duration: Duration,
-    ) -> Result<String, String> {
-        let source_file = workspace_dir.join(format!("main{}", config.file_extension));
-        let executable_file = workspace_dir.join("main");
+    ) -> Result<RunOutcome, String> {
+        let source_file = format!("main{}", config.file_extension);
 
-        
-        fs::write(&source_file, &request.code).await
+        fs::write(workspace_dir.join(&source_file), &request.code).await
             .map_err(|e| format!("Failed to write source file: {}", e))?;
 
-        
         if let Some(compile_cmd) = &config.compile_command {
-            let mut cmd = Command::new(&compile_cmd[0]);
-            cmd.args(&compile_cmd[1..])
-                .arg(&executable_file)
-                .arg(&source_file)
-                .current_dir(workspace_dir);
-
-            let compile_result = timeout(timeout_duration, cmd.output()).await
-                .map_err(|_| "Compilation timeout".to_string())?
+            let mut program = compile_cmd.clone();
+            program.push("main".to_string());
+            program.push(source_file);
+
+            let compile_result = self.run_step(workspace_dir, &program, None, None, timeout_duration).await
                 .map_err(|e| format!("Compilation failed: {}", e))?;
 
-            if !compile_result.status.success() {
-                let stderr = String::from_utf8_lossy(&compile_result.stderr);
-                return Err(format!("Compilation error: {}", stderr));
+            if let Some(failure) = self.compile_failure(compile_result, "Compilation timeout", "Compilation error") {
+                return Ok(failure);
             }
         }
 
-        let mut run_cmd = Command::new(&executable_file);
-        run_cmd.current_dir(workspace_dir);
+        let output = self.run_step(workspace_dir, &["./main".to_string()], request.input.as_deref(), stream, timeout_duration).await
+            .map_err(|e| format!("Execution failed: {}", e))?;
 
-        if let Some(input) = &request.input {
-            run_cmd.stdin(Stdio::piped())
-                .stdout(Stdio::piped())
-                .stderr(Stdio::piped());
-
-            let mut child = run_cmd.spawn()
-                .map_err(|e| format!("Failed to start execution: {}", e))?;
-
-
-            if let Some(stdin) = child.stdin.take() {
-                let mut stdin = stdin;
-                stdin.write_all(input.as_bytes()).await
-                    .map_err(|e| format!("Failed to write input: {}", e))?;
-                stdin.shutdown().await
-                    .map_err(|e| format!("Failed to close stdin: {}", e))?;
-            }
-
-            let output = timeout(timeout_duration, child.wait_with_output()).await
-                .map_err(|_| "Execution timeout".to_string())?
-                .map_err(|e| format!("Execution failed: {}", e))?;
-
-            if !output.status.success() {
-                let stderr = String::from_utf8_lossy(&output.stderr);
-                return Err(format!("Runtime error: {}", stderr));
-            }
-
-            Ok(String::from_utf8_lossy(&output.stdout).to_string())
-        } else {
-            let output = timeout(timeout_duration, run_cmd.output()).await
-                .map_err(|_| "Execution timeout".to_string())?
-                .map_err(|e| format!("Execution failed: {}", e))?;
-
-            if !output.status.success() {
-                let stderr = String::from_utf8_lossy(&output.stderr);
-                return Err(format!("Runtime error: {}", stderr));
-            }
-
-            Ok(String::from_utf8_lossy(&output.stdout).to_string())
-        }
+        Ok(self.outcome(output, "Execution timeout", "Runtime error"))
     }
 
     async fn execute_java(
         &self,
         request: &CompileRequest,
-        workspace_dir: &PathBuf,
+        workspace_dir: &Path,
+        stream: Option<RunStream>,
         timeout_duration: Duration,
-    ) -> Result<String, String> {
+    ) -> Result<RunOutcome, String> {
 
         let class_name = self.extract_java_class_name(&request.code)
             .unwrap_or_else(|| "Main".to_string());
 
-        let source_file = workspace_dir.join(format!("{}.java", class_name));
+        let source_file = format!("{}.java", class_name);
 
-        
-        fs::write(&source_file, &request.code).await
+        fs::write(workspace_dir.join(&source_file), &request.code).await
             .map_err(|e| format!("Failed to write Java file: {}", e))?;
 
-        
-        let mut compile_cmd = Command::new("javac");
-        compile_cmd.arg(&source_file)
-            .current_dir(workspace_dir);
+        let mut program = vec!["javac".to_string()];
+        program.extend(JVM_OPTIONS.iter().map(|option| format!("-J{}", option)));
+        program.push(source_file);
 
-        let compile_result = timeout(timeout_duration, compile_cmd.output()).await
-            .map_err(|_| "Java compilation timeout".to_string())?
+        let compile_result = self.run_step(workspace_dir, &program, None, None, timeout_duration).await
             .map_err(|e| format!("Java compilation failed: {}", e))?;
 
-        if !compile_result.status.success() {
-            let stderr = String::from_utf8_lossy(&compile_result.stderr);
-            return Err(format!("Java compilation error: {}", stderr));
+        if let Some(failure) = self.compile_failure(compile_result, "Java compilation timeout", "Java compilation error") {
+            return Ok(failure);
         }
 
-        
-        let mut run_cmd = Command::new("java");
-        run_cmd.arg(&class_name)
-            .current_dir(workspace_dir);
-
-        if let Some(input) = &request.input {
-            run_cmd.stdin(Stdio::piped())
-                .stdout(Stdio::piped())
-                .stderr(Stdio::piped());
-
-            let mut child = run_cmd.spawn()
-                .map_err(|e| format!("Failed to start Java execution: {}", e))?;
-
-            if let Some(stdin) = child.stdin.take() {
-                let mut stdin = stdin;
-                stdin.write_all(input.as_bytes()).await
-                    .map_err(|e| format!("Failed to write input: {}", e))?;
-                stdin.shutdown().await
-                    .map_err(|e| format!("Failed to close stdin: {}", e))?;
-            }
+        let mut program = vec!["java".to_string()];
+        program.extend(JVM_OPTIONS.iter().map(|option| option.to_string()));
+        program.extend(["-cp".to_string(), ".".to_string(), class_name]);
 
-            let output = timeout(timeout_duration, child.wait_with_output()).await
-                .map_err(|_| "Java execution timeout".to_string())?
-                .map_err(|e| format!("Java execution failed: {}", e))?;
+        let output = self.run_step(workspace_dir, &program, request.input.as_deref(), stream, timeout_duration).await
+            .map_err(|e| format!("Java execution failed: {}", e))?;
 
-            if !output.status.success() {
-                let stderr = String::from_utf8_lossy(&output.stderr);
-                return Err(format!("Java runtime error: {}", stderr));
-            }
-
-            Ok(String::from_utf8_lossy(&output.stdout).to_string())
-        } else {
-            let output = timeout(timeout_duration, run_cmd.output()).await
-                .map_err(|_| "Java execution timeout".to_string())?
-                .map_err(|e| format!("Java execution failed: {}", e))?;
-
-            if !output.status.success() {
-                let stderr = String::from_utf8_lossy(&output.stderr);
-                return Err(format!("Java runtime error: {}", stderr));
-            }
-
-            Ok(String::from_utf8_lossy(&output.stdout).to_string())
-        }
+        Ok(self.outcome(output, "Java execution timeout", "Java runtime error"))
     }
 
     async fn execute_interpreted_language(
         &self,
         request: &CompileRequest,
         config: &LanguageConfig,
-        workspace_dir: &PathBuf,
+        workspace_dir: &Path,
+        stream: Option<RunStream>,
         timeout_duration: Duration,
-    ) -> Result<String, String> {
-        let source_file = workspace_dir.join(format!("main{}", config.file_extension));
+    ) -> Result<RunOutcome, String> {
+        let source_file = format!("main{}", config.file_extension);
 
-        
-        fs::write(&source_file, &request.code).await
+        fs::write(workspace_dir.join(&source_file), &request.code).await
             .map_err(|e| format!("Failed to write source file: {}", e))?;
 
+        let mut program = config.run_command.clone();
+        program.push(source_file);
 
-        let mut cmd = Command::new(&config.run_command[0]);
-        cmd.args(&config.run_command[1..])
-            .arg(&source_file)
-            .current_dir(workspace_dir);
-
-        if let Some(input) = &request.input {
-            cmd.stdin(Stdio::piped())
-                .stdout(Stdio::piped())
-                .stderr(Stdio::piped());
+        let output = self.run_step(workspace_dir, &program, request.input.as_deref(), stream, timeout_duration).await
+            .map_err(|e| format!("Execution failed: {}", e))?;
 
-            let mut child = cmd.spawn()
-                .map_err(|e| format!("Failed to start interpreter: {}", e))?;
-
-            if let Some(stdin) = child.stdin.take() {
-                let mut stdin = stdin;
-                stdin.write_all(input.as_bytes()).await
-                    .map_err(|e| format!("Failed to write input: {}", e))?;
-                stdin.shutdown().await
-                    .map_err(|e| format!("Failed to close stdin: {}", e))?;
-            }
-
-            let output = timeout(timeout_duration, child.wait_with_output()).await
-                .map_err(|_| "Execution timeout".to_string())?
-                .map_err(|e| format!("Execution failed: {}", e))?;
-
-            if !output.status.success() {
-                let stderr = String::from_utf8_lossy(&output.stderr);
-                return Err(format!("Runtime error: {}", stderr));
-            }
-
-            Ok(String::from_utf8_lossy(&output.stdout).to_string())
-        } else {
-            let output = timeout(timeout_duration, cmd.output()).await
-                .map_err(|_| "Execution timeout".to_string())?
-                .map_err(|e| format!("Execution failed: {}", e))?;
-
-            if !output.status.success() {
-                let stderr = String::from_utf8_lossy(&output.stderr);
-                return Err(format!("Runtime error: {}", stderr));
-            }
-
-            Ok(String::from_utf8_lossy(&output.stdout).to_string())
-        }
+        Ok(self.outcome(output, "Execution timeout", "Runtime error"))
     }
 
     fn extract_java_class_name(&self, code: &str) -> Option<String> {
@@ -318,3 +468,54 @@ impl CompilerService {
             .collect()
     }
 } 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn capture_all<R: AsyncRead + Unpin>(pipe: R, limit: usize) -> (Vec<u8>, bool, bool, String) {
+        let (chunks_tx, mut chunks_rx) = mpsc::channel(16);
+        let overflow = Arc::new(Notify::new());
+        let (captured, truncated) = capture(pipe, OutputStream::Stdout, limit, Some(chunks_tx), overflow.clone()).await;
+
+        let mut streamed = String::new();
+        while let Ok(chunk) = chunks_rx.try_recv() {
+            streamed.push_str(&chunk.data);
+        }
+        let notified = timeout(Duration::from_millis(10), overflow.notified()).await.is_ok();
+        (captured, truncated, notified, streamed)
+    }
+
+    #[tokio::test]
+    async fn keeps_output_of_exactly_the_limit() {
+        let (captured, truncated, notified, streamed) = capture_all(&b"abcd"[..], 4).await;
+        assert_eq!(captured, b"abcd");
+        assert!(!truncated && !notified);
+        assert_eq!(streamed, "abcd");
+    }
+
+    #[tokio::test]
+    async fn truncates_one_byte_past_the_limit() {
+        // The extra byte arrives in a read of its own.
+        let (captured, truncated, notified, streamed) = capture_all((&b"abcd"[..]).chain(&b"e"[..]), 4).await;
+        assert_eq!(captured, b"abcd");
+        assert!(truncated && notified);
+        assert_eq!(streamed, "abcd");
+    }
+
+    #[tokio::test]
+    async fn streams_characters_split_across_reads_whole() {
+        let (captured, truncated, _, streamed) = capture_all((&b"a\xc3"[..]).chain(&b"\xa9b"[..]), 8).await;
+        assert_eq!(captured, "a\u{e9}b".as_bytes());
+        assert!(!truncated);
+        assert_eq!(streamed, "a\u{e9}b");
+    }
+
+    #[tokio::test]
+    async fn cuts_a_character_split_by_the_limit() {
+        let (captured, truncated, notified, streamed) = capture_all("ab\u{e9}".as_bytes(), 3).await;
+        assert_eq!(captured, b"ab\xc3");
+        assert!(truncated && notified);
+        assert_eq!(streamed, "ab\u{fffd}");
+    }
+}
@@ -1,14 +1,18 @@
-use actix_web::{web, HttpResponse, HttpRequest};
+use actix_web::{web, HttpResponse};
 use scylla::client::session::Session;
 use crate::models::{
     conversation::{ NewConversation, ConversationCustomization},
-    message::{NewMessage},
+    message::{NewMessage, UpdateMessage},
 };
 use crate::error::AppError;
 use crate::conversations::service::ConversationService;
 use serde::{Serialize, Deserialize};
 use actix_web::http::StatusCode;
-use crate::utils::jwt::get_user_id_from_token;
+use crate::utils::jwt::AuthenticatedUser;
+use crate::utils::pagination::{clamp_page_size, MessageCursor};
+use crate::utils::protocol::ServerEvent;
+use crate::utils::read_markers::ReadMarkers;
+use crate::utils::websocket::{self, RoomStore};
 use futures_util::StreamExt as _;
 
 
@@ -21,14 +25,16 @@ pub struct ListMessagesRequest {
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ListMessagesQuery {
     pub limit: Option<i32>,
+    pub before: Option<String>,
+    pub after: Option<String>,
 }
 
 
 pub async fn list_conversations(
     session: web::Data<Session>,
-    req: HttpRequest,
+    user: AuthenticatedUser,
 ) -> Result<HttpResponse, AppError> {
-    let user_id = get_user_id_from_token(&req)?;
+    let user_id = user.user_id;
     let service = ConversationService::new(session).await?;
     let conversations = service.list_conversations(&user_id).await?;
     Ok(HttpResponse::Ok().json(conversations))
@@ -36,10 +42,10 @@ pub async fn list_conversations(
 
 pub async fn create_conversation(
     session: web::Data<Session>,
-    req: HttpRequest,
+    user: AuthenticatedUser,
     new_conversation: web::Json<NewConversation>,
 ) -> Result<HttpResponse, AppError> {
-    let user_id = get_user_id_from_token(&req)?;
+    let user_id = user.user_id;
     let service = ConversationService::new(session).await?;
     let conversation = service
         .create_conversation(new_conversation.into_inner(), user_id)
@@ -58,11 +64,11 @@ pub async fn get_conversation(
 
 pub async fn update_conversation(
     session: web::Data<Session>,
-    req: HttpRequest,
+    user: AuthenticatedUser,
     conversation_id: web::Path<String>,
     name: web::Json<Option<String>>,
 ) -> Result<HttpResponse, AppError> {
-    let user_id = get_user_id_from_token(&req)?;
+    let user_id = user.user_id;
     let service = ConversationService::new(session).await?;
     
     let conversation = service.get_conversation(&conversation_id).await?;
@@ -78,11 +84,11 @@ pub async fn update_conversation(
 
 pub async fn list_messages(
     session: web::Data<Session>,
-    req: HttpRequest,
+    user: AuthenticatedUser,
     conversation_id: web::Path<String>,
     query: web::Query<ListMessagesQuery>,
 ) -> Result<HttpResponse, AppError> {
-    let user_id = get_user_id_from_token(&req)?;
+    let user_id = user.user_id;
     let service = ConversationService::new(session).await?;
     
     let conversation = service.get_conversation(&conversation_id).await?;
@@ -90,18 +96,19 @@ pub async fn list_messages(
         return Err(AppError("Not authorized to view messages in this conversation".to_string(), StatusCode::FORBIDDEN));
     }
 
-    let limit = query.limit.unwrap_or(50);
-    let messages = service.list_messages(&conversation_id, limit).await?;
-    Ok(HttpResponse::Ok().json(messages))
+    let cursor = MessageCursor::from_query(query.before.as_deref(), query.after.as_deref())?;
+    let limit = clamp_page_size(query.limit);
+    let page = service.list_messages(&conversation_id, cursor, limit).await?;
+    Ok(HttpResponse::Ok().json(page))
 }
 
 pub async fn send_message(
     session: web::Data<Session>,
-    req: HttpRequest,
+    user: AuthenticatedUser,
     conversation_id: web::Path<String>,
     new_message: web::Json<NewMessage>,
 ) -> Result<HttpResponse, AppError> {
-    let user_id = get_user_id_from_token(&req)?;
+    let user_id = user.user_id;
     let service = ConversationService::new(session).await?;
     
     let conversation = service.get_conversation(&conversation_id).await?;
@@ -115,13 +122,75 @@ pub async fn send_message(
     Ok(HttpResponse::Created().json(message))
 }
 
+pub async fn edit_message(
+    session: web::Data<Session>,
+    room_store: web::Data<RoomStore>,
+    user: AuthenticatedUser,
+    path: web::Path<(String, String)>,
+    update: web::Json<UpdateMessage>,
+) -> Result<HttpResponse, AppError> {
+    let user_id = user.user_id;
+    let (conversation_id, message_id) = path.into_inner();
+    let service = ConversationService::new(session.clone()).await?;
+
+    let conversation = service.get_conversation(&conversation_id).await?;
+    if !conversation.participant_ids.contains(&user_id) {
+        return Err(AppError("Not authorized to edit messages in this conversation".to_string(), StatusCode::FORBIDDEN));
+    }
+
+    let message = service
+        .edit_message(&conversation_id, &message_id, &user_id, update.into_inner())
+        .await?;
+    websocket::broadcast_event(&session, &room_store, &conversation_id, &conversation.participant_ids, &user_id, &ServerEvent::MessageEdited(&message)).await;
+    Ok(HttpResponse::Ok().json(message))
+}
+
+pub async fn delete_message(
+    session: web::Data<Session>,
+    room_store: web::Data<RoomStore>,
+    user: AuthenticatedUser,
+    path: web::Path<(String, String)>,
+) -> Result<HttpResponse, AppError> {
+    let user_id = user.user_id;
+    let (conversation_id, message_id) = path.into_inner();
+    let service = ConversationService::new(session.clone()).await?;
+
+    let conversation = service.get_conversation(&conversation_id).await?;
+    if !conversation.participant_ids.contains(&user_id) {
+        return Err(AppError("Not authorized to delete messages in this conversation".to_string(), StatusCode::FORBIDDEN));
+    }
+
+    let message = service
+        .delete_message(&conversation_id, &message_id, &user_id)
+        .await?;
+    websocket::broadcast_event(&session, &room_store, &conversation_id, &conversation.participant_ids, &user_id, &ServerEvent::MessageDeleted(&message)).await;
+    Ok(HttpResponse::Ok().json(message))
+}
+
+pub async fn list_read_markers(
+    session: web::Data<Session>,
+    user: AuthenticatedUser,
+    conversation_id: web::Path<String>,
+) -> Result<HttpResponse, AppError> {
+    let user_id = user.user_id;
+    let service = ConversationService::new(session.clone()).await?;
+
+    let conversation = service.get_conversation(&conversation_id).await?;
+    if !conversation.participant_ids.contains(&user_id) {
+        return Err(AppError("Not authorized to view this conversation".to_string(), StatusCode::FORBIDDEN));
+    }
+
+    let markers = ReadMarkers::new(session).list(&conversation_id).await?;
+    Ok(HttpResponse::Ok().json(markers))
+}
+
 pub async fn update_conversation_customization(
     session: web::Data<Session>,
-    req: HttpRequest,
+    user: AuthenticatedUser,
     conversation_id: web::Path<String>,
     customization: web::Json<ConversationCustomization>,
 ) -> Result<HttpResponse, AppError> {
-    let user_id = get_user_id_from_token(&req)?;
+    let user_id = user.user_id;
     let service = ConversationService::new(session).await?;
     let conversation = service.get_conversation(&conversation_id).await?;
     log::info!("Conversation: {:?}", conversation);
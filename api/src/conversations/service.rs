@@ -4,18 +4,35 @@ use serde::{Deserialize, Serialize};
 use crate::{
     error::AppError, models::{
         conversation::{Conversation, ConversationCustomization, NewConversation},
-        message::{Message, NewMessage},
+        message::{Message, NewMessage, UpdateMessage},
         user::User,
-    }, utils::{db_client::DbClient, one_to_one::one_to_one_key}
+    }, utils::{db_client::{DbClient, MAX_KEYS_PER_QUERY}, one_to_one::one_to_one_key, pagination::{MessageCursor, MessagePage}, read_markers::{ReadMarkers, MAX_UNREAD_COUNT}, delivery::timeuuid_at}
 };
 use chrono::Utc;
-use uuid::{NoContext, Timestamp, Uuid};
+use uuid::Uuid;
 use scylla::value::CqlTimestamp;
 use actix_web::http::StatusCode;
 use actix_web::web;
 use std::time::{SystemTime, UNIX_EPOCH};
 use scylla::value::CqlTimeuuid;
+use std::collections::HashMap;
 use std::marker::PhantomData;
+
+type MessageRow = (Uuid, CqlTimeuuid, Uuid, String, CqlTimestamp, CqlTimestamp, Option<bool>);
+
+fn message_from_row((conv_id, msg_id, sender_id, content, sent_at, edited_at, is_deleted): MessageRow) -> Message {
+    Message {
+        id: msg_id.to_string(),
+        conversation_id: conv_id.to_string(),
+        sender_id: sender_id.to_string(),
+        content,
+        created_at: sent_at.0,
+        updated_at: edited_at.0,
+        is_edited: sent_at.0 != edited_at.0,
+        is_deleted: is_deleted.unwrap_or(false),
+    }
+}
+
 pub struct ConversationService {
     session: web::Data<Session>,
 }
@@ -67,6 +84,7 @@ impl ConversationService {
                     last_message_at: None,
                     participant_ids: participants.into_iter().map(|p| p.to_string()).collect(),
                     customization: None,
+                    unread_count: None,
                 });
             }
         }
@@ -131,6 +149,7 @@ impl ConversationService {
             last_message_at: None,
             participant_ids: participants.into_iter().map(|p| p.to_string()).collect(),
             customization: None,
+            unread_count: None,
         })
     }
     
@@ -195,6 +214,7 @@ impl ConversationService {
                 last_message_at: None,
                 participant_ids: participants.into_iter().map(|p| p.to_string()).collect(),
                 customization,
+                unread_count: None,
             })
         } else {
             Err(AppError("Conversation not found".into(), StatusCode::NOT_FOUND))
@@ -224,6 +244,7 @@ impl ConversationService {
         let conversation_ids: Vec<Uuid> = results.into_iter()
             .map(|(id,)| id)
             .collect();
+        let unread_counts = self.unread_counts(&conversation_ids, user_id).await?;
     
         let mut conversations = Vec::new();
         for id in conversation_ids {
@@ -258,6 +279,7 @@ impl ConversationService {
                     last_message_at: None,
                     participant_ids: participants.into_iter().map(|p| p.to_string()).collect(),
                     customization,
+                    unread_count: Some(unread_counts.get(&id).copied().unwrap_or(0)),
                 });
             }
         }
@@ -265,6 +287,91 @@ impl ConversationService {
         Ok(conversations)
     }
     
+    /// [`Self::unread_count`] for many conversations, with one query for the
+    /// read markers and at most two for the messages per chunk of
+    /// conversations. Conversations the user has read are scanned from the
+    /// oldest of their markers, so one read far later than the rest of its
+    /// chunk can fill its scan with messages it has already seen; those are
+    /// counted again on their own.
+    async fn unread_counts(&self, conversation_ids: &[Uuid], user_id: &str) -> Result<HashMap<Uuid, i64>, AppError> {
+        let user_uuid = Uuid::parse_str(user_id)
+            .map_err(|e| AppError(format!("Invalid user ID: {}", e), StatusCode::BAD_REQUEST))?;
+        let markers = ReadMarkers::new(self.session.clone())
+            .get_many(conversation_ids, user_id)
+            .await?;
+
+        let db_client = DbClient::<Message> { 
+            session: &self.session, 
+            _phantom: PhantomData 
+        };
+
+        let mut counts = HashMap::with_capacity(conversation_ids.len());
+        for chunk in conversation_ids.chunks(MAX_KEYS_PER_QUERY) {
+            let (read, never_read): (Vec<Uuid>, Vec<Uuid>) = chunk.iter().copied().partition(|id| markers.contains_key(id));
+            let oldest_marker = read.iter().map(|id| markers[id]).min();
+
+            let mut rows = Vec::new();
+            if !never_read.is_empty() {
+                rows.extend(db_client.query::<(Uuid, CqlTimeuuid, Uuid, Option<bool>), _>(
+                    "SELECT conversation_id, message_id, sender_id, is_deleted FROM messages WHERE conversation_id IN ? PER PARTITION LIMIT ?",
+                    Some((never_read, MAX_UNREAD_COUNT))
+                ).await?);
+            }
+            if let Some(oldest_marker) = oldest_marker {
+                rows.extend(db_client.query::<(Uuid, CqlTimeuuid, Uuid, Option<bool>), _>(
+                    "SELECT conversation_id, message_id, sender_id, is_deleted FROM messages WHERE conversation_id IN ? AND message_id > ? PER PARTITION LIMIT ?",
+                    Some((read, oldest_marker, MAX_UNREAD_COUNT))
+                ).await?);
+            }
+
+            let mut scanned: HashMap<Uuid, i32> = HashMap::new();
+            for (conversation_id, message_id, sender_id, is_deleted) in rows {
+                *scanned.entry(conversation_id).or_default() += 1;
+                let after_marker = markers.get(&conversation_id).is_none_or(|last_read| message_id > *last_read);
+                if after_marker && sender_id != user_uuid && !is_deleted.unwrap_or(false) {
+                    *counts.entry(conversation_id).or_default() += 1;
+                }
+            }
+
+            for id in chunk {
+                let scan_full = scanned.get(id).copied().unwrap_or(0) >= MAX_UNREAD_COUNT;
+                if scan_full && markers.get(id).copied() > oldest_marker {
+                    counts.insert(*id, self.unread_count(*id, user_id).await?);
+                }
+            }
+        }
+        Ok(counts)
+    }
+
+    /// Counts messages from other participants after the user's read marker,
+    /// capped at `MAX_UNREAD_COUNT`.
+    async fn unread_count(&self, conversation_id: Uuid, user_id: &str) -> Result<i64, AppError> {
+        let last_read = ReadMarkers::new(self.session.clone())
+            .get(&conversation_id.to_string(), user_id)
+            .await?;
+
+        let db_client = DbClient::<Message> { 
+            session: &self.session, 
+            _phantom: PhantomData 
+        };
+
+        let results = match last_read {
+            Some(last_read) => db_client.query::<(Uuid, Option<bool>), _>(
+                "SELECT sender_id, is_deleted FROM messages WHERE conversation_id = ? AND message_id > ? LIMIT ?",
+                Some((conversation_id, last_read, MAX_UNREAD_COUNT))
+            ).await?,
+            None => db_client.query::<(Uuid, Option<bool>), _>(
+                "SELECT sender_id, is_deleted FROM messages WHERE conversation_id = ? LIMIT ?",
+                Some((conversation_id, MAX_UNREAD_COUNT))
+            ).await?,
+        };
+
+        Ok(results
+            .iter()
+            .filter(|(sender_id, is_deleted)| sender_id.to_string() != user_id && !is_deleted.unwrap_or(false))
+            .count() as i64)
+    }
+
     pub async fn update_conversation(
         &self,
         id: &str,
@@ -299,8 +406,7 @@ impl ConversationService {
             .map_err(|e| AppError(format!("Invalid sender ID: {}", e), StatusCode::BAD_REQUEST))?;
     
         let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
-        let ts = Timestamp::from_unix(&NoContext, now.as_secs(), now.subsec_nanos());
-        let message_id = Uuid::new_v1(ts, &[1, 2, 3, 4, 5, 6]);
+        let message_id = timeuuid_at(now.as_secs(), now.subsec_nanos());
     
         let now_ts = CqlTimestamp(now.as_secs() as i64 * 1000); 
     
@@ -337,8 +443,9 @@ impl ConversationService {
     pub async fn list_messages(
         &self,
         conversation_id: &str,
+        cursor: MessageCursor,
         limit: i32,
-    ) -> Result<Vec<Message>, AppError> {
+    ) -> Result<MessagePage<Message>, AppError> {
         let conversation_uuid = Uuid::parse_str(conversation_id)
             .map_err(|e| AppError(format!("Invalid conversation ID: {}", e), StatusCode::BAD_REQUEST))?;
 
@@ -347,31 +454,157 @@ impl ConversationService {
             _phantom: PhantomData 
         };
 
-        let results = db_client.query::<(Uuid, CqlTimeuuid, Uuid, String, CqlTimestamp, CqlTimestamp), _>(
-            "SELECT conversation_id, message_id, sender_id, content, sent_at, edited_at 
-             FROM messages 
-             WHERE conversation_id = ? 
-             ORDER BY message_id DESC 
-             LIMIT ?",
-            Some((conversation_uuid, limit))
-        ).await?;
+        let fetch_limit = limit + 1;
+        let results = match cursor {
+            MessageCursor::Latest => db_client.query::<MessageRow, _>(
+                "SELECT conversation_id, message_id, sender_id, content, sent_at, edited_at, is_deleted 
+                 FROM messages 
+                 WHERE conversation_id = ? 
+                 ORDER BY message_id DESC 
+                 LIMIT ?",
+                Some((conversation_uuid, fetch_limit))
+            ).await?,
+            MessageCursor::Before(message_id) => db_client.query::<MessageRow, _>(
+                "SELECT conversation_id, message_id, sender_id, content, sent_at, edited_at, is_deleted 
+                 FROM messages 
+                 WHERE conversation_id = ? AND message_id < ? 
+                 ORDER BY message_id DESC 
+                 LIMIT ?",
+                Some((conversation_uuid, message_id, fetch_limit))
+            ).await?,
+            MessageCursor::After(message_id) => db_client.query::<MessageRow, _>(
+                "SELECT conversation_id, message_id, sender_id, content, sent_at, edited_at, is_deleted 
+                 FROM messages 
+                 WHERE conversation_id = ? AND message_id > ? 
+                 ORDER BY message_id ASC 
+                 LIMIT ?",
+                Some((conversation_uuid, message_id, fetch_limit))
+            ).await?,
+        };
 
         let messages = results.into_iter()
-            .map(|(conv_id, msg_id, sender_id, content, sent_at, edited_at)| {
-                Message {
-                    id: msg_id.to_string(),
-                    conversation_id: conv_id.to_string(),
-                    sender_id: sender_id.to_string(),
-                    content,
-                    created_at: sent_at.0,
-                    updated_at: edited_at.0,
-                    is_edited: sent_at.0 != edited_at.0,
-                    is_deleted: false,
-                }
-            })
+            .map(message_from_row)
             .collect();
 
-        Ok(messages)
+        Ok(MessagePage::from_rows(messages, limit, |message: &Message| message.id.clone()))
+    }
+
+    pub async fn get_message(
+        &self,
+        conversation_id: &str,
+        message_id: &str,
+    ) -> Result<Message, AppError> {
+        let conversation_uuid = Uuid::parse_str(conversation_id)
+            .map_err(|e| AppError(format!("Invalid conversation ID: {}", e), StatusCode::BAD_REQUEST))?;
+        let message_uuid = Uuid::parse_str(message_id)
+            .map_err(|e| AppError(format!("Invalid message ID: {}", e), StatusCode::BAD_REQUEST))?;
+
+        let db_client = DbClient::<Message> { 
+            session: &self.session, 
+            _phantom: PhantomData 
+        };
+
+        let results = db_client.query::<MessageRow, _>(
+            "SELECT conversation_id, message_id, sender_id, content, sent_at, edited_at, is_deleted 
+             FROM messages 
+             WHERE conversation_id = ? AND message_id = ?",
+            Some((conversation_uuid, CqlTimeuuid::from_bytes(*message_uuid.as_bytes())))
+        ).await?;
+
+        results.into_iter()
+            .next()
+            .map(message_from_row)
+            .ok_or_else(|| AppError("Message not found".to_string(), StatusCode::NOT_FOUND))
+    }
+
+    pub async fn edit_message(
+        &self,
+        conversation_id: &str,
+        message_id: &str,
+        user_id: &str,
+        update: UpdateMessage,
+    ) -> Result<Message, AppError> {
+        if update.content.trim().is_empty() {
+            return Err(AppError("Message content cannot be empty".to_string(), StatusCode::BAD_REQUEST));
+        }
+
+        let mut message = self.get_message(conversation_id, message_id).await?;
+        if message.sender_id != user_id {
+            return Err(AppError("You can only edit your own messages".to_string(), StatusCode::FORBIDDEN));
+        }
+        if message.is_deleted {
+            return Err(AppError("Cannot edit a deleted message".to_string(), StatusCode::BAD_REQUEST));
+        }
+
+        let conversation_uuid = Uuid::parse_str(conversation_id)
+            .map_err(|e| AppError(format!("Invalid conversation ID: {}", e), StatusCode::BAD_REQUEST))?;
+        let message_uuid = Uuid::parse_str(message_id)
+            .map_err(|e| AppError(format!("Invalid message ID: {}", e), StatusCode::BAD_REQUEST))?;
+        let now_ms = Utc::now().timestamp_millis();
+
+        let db_client = DbClient::<Message> { 
+            session: &self.session, 
+            _phantom: PhantomData 
+        };
+
+        db_client.insert(
+            "UPDATE messages SET content = ?, edited_at = ? WHERE conversation_id = ? AND message_id = ?",
+            (
+                update.content.clone(),
+                CqlTimestamp(now_ms),
+                conversation_uuid,
+                CqlTimeuuid::from_bytes(*message_uuid.as_bytes()),
+            )
+        ).await?;
+
+        message.content = update.content;
+        message.updated_at = now_ms;
+        message.is_edited = true;
+        Ok(message)
+    }
+
+    /// Soft-deletes a message: the row stays in place as a tombstone with its
+    /// content cleared so history pagination and ordering are unaffected.
+    pub async fn delete_message(
+        &self,
+        conversation_id: &str,
+        message_id: &str,
+        user_id: &str,
+    ) -> Result<Message, AppError> {
+        let mut message = self.get_message(conversation_id, message_id).await?;
+        if message.sender_id != user_id {
+            return Err(AppError("You can only delete your own messages".to_string(), StatusCode::FORBIDDEN));
+        }
+        if message.is_deleted {
+            return Ok(message);
+        }
+
+        let conversation_uuid = Uuid::parse_str(conversation_id)
+            .map_err(|e| AppError(format!("Invalid conversation ID: {}", e), StatusCode::BAD_REQUEST))?;
+        let message_uuid = Uuid::parse_str(message_id)
+            .map_err(|e| AppError(format!("Invalid message ID: {}", e), StatusCode::BAD_REQUEST))?;
+        let now_ms = Utc::now().timestamp_millis();
+
+        let db_client = DbClient::<Message> { 
+            session: &self.session, 
+            _phantom: PhantomData 
+        };
+
+        db_client.insert(
+            "UPDATE messages SET content = ?, is_deleted = ?, edited_at = ? WHERE conversation_id = ? AND message_id = ?",
+            (
+                String::new(),
+                true,
+                CqlTimestamp(now_ms),
+                conversation_uuid,
+                CqlTimeuuid::from_bytes(*message_uuid.as_bytes()),
+            )
+        ).await?;
+
+        message.content = String::new();
+        message.updated_at = now_ms;
+        message.is_deleted = true;
+        Ok(message)
     }
 
     pub async fn update_conversation_customization(
@@ -36,13 +36,34 @@ pub async fn setup_database(session: &web::Data<Session>, new: bool) -> Result<(
     
     if new {
         session.query_unpaged("DROP TABLE IF EXISTS messages", &[]).await?;
+        session.query_unpaged("DROP TABLE IF EXISTS pending_deliveries", &[]).await?;
+        session.query_unpaged("DROP TABLE IF EXISTS read_markers", &[]).await?;
         session.query_unpaged("DROP TABLE IF EXISTS conversation_participants", &[]).await?;
         session.query_unpaged("DROP TABLE IF EXISTS conversations", &[]).await?;
         session.query_unpaged("DROP TABLE IF EXISTS users", &[]).await?;
+        session.query_unpaged("DROP TABLE IF EXISTS user_match_terms", &[]).await?;
+        session.query_unpaged("DROP TABLE IF EXISTS tag_usage", &[]).await?;
+        session.query_unpaged("DROP TABLE IF EXISTS user_following", &[]).await?;
+        session.query_unpaged("DROP TABLE IF EXISTS user_followers", &[]).await?;
+        session.query_unpaged("DROP TABLE IF EXISTS follow_counts", &[]).await?;
+        session.query_unpaged("DROP TABLE IF EXISTS auth_sessions", &[]).await?;
+        session.query_unpaged("DROP TABLE IF EXISTS refresh_tokens", &[]).await?;
         session.query_unpaged("DROP TABLE IF EXISTS user_conversations", &[]).await?;
         session.query_unpaged("DROP TABLE IF EXISTS one_to_one_conversations", &[]).await?;
         session.query_unpaged("DROP TABLE IF EXISTS code_posts" , &[]).await?;
         session.query_unpaged("DROP TABLE IF EXISTS posts", &[]).await?;
+        session.query_unpaged("DROP TABLE IF EXISTS post_timeline", &[]).await?;
+        session.query_unpaged("DROP TABLE IF EXISTS user_post_timeline", &[]).await?;
+        session.query_unpaged("DROP TABLE IF EXISTS post_likes", &[]).await?;
+        session.query_unpaged("DROP TABLE IF EXISTS post_like_counts", &[]).await?;
+        session.query_unpaged("DROP TABLE IF EXISTS post_comments", &[]).await?;
+        session.query_unpaged("DROP TABLE IF EXISTS post_comment_counts", &[]).await?;
+        session.query_unpaged("DROP TABLE IF EXISTS posts_by_tag", &[]).await?;
+        session.query_unpaged("DROP TABLE IF EXISTS posts_by_language", &[]).await?;
+        session.query_unpaged("DROP TABLE IF EXISTS post_tag_usage", &[]).await?;
+        session.query_unpaged("DROP TABLE IF EXISTS post_search_terms", &[]).await?;
+        session.query_unpaged("DROP TABLE IF EXISTS post_revisions", &[]).await?;
+        session.query_unpaged("DROP TABLE IF EXISTS post_runs", &[]).await?;
     }
     
     session.query_unpaged(
@@ -57,15 +78,93 @@ pub async fn setup_database(session: &web::Data<Session>, new: bool) -> Result<(
             is_online BOOLEAN,
             interests TEXT,
             language TEXT,
+            interest_tags MAP<TEXT, TEXT>,
+            languages SET<TEXT>,
             profile_image_url TEXT,
             home_country TEXT,
             project_building TEXT
         )",
         &[]
     ).await?;
+    add_column_if_missing(session, "users", "interest_tags", "MAP<TEXT, TEXT>").await?;
+    add_column_if_missing(session, "users", "languages", "SET<TEXT>").await?;
     
     session.query_unpaged("CREATE INDEX IF NOT EXISTS ON users (username)", &[]).await?;
     session.query_unpaged("CREATE INDEX IF NOT EXISTS ON users (email)", &[]).await?;
+
+    session.query_unpaged(
+        "CREATE TABLE IF NOT EXISTS user_match_terms (
+            term TEXT,
+            user_id UUID,
+            field TEXT,
+            PRIMARY KEY (term, user_id, field)
+        )",
+        &[]
+    ).await?;
+
+    session.query_unpaged(
+        "CREATE TABLE IF NOT EXISTS tag_usage (
+            kind TEXT,
+            tag TEXT,
+            users COUNTER,
+            PRIMARY KEY (kind, tag)
+        )",
+        &[]
+    ).await?;
+
+    session.query_unpaged(
+        "CREATE TABLE IF NOT EXISTS user_following (
+            follower_id UUID,
+            followee_id UUID,
+            created_at TIMESTAMP,
+            PRIMARY KEY (follower_id, followee_id)
+        )",
+        &[]
+    ).await?;
+
+    session.query_unpaged(
+        "CREATE TABLE IF NOT EXISTS user_followers (
+            followee_id UUID,
+            follower_id UUID,
+            created_at TIMESTAMP,
+            PRIMARY KEY (followee_id, follower_id)
+        )",
+        &[]
+    ).await?;
+
+    session.query_unpaged(
+        "CREATE TABLE IF NOT EXISTS follow_counts (
+            user_id UUID PRIMARY KEY,
+            followers COUNTER,
+            following COUNTER
+        )",
+        &[]
+    ).await?;
+
+    session.query_unpaged(
+        "CREATE TABLE IF NOT EXISTS auth_sessions (
+            session_id UUID PRIMARY KEY,
+            user_id UUID,
+            device_name TEXT,
+            user_agent TEXT,
+            ip_address TEXT,
+            created_at TIMESTAMP,
+            last_used_at TIMESTAMP,
+            revoked BOOLEAN
+        )",
+        &[]
+    ).await?;
+
+    session.query_unpaged(
+        "CREATE TABLE IF NOT EXISTS refresh_tokens (
+            token_hash TEXT PRIMARY KEY,
+            session_id UUID,
+            user_id UUID,
+            created_at TIMESTAMP,
+            used BOOLEAN
+        ) WITH default_time_to_live = 2592000",
+        &[]
+    ).await?;
     
     session.query_unpaged(
         "CREATE TABLE IF NOT EXISTS conversations (
@@ -114,11 +213,37 @@ pub async fn setup_database(session: &web::Data<Session>, new: bool) -> Result<(
             content TEXT,
             sent_at TIMESTAMP,
             edited_at TIMESTAMP,
+            is_deleted BOOLEAN,
             PRIMARY KEY (conversation_id, message_id)
         ) WITH CLUSTERING ORDER BY (message_id ASC)",
         &[]
     ).await?;
 
+    add_column_if_missing(session, "messages", "is_deleted", "BOOLEAN").await?;
+
+    session.query_unpaged(
+        "CREATE TABLE IF NOT EXISTS pending_deliveries (
+            user_id UUID,
+            room_id UUID,
+            message_id TIMEUUID,
+            payload TEXT,
+            PRIMARY KEY ((user_id, room_id), message_id)
+        ) WITH CLUSTERING ORDER BY (message_id ASC)
+          AND default_time_to_live = 604800",
+        &[]
+    ).await?;
+
+    session.query_unpaged(
+        "CREATE TABLE IF NOT EXISTS read_markers (
+            room_id UUID,
+            user_id UUID,
+            last_read_message_id TIMEUUID,
+            updated_at TIMESTAMP,
+            PRIMARY KEY (room_id, user_id)
+        )",
+        &[]
+    ).await?;
+
     session.query_unpaged(
         "CREATE TABLE IF NOT EXISTS conversation_customization (
             conversation_id UUID,
@@ -139,21 +264,66 @@ pub async fn setup_database(session: &web::Data<Session>, new: bool) -> Result<(
             id UUID PRIMARY KEY,
             name TEXT,
             created_at TIMESTAMP,
-            updated_at TIMESTAMP
+            updated_at TIMESTAMP,
+            is_private BOOLEAN,
+            description TEXT,
+            tags LIST<TEXT>
         )",     
         &[]
     ).await?;
 
+    add_column_if_missing(session, "groups", "is_private", "BOOLEAN").await?;
+    add_column_if_missing(session, "groups", "description", "TEXT").await?;
+    add_column_if_missing(session, "groups", "tags", "LIST<TEXT>").await?;
+
+    session.query_unpaged(
+        "CREATE TABLE IF NOT EXISTS group_tags (
+            tag TEXT,
+            group_id UUID,
+            PRIMARY KEY (tag, group_id)
+        )",
+        &[]
+    ).await?;
+
+    session.query_unpaged(
+        "CREATE TABLE IF NOT EXISTS group_invites (
+            code TEXT PRIMARY KEY,
+            group_id UUID,
+            created_by UUID,
+            created_at TIMESTAMP,
+            expires_at TIMESTAMP,
+            max_uses INT,
+            uses INT
+        )",
+        &[]
+    ).await?;
+
+    session.query_unpaged("CREATE INDEX IF NOT EXISTS ON group_invites (group_id)", &[]).await?;
+
+    session.query_unpaged(
+        "CREATE TABLE IF NOT EXISTS group_join_requests (
+            group_id UUID,
+            user_id UUID,
+            message TEXT,
+            requested_at TIMESTAMP,
+            PRIMARY KEY (group_id, user_id)
+        )",
+        &[]
+    ).await?;
+
     session.query_unpaged(
         "CREATE TABLE IF NOT EXISTS group_members (
             group_id UUID,
             user_id UUID,
             joined_at TIMESTAMP,
+            role TEXT,
             PRIMARY KEY (group_id, user_id)
         )",
         &[]
     ).await?;
 
+    add_column_if_missing(session, "group_members", "role", "TEXT").await?;
+
     session.query_unpaged(
         "CREATE TABLE IF NOT EXISTS group_messages (
             group_id UUID,
@@ -205,5 +375,158 @@ pub async fn setup_database(session: &web::Data<Session>, new: bool) -> Result<(
     session.query_unpaged("CREATE INDEX IF NOT EXISTS ON posts (created_at)", &[]).await?;
     session.query_unpaged("CREATE INDEX IF NOT EXISTS ON posts (is_published)", &[]).await?;
 
+    session.query_unpaged(
+        "CREATE TABLE IF NOT EXISTS post_timeline (
+            bucket TEXT,
+            created_at TIMESTAMP,
+            post_id UUID,
+            user_id UUID,
+            PRIMARY KEY (bucket, created_at, post_id)
+        ) WITH CLUSTERING ORDER BY (created_at DESC, post_id DESC)",
+        &[]
+    ).await?;
+
+    session.query_unpaged(
+        "CREATE TABLE IF NOT EXISTS user_post_timeline (
+            user_id UUID,
+            created_at TIMESTAMP,
+            post_id UUID,
+            PRIMARY KEY (user_id, created_at, post_id)
+        ) WITH CLUSTERING ORDER BY (created_at DESC, post_id DESC)",
+        &[]
+    ).await?;
+
+    session.query_unpaged(
+        "CREATE TABLE IF NOT EXISTS post_likes (
+            post_id UUID,
+            user_id UUID,
+            created_at TIMESTAMP,
+            PRIMARY KEY (post_id, user_id)
+        )",
+        &[]
+    ).await?;
+
+    session.query_unpaged(
+        "CREATE TABLE IF NOT EXISTS post_like_counts (
+            post_id UUID PRIMARY KEY,
+            likes COUNTER
+        )",
+        &[]
+    ).await?;
+
+    // Comments of a post live in its partition, grouped by parent so each
+    // thread level reads as one slice; top-level comments use the nil UUID.
+    session.query_unpaged(
+        "CREATE TABLE IF NOT EXISTS post_comments (
+            post_id UUID,
+            parent_id UUID,
+            comment_id TIMEUUID,
+            user_id UUID,
+            content TEXT,
+            line_start INT,
+            line_end INT,
+            created_at TIMESTAMP,
+            updated_at TIMESTAMP,
+            is_deleted BOOLEAN,
+            PRIMARY KEY (post_id, parent_id, comment_id)
+        )",
+        &[]
+    ).await?;
+
+    session.query_unpaged(
+        "CREATE TABLE IF NOT EXISTS post_comment_counts (
+            post_id UUID PRIMARY KEY,
+            comments COUNTER
+        )",
+        &[]
+    ).await?;
+
+    // Published posts only, newest first, like `post_timeline`.
+    session.query_unpaged(
+        "CREATE TABLE IF NOT EXISTS posts_by_tag (
+            tag TEXT,
+            created_at TIMESTAMP,
+            post_id UUID,
+            PRIMARY KEY (tag, created_at, post_id)
+        ) WITH CLUSTERING ORDER BY (created_at DESC, post_id DESC)",
+        &[]
+    ).await?;
+
+    session.query_unpaged(
+        "CREATE TABLE IF NOT EXISTS posts_by_language (
+            language TEXT,
+            created_at TIMESTAMP,
+            post_id UUID,
+            PRIMARY KEY (language, created_at, post_id)
+        ) WITH CLUSTERING ORDER BY (created_at DESC, post_id DESC)",
+        &[]
+    ).await?;
+
+    session.query_unpaged(
+        "CREATE TABLE IF NOT EXISTS post_tag_usage (
+            kind TEXT,
+            tag TEXT,
+            posts COUNTER,
+            PRIMARY KEY (kind, tag)
+        )",
+        &[]
+    ).await?;
+
+    // Every saved state of a post's text, newest first. `restored_from` is set
+    // on revisions made by restoring an earlier one.
+    session.query_unpaged(
+        "CREATE TABLE IF NOT EXISTS post_revisions (
+            post_id UUID,
+            revision INT,
+            title TEXT,
+            content TEXT,
+            code TEXT,
+            language TEXT,
+            tags SET<TEXT>,
+            restored_from INT,
+            created_at TIMESTAMP,
+            PRIMARY KEY (post_id, revision)
+        ) WITH CLUSTERING ORDER BY (revision DESC)",
+        &[]
+    ).await?;
+
+    // The author's input and expected output for a post's code, and the
+    // result of the last run with that input. `run_key` identifies the
+    // language, code and input the result is for.
+    session.query_unpaged(
+        "CREATE TABLE IF NOT EXISTS post_runs (
+            post_id UUID PRIMARY KEY,
+            stdin TEXT,
+            expected_output TEXT,
+            run_key TEXT,
+            success BOOLEAN,
+            output TEXT,
+            error TEXT,
+            execution_time BIGINT,
+            matches_expected BOOLEAN,
+            ran_at TIMESTAMP
+        )",
+        &[]
+    ).await?;
+
+    session.query_unpaged(
+        "CREATE TABLE IF NOT EXISTS post_search_terms (
+            term TEXT,
+            post_id UUID,
+            field TEXT,
+            PRIMARY KEY (term, post_id, field)
+        )",
+        &[]
+    ).await?;
+
     Ok(())
 }
+
+async fn add_column_if_missing(session: &Session, table: &str, column: &str, cql_type: &str) -> Result<(), ExecutionError> {
+    let query = format!("ALTER TABLE {} ADD {} {}", table, column, cql_type);
+    match session.query_unpaged(query, &[]).await {
+        Ok(_) => Ok(()),
+        Err(e) if e.to_string().contains("conflicts with an existing column") => Ok(()),
+        Err(e) => Err(e),
+    }
+}
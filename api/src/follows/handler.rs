@@ -0,0 +1,81 @@
+use actix_web::{web, HttpResponse};
+use scylla::client::session::Session;
+use crate::error::AppError;
+use crate::follows::service::FollowService;
+use crate::models::follow::{FollowListQuery, SuggestionQuery};
+use crate::utils::jwt::AuthenticatedUser;
+use crate::utils::pagination::clamp_page_size;
+
+pub async fn follow(
+    session: web::Data<Session>,
+    user: AuthenticatedUser,
+    target_id: web::Path<String>,
+) -> Result<HttpResponse, AppError> {
+    let service = FollowService::new(session);
+    service.follow(&user.user_id, &target_id).await?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+pub async fn unfollow(
+    session: web::Data<Session>,
+    user: AuthenticatedUser,
+    target_id: web::Path<String>,
+) -> Result<HttpResponse, AppError> {
+    let service = FollowService::new(session);
+    service.unfollow(&user.user_id, &target_id).await?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+pub async fn list_followers(
+    session: web::Data<Session>,
+    _user: AuthenticatedUser,
+    user_id: web::Path<String>,
+    query: web::Query<FollowListQuery>,
+) -> Result<HttpResponse, AppError> {
+    let service = FollowService::new(session);
+    let limit = clamp_page_size(query.limit);
+    let page = service.list_followers(&user_id, query.cursor.as_deref(), limit).await?;
+    Ok(HttpResponse::Ok().json(page))
+}
+
+pub async fn list_following(
+    session: web::Data<Session>,
+    _user: AuthenticatedUser,
+    user_id: web::Path<String>,
+    query: web::Query<FollowListQuery>,
+) -> Result<HttpResponse, AppError> {
+    let service = FollowService::new(session);
+    let limit = clamp_page_size(query.limit);
+    let page = service.list_following(&user_id, query.cursor.as_deref(), limit).await?;
+    Ok(HttpResponse::Ok().json(page))
+}
+
+pub async fn get_follow_stats(
+    session: web::Data<Session>,
+    _user: AuthenticatedUser,
+    user_id: web::Path<String>,
+) -> Result<HttpResponse, AppError> {
+    let service = FollowService::new(session);
+    let stats = service.stats(&user_id).await?;
+    Ok(HttpResponse::Ok().json(stats))
+}
+
+pub async fn list_connections(
+    session: web::Data<Session>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, AppError> {
+    let service = FollowService::new(session);
+    let connections = service.list_connections(&user.user_id).await?;
+    Ok(HttpResponse::Ok().json(connections))
+}
+
+pub async fn suggest_connections(
+    session: web::Data<Session>,
+    user: AuthenticatedUser,
+    query: web::Query<SuggestionQuery>,
+) -> Result<HttpResponse, AppError> {
+    let service = FollowService::new(session);
+    let limit = clamp_page_size(query.limit) as usize;
+    let suggestions = service.suggestions(&user.user_id, limit).await?;
+    Ok(HttpResponse::Ok().json(suggestions))
+}
@@ -0,0 +1,355 @@
+use actix_web::http::StatusCode;
+use actix_web::web;
+use chrono::Utc;
+use scylla::client::session::Session;
+use scylla::value::{CqlTimestamp, Counter};
+use std::collections::{HashMap, HashSet};
+use std::marker::PhantomData;
+use uuid::Uuid;
+use crate::error::AppError;
+use crate::models::follow::{ConnectionSuggestion, FollowEdge, FollowStats};
+use crate::users::service as user_service;
+use crate::utils::db_client::{DbClient, MAX_KEYS_PER_QUERY};
+use crate::utils::pagination::Page;
+
+/// Suggestions walk at most this many of the user's followees, and this many
+/// of each followee's followees.
+const SUGGESTION_FANOUT: i32 = 100;
+
+fn parse_user_id(id: &str) -> Result<Uuid, AppError> {
+    Uuid::parse_str(id).map_err(|e| AppError(format!("Invalid user ID: {}", e), StatusCode::BAD_REQUEST))
+}
+
+/// Which adjacency table a listing reads: `user_following` is partitioned by
+/// follower, `user_followers` by the followed user.
+#[derive(Debug, Clone, Copy)]
+enum Direction {
+    Following,
+    Followers,
+}
+
+impl Direction {
+    fn query(&self, paged: bool) -> &'static str {
+        match (self, paged) {
+            (Direction::Following, false) => "SELECT followee_id, created_at FROM user_following WHERE follower_id = ? LIMIT ?",
+            (Direction::Following, true) => "SELECT followee_id, created_at FROM user_following WHERE follower_id = ? AND followee_id > ? LIMIT ?",
+            (Direction::Followers, false) => "SELECT follower_id, created_at FROM user_followers WHERE followee_id = ? LIMIT ?",
+            (Direction::Followers, true) => "SELECT follower_id, created_at FROM user_followers WHERE followee_id = ? AND follower_id > ? LIMIT ?",
+        }
+    }
+}
+
+/// Counts, for each second-degree candidate, how many of the user's
+/// followees lead there, leaving out the user and anyone already followed.
+fn tally_candidates(user: Uuid, following: &HashSet<Uuid>, second_degree: impl IntoIterator<Item = Uuid>) -> HashMap<Uuid, usize> {
+    let mut candidates: HashMap<Uuid, usize> = HashMap::new();
+    for candidate in second_degree {
+        if candidate != user && !following.contains(&candidate) {
+            *candidates.entry(candidate).or_default() += 1;
+        }
+    }
+    candidates
+}
+
+/// Most-connected candidates first; ties are broken by id so the order is stable.
+fn rank_candidates(candidates: HashMap<Uuid, usize>) -> Vec<(Uuid, usize)> {
+    let mut ranked: Vec<(Uuid, usize)> = candidates.into_iter().collect();
+    ranked.sort_by(|(a_id, a_count), (b_id, b_count)| b_count.cmp(a_count).then_with(|| a_id.cmp(b_id)));
+    ranked
+}
+
+/// The follow graph. Each edge is written to two tables so both "who do I
+/// follow" and "who follows me" are single-partition reads; two users who
+/// follow each other are connections.
+pub struct FollowService {
+    session: web::Data<Session>,
+}
+
+impl FollowService {
+    pub fn new(session: web::Data<Session>) -> Self {
+        Self { session }
+    }
+
+    /// Follows `followee_id`. Following someone twice is a no-op.
+    pub async fn follow(&self, follower_id: &str, followee_id: &str) -> Result<(), AppError> {
+        let follower = parse_user_id(follower_id)?;
+        let followee = parse_user_id(followee_id)?;
+        if follower == followee {
+            return Err(AppError("You cannot follow yourself".to_string(), StatusCode::BAD_REQUEST));
+        }
+        if user_service::find_by_id(&self.session, followee_id).await?.is_none() {
+            return Err(AppError("User not found".to_string(), StatusCode::NOT_FOUND));
+        }
+
+        let db_client = DbClient::<FollowEdge> {
+            session: &self.session,
+            _phantom: PhantomData,
+        };
+        let now = CqlTimestamp(Utc::now().timestamp_millis());
+
+        // Only the request that creates the edge touches the counters, so a
+        // double click can't count one follow twice.
+        let created = db_client.apply(
+            "INSERT INTO user_following (follower_id, followee_id, created_at) VALUES (?, ?, ?) IF NOT EXISTS",
+            (follower, followee, now)
+        ).await?;
+        if !created {
+            return Ok(());
+        }
+        db_client.insert(
+            "INSERT INTO user_followers (followee_id, follower_id, created_at) VALUES (?, ?, ?)",
+            (followee, follower, now)
+        ).await?;
+        db_client.insert(
+            "UPDATE follow_counts SET following = following + 1 WHERE user_id = ?",
+            (follower,)
+        ).await?;
+        db_client.insert(
+            "UPDATE follow_counts SET followers = followers + 1 WHERE user_id = ?",
+            (followee,)
+        ).await
+    }
+
+    /// Stops following `followee_id`. Unfollowing someone not followed is a
+    /// no-op.
+    pub async fn unfollow(&self, follower_id: &str, followee_id: &str) -> Result<(), AppError> {
+        let follower = parse_user_id(follower_id)?;
+        let followee = parse_user_id(followee_id)?;
+
+        let db_client = DbClient::<FollowEdge> {
+            session: &self.session,
+            _phantom: PhantomData,
+        };
+
+        let removed = db_client.apply(
+            "DELETE FROM user_following WHERE follower_id = ? AND followee_id = ? IF EXISTS",
+            (follower, followee)
+        ).await?;
+        if !removed {
+            return Ok(());
+        }
+        db_client.insert(
+            "DELETE FROM user_followers WHERE followee_id = ? AND follower_id = ?",
+            (followee, follower)
+        ).await?;
+        db_client.insert(
+            "UPDATE follow_counts SET following = following - 1 WHERE user_id = ?",
+            (follower,)
+        ).await?;
+        db_client.insert(
+            "UPDATE follow_counts SET followers = followers - 1 WHERE user_id = ?",
+            (followee,)
+        ).await
+    }
+
+    pub async fn stats(&self, user_id: &str) -> Result<FollowStats, AppError> {
+        let user = parse_user_id(user_id)?;
+
+        let db_client = DbClient::<FollowStats> {
+            session: &self.session,
+            _phantom: PhantomData,
+        };
+
+        let results = db_client.query::<(Option<Counter>, Option<Counter>), _>(
+            "SELECT followers, following FROM follow_counts WHERE user_id = ?",
+            Some((user,))
+        ).await?;
+
+        let (followers, following) = results.into_iter().next().unwrap_or((None, None));
+        Ok(FollowStats {
+            followers: followers.map_or(0, |count| count.0),
+            following: following.map_or(0, |count| count.0),
+        })
+    }
+
+    /// Ids of everyone `user_id` follows, for personalizing feeds and search.
+    pub async fn following_ids(&self, user_id: &str) -> Result<Vec<String>, AppError> {
+        let user = parse_user_id(user_id)?;
+
+        let db_client = DbClient::<FollowEdge> {
+            session: &self.session,
+            _phantom: PhantomData,
+        };
+
+        let results = db_client.query::<(Uuid,), _>(
+            "SELECT followee_id FROM user_following WHERE follower_id = ?",
+            Some((user,))
+        ).await?;
+
+        Ok(results.into_iter().map(|(id,)| id.to_string()).collect())
+    }
+
+    /// Which of `others` follow `user`, one query per chunk of them.
+    async fn followers_among(&self, user: Uuid, others: &[Uuid]) -> Result<HashSet<Uuid>, AppError> {
+        let db_client = DbClient::<FollowEdge> {
+            session: &self.session,
+            _phantom: PhantomData,
+        };
+
+        let mut followers = HashSet::new();
+        for chunk in others.chunks(MAX_KEYS_PER_QUERY) {
+            let rows = db_client.query::<(Uuid,), _>(
+                "SELECT follower_id FROM user_following WHERE follower_id IN ? AND followee_id = ?",
+                Some((chunk.to_vec(), user))
+            ).await?;
+            followers.extend(rows.into_iter().map(|(id,)| id));
+        }
+        Ok(followers)
+    }
+
+    /// Which of `others` `user` follows, one query per chunk of them.
+    async fn followed_among(&self, user: Uuid, others: &[Uuid]) -> Result<HashSet<Uuid>, AppError> {
+        let db_client = DbClient::<FollowEdge> {
+            session: &self.session,
+            _phantom: PhantomData,
+        };
+
+        let mut followed = HashSet::new();
+        for chunk in others.chunks(MAX_KEYS_PER_QUERY) {
+            let rows = db_client.query::<(Uuid,), _>(
+                "SELECT followee_id FROM user_following WHERE follower_id = ? AND followee_id IN ?",
+                Some((user, chunk.to_vec()))
+            ).await?;
+            followed.extend(rows.into_iter().map(|(id,)| id));
+        }
+        Ok(followed)
+    }
+
+    pub async fn list_following(&self, user_id: &str, cursor: Option<&str>, limit: i32) -> Result<Page<FollowEdge>, AppError> {
+        self.list(Direction::Following, user_id, cursor, limit).await
+    }
+
+    pub async fn list_followers(&self, user_id: &str, cursor: Option<&str>, limit: i32) -> Result<Page<FollowEdge>, AppError> {
+        self.list(Direction::Followers, user_id, cursor, limit).await
+    }
+
+    async fn list(&self, direction: Direction, user_id: &str, cursor: Option<&str>, limit: i32) -> Result<Page<FollowEdge>, AppError> {
+        let user = parse_user_id(user_id)?;
+
+        let db_client = DbClient::<FollowEdge> {
+            session: &self.session,
+            _phantom: PhantomData,
+        };
+
+        let rows = match cursor {
+            Some(cursor) => {
+                let after = Uuid::parse_str(cursor)
+                    .map_err(|e| AppError(format!("Invalid cursor: {}", e), StatusCode::BAD_REQUEST))?;
+                db_client.query::<(Uuid, CqlTimestamp), _>(direction.query(true), Some((user, after, limit))).await?
+            }
+            None => db_client.query::<(Uuid, CqlTimestamp), _>(direction.query(false), Some((user, limit))).await?,
+        };
+
+        let next_cursor = if rows.len() == limit as usize {
+            rows.last().map(|(id, _)| id.to_string())
+        } else {
+            None
+        };
+
+        let others: Vec<Uuid> = rows.iter().map(|(id, _)| *id).collect();
+        let mutual = match direction {
+            Direction::Following => self.followers_among(user, &others).await?,
+            Direction::Followers => self.followed_among(user, &others).await?,
+        };
+
+        let mut items = Vec::with_capacity(rows.len());
+        for (other_id, created_at) in rows {
+            // Edges can outlive a deleted account.
+            let Some(other) = user_service::find_by_id(&self.session, &other_id.to_string()).await? else { continue };
+            items.push(FollowEdge { user: other.to_profile(), followed_at: created_at.0, is_mutual: mutual.contains(&other_id) });
+        }
+
+        Ok(Page { items, next_cursor })
+    }
+
+    /// Mutual follows of `user_id`.
+    pub async fn list_connections(&self, user_id: &str) -> Result<Vec<FollowEdge>, AppError> {
+        let user = parse_user_id(user_id)?;
+
+        let db_client = DbClient::<FollowEdge> {
+            session: &self.session,
+            _phantom: PhantomData,
+        };
+
+        let rows = db_client.query::<(Uuid, CqlTimestamp), _>(
+            "SELECT followee_id, created_at FROM user_following WHERE follower_id = ?",
+            Some((user,))
+        ).await?;
+
+        let followees: Vec<Uuid> = rows.iter().map(|(id, _)| *id).collect();
+        let followers = self.followers_among(user, &followees).await?;
+
+        let mut connections = Vec::new();
+        for (other_id, created_at) in rows {
+            if !followers.contains(&other_id) {
+                continue;
+            }
+            if let Some(other) = user_service::find_by_id(&self.session, &other_id.to_string()).await? {
+                connections.push(FollowEdge { user: other.to_profile(), followed_at: created_at.0, is_mutual: true });
+            }
+        }
+        Ok(connections)
+    }
+
+    /// Second-degree suggestions: people followed by the people `user_id`
+    /// follows, ranked by how many of them lead there.
+    pub async fn suggestions(&self, user_id: &str, limit: usize) -> Result<Vec<ConnectionSuggestion>, AppError> {
+        let user = parse_user_id(user_id)?;
+
+        let db_client = DbClient::<FollowEdge> {
+            session: &self.session,
+            _phantom: PhantomData,
+        };
+
+        let following: HashSet<Uuid> = db_client.query::<(Uuid,), _>(
+            "SELECT followee_id FROM user_following WHERE follower_id = ? LIMIT ?",
+            Some((user, SUGGESTION_FANOUT))
+        ).await?.into_iter().map(|(id,)| id).collect();
+
+        let mut second_degree = Vec::new();
+        for followee in &following {
+            let rows = db_client.query::<(Uuid,), _>(
+                "SELECT followee_id FROM user_following WHERE follower_id = ? LIMIT ?",
+                Some((*followee, SUGGESTION_FANOUT))
+            ).await?;
+            second_degree.extend(rows.into_iter().map(|(id,)| id));
+        }
+        let ranked = rank_candidates(tally_candidates(user, &following, second_degree));
+
+        let mut suggestions = Vec::new();
+        for (candidate, mutual_count) in ranked {
+            if suggestions.len() >= limit {
+                break;
+            }
+            if let Some(other) = user_service::find_by_id(&self.session, &candidate.to_string()).await? {
+                suggestions.push(ConnectionSuggestion { user: other.to_profile(), mutual_count });
+            }
+        }
+        Ok(suggestions)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn id(n: u128) -> Uuid {
+        Uuid::from_u128(n)
+    }
+
+    #[test]
+    fn tallies_second_degree_without_self_or_followees() {
+        let following: HashSet<Uuid> = [id(2), id(3)].into_iter().collect();
+        // Both followees follow 4; one also follows the user and the other followee.
+        let candidates = tally_candidates(id(1), &following, [id(4), id(1), id(3), id(4), id(5)]);
+        assert_eq!(candidates.len(), 2);
+        assert_eq!(candidates[&id(4)], 2);
+        assert_eq!(candidates[&id(5)], 1);
+    }
+
+    #[test]
+    fn ranks_by_count_then_id() {
+        let candidates: HashMap<Uuid, usize> = [(id(9), 1), (id(7), 3), (id(8), 1)].into_iter().collect();
+        assert_eq!(rank_candidates(candidates), vec![(id(7), 3), (id(8), 1), (id(9), 1)]);
+    }
+}
@@ -1,28 +1,59 @@
-use actix_web::{web, HttpResponse, HttpRequest};
+use actix_web::{web, HttpResponse};
 use scylla::client::session::Session;
 use crate::models::group::{
-    NewGroup, GroupCustomization, NewGroupMessage, 
-    AddMemberRequest, RemoveMemberRequest, UpdateGroupRequest
+    Group, GroupMessage, GroupRole, NewGroup, GroupCustomization, NewGroupMessage, 
+    AddMemberRequest, RemoveMemberRequest, UpdateGroupRequest,
+    UpdateMemberRoleRequest, TransferOwnershipRequest,
+    JoinGroupRequest, NewGroupInvite, PublicGroup, GroupSearchQuery
 };
 use crate::error::AppError;
 use crate::groups::service::GroupService;
 use serde::{Serialize, Deserialize};
-use serde_json;
 use actix_web::http::StatusCode;
-use crate::utils::jwt::get_user_id_from_token;
+use crate::utils::jwt::AuthenticatedUser;
+use crate::utils::pagination::{clamp_page_size, MessageCursor};
+use crate::utils::protocol::ServerEvent;
+use crate::utils::read_markers::ReadMarkers;
+use crate::utils::websocket::{self, RoomStore};
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ListMessagesQuery {
     pub limit: Option<i32>,
+    pub before: Option<String>,
+    pub after: Option<String>,
 }
 
+/// Checks that the user belongs to the group with at least `required` role.
+fn require_role(group: &Group, user_id: &str, required: GroupRole, action: &str) -> Result<GroupRole, AppError> {
+    match group.role_of(user_id) {
+        Some(role) if role >= required => Ok(role),
+        Some(_) => Err(AppError(format!("Only a group {} can {}", required.as_str(), action), StatusCode::FORBIDDEN)),
+        None => Err(AppError(format!("Not authorized to {}", action), StatusCode::FORBIDDEN)),
+    }
+}
+
+async fn announce_joined(session: &web::Data<Session>, room_store: &RoomStore, group: &Group, actor_id: &str, user_id: &str) {
+    let event = ServerEvent::<GroupMessage>::MemberJoined { user_id: user_id.to_string() };
+    websocket::set_members(room_store, &group.id, &group.member_ids).await;
+    websocket::broadcast_event(session, room_store, &group.id, &group.member_ids, actor_id, &event).await;
+}
+
+/// The member who left is told as well, then their sockets to the group are closed.
+async fn announce_left(session: &web::Data<Session>, room_store: &RoomStore, group: &Group, actor_id: &str, user_id: &str) {
+    let event = ServerEvent::<GroupMessage>::MemberLeft { user_id: user_id.to_string() };
+    let mut recipients = group.member_ids.clone();
+    recipients.push(user_id.to_string());
+    websocket::set_members(room_store, &group.id, &group.member_ids).await;
+    websocket::broadcast_event(session, room_store, &group.id, &recipients, actor_id, &event).await;
+    websocket::evict(room_store, &group.id, user_id).await;
+}
 
 pub async fn create_group(
     session: web::Data<Session>,
-    req: HttpRequest,
+    user: AuthenticatedUser,
     new_group: web::Json<NewGroup>,
 ) -> Result<HttpResponse, AppError> {
-    let user_id = get_user_id_from_token(&req)?;
+    let user_id = user.user_id;
     let service = GroupService::new(session).await?;
     let group = service
         .create_group(new_group.into_inner(), user_id)
@@ -33,10 +64,10 @@ pub async fn create_group(
 
 pub async fn get_group(
     session: web::Data<Session>,
-    req: HttpRequest,
+    user: AuthenticatedUser,
     group_id: web::Path<String>,
 ) -> Result<HttpResponse, AppError> {
-    let user_id = get_user_id_from_token(&req)?;
+    let user_id = user.user_id;
     let service = GroupService::new(session).await?;
     let group = service.get_group(&group_id).await?;
     
@@ -49,9 +80,9 @@ pub async fn get_group(
 
 pub async fn list_user_groups(
     session: web::Data<Session>,
-    req: HttpRequest,
+    user: AuthenticatedUser,
 ) -> Result<HttpResponse, AppError> {
-    let user_id = get_user_id_from_token(&req)?;
+    let user_id = user.user_id;
     let service = GroupService::new(session).await?;
     let groups = service.list_user_groups(&user_id).await?;
     Ok(HttpResponse::Ok().json(groups))
@@ -59,71 +90,78 @@ pub async fn list_user_groups(
 
 pub async fn update_group(
     session: web::Data<Session>,
-    req: HttpRequest,
+    user: AuthenticatedUser,
     group_id: web::Path<String>,
     update_request: web::Json<UpdateGroupRequest>,
 ) -> Result<HttpResponse, AppError> {
-    let user_id = get_user_id_from_token(&req)?;
+    let user_id = user.user_id;
     let service = GroupService::new(session).await?;
     
     let group = service.get_group(&group_id).await?;
-    if !group.member_ids.contains(&user_id) {
-        return Err(AppError("Not authorized to update this group".to_string(), StatusCode::FORBIDDEN));
-    }
+    require_role(&group, &user_id, GroupRole::Admin, "update this group")?;
 
     let updated_group = service
-        .update_group(&group_id, update_request.name.clone())
+        .update_group(&group_id, update_request.into_inner())
         .await?;
     Ok(HttpResponse::Ok().json(updated_group))
 }
 
 pub async fn add_member(
     session: web::Data<Session>,
-    req: HttpRequest,
+    room_store: web::Data<RoomStore>,
+    user: AuthenticatedUser,
     group_id: web::Path<String>,
     add_request: web::Json<AddMemberRequest>,
 ) -> Result<HttpResponse, AppError> {
-    let user_id = get_user_id_from_token(&req)?;
-    let service = GroupService::new(session).await?;
+    let user_id = user.user_id;
+    let service = GroupService::new(session.clone()).await?;
     
     let group = service.get_group(&group_id).await?;
-    if !group.member_ids.contains(&user_id) {
-        return Err(AppError("Not authorized to add members to this group".to_string(), StatusCode::FORBIDDEN));
-    }
+    require_role(&group, &user_id, GroupRole::Admin, "add members to this group")?;
 
     let updated_group = service
         .add_member(&group_id, &add_request.user_id)
         .await?;
+    announce_joined(&session, &room_store, &updated_group, &user_id, &add_request.user_id).await;
     Ok(HttpResponse::Ok().json(updated_group))
 }
 
 pub async fn remove_member(
     session: web::Data<Session>,
-    req: HttpRequest,
+    room_store: web::Data<RoomStore>,
+    user: AuthenticatedUser,
     group_id: web::Path<String>,
     remove_request: web::Json<RemoveMemberRequest>,
 ) -> Result<HttpResponse, AppError> {
-    let user_id = get_user_id_from_token(&req)?;
-    let service = GroupService::new(session).await?;
+    let user_id = user.user_id;
+    let service = GroupService::new(session.clone()).await?;
     
     let group = service.get_group(&group_id).await?;
-    if !group.member_ids.contains(&user_id) {
-        return Err(AppError("Not authorized to remove members from this group".to_string(), StatusCode::FORBIDDEN));
+    if remove_request.user_id != user_id {
+        let role = require_role(&group, &user_id, GroupRole::Admin, "remove members from this group")?;
+        let target_role = group.role_of(&remove_request.user_id)
+            .ok_or_else(|| AppError("User is not a member of this group".to_string(), StatusCode::NOT_FOUND))?;
+        if target_role >= role {
+            return Err(AppError("Cannot remove a member with an equal or higher role".to_string(), StatusCode::FORBIDDEN));
+        }
+    } else if !group.member_ids.contains(&user_id) {
+        return Err(AppError("You are not a member of this group".to_string(), StatusCode::BAD_REQUEST));
     }
 
     let updated_group = service
         .remove_member(&group_id, &remove_request.user_id)
         .await?;
+    announce_left(&session, &room_store, &updated_group, &user_id, &remove_request.user_id).await;
     Ok(HttpResponse::Ok().json(updated_group))
 }
 
 pub async fn send_message(
     session: web::Data<Session>,
-    req: HttpRequest,
+    user: AuthenticatedUser,
     group_id: web::Path<String>,
     new_message: web::Json<NewGroupMessage>,
 ) -> Result<HttpResponse, AppError> {
-    let user_id = get_user_id_from_token(&req)?;
+    let user_id = user.user_id;
     let service = GroupService::new(session).await?;
     
     let group = service.get_group(&group_id).await?;
@@ -139,11 +177,11 @@ pub async fn send_message(
 
 pub async fn list_messages(
     session: web::Data<Session>,
-    req: HttpRequest,
+    user: AuthenticatedUser,
     group_id: web::Path<String>,
     query: web::Query<ListMessagesQuery>,
 ) -> Result<HttpResponse, AppError> {
-    let user_id = get_user_id_from_token(&req)?;
+    let user_id = user.user_id;
     let service = GroupService::new(session).await?;
     
     let group = service.get_group(&group_id).await?;
@@ -151,20 +189,39 @@ pub async fn list_messages(
         return Err(AppError("Not authorized to view messages in this group".to_string(), StatusCode::FORBIDDEN));
     }
 
-    let limit = query.limit.unwrap_or(50);
-    let messages = service.list_messages(&group_id, limit).await?;
-    Ok(HttpResponse::Ok().json(messages))
+    let cursor = MessageCursor::from_query(query.before.as_deref(), query.after.as_deref())?;
+    let limit = clamp_page_size(query.limit);
+    let page = service.list_messages(&group_id, cursor, limit).await?;
+    Ok(HttpResponse::Ok().json(page))
+}
+
+pub async fn list_read_markers(
+    session: web::Data<Session>,
+    user: AuthenticatedUser,
+    group_id: web::Path<String>,
+) -> Result<HttpResponse, AppError> {
+    let user_id = user.user_id;
+    let service = GroupService::new(session.clone()).await?;
+
+    let group = service.get_group(&group_id).await?;
+    if !group.member_ids.contains(&user_id) {
+        return Err(AppError("Not authorized to view this group".to_string(), StatusCode::FORBIDDEN));
+    }
+
+    let markers = ReadMarkers::new(session).list(&group_id).await?;
+    Ok(HttpResponse::Ok().json(markers))
 }
 
 pub async fn update_group_customization(
     session: web::Data<Session>,
-    req: HttpRequest,
+    user: AuthenticatedUser,
     group_id: web::Path<String>,
     customization: web::Json<GroupCustomization>,
 ) -> Result<HttpResponse, AppError> {
-    let user_id = get_user_id_from_token(&req)?;
+    let user_id = user.user_id;
     let service = GroupService::new(session).await?;
     
+    // Customization is each member's own view of the group.
     let group = service.get_group(&group_id).await?;
     if !group.member_ids.contains(&user_id) {
         return Err(AppError("Not authorized to customize this group".to_string(), StatusCode::FORBIDDEN));
@@ -178,62 +235,80 @@ pub async fn update_group_customization(
 
 pub async fn delete_group(
     session: web::Data<Session>,
-    req: HttpRequest,
+    user: AuthenticatedUser,
     group_id: web::Path<String>,
 ) -> Result<HttpResponse, AppError> {
-    let user_id = get_user_id_from_token(&req)?;
+    let user_id = user.user_id;
     let service = GroupService::new(session).await?;
     
     let group = service.get_group(&group_id).await?;
-    if !group.member_ids.contains(&user_id) {
-        return Err(AppError("Not authorized to delete this group".to_string(), StatusCode::FORBIDDEN));
-    }
+    require_role(&group, &user_id, GroupRole::Owner, "delete this group")?;
 
     service.delete_group(&group_id).await?;
     Ok(HttpResponse::NoContent().finish())
 }
 
+/// Public groups are joined directly. For private groups this files a join
+/// request for the admins and answers 202 Accepted.
 pub async fn join_group(
     session: web::Data<Session>,
-    req: HttpRequest,
+    room_store: web::Data<RoomStore>,
+    user: AuthenticatedUser,
     group_id: web::Path<String>,
+    join_request: Option<web::Json<JoinGroupRequest>>,
 ) -> Result<HttpResponse, AppError> {
-    let user_id = get_user_id_from_token(&req)?;
-    let service = GroupService::new(session).await?;
-    
+    let user_id = user.user_id;
+    let service = GroupService::new(session.clone()).await?;
+
+    let group = service.get_group(&group_id).await?;
+    if group.member_ids.contains(&user_id) {
+        return Err(AppError("User is already a member of this group".to_string(), StatusCode::BAD_REQUEST));
+    }
+
+    if group.is_private {
+        let message = join_request.and_then(|request| request.into_inner().message);
+        let request = service.request_to_join(&group_id, &user_id, message).await?;
+        return Ok(HttpResponse::Accepted().json(request));
+    }
+
     let updated_group = service
         .add_member(&group_id, &user_id)
         .await?;
+    announce_joined(&session, &room_store, &updated_group, &user_id, &user_id).await;
     Ok(HttpResponse::Ok().json(updated_group))
 }
 
 pub async fn get_group_public(
     session: web::Data<Session>,
-    req: HttpRequest,
+    _user: AuthenticatedUser,
     group_id: web::Path<String>,
 ) -> Result<HttpResponse, AppError> {
-    let _user_id = get_user_id_from_token(&req)?; 
     let service = GroupService::new(session).await?;
     let group = service.get_group(&group_id).await?;
-    
 
-    let public_info = serde_json::json!({
-        "id": group.id,
-        "name": group.name,
-        "member_ids": group.member_ids,
-        "customization": group.customization
-    });
-    
-    Ok(HttpResponse::Ok().json(public_info))
+    Ok(HttpResponse::Ok().json(PublicGroup::from(group)))
+}
+
+pub async fn discover_groups(
+    session: web::Data<Session>,
+    _user: AuthenticatedUser,
+    query: web::Query<GroupSearchQuery>,
+) -> Result<HttpResponse, AppError> {
+    let service = GroupService::new(session).await?;
+    let limit = clamp_page_size(query.limit);
+
+    let page = service.discover_groups(&query, limit).await?;
+    Ok(HttpResponse::Ok().json(page))
 }
 
 pub async fn leave_group(
     session: web::Data<Session>,
-    req: HttpRequest,
+    room_store: web::Data<RoomStore>,
+    user: AuthenticatedUser,
     group_id: web::Path<String>,
 ) -> Result<HttpResponse, AppError> {
-    let user_id = get_user_id_from_token(&req)?;
-    let service = GroupService::new(session).await?;
+    let user_id = user.user_id;
+    let service = GroupService::new(session.clone()).await?;
                 
     let group = service.get_group(&group_id).await?;
     if !group.member_ids.contains(&user_id) {
@@ -243,5 +318,154 @@ pub async fn leave_group(
     let updated_group = service
         .remove_member(&group_id, &user_id)
         .await?;
+    announce_left(&session, &room_store, &updated_group, &user_id, &user_id).await;
+    Ok(HttpResponse::Ok().json(updated_group))
+}
+
+pub async fn update_member_role(
+    session: web::Data<Session>,
+    user: AuthenticatedUser,
+    path: web::Path<(String, String)>,
+    role_request: web::Json<UpdateMemberRoleRequest>,
+) -> Result<HttpResponse, AppError> {
+    let user_id = user.user_id;
+    let (group_id, member_id) = path.into_inner();
+    let service = GroupService::new(session).await?;
+
+    let group = service.get_group(&group_id).await?;
+    require_role(&group, &user_id, GroupRole::Owner, "change member roles")?;
+
+    let updated_group = service
+        .set_member_role(&group_id, &member_id, role_request.role)
+        .await?;
+    Ok(HttpResponse::Ok().json(updated_group))
+}
+
+pub async fn transfer_ownership(
+    session: web::Data<Session>,
+    user: AuthenticatedUser,
+    group_id: web::Path<String>,
+    transfer_request: web::Json<TransferOwnershipRequest>,
+) -> Result<HttpResponse, AppError> {
+    let user_id = user.user_id;
+    let service = GroupService::new(session).await?;
+
+    let group = service.get_group(&group_id).await?;
+    require_role(&group, &user_id, GroupRole::Owner, "transfer ownership")?;
+
+    let updated_group = service
+        .transfer_ownership(&group_id, &user_id, &transfer_request.user_id)
+        .await?;
     Ok(HttpResponse::Ok().json(updated_group))
 }
+
+pub async fn create_invite(
+    session: web::Data<Session>,
+    user: AuthenticatedUser,
+    group_id: web::Path<String>,
+    new_invite: Option<web::Json<NewGroupInvite>>,
+) -> Result<HttpResponse, AppError> {
+    let user_id = user.user_id;
+    let service = GroupService::new(session).await?;
+
+    let group = service.get_group(&group_id).await?;
+    require_role(&group, &user_id, GroupRole::Admin, "invite to this group")?;
+
+    let new_invite = new_invite.map(|invite| invite.into_inner()).unwrap_or_default();
+    let invite = service.create_invite(&group_id, &user_id, new_invite).await?;
+    Ok(HttpResponse::Created().json(invite))
+}
+
+pub async fn list_invites(
+    session: web::Data<Session>,
+    user: AuthenticatedUser,
+    group_id: web::Path<String>,
+) -> Result<HttpResponse, AppError> {
+    let user_id = user.user_id;
+    let service = GroupService::new(session).await?;
+
+    let group = service.get_group(&group_id).await?;
+    require_role(&group, &user_id, GroupRole::Admin, "view invites for this group")?;
+
+    let invites = service.list_invites(&group_id).await?;
+    Ok(HttpResponse::Ok().json(invites))
+}
+
+pub async fn revoke_invite(
+    session: web::Data<Session>,
+    user: AuthenticatedUser,
+    path: web::Path<(String, String)>,
+) -> Result<HttpResponse, AppError> {
+    let user_id = user.user_id;
+    let (group_id, code) = path.into_inner();
+    let service = GroupService::new(session).await?;
+
+    let group = service.get_group(&group_id).await?;
+    require_role(&group, &user_id, GroupRole::Admin, "revoke invites for this group")?;
+
+    service.revoke_invite(&group_id, &code).await?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+pub async fn accept_invite(
+    session: web::Data<Session>,
+    room_store: web::Data<RoomStore>,
+    user: AuthenticatedUser,
+    code: web::Path<String>,
+) -> Result<HttpResponse, AppError> {
+    let user_id = user.user_id;
+    let service = GroupService::new(session.clone()).await?;
+
+    let updated_group = service.redeem_invite(&code, &user_id).await?;
+    announce_joined(&session, &room_store, &updated_group, &user_id, &user_id).await;
+    Ok(HttpResponse::Ok().json(updated_group))
+}
+
+pub async fn list_join_requests(
+    session: web::Data<Session>,
+    user: AuthenticatedUser,
+    group_id: web::Path<String>,
+) -> Result<HttpResponse, AppError> {
+    let user_id = user.user_id;
+    let service = GroupService::new(session).await?;
+
+    let group = service.get_group(&group_id).await?;
+    require_role(&group, &user_id, GroupRole::Admin, "view join requests for this group")?;
+
+    let requests = service.list_join_requests(&group_id).await?;
+    Ok(HttpResponse::Ok().json(requests))
+}
+
+pub async fn approve_join_request(
+    session: web::Data<Session>,
+    room_store: web::Data<RoomStore>,
+    user: AuthenticatedUser,
+    path: web::Path<(String, String)>,
+) -> Result<HttpResponse, AppError> {
+    let user_id = user.user_id;
+    let (group_id, requester_id) = path.into_inner();
+    let service = GroupService::new(session.clone()).await?;
+
+    let group = service.get_group(&group_id).await?;
+    require_role(&group, &user_id, GroupRole::Admin, "approve join requests for this group")?;
+
+    let updated_group = service.approve_join_request(&group_id, &requester_id).await?;
+    announce_joined(&session, &room_store, &updated_group, &user_id, &requester_id).await;
+    Ok(HttpResponse::Ok().json(updated_group))
+}
+
+pub async fn reject_join_request(
+    session: web::Data<Session>,
+    user: AuthenticatedUser,
+    path: web::Path<(String, String)>,
+) -> Result<HttpResponse, AppError> {
+    let user_id = user.user_id;
+    let (group_id, requester_id) = path.into_inner();
+    let service = GroupService::new(session).await?;
+
+    let group = service.get_group(&group_id).await?;
+    require_role(&group, &user_id, GroupRole::Admin, "reject join requests for this group")?;
+
+    service.reject_join_request(&group_id, &requester_id).await?;
+    Ok(HttpResponse::NoContent().finish())
+}
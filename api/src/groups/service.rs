@@ -3,17 +3,48 @@ use scylla::client::session::Session;
 use serde::{Deserialize, Serialize};
 use crate::{
     error::AppError,
-    models::{group::{Group, GroupCustomization, GroupMessage, NewGroup, NewGroupMessage}, user},
-    utils::db_client::DbClient,
+    models::{group::{Group, GroupCustomization, GroupInvite, GroupJoinRequest, GroupMessage, GroupRole, GroupSearchQuery, NewGroup, NewGroupInvite, NewGroupMessage, PublicGroup, UpdateGroupRequest}, user},
+    utils::{db_client::{DbClient, MAX_KEYS_PER_QUERY}, delivery::timeuuid_at, pagination::{MessageCursor, MessagePage, Page}, read_markers::{ReadMarkers, MAX_UNREAD_COUNT}, tags::normalize_tags},
 };
 use chrono::Utc;
-use uuid::{NoContext, Timestamp, Uuid};
+use uuid::Uuid;
 use scylla::value::CqlTimestamp;
 use actix_web::http::StatusCode;
 use actix_web::web;
 use std::time::{SystemTime, UNIX_EPOCH};
 use scylla::value::CqlTimeuuid;
+use std::collections::HashMap;
 use std::marker::PhantomData;
+use log::info;
+use rand::distr::Alphanumeric;
+use rand::Rng;
+
+/// Invite links last a week unless the admin asks otherwise, and never more
+/// than 30 days.
+pub const DEFAULT_INVITE_TTL_SECS: i64 = 7 * 24 * 60 * 60;
+pub const MAX_INVITE_TTL_SECS: i64 = 30 * 24 * 60 * 60;
+const INVITE_CODE_LENGTH: usize = 10;
+
+pub const MAX_GROUP_TAGS: usize = 10;
+pub const MAX_DESCRIPTION_LENGTH: usize = 1000;
+/// Discovery filters candidates in the application, so one request looks at
+/// no more than this many groups before returning a (possibly short) page.
+const DISCOVERY_SCAN_LIMIT: usize = 500;
+const DISCOVERY_BATCH_SIZE: i32 = 100;
+
+fn validate_description(description: Option<String>) -> Result<Option<String>, AppError> {
+    let description = description.map(|d| d.trim().to_string()).filter(|d| !d.is_empty());
+    if matches!(&description, Some(d) if d.chars().count() > MAX_DESCRIPTION_LENGTH) {
+        return Err(AppError(format!("Description must be at most {} characters", MAX_DESCRIPTION_LENGTH), StatusCode::BAD_REQUEST));
+    }
+    Ok(description)
+}
+
+struct GroupMemberRow {
+    user_id: Uuid,
+    role: GroupRole,
+    joined_at: i64,
+}
 
 pub struct GroupService {
     session: web::Data<Session>,
@@ -33,6 +64,8 @@ impl GroupService {
         let group_id = Uuid::new_v4();
         let creator_uuid = Uuid::parse_str(&creator_id)
             .map_err(|e| AppError(format!("Invalid creator ID: {}", e), StatusCode::BAD_REQUEST))?;
+        let description = validate_description(new_group.description)?;
+        let tags = normalize_tags(&new_group.tags, MAX_GROUP_TAGS)?;
 
         let db_client = DbClient::<Group> { 
             session: &self.session, 
@@ -40,18 +73,23 @@ impl GroupService {
         };
 
         db_client.insert(
-            "INSERT INTO groups (id, name, created_at, updated_at) VALUES (?, ?, ?, ?)",
+            "INSERT INTO groups (id, name, created_at, updated_at, is_private, description, tags) VALUES (?, ?, ?, ?, ?, ?, ?)",
             (
                 group_id,
                 &new_group.name,
                 CqlTimestamp(now * 1000),
                 CqlTimestamp(now * 1000),
+                new_group.is_private,
+                description.as_deref(),
+                &tags,
             )
         ).await?;
 
+        self.index_tags(group_id, &[], &tags).await?;
+
         db_client.insert(
-            "INSERT INTO group_members (group_id, user_id, joined_at) VALUES (?, ?, ?)",
-            (group_id, creator_uuid, CqlTimestamp(now * 1000))
+            "INSERT INTO group_members (group_id, user_id, joined_at, role) VALUES (?, ?, ?, ?)",
+            (group_id, creator_uuid, CqlTimestamp(now * 1000), GroupRole::Owner.as_str())
         ).await?;
             
         for member_id in &new_group.member_ids {
@@ -60,22 +98,13 @@ impl GroupService {
                     .map_err(|e| AppError(format!("Invalid member ID: {}", e), StatusCode::BAD_REQUEST))?;
                 
                 db_client.insert(
-                    "INSERT INTO group_members (group_id, user_id, joined_at) VALUES (?, ?, ?)",
-                    (group_id, member_uuid, CqlTimestamp(now * 1000))
+                    "INSERT INTO group_members (group_id, user_id, joined_at, role) VALUES (?, ?, ?, ?)",
+                    (group_id, member_uuid, CqlTimestamp(now * 1000), GroupRole::Member.as_str())
                 ).await?;
             }
         }
 
-        let members = self.get_group_members(&group_id.to_string()).await?;
-
-        Ok(Group {
-            id: group_id.to_string(),
-            name: new_group.name,
-            created_at: now,
-            updated_at: now,
-            member_ids: members.into_iter().map(|m| m.to_string()).collect(),
-            customization: None,
-        })
+        self.get_group(&group_id.to_string()).await
     }
 
     pub async fn get_group(&self, id: &str) -> Result<Group, AppError> {
@@ -87,12 +116,12 @@ impl GroupService {
             _phantom: PhantomData 
         };
 
-        let results = db_client.query::<(Uuid, String, CqlTimestamp, CqlTimestamp), _>(
-            "SELECT id, name, created_at, updated_at FROM groups WHERE id = ? ALLOW FILTERING",
+        let results = db_client.query::<(Uuid, String, CqlTimestamp, CqlTimestamp, Option<bool>, Option<String>, Option<Vec<String>>), _>(
+            "SELECT id, name, created_at, updated_at, is_private, description, tags FROM groups WHERE id = ? ALLOW FILTERING",
             Some((group_id,))
         ).await?;
 
-        if let Some((id, name, created_at, updated_at)) = results.first() {
+        if let Some((id, name, created_at, updated_at, is_private, description, tags)) = results.first() {
             let members = self.get_group_members(&id.to_string()).await?;
             
             let customization_results = db_client.query::<(Option<String>, Option<String>, Option<String>, Option<String>, Option<String>, Option<String>), _>(
@@ -114,17 +143,24 @@ impl GroupService {
             Ok(Group {
                 id: id.to_string(),
                 name: name.clone(),
+                description: description.clone(),
+                tags: tags.clone().unwrap_or_default(),
                 created_at: created_at.0 / 1000,
                 updated_at: updated_at.0 / 1000,
-                member_ids: members.into_iter().map(|m| m.to_string()).collect(),
+                is_private: is_private.unwrap_or(false),
+                member_ids: members.iter().map(|m| m.user_id.to_string()).collect(),
+                owner_id: members.iter().find(|m| m.role == GroupRole::Owner).map(|m| m.user_id.to_string()),
+                admin_ids: members.iter().filter(|m| m.role == GroupRole::Admin).map(|m| m.user_id.to_string()).collect(),
                 customization,
+                unread_count: None,
             })
         } else {
             Err(AppError("Group not found".into(), StatusCode::NOT_FOUND))
         }
     }
 
-    async fn get_group_members(&self, group_id: &str) -> Result<Vec<Uuid>, AppError> {
+    /// Members in join order.
+    async fn get_group_members(&self, group_id: &str) -> Result<Vec<GroupMemberRow>, AppError> {
         let group_uuid = Uuid::parse_str(group_id)
             .map_err(|e| AppError(format!("Invalid group ID: {}", e), StatusCode::BAD_REQUEST))?;
         
@@ -133,18 +169,116 @@ impl GroupService {
             _phantom: PhantomData 
         };
 
-        let results = db_client.query::<(Uuid, CqlTimestamp), _>(
-            "SELECT user_id, joined_at FROM group_members WHERE group_id = ? ALLOW FILTERING",
+        let results = db_client.query::<(Uuid, CqlTimestamp, Option<String>), _>(
+            "SELECT user_id, joined_at, role FROM group_members WHERE group_id = ? ALLOW FILTERING",
             Some((group_uuid,))
         ).await?;
 
-        let members = results.into_iter()
-            .map(|(user_id, _)| (user_id))
+        let mut members: Vec<GroupMemberRow> = results.into_iter()
+            .map(|(user_id, joined_at, role)| GroupMemberRow {
+                user_id,
+                role: GroupRole::from_column(role.as_deref()),
+                joined_at: joined_at.0,
+            })
             .collect();
+        members.sort_by_key(|m| m.joined_at);
 
         Ok(members)
     }
 
+    /// Groups created before roles existed have no owner; their earliest
+    /// member becomes it. Runs at startup.
+    pub async fn migrate_group_owners(&self) -> Result<(), AppError> {
+        let db_client = DbClient::<Group> {
+            session: &self.session,
+            _phantom: PhantomData
+        };
+
+        let rows = db_client.query::<(Uuid, Uuid, CqlTimestamp, Option<String>), ()>(
+            "SELECT group_id, user_id, joined_at, role FROM group_members",
+            None
+        ).await?;
+
+        // Per group: whether it has an owner, and its earliest member.
+        let mut groups: HashMap<Uuid, (bool, Option<(i64, Uuid)>)> = HashMap::new();
+        for (group_id, user_id, joined_at, role) in rows {
+            let (has_owner, earliest) = groups.entry(group_id).or_default();
+            *has_owner |= GroupRole::from_column(role.as_deref()) == GroupRole::Owner;
+            if earliest.is_none_or(|(first_joined, _)| joined_at.0 < first_joined) {
+                *earliest = Some((joined_at.0, user_id));
+            }
+        }
+
+        let mut promoted = 0;
+        for (group_id, (has_owner, earliest)) in groups {
+            let Some((_, user_id)) = earliest.filter(|_| !has_owner) else { continue };
+            self.set_role(group_id, user_id, GroupRole::Owner).await?;
+            promoted += 1;
+        }
+
+        if promoted > 0 {
+            info!("Made the earliest member owner of {} groups without one", promoted);
+        }
+        Ok(())
+    }
+
+    async fn set_role(&self, group_id: Uuid, user_id: Uuid, role: GroupRole) -> Result<(), AppError> {
+        let db_client = DbClient::<Group> { 
+            session: &self.session, 
+            _phantom: PhantomData 
+        };
+
+        db_client.insert(
+            "UPDATE group_members SET role = ? WHERE group_id = ? AND user_id = ?",
+            (role.as_str(), group_id, user_id)
+        ).await
+    }
+
+    /// Makes a member an admin or demotes an admin. Ownership changes go
+    /// through [`GroupService::transfer_ownership`].
+    pub async fn set_member_role(&self, group_id: &str, user_id: &str, role: GroupRole) -> Result<Group, AppError> {
+        let group_uuid = Uuid::parse_str(group_id)
+            .map_err(|e| AppError(format!("Invalid group ID: {}", e), StatusCode::BAD_REQUEST))?;
+        let user_uuid = Uuid::parse_str(user_id)
+            .map_err(|e| AppError(format!("Invalid user ID: {}", e), StatusCode::BAD_REQUEST))?;
+
+        if role == GroupRole::Owner {
+            return Err(AppError("Use ownership transfer to change the owner".to_string(), StatusCode::BAD_REQUEST));
+        }
+
+        let members = self.get_group_members(group_id).await?;
+        let member = members.iter().find(|m| m.user_id == user_uuid)
+            .ok_or_else(|| AppError("User is not a member of this group".to_string(), StatusCode::NOT_FOUND))?;
+        if member.role == GroupRole::Owner {
+            return Err(AppError("The owner must transfer ownership before changing roles".to_string(), StatusCode::BAD_REQUEST));
+        }
+
+        self.set_role(group_uuid, user_uuid, role).await?;
+        self.get_group(group_id).await
+    }
+
+    /// Hands ownership to another member; the previous owner stays on as an admin.
+    pub async fn transfer_ownership(&self, group_id: &str, from_user_id: &str, to_user_id: &str) -> Result<Group, AppError> {
+        let group_uuid = Uuid::parse_str(group_id)
+            .map_err(|e| AppError(format!("Invalid group ID: {}", e), StatusCode::BAD_REQUEST))?;
+        let from_uuid = Uuid::parse_str(from_user_id)
+            .map_err(|e| AppError(format!("Invalid user ID: {}", e), StatusCode::BAD_REQUEST))?;
+        let to_uuid = Uuid::parse_str(to_user_id)
+            .map_err(|e| AppError(format!("Invalid user ID: {}", e), StatusCode::BAD_REQUEST))?;
+
+        let members = self.get_group_members(group_id).await?;
+        if !members.iter().any(|m| m.user_id == to_uuid) {
+            return Err(AppError("User is not a member of this group".to_string(), StatusCode::NOT_FOUND));
+        }
+        if from_uuid == to_uuid {
+            return self.get_group(group_id).await;
+        }
+
+        self.set_role(group_uuid, to_uuid, GroupRole::Owner).await?;
+        self.set_role(group_uuid, from_uuid, GroupRole::Admin).await?;
+        self.get_group(group_id).await
+    }
+
     pub async fn list_user_groups(&self, user_id: &str) -> Result<Vec<Group>, AppError> {
         let user_uuid = Uuid::parse_str(user_id)
             .map_err(|e| AppError(format!("Invalid user ID: {}", e), StatusCode::BAD_REQUEST))?;
@@ -159,9 +293,13 @@ impl GroupService {
             Some((user_uuid,))
         ).await?;
 
+        let group_ids: Vec<Uuid> = group_results.iter().map(|(group_id, _)| *group_id).collect();
+        let unread_counts = self.unread_counts(&group_ids, user_id).await?;
+
         let mut groups = Vec::new();
-        for (group_id, _) in group_results {
-            if let Ok(group) = self.get_group(&group_id.to_string()).await {
+        for group_id in group_ids {
+            if let Ok(mut group) = self.get_group(&group_id.to_string()).await {
+                group.unread_count = Some(unread_counts.get(&group_id).copied().unwrap_or(0));
                 groups.push(group);
             }
         }
@@ -169,26 +307,500 @@ impl GroupService {
         Ok(groups)
     }
 
-    pub async fn update_group(&self, id: &str, name: Option<String>) -> Result<Group, AppError> {
+    /// [`Self::unread_count`] for many groups, batched the same way as
+    /// conversations: markers and messages are fetched per chunk of groups,
+    /// and a group whose scan filled up with messages before its own marker
+    /// is counted again on its own.
+    async fn unread_counts(&self, group_ids: &[Uuid], user_id: &str) -> Result<HashMap<Uuid, i64>, AppError> {
+        let user_uuid = Uuid::parse_str(user_id)
+            .map_err(|e| AppError(format!("Invalid user ID: {}", e), StatusCode::BAD_REQUEST))?;
+        let markers = ReadMarkers::new(self.session.clone())
+            .get_many(group_ids, user_id)
+            .await?;
+
+        let db_client = DbClient::<GroupMessage> { 
+            session: &self.session, 
+            _phantom: PhantomData 
+        };
+
+        let mut counts = HashMap::with_capacity(group_ids.len());
+        for chunk in group_ids.chunks(MAX_KEYS_PER_QUERY) {
+            let (read, never_read): (Vec<Uuid>, Vec<Uuid>) = chunk.iter().copied().partition(|id| markers.contains_key(id));
+            let oldest_marker = read.iter().map(|id| markers[id]).min();
+
+            let mut rows = Vec::new();
+            if !never_read.is_empty() {
+                rows.extend(db_client.query::<(Uuid, CqlTimeuuid, Uuid), _>(
+                    "SELECT group_id, message_id, sender_id FROM group_messages WHERE group_id IN ? PER PARTITION LIMIT ?",
+                    Some((never_read, MAX_UNREAD_COUNT))
+                ).await?);
+            }
+            if let Some(oldest_marker) = oldest_marker {
+                rows.extend(db_client.query::<(Uuid, CqlTimeuuid, Uuid), _>(
+                    "SELECT group_id, message_id, sender_id FROM group_messages WHERE group_id IN ? AND message_id > ? PER PARTITION LIMIT ?",
+                    Some((read, oldest_marker, MAX_UNREAD_COUNT))
+                ).await?);
+            }
+
+            let mut scanned: HashMap<Uuid, i32> = HashMap::new();
+            for (group_id, message_id, sender_id) in rows {
+                *scanned.entry(group_id).or_default() += 1;
+                let after_marker = markers.get(&group_id).is_none_or(|last_read| message_id > *last_read);
+                if after_marker && sender_id != user_uuid {
+                    *counts.entry(group_id).or_default() += 1;
+                }
+            }
+
+            for id in chunk {
+                let scan_full = scanned.get(id).copied().unwrap_or(0) >= MAX_UNREAD_COUNT;
+                if scan_full && markers.get(id).copied() > oldest_marker {
+                    counts.insert(*id, self.unread_count(*id, user_id).await?);
+                }
+            }
+        }
+        Ok(counts)
+    }
+
+    /// Counts messages from other members after the user's read marker,
+    /// capped at `MAX_UNREAD_COUNT`.
+    async fn unread_count(&self, group_id: Uuid, user_id: &str) -> Result<i64, AppError> {
+        let last_read = ReadMarkers::new(self.session.clone())
+            .get(&group_id.to_string(), user_id)
+            .await?;
+
+        let db_client = DbClient::<GroupMessage> { 
+            session: &self.session, 
+            _phantom: PhantomData 
+        };
+
+        let results = match last_read {
+            Some(last_read) => db_client.query::<(Uuid,), _>(
+                "SELECT sender_id FROM group_messages WHERE group_id = ? AND message_id > ? LIMIT ?",
+                Some((group_id, last_read, MAX_UNREAD_COUNT))
+            ).await?,
+            None => db_client.query::<(Uuid,), _>(
+                "SELECT sender_id FROM group_messages WHERE group_id = ? LIMIT ?",
+                Some((group_id, MAX_UNREAD_COUNT))
+            ).await?,
+        };
+
+        Ok(results
+            .iter()
+            .filter(|(sender_id,)| sender_id.to_string() != user_id)
+            .count() as i64)
+    }
+
+    pub async fn update_group(&self, id: &str, update: UpdateGroupRequest) -> Result<Group, AppError> {
         let group_id = Uuid::parse_str(id)
             .map_err(|e| AppError(format!("Invalid group ID: {}", e), StatusCode::BAD_REQUEST))?;
+        let UpdateGroupRequest { name, is_private, description, tags } = update;
 
-        if let Some(name) = name {
-            let now = Utc::now().timestamp();
-            let db_client = DbClient::<Group> { 
-                session: &self.session, 
-                _phantom: PhantomData 
-            };
+        let now = Utc::now().timestamp();
+        let db_client = DbClient::<Group> { 
+            session: &self.session, 
+            _phantom: PhantomData 
+        };
 
+        if let Some(name) = name {
             db_client.insert(
                 "UPDATE groups SET name = ?, updated_at = ? WHERE id = ?",
                 (&name, CqlTimestamp(now * 1000), group_id)
             ).await?;
         }
 
+        if let Some(is_private) = is_private {
+            db_client.insert(
+                "UPDATE groups SET is_private = ?, updated_at = ? WHERE id = ?",
+                (is_private, CqlTimestamp(now * 1000), group_id)
+            ).await?;
+        }
+
+        if let Some(description) = description {
+            let description = validate_description(Some(description))?;
+            db_client.insert(
+                "UPDATE groups SET description = ?, updated_at = ? WHERE id = ?",
+                (description.as_deref(), CqlTimestamp(now * 1000), group_id)
+            ).await?;
+        }
+
+        if let Some(tags) = tags {
+            let tags = normalize_tags(&tags, MAX_GROUP_TAGS)?;
+            let previous = self.get_group(id).await?.tags;
+            db_client.insert(
+                "UPDATE groups SET tags = ?, updated_at = ? WHERE id = ?",
+                (&tags, CqlTimestamp(now * 1000), group_id)
+            ).await?;
+            self.index_tags(group_id, &previous, &tags).await?;
+        }
+
         self.get_group(id).await
     }
 
+    /// Keeps the `group_tags` lookup table in step with a group's tags.
+    async fn index_tags(&self, group_id: Uuid, previous: &[String], tags: &[String]) -> Result<(), AppError> {
+        let db_client = DbClient::<Group> { 
+            session: &self.session, 
+            _phantom: PhantomData 
+        };
+
+        for tag in previous.iter().filter(|tag| !tags.contains(tag)) {
+            db_client.insert(
+                "DELETE FROM group_tags WHERE tag = ? AND group_id = ?",
+                (tag, group_id)
+            ).await?;
+        }
+        for tag in tags.iter().filter(|tag| !previous.contains(tag)) {
+            db_client.insert(
+                "INSERT INTO group_tags (tag, group_id) VALUES (?, ?)",
+                (tag, group_id)
+            ).await?;
+        }
+        Ok(())
+    }
+
+    /// Pages through public groups, optionally narrowed to one tag, a name
+    /// substring and a member count range. Tag searches walk the `group_tags`
+    /// partition for that tag; other searches walk the `groups` table in token
+    /// order. Either way the cursor is the last group id looked at.
+    pub async fn discover_groups(&self, query: &GroupSearchQuery, limit: i32) -> Result<Page<PublicGroup>, AppError> {
+        let tag = match query.tag.as_deref() {
+            Some(tag) => Some(crate::utils::tags::normalize_tag(tag)
+                .ok_or_else(|| AppError(format!("Invalid tag: {:?}", tag), StatusCode::BAD_REQUEST))?),
+            None => None,
+        };
+        let needle = query.q.as_deref().map(|q| q.trim().to_lowercase()).filter(|q| !q.is_empty());
+        let mut cursor = match query.cursor.as_deref() {
+            Some(cursor) => Some(Uuid::parse_str(cursor)
+                .map_err(|e| AppError(format!("Invalid cursor: {}", e), StatusCode::BAD_REQUEST))?),
+            None => None,
+        };
+
+        let db_client = DbClient::<Group> { 
+            session: &self.session, 
+            _phantom: PhantomData 
+        };
+
+        let mut items = Vec::new();
+        let mut scanned = 0;
+        let mut exhausted = false;
+
+        'scan: while items.len() < limit as usize && scanned < DISCOVERY_SCAN_LIMIT {
+            let batch: Vec<(Uuid, Option<String>, Option<bool>)> = match (&tag, cursor) {
+                (Some(tag), Some(after)) => db_client.query::<(Uuid,), _>(
+                    "SELECT group_id FROM group_tags WHERE tag = ? AND group_id > ? LIMIT ?",
+                    Some((tag, after, DISCOVERY_BATCH_SIZE))
+                ).await?.into_iter().map(|(id,)| (id, None, None)).collect(),
+                (Some(tag), None) => db_client.query::<(Uuid,), _>(
+                    "SELECT group_id FROM group_tags WHERE tag = ? LIMIT ?",
+                    Some((tag, DISCOVERY_BATCH_SIZE))
+                ).await?.into_iter().map(|(id,)| (id, None, None)).collect(),
+                (None, Some(after)) => db_client.query::<(Uuid, String, Option<bool>), _>(
+                    "SELECT id, name, is_private FROM groups WHERE token(id) > token(?) LIMIT ?",
+                    Some((after, DISCOVERY_BATCH_SIZE))
+                ).await?.into_iter().map(|(id, name, is_private)| (id, Some(name), is_private)).collect(),
+                (None, None) => db_client.query::<(Uuid, String, Option<bool>), _>(
+                    "SELECT id, name, is_private FROM groups LIMIT ?",
+                    Some((DISCOVERY_BATCH_SIZE,))
+                ).await?.into_iter().map(|(id, name, is_private)| (id, Some(name), is_private)).collect(),
+            };
+
+            let batch_len = batch.len();
+            for (index, (group_id, name, is_private)) in batch.into_iter().enumerate() {
+                scanned += 1;
+                cursor = Some(group_id);
+
+                // Rows from the groups table can be ruled out before loading the group.
+                let private = is_private.unwrap_or(false);
+                let name_matches = match (&needle, &name) {
+                    (Some(needle), Some(name)) => name.to_lowercase().contains(needle),
+                    _ => true,
+                };
+                if !private && name_matches {
+                    let group = match self.get_group(&group_id.to_string()).await {
+                        Ok(group) => group,
+                        Err(AppError(_, StatusCode::NOT_FOUND)) => continue,
+                        Err(e) => return Err(e),
+                    };
+                    let member_count = group.member_ids.len();
+                    let matches = !group.is_private
+                        && needle.as_ref().is_none_or(|needle| group.name.to_lowercase().contains(needle))
+                        && query.min_members.is_none_or(|min| member_count >= min)
+                        && query.max_members.is_none_or(|max| member_count <= max);
+                    if matches {
+                        items.push(PublicGroup::from(group));
+                    }
+                }
+
+                if items.len() >= limit as usize || scanned >= DISCOVERY_SCAN_LIMIT {
+                    exhausted = index + 1 == batch_len && batch_len < DISCOVERY_BATCH_SIZE as usize;
+                    break 'scan;
+                }
+            }
+
+            if batch_len < DISCOVERY_BATCH_SIZE as usize {
+                exhausted = true;
+                break;
+            }
+        }
+
+        Ok(Page {
+            items,
+            next_cursor: if exhausted { None } else { cursor.map(|id| id.to_string()) },
+        })
+    }
+
+    pub async fn create_invite(
+        &self,
+        group_id: &str,
+        created_by: &str,
+        new_invite: NewGroupInvite,
+    ) -> Result<GroupInvite, AppError> {
+        let group_uuid = Uuid::parse_str(group_id)
+            .map_err(|e| AppError(format!("Invalid group ID: {}", e), StatusCode::BAD_REQUEST))?;
+        let creator_uuid = Uuid::parse_str(created_by)
+            .map_err(|e| AppError(format!("Invalid user ID: {}", e), StatusCode::BAD_REQUEST))?;
+
+        let ttl = new_invite.expires_in_secs.unwrap_or(DEFAULT_INVITE_TTL_SECS);
+        if ttl <= 0 || ttl > MAX_INVITE_TTL_SECS {
+            return Err(AppError(format!("expires_in_secs must be between 1 and {}", MAX_INVITE_TTL_SECS), StatusCode::BAD_REQUEST));
+        }
+        if matches!(new_invite.max_uses, Some(max_uses) if max_uses <= 0) {
+            return Err(AppError("max_uses must be positive".to_string(), StatusCode::BAD_REQUEST));
+        }
+
+        let code: String = rand::rng()
+            .sample_iter(&Alphanumeric)
+            .take(INVITE_CODE_LENGTH)
+            .map(char::from)
+            .collect();
+        let now = Utc::now().timestamp();
+        let expires_at = now + ttl;
+
+        let db_client = DbClient::<GroupInvite> { 
+            session: &self.session, 
+            _phantom: PhantomData 
+        };
+
+        db_client.insert(
+            "INSERT INTO group_invites (code, group_id, created_by, created_at, expires_at, max_uses, uses) VALUES (?, ?, ?, ?, ?, ?, ?) USING TTL ?",
+            (
+                &code,
+                group_uuid,
+                creator_uuid,
+                CqlTimestamp(now * 1000),
+                CqlTimestamp(expires_at * 1000),
+                new_invite.max_uses,
+                0i32,
+                ttl as i32,
+            )
+        ).await?;
+
+        Ok(GroupInvite {
+            code,
+            group_id: group_id.to_string(),
+            created_by: created_by.to_string(),
+            created_at: now,
+            expires_at,
+            max_uses: new_invite.max_uses,
+            uses: 0,
+        })
+    }
+
+    pub async fn list_invites(&self, group_id: &str) -> Result<Vec<GroupInvite>, AppError> {
+        let group_uuid = Uuid::parse_str(group_id)
+            .map_err(|e| AppError(format!("Invalid group ID: {}", e), StatusCode::BAD_REQUEST))?;
+
+        let db_client = DbClient::<GroupInvite> { 
+            session: &self.session, 
+            _phantom: PhantomData 
+        };
+
+        let results = db_client.query::<(String, Uuid, Uuid, CqlTimestamp, CqlTimestamp, Option<i32>, Option<i32>), _>(
+            "SELECT code, group_id, created_by, created_at, expires_at, max_uses, uses FROM group_invites WHERE group_id = ?",
+            Some((group_uuid,))
+        ).await?;
+
+        Ok(results.into_iter().map(invite_from_row).collect())
+    }
+
+    async fn get_invite(&self, code: &str) -> Result<Option<GroupInvite>, AppError> {
+        let db_client = DbClient::<GroupInvite> { 
+            session: &self.session, 
+            _phantom: PhantomData 
+        };
+
+        let results = db_client.query::<(String, Uuid, Uuid, CqlTimestamp, CqlTimestamp, Option<i32>, Option<i32>), _>(
+            "SELECT code, group_id, created_by, created_at, expires_at, max_uses, uses FROM group_invites WHERE code = ?",
+            Some((code.to_string(),))
+        ).await?;
+
+        Ok(results.into_iter().next().map(invite_from_row))
+    }
+
+    pub async fn revoke_invite(&self, group_id: &str, code: &str) -> Result<(), AppError> {
+        match self.get_invite(code).await? {
+            Some(invite) if invite.group_id == group_id => {}
+            _ => return Err(AppError("Invite not found".to_string(), StatusCode::NOT_FOUND)),
+        }
+
+        let db_client = DbClient::<GroupInvite> { 
+            session: &self.session, 
+            _phantom: PhantomData 
+        };
+
+        db_client.insert(
+            "DELETE FROM group_invites WHERE code = ?",
+            (code.to_string(),)
+        ).await
+    }
+
+    /// Adds the user to the invite's group, public or private, and counts the use.
+    pub async fn redeem_invite(&self, code: &str, user_id: &str) -> Result<Group, AppError> {
+        let user_uuid = Uuid::parse_str(user_id)
+            .map_err(|e| AppError(format!("Invalid user ID: {}", e), StatusCode::BAD_REQUEST))?;
+
+        let db_client = DbClient::<GroupInvite> { 
+            session: &self.session, 
+            _phantom: PhantomData 
+        };
+
+        // A use is claimed with a conditional write on the count that was
+        // read, so concurrent redemptions can't share one use. Losing the race
+        // means someone else claimed a use; read the invite again and retry.
+        let invite = loop {
+            let invite = self.get_invite(code).await?
+                .ok_or_else(|| AppError("Invite not found or expired".to_string(), StatusCode::NOT_FOUND))?;
+
+            if invite.expires_at <= Utc::now().timestamp() {
+                return Err(AppError("Invite not found or expired".to_string(), StatusCode::NOT_FOUND));
+            }
+            if matches!(invite.max_uses, Some(max_uses) if invite.uses >= max_uses) {
+                return Err(AppError("Invite has reached its maximum uses".to_string(), StatusCode::GONE));
+            }
+            let members = self.get_group_members(&invite.group_id).await?;
+            if members.iter().any(|m| m.user_id == user_uuid) {
+                return Err(AppError("User is already a member of this group".to_string(), StatusCode::BAD_REQUEST));
+            }
+
+            let remaining_ttl = (invite.expires_at - Utc::now().timestamp()).max(1);
+            let claimed = db_client.apply(
+                "UPDATE group_invites USING TTL ? SET uses = ? WHERE code = ? IF uses = ?",
+                (remaining_ttl as i32, invite.uses + 1, code.to_string(), invite.uses)
+            ).await?;
+            if claimed {
+                break invite;
+            }
+        };
+
+        let group = self.add_member(&invite.group_id, user_id).await?;
+        self.delete_join_request(&invite.group_id, user_id).await?;
+
+        Ok(group)
+    }
+
+    /// Files a request to join a private group; asking again replaces the message.
+    pub async fn request_to_join(&self, group_id: &str, user_id: &str, message: Option<String>) -> Result<GroupJoinRequest, AppError> {
+        let group_uuid = Uuid::parse_str(group_id)
+            .map_err(|e| AppError(format!("Invalid group ID: {}", e), StatusCode::BAD_REQUEST))?;
+        let user_uuid = Uuid::parse_str(user_id)
+            .map_err(|e| AppError(format!("Invalid user ID: {}", e), StatusCode::BAD_REQUEST))?;
+
+        let now = Utc::now().timestamp();
+        let db_client = DbClient::<GroupJoinRequest> { 
+            session: &self.session, 
+            _phantom: PhantomData 
+        };
+
+        db_client.insert(
+            "INSERT INTO group_join_requests (group_id, user_id, message, requested_at) VALUES (?, ?, ?, ?)",
+            (group_uuid, user_uuid, message.as_deref(), CqlTimestamp(now * 1000))
+        ).await?;
+
+        Ok(GroupJoinRequest {
+            group_id: group_id.to_string(),
+            user_id: user_id.to_string(),
+            message,
+            requested_at: now,
+        })
+    }
+
+    pub async fn list_join_requests(&self, group_id: &str) -> Result<Vec<GroupJoinRequest>, AppError> {
+        let group_uuid = Uuid::parse_str(group_id)
+            .map_err(|e| AppError(format!("Invalid group ID: {}", e), StatusCode::BAD_REQUEST))?;
+
+        let db_client = DbClient::<GroupJoinRequest> { 
+            session: &self.session, 
+            _phantom: PhantomData 
+        };
+
+        let results = db_client.query::<(Uuid, Option<String>, CqlTimestamp), _>(
+            "SELECT user_id, message, requested_at FROM group_join_requests WHERE group_id = ?",
+            Some((group_uuid,))
+        ).await?;
+
+        Ok(results
+            .into_iter()
+            .map(|(user_id, message, requested_at)| GroupJoinRequest {
+                group_id: group_id.to_string(),
+                user_id: user_id.to_string(),
+                message,
+                requested_at: requested_at.0 / 1000,
+            })
+            .collect())
+    }
+
+    async fn has_join_request(&self, group_id: &str, user_id: &str) -> Result<bool, AppError> {
+        let group_uuid = Uuid::parse_str(group_id)
+            .map_err(|e| AppError(format!("Invalid group ID: {}", e), StatusCode::BAD_REQUEST))?;
+        let user_uuid = Uuid::parse_str(user_id)
+            .map_err(|e| AppError(format!("Invalid user ID: {}", e), StatusCode::BAD_REQUEST))?;
+
+        let db_client = DbClient::<GroupJoinRequest> { 
+            session: &self.session, 
+            _phantom: PhantomData 
+        };
+
+        let results = db_client.query::<(Uuid,), _>(
+            "SELECT user_id FROM group_join_requests WHERE group_id = ? AND user_id = ?",
+            Some((group_uuid, user_uuid))
+        ).await?;
+
+        Ok(!results.is_empty())
+    }
+
+    async fn delete_join_request(&self, group_id: &str, user_id: &str) -> Result<(), AppError> {
+        let group_uuid = Uuid::parse_str(group_id)
+            .map_err(|e| AppError(format!("Invalid group ID: {}", e), StatusCode::BAD_REQUEST))?;
+        let user_uuid = Uuid::parse_str(user_id)
+            .map_err(|e| AppError(format!("Invalid user ID: {}", e), StatusCode::BAD_REQUEST))?;
+
+        let db_client = DbClient::<GroupJoinRequest> { 
+            session: &self.session, 
+            _phantom: PhantomData 
+        };
+
+        db_client.insert(
+            "DELETE FROM group_join_requests WHERE group_id = ? AND user_id = ?",
+            (group_uuid, user_uuid)
+        ).await
+    }
+
+    pub async fn approve_join_request(&self, group_id: &str, user_id: &str) -> Result<Group, AppError> {
+        if !self.has_join_request(group_id, user_id).await? {
+            return Err(AppError("Join request not found".to_string(), StatusCode::NOT_FOUND));
+        }
+        let group = self.add_member(group_id, user_id).await?;
+        self.delete_join_request(group_id, user_id).await?;
+        Ok(group)
+    }
+
+    pub async fn reject_join_request(&self, group_id: &str, user_id: &str) -> Result<(), AppError> {
+        if !self.has_join_request(group_id, user_id).await? {
+            return Err(AppError("Join request not found".to_string(), StatusCode::NOT_FOUND));
+        }
+        self.delete_join_request(group_id, user_id).await
+    }
+
     pub async fn add_member(&self, group_id: &str, user_id: &str) -> Result<Group, AppError> {
         let group_uuid = Uuid::parse_str(group_id)
             .map_err(|e| AppError(format!("Invalid group ID: {}", e), StatusCode::BAD_REQUEST))?;
@@ -202,13 +814,13 @@ impl GroupService {
         };
 
         let existing_members = self.get_group_members(group_id).await?;
-        if existing_members.contains(&user_uuid) {
+        if existing_members.iter().any(|m| m.user_id == user_uuid) {
             return Err(AppError("User is already a member of this group".to_string(), StatusCode::BAD_REQUEST));
         }
 
         db_client.insert(
-            "INSERT INTO group_members (group_id, user_id, joined_at) VALUES (?, ?, ?)",
-            (group_uuid, user_uuid, CqlTimestamp(now * 1000))
+            "INSERT INTO group_members (group_id, user_id, joined_at, role) VALUES (?, ?, ?, ?)",
+            (group_uuid, user_uuid, CqlTimestamp(now * 1000), GroupRole::Member.as_str())
         ).await?;
 
         self.get_group(group_id).await
@@ -225,6 +837,20 @@ impl GroupService {
             _phantom: PhantomData 
         };
 
+        let members = self.get_group_members(group_id).await?;
+        let leaving = members.iter().find(|m| m.user_id == user_uuid)
+            .ok_or_else(|| AppError("User is not a member of this group".to_string(), StatusCode::NOT_FOUND))?;
+
+        if leaving.role == GroupRole::Owner {
+            let remaining = members.iter().filter(|m| m.user_id != user_uuid);
+            let successor = remaining.clone().find(|m| m.role == GroupRole::Admin)
+                .or_else(|| remaining.clone().next());
+            if let Some(successor) = successor {
+                info!("Owner {} left group {}; transferring ownership to {}", user_id, group_id, successor.user_id);
+                self.set_role(group_uuid, successor.user_id, GroupRole::Owner).await?;
+            }
+        }
+
         db_client.insert(
             "DELETE FROM group_members WHERE group_id = ? AND user_id = ?",
             (group_uuid, user_uuid)
@@ -245,8 +871,7 @@ impl GroupService {
             .map_err(|e| AppError(format!("Invalid sender ID: {}", e), StatusCode::BAD_REQUEST))?;
 
         let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
-        let ts = Timestamp::from_unix(&NoContext, now.as_secs(), now.subsec_nanos());
-        let message_id = Uuid::new_v1(ts, &[1, 2, 3, 4, 5, 6]);
+        let message_id = timeuuid_at(now.as_secs(), now.subsec_nanos());
 
         let now_ts = CqlTimestamp(now.as_secs() as i64 * 1000); 
 
@@ -283,8 +908,9 @@ impl GroupService {
     pub async fn list_messages(
         &self,
         group_id: &str,
+        cursor: MessageCursor,
         limit: i32,
-    ) -> Result<Vec<GroupMessage>, AppError> {
+    ) -> Result<MessagePage<GroupMessage>, AppError> {
         let group_uuid = Uuid::parse_str(group_id)
             .map_err(|e| AppError(format!("Invalid group ID: {}", e), StatusCode::BAD_REQUEST))?;
 
@@ -293,10 +919,21 @@ impl GroupService {
             _phantom: PhantomData 
         };
 
-        let results = db_client.query::<(Uuid, CqlTimeuuid, Uuid, String, CqlTimestamp, CqlTimestamp), _>(
-            "SELECT group_id, message_id, sender_id, content, sent_at, edited_at FROM group_messages WHERE group_id = ? ORDER BY message_id DESC LIMIT ? ALLOW FILTERING",
-            Some((group_uuid, limit))
-        ).await?;
+        let fetch_limit = limit + 1;
+        let results = match cursor {
+            MessageCursor::Latest => db_client.query::<(Uuid, CqlTimeuuid, Uuid, String, CqlTimestamp, CqlTimestamp), _>(
+                "SELECT group_id, message_id, sender_id, content, sent_at, edited_at FROM group_messages WHERE group_id = ? ORDER BY message_id DESC LIMIT ?",
+                Some((group_uuid, fetch_limit))
+            ).await?,
+            MessageCursor::Before(message_id) => db_client.query::<(Uuid, CqlTimeuuid, Uuid, String, CqlTimestamp, CqlTimestamp), _>(
+                "SELECT group_id, message_id, sender_id, content, sent_at, edited_at FROM group_messages WHERE group_id = ? AND message_id < ? ORDER BY message_id DESC LIMIT ?",
+                Some((group_uuid, message_id, fetch_limit))
+            ).await?,
+            MessageCursor::After(message_id) => db_client.query::<(Uuid, CqlTimeuuid, Uuid, String, CqlTimestamp, CqlTimestamp), _>(
+                "SELECT group_id, message_id, sender_id, content, sent_at, edited_at FROM group_messages WHERE group_id = ? AND message_id > ? ORDER BY message_id ASC LIMIT ?",
+                Some((group_uuid, message_id, fetch_limit))
+            ).await?,
+        };
 
         let messages = results.into_iter()
             .map(|(group_id, message_id, sender_id, content, sent_at, edited_at)| {
@@ -313,7 +950,7 @@ impl GroupService {
             })
             .collect();
 
-        Ok(messages)
+        Ok(MessagePage::from_rows(messages, limit, |message: &GroupMessage| message.id.clone()))
     }
 
     pub async fn update_group_customization(
@@ -353,6 +990,9 @@ impl GroupService {
         let group_uuid = Uuid::parse_str(group_id)
             .map_err(|e| AppError(format!("Invalid group ID: {}", e), StatusCode::BAD_REQUEST))?;
 
+        let tags = self.get_group(group_id).await?.tags;
+        self.index_tags(group_uuid, &tags, &[]).await?;
+
         let db_client = DbClient::<Group> { 
             session: &self.session, 
             _phantom: PhantomData 
@@ -381,3 +1021,17 @@ impl GroupService {
         Ok(())
     }
 }
+
+fn invite_from_row(
+    (code, group_id, created_by, created_at, expires_at, max_uses, uses): (String, Uuid, Uuid, CqlTimestamp, CqlTimestamp, Option<i32>, Option<i32>),
+) -> GroupInvite {
+    GroupInvite {
+        code,
+        group_id: group_id.to_string(),
+        created_by: created_by.to_string(),
+        created_at: created_at.0 / 1000,
+        expires_at: expires_at.0 / 1000,
+        max_uses,
+        uses: uses.unwrap_or(0),
+    }
+}
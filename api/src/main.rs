@@ -5,8 +5,10 @@ mod middleware;
 mod users;
 mod conversations;
 mod groups;
+mod follows;
 mod utils;
 mod posts;
+mod comments;
 mod compiler;
 use actix_cors::Cors;
 use actix_web::{web, App, HttpServer};
@@ -18,10 +20,15 @@ use actix_web::middleware::Logger;
 use crate::users::handler as user_handler;
 use crate::conversations::handler as conversation_handler;
 use crate::groups::handler as group_handler;
+use crate::follows::handler as follow_handler;
 use crate::utils::websocket as websocket_handler;
 use crate::utils::websocket::RoomStore;
+use crate::utils::presence::{self, PresenceStore};
+use crate::utils::jwt::{Authenticator, JwtConfig};
 use crate::utils::seed;
 use crate::compiler::handler as compiler_handler;
+use crate::compiler::pool::CompilerPool;
+use crate::comments::handler as comment_handler;
 use std::sync::Arc;
 use tokio::sync::RwLock;
 use std::collections::HashMap;
@@ -44,14 +51,28 @@ async fn main() -> std::io::Result<()> {
         println!("Database seeded successfully!");
     }
 
+    presence::reset_online_users(&session_data).await.unwrap();
+    // The index must be built before migrating: the migration indexes the
+    // users it converts, after which the index no longer looks empty.
+    users::service::rebuild_match_index_if_empty(&session_data).await.unwrap();
+    users::service::migrate_legacy_interests(&session_data).await.unwrap();
+    groups::service::GroupService::new(session_data.clone()).await.unwrap().migrate_group_owners().await.unwrap();
+    posts::service::PostsService::new(session_data.clone()).migrate_legacy_likes().await.unwrap();
+    posts::service::PostsService::new(session_data.clone()).seed_revisions().await.unwrap();
+    posts::service::PostsService::new(session_data.clone()).rebuild_timeline_if_empty().await.unwrap();
+    posts::service::PostsService::new(session_data.clone()).rebuild_indexes_if_empty().await.unwrap();
+
     let host = env::var("HOST").unwrap_or_else(|_| "127.0.0.1".to_string());
     let port = env::var("PORT")
         .unwrap_or_else(|_| "8080".to_string())
         .parse::<u16>()
         .expect("PORT must be a number");
 
-    let jwt_secret = env::var("JWT_SECRET").expect("JWT_SECRET must be set");
+    let jwt_config = JwtConfig::from_env().unwrap_or_else(|e| panic!("Invalid JWT configuration: {}", e));
+    let authenticator = web::Data::new(Authenticator::new(jwt_config));
+    let compiler_pool = web::Data::new(CompilerPool::from_env().unwrap_or_else(|e| panic!("Invalid compiler configuration: {}", e)));
     let room_store: RoomStore = Arc::new(RwLock::new(HashMap::new()));
+    let presence_store: PresenceStore = Arc::new(RwLock::new(Default::default()));
     HttpServer::new(move || {   
         let cors = Cors::default()  
             .allow_any_header()
@@ -64,7 +85,10 @@ async fn main() -> std::io::Result<()> {
             .wrap(cors)
             .app_data(session_data.clone())
             .app_data(web::Data::new(room_store.clone()))
-            .app_data(web::Data::new(jwt_secret.clone()))
+            .app_data(web::Data::new(presence_store.clone()))
+            .app_data(authenticator.clone())
+            .app_data(compiler_pool.clone())
+            .route("/ws/compiler", web::get().to(compiler_handler::run_socket))
             .route("/ws/{id}", web::get().to(websocket_handler::echo))
             .route("/ws/groups/{id}", web::get().to(websocket_handler::group_echo))
             .route("/ws/online", web::get().to(websocket_handler::online))
@@ -74,11 +98,13 @@ async fn main() -> std::io::Result<()> {
                         web::scope("/auth")
                             .route("/register", web::post().to(user_handler::register))
                             .route("/login", web::post().to(user_handler::login))
+                            .route("/refresh", web::post().to(user_handler::refresh))
+                            .route("/logout", web::post().to(user_handler::logout))
                     )
                     .service(
                         web::scope("")
                             .wrap(Auth {
-                                secret: jwt_secret.clone(),
+                                authenticator: authenticator.clone(),
                             })
                             .service(
                                 web::scope("/users")
@@ -86,8 +112,16 @@ async fn main() -> std::io::Result<()> {
                                     .route("/profile/{id}", web::get().to(user_handler::get_profile))
                                     .route("/profile", web::put().to(user_handler::update_profile))
                                     .route("/profile/search/{id}", web::get().to(user_handler::search_users))
+                                    .route("/match", web::get().to(user_handler::match_users))
+                                    .route("/tags", web::get().to(user_handler::tag_catalog))
+                                    .route("/connections", web::get().to(follow_handler::list_connections))
+                                    .route("/suggestions", web::get().to(follow_handler::suggest_connections))
+                                    .route("/{id}/follow", web::post().to(follow_handler::follow))
+                                    .route("/{id}/follow", web::delete().to(follow_handler::unfollow))
+                                    .route("/{id}/followers", web::get().to(follow_handler::list_followers))
+                                    .route("/{id}/following", web::get().to(follow_handler::list_following))
+                                    .route("/{id}/follow-stats", web::get().to(follow_handler::get_follow_stats))
                                     .route("", web::get().to(user_handler::get_all_users))
-                                    .route("/online", web::post().to(user_handler::set_user_online))
                             )
                             .service(
                                 web::scope("/conversations")
@@ -97,12 +131,17 @@ async fn main() -> std::io::Result<()> {
                                     .route("/{id}", web::put().to(conversation_handler::update_conversation))
                                     .route("/{id}/messages", web::post().to(conversation_handler::send_message))
                                     .route("/{id}/messages", web::get().to(conversation_handler::list_messages))
+                                    .route("/{id}/messages/{message_id}", web::put().to(conversation_handler::edit_message))
+                                    .route("/{id}/messages/{message_id}", web::delete().to(conversation_handler::delete_message))
+                                    .route("/{id}/reads", web::get().to(conversation_handler::list_read_markers))
                                     .route("/{id}/customization", web::post().to(conversation_handler::update_conversation_customization))
                             )
                             .service(
                                 web::scope("/groups")
                                     .route("", web::post().to(group_handler::create_group))
                                     .route("", web::get().to(group_handler::list_user_groups))
+                                    .route("/discover", web::get().to(group_handler::discover_groups))
+                                    .route("/invites/{code}/accept", web::post().to(group_handler::accept_invite))
                                     .route("/{id}", web::get().to(group_handler::get_group))
                                     .route("/{id}/public", web::get().to(group_handler::get_group_public))
                                     .route("/{id}/join", web::post().to(group_handler::join_group))
@@ -110,9 +149,18 @@ async fn main() -> std::io::Result<()> {
                                     .route("/{id}", web::delete().to(group_handler::delete_group))
                                     .route("/{id}/members", web::post().to(group_handler::add_member))
                                     .route("/{id}/members", web::delete().to(group_handler::remove_member))
+                                    .route("/{id}/members/{user_id}/role", web::put().to(group_handler::update_member_role))
+                                    .route("/{id}/owner", web::post().to(group_handler::transfer_ownership))
+                                    .route("/{id}/invites", web::post().to(group_handler::create_invite))
+                                    .route("/{id}/invites", web::get().to(group_handler::list_invites))
+                                    .route("/{id}/invites/{code}", web::delete().to(group_handler::revoke_invite))
+                                    .route("/{id}/requests", web::get().to(group_handler::list_join_requests))
+                                    .route("/{id}/requests/{user_id}/approve", web::post().to(group_handler::approve_join_request))
+                                    .route("/{id}/requests/{user_id}/reject", web::post().to(group_handler::reject_join_request))
                                     .route("/{id}/leave", web::post().to(group_handler::leave_group))
                                     .route("/{id}/messages", web::post().to(group_handler::send_message))
                                     .route("/{id}/messages", web::get().to(group_handler::list_messages))
+                                    .route("/{id}/reads", web::get().to(group_handler::list_read_markers))
                                     .route("/{id}/customization", web::post().to(group_handler::update_group_customization))
                             )
                             .service(
@@ -120,16 +168,35 @@ async fn main() -> std::io::Result<()> {
                                     .route("", web::get().to(posts::handler::get_all_posts))
                                     .route("", web::post().to(posts::handler::create_post))
                                     .route("/my", web::get().to(posts::handler::get_my_posts))
+                                    .route("/feed", web::get().to(posts::handler::get_latest_feed))
+                                    .route("/feed/following", web::get().to(posts::handler::get_following_feed))
+                                    .route("/feed/trending", web::get().to(posts::handler::get_trending_feed))
+                                    .route("/tags", web::get().to(posts::handler::get_popular_tags))
+                                    .route("/search", web::get().to(posts::handler::search_posts))
+                                    .route("/tag/{tag}", web::get().to(posts::handler::get_posts_by_tag))
+                                    .route("/language/{language}", web::get().to(posts::handler::get_posts_by_language))
                                     .route("/user/{user_id}", web::get().to(posts::handler::get_posts_by_user))
                                     .route("/{id}", web::get().to(posts::handler::get_post_by_id))
                                     .route("/{id}", web::put().to(posts::handler::update_post))
                                     .route("/{id}", web::delete().to(posts::handler::delete_post))
                                     .route("/{id}/like", web::post().to(posts::handler::toggle_like_post))
+                                    .route("/{id}/run", web::post().to(posts::handler::run_post))
+                                    .route("/{id}/run", web::get().to(posts::handler::get_latest_run))
+                                    .route("/{id}/run/settings", web::put().to(posts::handler::update_run_settings))
+                                    .route("/{id}/revisions", web::get().to(posts::handler::list_revisions))
+                                    .route("/{id}/revisions/diff", web::get().to(posts::handler::diff_revisions))
+                                    .route("/{id}/revisions/{revision}", web::get().to(posts::handler::get_revision))
+                                    .route("/{id}/revisions/{revision}/restore", web::post().to(posts::handler::restore_revision))
+                                    .route("/{id}/comments", web::get().to(comment_handler::list_comments))
+                                    .route("/{id}/comments", web::post().to(comment_handler::create_comment))
+                                    .route("/{id}/comments/{comment_id}", web::put().to(comment_handler::update_comment))
+                                    .route("/{id}/comments/{comment_id}", web::delete().to(comment_handler::delete_comment))
                                 )
                             .service(
                                 web::scope("/compiler")
                                     .route("/compile", web::post().to(compiler_handler::compile_code))
                                     .route("/languages", web::get().to(compiler_handler::get_supported_languages))
+                                    .route("/metrics", web::get().to(compiler_handler::get_metrics))
                                 )
                             .route("/run-code", web::post().to(compiler_handler::run_code_legacy))
                     )
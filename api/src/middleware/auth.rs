@@ -1,23 +1,17 @@
-
 use std::future::{ready, Ready};
+use std::rc::Rc;
 
 use actix_web::{
     dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
-    error::ErrorUnauthorized,
-    Error, HttpMessage,
+    error::ErrorInternalServerError,
+    web, Error, HttpMessage,
 };
 use futures_util::future::LocalBoxFuture;
-use jsonwebtoken::{decode, DecodingKey, Validation};
-use serde::{Deserialize, Serialize};
-
-#[derive(Debug, Serialize, Deserialize)]
-struct Claims {
-    sub: String,
-    exp: usize,
-}
+use scylla::client::session::Session;
+use crate::utils::jwt::{bearer_token, Authenticator};
 
 pub struct Auth {
-    pub secret: String,
+    pub authenticator: web::Data<Authenticator>,
 }
 
 impl<S, B> Transform<S, ServiceRequest> for Auth
@@ -34,15 +28,15 @@ where
 
     fn new_transform(&self, service: S) -> Self::Future {
         ready(Ok(AuthMiddleware {
-            service,
-            secret: self.secret.clone(),
+            service: Rc::new(service),
+            authenticator: self.authenticator.clone(),
         }))
     }
 }
 
 pub struct AuthMiddleware<S> {
-    service: S,
-    secret: String,
+    service: Rc<S>,
+    authenticator: web::Data<Authenticator>,
 }
 
 impl<S, B> Service<ServiceRequest> for AuthMiddleware<S>
@@ -58,47 +52,24 @@ where
     forward_ready!(service);
 
     fn call(&self, req: ServiceRequest) -> Self::Future {
-        let auth_header = req
-            .headers()
-            .get("Authorization")
-            .and_then(|h| h.to_str().ok())
-            .map(|s| s.to_owned());
-
-        let secret = self.secret.clone();
-
-        let token = match auth_header {
-            Some(header_value) => {
-                if header_value.starts_with("Bearer ") {
-                    header_value.trim_start_matches("Bearer ").to_string()
-                } else {
-                    return Box::pin(async { 
-                        Err(ErrorUnauthorized("Invalid token format"))
-                    });
-                }
-            }
-            None => {
-                return Box::pin(async { 
-                    Err(ErrorUnauthorized("Missing Authorization header")) 
-                });
-            }
+        let token = match bearer_token(req.request()) {
+            Ok(token) => token.to_owned(),
+            Err(e) => return Box::pin(async move { Err(e.into()) }),
         };
 
-        let validation = Validation::default();
-        let token_data = match decode::<Claims>(
-            &token,
-            &DecodingKey::from_secret(secret.as_ref()),
-            &validation,
-        ) {
-            Ok(data) => data,
-            Err(e) => return Box::pin(async move { Err(ErrorUnauthorized(format!("Invalid token: {}", e))) }),
+        let dbsession = match req.app_data::<web::Data<Session>>() {
+            Some(dbsession) => dbsession.clone(),
+            None => return Box::pin(async { Err(ErrorInternalServerError("Database session not configured")) }),
         };
 
-        req.extensions_mut().insert(token_data.claims);
-
-        let fut = self.service.call(req);
+        let authenticator = self.authenticator.clone();
+        let service = Rc::clone(&self.service);
 
         Box::pin(async move {
-            let res = fut.await?;
+            let user = authenticator.authenticate(&dbsession, &token).await?;
+            req.extensions_mut().insert(user);
+
+            let res = service.call(req).await?;
             Ok(res)
         })
     }
@@ -0,0 +1,85 @@
+use serde::{Deserialize, Serialize};
+use crate::models::post::PostAuthor;
+
+/// An inclusive, 1-based range of lines in a post's `code`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LineRange {
+    pub start: i32,
+    pub end: i32,
+}
+
+impl LineRange {
+    /// Checks that the range is well formed and inside `code`.
+    pub fn validate(self, code: Option<&str>) -> Result<Self, String> {
+        let code = code.ok_or_else(|| "This post has no code to comment on".to_string())?;
+        let line_count = code.lines().count() as i32;
+        if self.start < 1 || self.end < self.start {
+            return Err(format!("Invalid line range {}-{}", self.start, self.end));
+        }
+        if self.end > line_count {
+            return Err(format!("Line {} is past the end of the code ({} lines)", self.end, line_count));
+        }
+        Ok(self)
+    }
+
+    pub fn contains(&self, line: i32) -> bool {
+        self.start <= line && line <= self.end
+    }
+}
+
+/// A comment on a post. Top-level comments may be anchored to `lines` of the
+/// post's code; replies point at their parent with `parent_id`. Deleted
+/// comments keep their place in the thread with their content removed.
+#[derive(Debug, Serialize)]
+pub struct Comment {
+    pub id: String,
+    pub post_id: String,
+    pub parent_id: Option<String>,
+    pub author: PostAuthor,
+    pub content: String,
+    pub lines: Option<LineRange>,
+    pub created_at: i64,
+    pub updated_at: i64,
+    pub is_edited: bool,
+    pub is_deleted: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct NewComment {
+    pub content: String,
+    pub parent_id: Option<String>,
+    pub lines: Option<LineRange>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateComment {
+    pub content: String,
+}
+
+/// Lists top-level comments, or the replies to `parent_id`. `line` keeps only
+/// comments anchored to a range containing that line.
+#[derive(Debug, Deserialize)]
+pub struct CommentListQuery {
+    pub parent_id: Option<String>,
+    pub line: Option<i32>,
+    pub limit: Option<i32>,
+    pub cursor: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn validates_line_ranges_against_code() {
+        let code = Some("fn main() {\n    println!(\"hi\");\n}\n");
+        assert!(LineRange { start: 1, end: 3 }.validate(code).is_ok());
+        assert!(LineRange { start: 2, end: 2 }.validate(code).is_ok());
+        assert!(LineRange { start: 0, end: 1 }.validate(code).is_err());
+        assert!(LineRange { start: 3, end: 2 }.validate(code).is_err());
+        assert!(LineRange { start: 2, end: 4 }.validate(code).is_err());
+        assert!(LineRange { start: 1, end: 1 }.validate(None).is_err());
+        assert!(LineRange { start: 2, end: 3 }.contains(3));
+        assert!(!LineRange { start: 2, end: 3 }.contains(1));
+    }
+}
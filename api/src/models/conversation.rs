@@ -11,6 +11,8 @@ pub struct Conversation {
     pub last_message_at: Option<i64>,
     pub participant_ids: Vec<String>,
     pub customization: Option<ConversationCustomization>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub unread_count: Option<i64>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -50,6 +52,7 @@ impl Conversation {
             last_message_at: None,
             participant_ids,
             customization: None,
+            unread_count: None,
         }
     }
 }
@@ -0,0 +1,35 @@
+use serde::{Deserialize, Serialize};
+use crate::models::user::UserProfile;
+
+/// One side of a follow edge as seen from the listed user. `is_mutual` is set
+/// when the two users follow each other, which makes them connections.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FollowEdge {
+    pub user: UserProfile,
+    pub followed_at: i64,
+    pub is_mutual: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FollowStats {
+    pub followers: i64,
+    pub following: i64,
+}
+
+/// Someone followed by people the user follows, but not by the user yet.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ConnectionSuggestion {
+    pub user: UserProfile,
+    pub mutual_count: usize,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct FollowListQuery {
+    pub limit: Option<i32>,
+    pub cursor: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SuggestionQuery {
+    pub limit: Option<i32>,
+}
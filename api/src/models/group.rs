@@ -5,16 +5,69 @@ use chrono::{DateTime, Utc};
 pub struct Group {
     pub id: String,
     pub name: String,
+    pub description: Option<String>,
+    pub tags: Vec<String>,
     pub created_at: i64,
     pub updated_at: i64,
+    pub is_private: bool,
     pub member_ids: Vec<String>,
+    pub owner_id: Option<String>,
+    pub admin_ids: Vec<String>,
     pub customization: Option<GroupCustomization>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub unread_count: Option<i64>,
+}
+
+/// Member roles, ordered by privilege so checks can use `>=`.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum GroupRole {
+    Member,
+    Admin,
+    Owner,
+}
+
+impl GroupRole {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            GroupRole::Member => "member",
+            GroupRole::Admin => "admin",
+            GroupRole::Owner => "owner",
+        }
+    }
+
+    /// Rows written before roles existed have no role and count as members.
+    pub fn from_column(value: Option<&str>) -> Self {
+        match value {
+            Some("owner") => GroupRole::Owner,
+            Some("admin") => GroupRole::Admin,
+            _ => GroupRole::Member,
+        }
+    }
 }
 
 #[derive(Debug, Deserialize)]
 pub struct NewGroup {
     pub name: String,
     pub member_ids: Vec<String>,
+    #[serde(default)]
+    pub is_private: bool,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+/// What anyone may see about a group without being a member.
+#[derive(Debug, Serialize)]
+pub struct PublicGroup {
+    pub id: String,
+    pub name: String,
+    pub description: Option<String>,
+    pub tags: Vec<String>,
+    pub is_private: bool,
+    pub member_count: usize,
+    pub customization: Option<GroupCustomization>,
 }
 
 #[derive(Debug, Serialize)]
@@ -30,6 +83,20 @@ pub struct GroupMember {
     pub joined_at: i64,
 }
 
+impl From<Group> for PublicGroup {
+    fn from(group: Group) -> Self {
+        PublicGroup {
+            member_count: group.member_ids.len(),
+            id: group.id,
+            name: group.name,
+            description: group.description,
+            tags: group.tags,
+            is_private: group.is_private,
+            customization: group.customization,
+        }
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct AddMemberRequest {
     pub user_id: String,
@@ -43,6 +110,61 @@ pub struct RemoveMemberRequest {
 #[derive(Debug, Serialize, Deserialize)]
 pub struct UpdateGroupRequest {
     pub name: Option<String>,
+    pub is_private: Option<bool>,
+    pub description: Option<String>,
+    pub tags: Option<Vec<String>>,
+}
+
+/// Filters for public group discovery; `cursor` is the `next_cursor` of the
+/// previous page.
+#[derive(Debug, Deserialize)]
+pub struct GroupSearchQuery {
+    pub q: Option<String>,
+    pub tag: Option<String>,
+    pub min_members: Option<usize>,
+    pub max_members: Option<usize>,
+    pub limit: Option<i32>,
+    pub cursor: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateMemberRoleRequest {
+    pub role: GroupRole,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TransferOwnershipRequest {
+    pub user_id: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct GroupInvite {
+    pub code: String,
+    pub group_id: String,
+    pub created_by: String,
+    pub created_at: i64,
+    pub expires_at: i64,
+    pub max_uses: Option<i32>,
+    pub uses: i32,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct NewGroupInvite {
+    pub expires_in_secs: Option<i64>,
+    pub max_uses: Option<i32>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct GroupJoinRequest {
+    pub group_id: String,
+    pub user_id: String,
+    pub message: Option<String>,
+    pub requested_at: i64,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct JoinGroupRequest {
+    pub message: Option<String>,
 }
 
 impl Group {
@@ -55,10 +177,28 @@ impl Group {
         Self {
             id,
             name,
+            description: None,
+            tags: Vec::new(),
             created_at: now,
             updated_at: now,
+            is_private: false,
             member_ids,
+            owner_id: None,
+            admin_ids: Vec::new(),
             customization: None,
+            unread_count: None,
+        }
+    }
+
+    pub fn role_of(&self, user_id: &str) -> Option<GroupRole> {
+        if self.owner_id.as_deref() == Some(user_id) {
+            Some(GroupRole::Owner)
+        } else if self.admin_ids.iter().any(|id| id == user_id) {
+            Some(GroupRole::Admin)
+        } else if self.member_ids.iter().any(|id| id == user_id) {
+            Some(GroupRole::Member)
+        } else {
+            None
         }
     }
 }
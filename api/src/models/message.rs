@@ -55,3 +55,10 @@ impl Message {
         }
     }
 }
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ReadMarker {
+    pub user_id: String,
+    pub last_read_message_id: String,
+    pub updated_at: i64,
+}
@@ -3,3 +3,5 @@ pub mod conversation;
 pub mod message;
 pub mod group;
 pub mod post;
+pub mod follow;
+pub mod comment;
@@ -40,6 +40,10 @@ pub struct UpdatePost {
 pub struct PostResponse {
     pub post: Post,
     pub author: PostAuthor,
+    pub liked_by_me: bool,
+    pub comment_count: i64,
+    /// The outcome of the last run of the post's current code, if any.
+    pub last_run: Option<PostRunStatus>,
 }
 
 #[derive(Debug, Serialize, Clone)]
@@ -48,6 +52,121 @@ pub struct PostAuthor {
     pub username: String,
 }
 
+#[derive(Debug, Serialize, Clone)]
+pub struct PostRunStatus {
+    pub success: bool,
+    pub matches_expected: Option<bool>,
+    pub ran_at: i64,
+}
+
+/// Runs a post's code. Without `stdin` the author's input is used and the
+/// result is cached on the post; `force` runs it again even when the cached
+/// result is for the current code.
+#[derive(Debug, Deserialize)]
+pub struct RunPostRequest {
+    pub stdin: Option<String>,
+    pub force: Option<bool>,
+}
+
+/// What the author expects a post's code to print for `stdin`.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct PostRunSettings {
+    pub stdin: Option<String>,
+    pub expected_output: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PostRun {
+    pub post_id: String,
+    pub language: String,
+    pub stdin: Option<String>,
+    pub success: bool,
+    pub output: Option<String>,
+    pub error: Option<String>,
+    pub execution_time: i64,
+    pub expected_output: Option<String>,
+    /// Set when the author attached expected output: whether the run
+    /// succeeded and printed it.
+    pub matches_expected: Option<bool>,
+    /// Whether this is the cached result rather than a fresh run.
+    pub cached: bool,
+    pub ran_at: i64,
+}
+
+/// A saved state of a post's text. Revision 1 is the post as first written;
+/// each edit that changes the text adds the next one.
+#[derive(Debug, Serialize, Clone)]
+pub struct PostRevision {
+    pub post_id: String,
+    pub revision: i32,
+    pub title: String,
+    pub content: String,
+    pub code: Option<String>,
+    pub language: Option<String>,
+    pub tags: Option<Vec<String>>,
+    pub restored_from: Option<i32>,
+    pub created_at: i64,
+}
+
+/// Lists revisions newest first; `cursor` is the revision number the
+/// previous page ended at.
+#[derive(Debug, Deserialize)]
+pub struct RevisionListQuery {
+    pub limit: Option<i32>,
+    pub cursor: Option<i32>,
+}
+
+/// Compares revision `from` with `to`, or with the latest revision when `to`
+/// is left out.
+#[derive(Debug, Deserialize)]
+pub struct RevisionDiffQuery {
+    pub from: i32,
+    pub to: Option<i32>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RevisionDiff {
+    pub post_id: String,
+    pub from: i32,
+    pub to: i32,
+    /// A unified diff with one section per changed field (title, content,
+    /// code, language, tags); empty when the revisions are the same.
+    pub diff: String,
+}
+
+/// Lists the most used post tags (`kind` "tag", the default) or languages
+/// (`kind` "language").
+#[derive(Debug, Deserialize)]
+pub struct PostTagQuery {
+    pub kind: Option<String>,
+    pub limit: Option<i32>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TagPopularity {
+    pub tag: String,
+    pub posts: i64,
+}
+
+/// Full-text search over titles, tags, content and code, optionally narrowed
+/// to one tag or language.
+#[derive(Debug, Deserialize)]
+pub struct PostSearchQuery {
+    pub q: String,
+    pub tag: Option<String>,
+    pub language: Option<String>,
+    pub limit: Option<i32>,
+    pub cursor: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PostSearchResult {
+    #[serde(flatten)]
+    pub post: PostResponse,
+    pub score: u32,
+    pub matched_terms: Vec<String>,
+}
+
 impl Post {
     pub fn new(
         id: String,
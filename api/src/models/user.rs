@@ -1,6 +1,78 @@
 use serde::{Serialize, Deserialize};
 use serde;
 use chrono::{DateTime, Utc};
+use crate::utils::tags::normalize_tag;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SkillLevel {
+    Beginner,
+    Intermediate,
+    Advanced,
+    Expert,
+}
+
+impl SkillLevel {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SkillLevel::Beginner => "beginner",
+            SkillLevel::Intermediate => "intermediate",
+            SkillLevel::Advanced => "advanced",
+            SkillLevel::Expert => "expert",
+        }
+    }
+
+    pub fn from_column(value: &str) -> Option<Self> {
+        match value {
+            "beginner" => Some(SkillLevel::Beginner),
+            "intermediate" => Some(SkillLevel::Intermediate),
+            "advanced" => Some(SkillLevel::Advanced),
+            "expert" => Some(SkillLevel::Expert),
+            _ => None,
+        }
+    }
+}
+
+/// A normalized interest tag with an optional skill level, written as
+/// `"rust"` or `"rust:expert"` in JSON.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Interest {
+    pub tag: String,
+    pub level: Option<SkillLevel>,
+}
+
+impl Interest {
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let (tag, level) = match spec.rsplit_once(':') {
+            Some((tag, level)) => {
+                let level = SkillLevel::from_column(&level.trim().to_lowercase())
+                    .ok_or_else(|| format!("Unknown skill level in {:?}", spec))?;
+                (tag, Some(level))
+            }
+            None => (spec, None),
+        };
+        let tag = normalize_tag(tag).ok_or_else(|| format!("Invalid interest: {:?}", spec))?;
+        Ok(Interest { tag, level })
+    }
+}
+
+impl TryFrom<String> for Interest {
+    type Error = String;
+
+    fn try_from(spec: String) -> Result<Self, Self::Error> {
+        Interest::parse(&spec)
+    }
+}
+
+impl From<Interest> for String {
+    fn from(interest: Interest) -> Self {
+        match interest.level {
+            Some(level) => format!("{}:{}", interest.tag, level.as_str()),
+            None => interest.tag,
+        }
+    }
+}
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct User {
@@ -13,8 +85,8 @@ pub struct User {
     pub updated_at: i64,
     pub last_seen_at: Option<i64>,
     pub is_online: bool,
-    pub interests: Option<String>,
-    pub language: Option<String>,
+    pub interests: Vec<Interest>,
+    pub languages: Vec<String>,
     pub profile_image_url: Option<String>,
     pub home_country: Option<String>,
     pub project_building: Option<String>,
@@ -25,8 +97,10 @@ pub struct NewUser {
     pub username: String,
     pub email: String,
     pub password: String,
-    pub interests: Option<String>,
-    pub language: Option<String>,
+    #[serde(default)]
+    pub interests: Vec<Interest>,
+    #[serde(default)]
+    pub languages: Vec<String>,
     pub profile_image_url: Option<String>,
     pub home_country: Option<String>,
     pub project_building: Option<String>,
@@ -36,21 +110,35 @@ pub struct NewUser {
 pub struct LoginRequest {
     pub email: String,
     pub password: String,
+    #[serde(default)]
+    pub device_name: Option<String>,
 }
 
 #[derive(Debug, Serialize)]
 pub struct AuthResponse {
     pub token: String,
+    pub refresh_token: String,
     pub user: User,
 }
 
+#[derive(Debug, Deserialize)]
+pub struct RefreshRequest {
+    pub refresh_token: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TokenResponse {
+    pub token: String,
+    pub refresh_token: String,
+}
+
 #[derive(Debug, Deserialize)]
 pub struct UpdateProfileRequest {
     pub username: Option<String>,
     pub email: Option<String>,
     pub password: Option<String>,
-    pub interests: Option<String>,
-    pub language: Option<String>,
+    pub interests: Option<Vec<Interest>>,
+    pub languages: Option<Vec<String>>,
     pub profile_image_url: Option<String>,
     pub home_country: Option<String>,
     pub project_building: Option<String>,
@@ -64,21 +152,55 @@ pub struct UserProfile {
     pub created_at: i64,
     pub last_seen_at: Option<i64>,
     pub is_online: bool,
-    pub interests: Option<String>,
-    pub language: Option<String>,
+    pub interests: Vec<Interest>,
+    pub languages: Vec<String>,
     pub profile_image_url: Option<String>,
     pub home_country: Option<String>,
     pub project_building: Option<String>,
 }
 
+/// Query for `GET /users/match`: free text such as "rust expert, speaks
+/// English, building a compiler", narrowed by online status and country.
+#[derive(Debug, Deserialize)]
+pub struct MatchUsersQuery {
+    pub q: String,
+    pub online: Option<bool>,
+    pub country: Option<String>,
+    pub limit: Option<i32>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct UserMatch {
+    pub user: UserProfile,
+    pub score: u32,
+    pub matched_terms: Vec<String>,
+    pub is_following: bool,
+}
+
+/// Query for `GET /users/tags`: tags of one `kind` ("interest" or
+/// "language") starting with `q`, most used first.
+#[derive(Debug, Deserialize)]
+pub struct TagCatalogQuery {
+    #[serde(default)]
+    pub q: String,
+    pub kind: Option<String>,
+    pub limit: Option<i32>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TagSuggestion {
+    pub tag: String,
+    pub users: i64,
+}
+
 impl User {
     pub fn new(
         id: String, 
         username: String, 
         email: String, 
         password_hash: String, 
-        interests: Option<String>, 
-        language: Option<String>,
+        interests: Vec<Interest>, 
+        languages: Vec<String>,
         profile_image_url: Option<String>,
         home_country: Option<String>,
         project_building: Option<String>
@@ -93,8 +215,8 @@ impl User {
             updated_at: now,
             last_seen_at: None,
             is_online: false,
-            interests: Some(interests.unwrap_or("".to_string())),
-            language: Some(language.unwrap_or("".to_string())),
+            interests,
+            languages,
             profile_image_url,
             home_country,
             project_building,
@@ -110,10 +232,27 @@ impl User {
             last_seen_at: self.last_seen_at,
             is_online: self.is_online,
             interests: self.interests.clone(),
-            language: self.language.clone(),
+            languages: self.languages.clone(),
             profile_image_url: self.profile_image_url.clone(),
             home_country: self.home_country.clone(),
             project_building: self.project_building.clone(),
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_interest_specs() {
+        assert_eq!(
+            Interest::parse("Rust:Expert").unwrap(),
+            Interest { tag: "rust".to_string(), level: Some(SkillLevel::Expert) }
+        );
+        assert_eq!(Interest::parse(" Machine Learning ").unwrap().tag, "machine-learning");
+        assert!(Interest::parse("rust:guru").is_err());
+        assert!(Interest::parse(":expert").is_err());
+        assert_eq!(String::from(Interest::parse("go:beginner").unwrap()), "go:beginner");
+    }
+}
@@ -1,70 +1,177 @@
-use actix_web::{web, HttpResponse, HttpRequest};
+use actix_web::{web, HttpResponse};
 use scylla::client::session::Session;
 use crate::{
-    models::post::{NewPost, UpdatePost},
+    compiler::pool::{CompilerPool, PoolError},
+    models::post::{NewPost, PostRunSettings, PostSearchQuery, PostTagQuery, RevisionDiffQuery, RevisionListQuery, RunPostRequest, UpdatePost},
     posts::service::PostsService
 };
 use crate::error::AppError;
 use serde::{Serialize, Deserialize};
 use actix_web::http::StatusCode;
-use crate::utils::jwt::get_user_id_from_token;
+use crate::utils::jwt::AuthenticatedUser;
+use crate::utils::pagination::clamp_page_size;
+use crate::utils::timeline::TimelineCursor;
 
 #[derive(Deserialize)]
 pub struct PostQueryParams {
     limit: Option<i32>,
 }
 
+#[derive(Deserialize)]
+pub struct FeedQueryParams {
+    limit: Option<i32>,
+    cursor: Option<String>,
+}
+
+/// Trending and search pages are addressed by offset into the ranking.
+fn parse_offset(cursor: Option<&str>) -> Result<usize, AppError> {
+    match cursor {
+        Some(cursor) => cursor
+            .parse::<usize>()
+            .map_err(|_| AppError(format!("Invalid cursor: {:?}", cursor), StatusCode::BAD_REQUEST)),
+        None => Ok(0),
+    }
+}
+
+pub async fn get_latest_feed(
+    db_session: web::Data<Session>,
+    user: AuthenticatedUser,
+    query: web::Query<FeedQueryParams>,
+) -> Result<HttpResponse, AppError> {
+    let cursor = query.cursor.as_deref().map(TimelineCursor::parse).transpose()?;
+    let service = PostsService::new(db_session.clone());
+    let page = service.latest_feed(&user.user_id, cursor, clamp_page_size(query.limit)).await?;
+
+    Ok(HttpResponse::Ok().json(page))
+}
+
+pub async fn get_following_feed(
+    db_session: web::Data<Session>,
+    user: AuthenticatedUser,
+    query: web::Query<FeedQueryParams>,
+) -> Result<HttpResponse, AppError> {
+    let cursor = query.cursor.as_deref().map(TimelineCursor::parse).transpose()?;
+    let service = PostsService::new(db_session.clone());
+    let page = service.following_feed(&user.user_id, cursor, clamp_page_size(query.limit)).await?;
+
+    Ok(HttpResponse::Ok().json(page))
+}
+
+pub async fn get_trending_feed(
+    db_session: web::Data<Session>,
+    user: AuthenticatedUser,
+    query: web::Query<FeedQueryParams>,
+) -> Result<HttpResponse, AppError> {
+    let offset = parse_offset(query.cursor.as_deref())?;
+    let service = PostsService::new(db_session.clone());
+    let page = service.trending_feed(&user.user_id, offset, clamp_page_size(query.limit)).await?;
+
+    Ok(HttpResponse::Ok().json(page))
+}
+
+pub async fn get_posts_by_tag(
+    db_session: web::Data<Session>,
+    user: AuthenticatedUser,
+    path: web::Path<String>,
+    query: web::Query<FeedQueryParams>,
+) -> Result<HttpResponse, AppError> {
+    let cursor = query.cursor.as_deref().map(TimelineCursor::parse).transpose()?;
+    let service = PostsService::new(db_session.clone());
+    let page = service.tag_feed(&path, &user.user_id, cursor, clamp_page_size(query.limit)).await?;
+
+    Ok(HttpResponse::Ok().json(page))
+}
+
+pub async fn get_posts_by_language(
+    db_session: web::Data<Session>,
+    user: AuthenticatedUser,
+    path: web::Path<String>,
+    query: web::Query<FeedQueryParams>,
+) -> Result<HttpResponse, AppError> {
+    let cursor = query.cursor.as_deref().map(TimelineCursor::parse).transpose()?;
+    let service = PostsService::new(db_session.clone());
+    let page = service.language_feed(&path, &user.user_id, cursor, clamp_page_size(query.limit)).await?;
+
+    Ok(HttpResponse::Ok().json(page))
+}
+
+pub async fn get_popular_tags(
+    db_session: web::Data<Session>,
+    _user: AuthenticatedUser,
+    query: web::Query<PostTagQuery>,
+) -> Result<HttpResponse, AppError> {
+    let service = PostsService::new(db_session.clone());
+    let tags = service.popular_tags(query.kind.as_deref(), clamp_page_size(query.limit) as usize).await?;
+
+    Ok(HttpResponse::Ok().json(tags))
+}
+
+pub async fn search_posts(
+    db_session: web::Data<Session>,
+    user: AuthenticatedUser,
+    query: web::Query<PostSearchQuery>,
+) -> Result<HttpResponse, AppError> {
+    let offset = parse_offset(query.cursor.as_deref())?;
+    let service = PostsService::new(db_session.clone());
+    let page = service.search(&user.user_id, &query, offset, clamp_page_size(query.limit)).await?;
+
+    Ok(HttpResponse::Ok().json(page))
+}
+
 pub async fn get_all_posts(
     db_session: web::Data<Session>,
+    user: AuthenticatedUser,
     query: web::Query<PostQueryParams>,
 ) -> Result<HttpResponse, AppError> {
     let service = PostsService::new(db_session.clone());
-    let posts = service.get_all_posts(query.limit).await?;
+    let posts = service.get_all_posts(&user.user_id, query.limit).await?;
     
     Ok(HttpResponse::Ok().json(posts))
 }
 
 pub async fn get_post_by_id(
     db_session: web::Data<Session>,
+    user: AuthenticatedUser,
     path: web::Path<String>,
 ) -> Result<HttpResponse, AppError> {
     let post_id = path.into_inner();
     let service = PostsService::new(db_session.clone());
-    let post = service.get_post_by_id(&post_id).await?;
+    let post = service.get_post_by_id(&post_id, Some(&user.user_id)).await?;
     
     Ok(HttpResponse::Ok().json(post))
 }
 
 pub async fn get_posts_by_user(
     db_session: web::Data<Session>,
+    user: AuthenticatedUser,
     path: web::Path<String>,
     query: web::Query<PostQueryParams>,
 ) -> Result<HttpResponse, AppError> {
     let user_id = path.into_inner();
     let service = PostsService::new(db_session.clone());
-    let posts = service.get_posts_by_user(&user_id, query.limit).await?;
+    let posts = service.get_posts_by_user(&user_id, &user.user_id, query.limit).await?;
     
     Ok(HttpResponse::Ok().json(posts))
 }
 
 pub async fn get_my_posts(
     db_session: web::Data<Session>,
-    request: HttpRequest,
+    user: AuthenticatedUser,
     query: web::Query<PostQueryParams>,
 ) -> Result<HttpResponse, AppError> {
-    let user_id = get_user_id_from_token(&request)?;
+    let user_id = user.user_id;
     let service = PostsService::new(db_session.clone());
-    let posts = service.get_posts_by_user(&user_id, query.limit).await?;
+    let posts = service.get_posts_by_user(&user_id, &user_id, query.limit).await?;
     
     Ok(HttpResponse::Ok().json(posts))
 }
 
 pub async fn create_post(
     db_session: web::Data<Session>,
-    request: HttpRequest,
+    user: AuthenticatedUser,
     new_post: web::Json<NewPost>,
 ) -> Result<HttpResponse, AppError> {
-    let user_id = get_user_id_from_token(&request)?;
+    let user_id = user.user_id;
     let service = PostsService::new(db_session.clone());
     let post = service.create_post(&user_id, new_post.into_inner()).await?;
     
@@ -73,11 +180,11 @@ pub async fn create_post(
 
 pub async fn update_post(
     db_session: web::Data<Session>,
-    request: HttpRequest,
+    user: AuthenticatedUser,
     path: web::Path<String>,
     update_post: web::Json<UpdatePost>,
 ) -> Result<HttpResponse, AppError> {
-    let user_id = get_user_id_from_token(&request)?;
+    let user_id = user.user_id;
     let post_id = path.into_inner();
     let service = PostsService::new(db_session.clone());
     let post = service.update_post(&post_id, &user_id, update_post.into_inner()).await?;
@@ -87,10 +194,10 @@ pub async fn update_post(
 
 pub async fn delete_post(
     db_session: web::Data<Session>,
-    request: HttpRequest,
+    user: AuthenticatedUser,
     path: web::Path<String>,
 ) -> Result<HttpResponse, AppError> {
-    let user_id = get_user_id_from_token(&request)?;
+    let user_id = user.user_id;
     let post_id = path.into_inner();
     let service = PostsService::new(db_session.clone());
     service.delete_post(&post_id, &user_id).await?;
@@ -100,10 +207,10 @@ pub async fn delete_post(
 
 pub async fn toggle_like_post(
     db_session: web::Data<Session>,
-    request: HttpRequest,
+    user: AuthenticatedUser,
     path: web::Path<String>,
 ) -> Result<HttpResponse, AppError> {
-    let user_id = get_user_id_from_token(&request)?;
+    let user_id = user.user_id;
     let post_id = path.into_inner();
     let service = PostsService::new(db_session.clone());
     let post = service.toggle_like_post(&post_id, &user_id).await?;
@@ -111,3 +218,91 @@ pub async fn toggle_like_post(
     Ok(HttpResponse::Ok().json(post))
 }
 
+pub async fn list_revisions(
+    db_session: web::Data<Session>,
+    user: AuthenticatedUser,
+    path: web::Path<String>,
+    query: web::Query<RevisionListQuery>,
+) -> Result<HttpResponse, AppError> {
+    let post_id = path.into_inner();
+    let service = PostsService::new(db_session.clone());
+    let page = service.list_revisions(&post_id, &user.user_id, query.cursor, clamp_page_size(query.limit)).await?;
+
+    Ok(HttpResponse::Ok().json(page))
+}
+
+pub async fn get_revision(
+    db_session: web::Data<Session>,
+    user: AuthenticatedUser,
+    path: web::Path<(String, i32)>,
+) -> Result<HttpResponse, AppError> {
+    let (post_id, revision) = path.into_inner();
+    let service = PostsService::new(db_session.clone());
+    let revision = service.get_revision(&post_id, &user.user_id, revision).await?;
+
+    Ok(HttpResponse::Ok().json(revision))
+}
+
+pub async fn diff_revisions(
+    db_session: web::Data<Session>,
+    user: AuthenticatedUser,
+    path: web::Path<String>,
+    query: web::Query<RevisionDiffQuery>,
+) -> Result<HttpResponse, AppError> {
+    let post_id = path.into_inner();
+    let service = PostsService::new(db_session.clone());
+    let diff = service.diff_revisions(&post_id, &user.user_id, query.from, query.to).await?;
+
+    Ok(HttpResponse::Ok().json(diff))
+}
+
+pub async fn restore_revision(
+    db_session: web::Data<Session>,
+    user: AuthenticatedUser,
+    path: web::Path<(String, i32)>,
+) -> Result<HttpResponse, AppError> {
+    let (post_id, revision) = path.into_inner();
+    let service = PostsService::new(db_session.clone());
+    let post = service.restore_revision(&post_id, &user.user_id, revision).await?;
+
+    Ok(HttpResponse::Ok().json(post))
+}
+
+pub async fn run_post(
+    db_session: web::Data<Session>,
+    pool: web::Data<CompilerPool>,
+    user: AuthenticatedUser,
+    path: web::Path<String>,
+    request: web::Json<RunPostRequest>,
+) -> Result<HttpResponse, PoolError> {
+    let post_id = path.into_inner();
+    let service = PostsService::new(db_session.clone());
+    let run = service.run_post(&pool, &post_id, &user.user_id, request.into_inner()).await?;
+
+    Ok(HttpResponse::Ok().json(run))
+}
+
+pub async fn get_latest_run(
+    db_session: web::Data<Session>,
+    user: AuthenticatedUser,
+    path: web::Path<String>,
+) -> Result<HttpResponse, AppError> {
+    let post_id = path.into_inner();
+    let service = PostsService::new(db_session.clone());
+    let run = service.latest_run(&post_id, &user.user_id).await?;
+
+    Ok(HttpResponse::Ok().json(run))
+}
+
+pub async fn update_run_settings(
+    db_session: web::Data<Session>,
+    user: AuthenticatedUser,
+    path: web::Path<String>,
+    settings: web::Json<PostRunSettings>,
+) -> Result<HttpResponse, AppError> {
+    let post_id = path.into_inner();
+    let service = PostsService::new(db_session.clone());
+    let settings = service.set_run_settings(&post_id, &user.user_id, settings.into_inner()).await?;
+
+    Ok(HttpResponse::Ok().json(settings))
+}
@@ -1,17 +1,130 @@
 use futures_util::TryStreamExt;
-use scylla::{client::session::Session, value::CqlTimestamp, DeserializeRow};
+use log::info;
+use scylla::{client::session::Session, value::{Counter, CqlTimestamp}, DeserializeRow};
 use crate::{
+    compiler::{models::{output_matches, CompileRequest, LanguageConfig}, pool::{CompilerPool, PoolError}},
     error::AppError, 
+    follows::service::FollowService,
     models::{
-        post::{Post, NewPost, UpdatePost, PostResponse, PostAuthor},
+        post::{Post, NewPost, UpdatePost, PostResponse, PostAuthor, PostRevision, PostRun, PostRunSettings, PostRunStatus, RunPostRequest, PostSearchQuery, PostSearchResult, RevisionDiff, TagPopularity},
         user::User,
     }, 
-    utils::db_client::DbClient
+    utils::db_client::{DbClient, MAX_KEYS_PER_QUERY},
+    utils::diff::unified_diff,
+    utils::pagination::{clamp_page_size, Page},
+    utils::search::{self, post_tags, post_terms, SearchField, MAX_SEARCH_TERMS},
+    utils::terms::IndexField,
+    utils::tags::{normalize_tag, normalize_tags},
+    utils::timeline::{bucket_of, bucket_start, trending_score, TimelineCursor}
 };
 use uuid::Uuid;
 use actix_web::{http::StatusCode, web};
+use std::collections::{HashMap, HashSet};
 use std::marker::PhantomData;
 use chrono::Utc;
+use sha2::{Digest, Sha256};
+
+/// This many empty monthly buckets in a row end the feed.
+const FEED_MAX_EMPTY_BUCKETS: usize = 12;
+/// A page of the latest feed reads at most this many buckets; a page cut
+/// short by it carries a cursor at the last bucket boundary it reached.
+const FEED_MAX_BUCKETS_PER_PAGE: usize = 24;
+/// The following feed merges the timelines of at most this many followees.
+const FOLLOWING_FEED_MAX_AUTHORS: usize = 200;
+/// Trending ranks posts from this window, up to this many of them.
+const TRENDING_WINDOW_DAYS: i64 = 7;
+const TRENDING_CANDIDATES: i32 = 500;
+const MAX_POST_TAGS: usize = 10;
+/// Kinds of `post_tag_usage` rows.
+const POST_TAGS: &str = "tag";
+const POST_LANGUAGES: &str = "language";
+/// Search reads at most this many posts per term, and loads only the
+/// best-scoring few of them.
+const SEARCH_TERM_FANOUT: i32 = 1000;
+const SEARCH_CANDIDATE_LIMIT: usize = 200;
+/// Limit on the stdin and expected output stored for a post's code.
+const MAX_RUN_TEXT_LENGTH: usize = 64 * 1024;
+
+type RevisionRow = (i32, String, String, Option<String>, Option<String>, Option<Vec<String>>, Option<i32>, CqlTimestamp);
+
+type RunRow = (Option<String>, Option<String>, Option<String>, Option<bool>, Option<String>, Option<String>, Option<i64>, Option<bool>, Option<CqlTimestamp>);
+
+type PostRow = (Uuid, Uuid, String, String, Option<String>, Option<String>, Option<Vec<String>>, CqlTimestamp, CqlTimestamp, bool, i64);
+
+/// Posts store their language normalized like a tag, so "Rust " and "rust"
+/// list together.
+fn normalize_language(language: Option<&str>) -> Result<Option<String>, AppError> {
+    match language.map(str::trim).filter(|language| !language.is_empty()) {
+        Some(language) => normalize_tag(language)
+            .map(Some)
+            .ok_or_else(|| AppError(format!("Invalid language: {:?}", language), StatusCode::BAD_REQUEST)),
+        None => Ok(None),
+    }
+}
+
+/// The compiler language and code of a post that can be run.
+fn runnable(post: &Post) -> Result<(&'static str, &str), AppError> {
+    let code = post.code.as_deref().filter(|code| !code.trim().is_empty())
+        .ok_or_else(|| AppError("This post has no code to run".to_string(), StatusCode::BAD_REQUEST))?;
+    let language = post.language.as_deref()
+        .ok_or_else(|| AppError("This post does not say what language its code is in".to_string(), StatusCode::BAD_REQUEST))?;
+    let key = LanguageConfig::key_for(language)
+        .ok_or_else(|| AppError(format!("Posts in {} cannot be run", language), StatusCode::BAD_REQUEST))?;
+    Ok((key, code))
+}
+
+/// Identifies what a cached run result is for, so editing the code or the
+/// input makes the result stale.
+fn run_key(language: &str, code: &str, stdin: Option<&str>) -> String {
+    let mut hasher = Sha256::new();
+    for part in [language, code, stdin.unwrap_or_default()] {
+        hasher.update(part.as_bytes());
+        hasher.update([0]);
+    }
+    hasher.finalize().iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn check_run_text(name: &str, text: Option<&str>) -> Result<(), AppError> {
+    if text.is_some_and(|text| text.len() > MAX_RUN_TEXT_LENGTH) {
+        return Err(AppError(format!("{} must be at most {} bytes", name, MAX_RUN_TEXT_LENGTH), StatusCode::BAD_REQUEST));
+    }
+    Ok(())
+}
+
+/// Whether two states of a post differ in anything a revision records.
+fn same_text(a: &Post, b: &Post) -> bool {
+    a.title == b.title && a.content == b.content && a.code == b.code && a.language == b.language && a.tags == b.tags
+}
+
+fn revision_from_row(post_id: &str, (revision, title, content, code, language, tags, restored_from, created_at): RevisionRow) -> PostRevision {
+    PostRevision {
+        post_id: post_id.to_string(),
+        revision,
+        title,
+        content,
+        code,
+        language,
+        tags,
+        restored_from,
+        created_at: created_at.0 / 1000,
+    }
+}
+
+fn post_from_row((id, user_id, title, content, code, language, tags, created_at, updated_at, is_published, likes_count): PostRow) -> Post {
+    Post {
+        id: id.to_string(),
+        user_id: user_id.to_string(),
+        title,
+        content,
+        code,
+        language,
+        tags,
+        created_at: created_at.0 / 1000,
+        updated_at: updated_at.0 / 1000,
+        is_published,
+        likes_count,
+    }
+}
 
 pub struct PostsService {
     session: web::Data<Session>,
@@ -39,7 +152,8 @@ impl PostsService {
             _phantom: PhantomData,
         };
 
-        let tags_set: Option<Vec<String>> = new_post.tags.clone();
+        let tags = new_post.tags.as_deref().map(|tags| normalize_tags(tags, MAX_POST_TAGS)).transpose()?;
+        let language = normalize_language(new_post.language.as_deref())?;
 
         db_client.insert(
             "INSERT INTO posts (id, user_id, title, content, code, language, tags, created_at, updated_at, is_published, likes_count) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
@@ -49,8 +163,8 @@ impl PostsService {
                 &new_post.title,
                 &new_post.content,
                 &new_post.code,
-                &new_post.language,
-                &tags_set,
+                &language,
+                &tags,
                 CqlTimestamp(now * 1000),
                 CqlTimestamp(now * 1000),
                 is_published,
@@ -58,60 +172,36 @@ impl PostsService {
             )
         ).await?;
 
-        Ok(Post::new(
+        let mut post = Post::new(
             post_id.to_string(),
             user_id.to_string(),
             new_post.title,
             new_post.content,
             new_post.code,
-            new_post.language,
-            new_post.tags,
+            language,
+            tags,
             is_published,
-        ))
-    }
-
-    pub async fn get_all_posts(&self, limit: Option<i32>) -> Result<Vec<PostResponse>, AppError> {
-        let limit = limit.unwrap_or(50);
-
-        let db_client = DbClient::<Post> {
-            session: &self.session,
-            _phantom: PhantomData,
-        };
-
-        let results = db_client.query::<(Uuid, Uuid, String, String, Option<String>, Option<String>, Option<Vec<String>>, CqlTimestamp, CqlTimestamp, bool, i64), _>(
-            "SELECT id, user_id, title, content, code, language, tags, created_at, updated_at, is_published, likes_count FROM posts WHERE is_published = true LIMIT ? ALLOW FILTERING",
-            Some((limit,))
-        ).await?;
-
-        let mut post_responses = Vec::new();
-        
-        for (id, user_id, title, content, code, language, tags, created_at, updated_at, is_published, likes_count) in results {
-            let author = self.get_user_info(&user_id.to_string()).await?;
-            
-            let post = Post {
-                id: id.to_string(),
-                user_id: user_id.to_string(),
-                title,
-                content,
-                code,
-                language,
-                tags,
-                created_at: created_at.0 / 1000,
-                updated_at: updated_at.0 / 1000,
-                is_published,
-                likes_count,
-            };
+        );
+        post.created_at = now;
+        post.updated_at = now;
+        self.insert_revision(&post, 1, None).await?;
 
-            post_responses.push(PostResponse {
-                post,
-                author,
-            });
+        if is_published {
+            self.add_to_timeline(post_id, user_uuid, now * 1000).await?;
+            self.update_indexes(None, Some(&post)).await?;
         }
 
-        Ok(post_responses)
+        Ok(post)
     }
 
-    pub async fn get_post_by_id(&self, post_id: &str) -> Result<PostResponse, AppError> {
+    /// The newest published posts; the first page of [`Self::latest_feed`].
+    pub async fn get_all_posts(&self, viewer_id: &str, limit: Option<i32>) -> Result<Vec<PostResponse>, AppError> {
+        let limit = clamp_page_size(limit);
+        Ok(self.latest_feed(viewer_id, None, limit).await?.items)
+    }
+
+    /// Loads one post. `liked_by_me` is filled in for `viewer_id` when given.
+    pub async fn get_post_by_id(&self, post_id: &str, viewer_id: Option<&str>) -> Result<PostResponse, AppError> {
         let post_uuid = Uuid::parse_str(post_id)
             .map_err(|e| AppError(format!("Invalid post ID: {}", e), StatusCode::BAD_REQUEST))?;
 
@@ -142,16 +232,21 @@ impl PostsService {
                 likes_count: *likes_count,
             };
 
-            Ok(PostResponse {
+            let mut response = PostResponse {
                 post,
                 author,
-            })
+                liked_by_me: false,
+                comment_count: 0,
+                last_run: None,
+            };
+            self.apply_engagement(std::slice::from_mut(&mut response), viewer_id).await?;
+            Ok(response)
         } else {
             Err(AppError("Post not found".to_string(), StatusCode::NOT_FOUND))
         }
     }
 
-    pub async fn get_posts_by_user(&self, user_id: &str, limit: Option<i32>) -> Result<Vec<PostResponse>, AppError> {
+    pub async fn get_posts_by_user(&self, user_id: &str, viewer_id: &str, limit: Option<i32>) -> Result<Vec<PostResponse>, AppError> {
         let user_uuid = Uuid::parse_str(user_id)
             .map_err(|e| AppError(format!("Invalid user ID: {}", e), StatusCode::BAD_REQUEST))?;
         let limit = limit.unwrap_or(50);
@@ -187,9 +282,13 @@ impl PostsService {
             post_responses.push(PostResponse {
                 post,
                 author: author.clone(),
+                liked_by_me: false,
+                comment_count: 0,
+                last_run: None,
             });
         }
 
+        self.apply_engagement(&mut post_responses, Some(viewer_id)).await?;
         Ok(post_responses)
     }
 
@@ -199,23 +298,13 @@ impl PostsService {
         user_id: &str,
         update_post: UpdatePost,
     ) -> Result<Post, AppError> {
-        let post_uuid = Uuid::parse_str(post_id)
-            .map_err(|e| AppError(format!("Invalid post ID: {}", e), StatusCode::BAD_REQUEST))?;
-        let user_uuid = Uuid::parse_str(user_id)
-            .map_err(|e| AppError(format!("Invalid user ID: {}", e), StatusCode::BAD_REQUEST))?;
-
-        let existing_post = self.get_post_by_id(post_id).await?;
+        let existing_post = self.get_post_by_id(post_id, None).await?;
         if existing_post.post.user_id != user_id {
             return Err(AppError("Unauthorized: You can only edit your own posts".to_string(), StatusCode::FORBIDDEN));
         }
 
-        let db_client = DbClient::<Post> {
-            session: &self.session,
-            _phantom: PhantomData,
-        };
-
-        let now = Utc::now().timestamp();
-        let mut post = existing_post.post;
+        let previous = existing_post.post;
+        let mut post = previous.clone();
 
         if let Some(title) = update_post.title {
             post.title = title;
@@ -227,18 +316,42 @@ impl PostsService {
             post.code = Some(code);
         }
         if let Some(language) = update_post.language {
-            post.language = Some(language);
+            post.language = normalize_language(Some(&language))?;
         }
         if let Some(tags) = update_post.tags {
-            post.tags = Some(tags);
+            post.tags = Some(normalize_tags(&tags, MAX_POST_TAGS)?);
         }
         if let Some(is_published) = update_post.is_published {
             post.is_published = is_published;
         }
+
+        self.save_post(&previous, post, None).await
+    }
+
+    /// Writes an edited post and keeps everything derived from it in step:
+    /// the revision history, the timelines and the tag and search indexes.
+    /// The revision goes first, so a concurrent edit fails before anything
+    /// is overwritten.
+    async fn save_post(&self, previous: &Post, mut post: Post, restored_from: Option<i32>) -> Result<Post, AppError> {
+        let post_uuid = Uuid::parse_str(&post.id)
+            .map_err(|e| AppError(format!("Invalid post ID: {}", e), StatusCode::BAD_REQUEST))?;
+        let user_uuid = Uuid::parse_str(&post.user_id)
+            .map_err(|e| AppError(format!("Invalid user ID: {}", e), StatusCode::BAD_REQUEST))?;
+
+        let db_client = DbClient::<Post> {
+            session: &self.session,
+            _phantom: PhantomData,
+        };
+
+        let now = Utc::now().timestamp();
+        let was_published = previous.is_published;
         post.updated_at = now;
 
+        self.record_revision(previous, &post, restored_from).await?;
+
+        // `likes_count` is left alone: totals live in `post_like_counts`.
         db_client.insert(
-            "INSERT INTO posts (id, user_id, title, content, code, language, tags, created_at, updated_at, is_published, likes_count) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+            "INSERT INTO posts (id, user_id, title, content, code, language, tags, created_at, updated_at, is_published) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
             (
                 post_uuid,
                 user_uuid,
@@ -250,10 +363,16 @@ impl PostsService {
                 CqlTimestamp(post.created_at * 1000),
                 CqlTimestamp(now * 1000),
                 post.is_published,
-                post.likes_count,
             )
         ).await?;
 
+        match (was_published, post.is_published) {
+            (false, true) => self.add_to_timeline(post_uuid, user_uuid, post.created_at * 1000).await?,
+            (true, false) => self.remove_from_timeline(post_uuid, user_uuid, post.created_at * 1000).await?,
+            _ => {}
+        }
+        self.update_indexes(was_published.then_some(previous), post.is_published.then_some(&post)).await?;
+
         Ok(post)
     }
 
@@ -261,7 +380,7 @@ impl PostsService {
         let post_uuid = Uuid::parse_str(post_id)
             .map_err(|e| AppError(format!("Invalid post ID: {}", e), StatusCode::BAD_REQUEST))?;
 
-        let existing_post = self.get_post_by_id(post_id).await?;
+        let existing_post = self.get_post_by_id(post_id, None).await?;
         if existing_post.post.user_id != user_id {
             return Err(AppError("Unauthorized: You can only delete your own posts".to_string(), StatusCode::FORBIDDEN));
         }
@@ -275,10 +394,367 @@ impl PostsService {
             "DELETE FROM posts WHERE id = ?",
             (post_uuid,)
         ).await?;
+        db_client.insert(
+            "DELETE FROM post_likes WHERE post_id = ?",
+            (post_uuid,)
+        ).await?;
+        db_client.insert(
+            "DELETE FROM post_like_counts WHERE post_id = ?",
+            (post_uuid,)
+        ).await?;
+        db_client.insert(
+            "DELETE FROM post_comments WHERE post_id = ?",
+            (post_uuid,)
+        ).await?;
+        db_client.insert(
+            "DELETE FROM post_comment_counts WHERE post_id = ?",
+            (post_uuid,)
+        ).await?;
+        db_client.insert(
+            "DELETE FROM post_revisions WHERE post_id = ?",
+            (post_uuid,)
+        ).await?;
+        db_client.insert(
+            "DELETE FROM post_runs WHERE post_id = ?",
+            (post_uuid,)
+        ).await?;
+
+        if existing_post.post.is_published {
+            let author_uuid = Uuid::parse_str(&existing_post.post.user_id)
+                .map_err(|e| AppError(format!("Invalid user ID: {}", e), StatusCode::BAD_REQUEST))?;
+            self.remove_from_timeline(post_uuid, author_uuid, existing_post.post.created_at * 1000).await?;
+            self.update_indexes(Some(&existing_post.post), None).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Stores `post` as `revision` unless that revision already exists.
+    async fn insert_revision(&self, post: &Post, revision: i32, restored_from: Option<i32>) -> Result<bool, AppError> {
+        let post_uuid = Uuid::parse_str(&post.id)
+            .map_err(|e| AppError(format!("Invalid post ID: {}", e), StatusCode::BAD_REQUEST))?;
+
+        let db_client = DbClient::<Post> {
+            session: &self.session,
+            _phantom: PhantomData,
+        };
+
+        db_client.apply(
+            "INSERT INTO post_revisions (post_id, revision, title, content, code, language, tags, restored_from, created_at) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?) IF NOT EXISTS",
+            (
+                post_uuid,
+                revision,
+                &post.title,
+                &post.content,
+                &post.code,
+                &post.language,
+                &post.tags,
+                restored_from,
+                CqlTimestamp(post.updated_at * 1000),
+            )
+        ).await
+    }
+
+    /// The newest revision number of a post, or 0 if it has none.
+    async fn latest_revision(&self, post: &Post) -> Result<i32, AppError> {
+        let post_uuid = Uuid::parse_str(&post.id)
+            .map_err(|e| AppError(format!("Invalid post ID: {}", e), StatusCode::BAD_REQUEST))?;
+
+        let db_client = DbClient::<Post> {
+            session: &self.session,
+            _phantom: PhantomData,
+        };
+
+        let results = db_client.query::<(i32,), _>(
+            "SELECT revision FROM post_revisions WHERE post_id = ? LIMIT 1",
+            Some((post_uuid,))
+        ).await?;
+
+        Ok(results.first().map(|(revision,)| *revision).unwrap_or(0))
+    }
 
+    /// Adds a revision for `post` when its text differs from `previous`. Two
+    /// edits racing for the same revision number cannot both win; the loser
+    /// gets a conflict and can retry on top of the other.
+    async fn record_revision(&self, previous: &Post, post: &Post, restored_from: Option<i32>) -> Result<(), AppError> {
+        if same_text(previous, post) {
+            return Ok(());
+        }
+        let revision = self.latest_revision(previous).await? + 1;
+        if !self.insert_revision(post, revision, restored_from).await? {
+            return Err(AppError("The post was edited at the same time; reload it and try again".to_string(), StatusCode::CONFLICT));
+        }
         Ok(())
     }
 
+    /// Loads a post for `viewer_id`; unpublished posts are only visible to
+    /// their author.
+    async fn get_visible_post(&self, post_id: &str, viewer_id: &str) -> Result<Post, AppError> {
+        let post = self.get_post_by_id(post_id, None).await?.post;
+        if !post.is_published && post.user_id != viewer_id {
+            return Err(AppError("Post not found".to_string(), StatusCode::NOT_FOUND));
+        }
+        Ok(post)
+    }
+
+    /// A post's revisions, newest first.
+    pub async fn list_revisions(&self, post_id: &str, viewer_id: &str, cursor: Option<i32>, limit: i32) -> Result<Page<PostRevision>, AppError> {
+        let post = self.get_visible_post(post_id, viewer_id).await?;
+        let before = match cursor {
+            Some(cursor) => cursor,
+            None => self.latest_revision(&post).await? + 1,
+        };
+        let post_uuid = Uuid::parse_str(post_id)
+            .map_err(|e| AppError(format!("Invalid post ID: {}", e), StatusCode::BAD_REQUEST))?;
+
+        let db_client = DbClient::<Post> {
+            session: &self.session,
+            _phantom: PhantomData,
+        };
+
+        let rows = db_client.query::<RevisionRow, _>(
+            "SELECT revision, title, content, code, language, tags, restored_from, created_at FROM post_revisions WHERE post_id = ? AND revision < ? LIMIT ?",
+            Some((post_uuid, before, limit))
+        ).await?;
+
+        let next_cursor = if rows.len() == limit as usize {
+            rows.last().map(|row| row.0.to_string())
+        } else {
+            None
+        };
+        Ok(Page {
+            items: rows.into_iter().map(|row| revision_from_row(post_id, row)).collect(),
+            next_cursor,
+        })
+    }
+
+    pub async fn get_revision(&self, post_id: &str, viewer_id: &str, revision: i32) -> Result<PostRevision, AppError> {
+        let post = self.get_visible_post(post_id, viewer_id).await?;
+        self.load_revision(&post, revision).await
+    }
+
+    async fn load_revision(&self, post: &Post, revision: i32) -> Result<PostRevision, AppError> {
+        let post_uuid = Uuid::parse_str(&post.id)
+            .map_err(|e| AppError(format!("Invalid post ID: {}", e), StatusCode::BAD_REQUEST))?;
+
+        let db_client = DbClient::<Post> {
+            session: &self.session,
+            _phantom: PhantomData,
+        };
+
+        let results = db_client.query::<RevisionRow, _>(
+            "SELECT revision, title, content, code, language, tags, restored_from, created_at FROM post_revisions WHERE post_id = ? AND revision = ?",
+            Some((post_uuid, revision))
+        ).await?;
+
+        results.into_iter().next()
+            .map(|row| revision_from_row(&post.id, row))
+            .ok_or_else(|| AppError(format!("Revision {} not found", revision), StatusCode::NOT_FOUND))
+    }
+
+    /// A unified diff from revision `from` to revision `to` (by default the
+    /// latest), one section per changed field.
+    pub async fn diff_revisions(&self, post_id: &str, viewer_id: &str, from: i32, to: Option<i32>) -> Result<RevisionDiff, AppError> {
+        let post = self.get_visible_post(post_id, viewer_id).await?;
+        let to = match to {
+            Some(to) => to,
+            None => self.latest_revision(&post).await?,
+        };
+        let old = self.load_revision(&post, from).await?;
+        let new = self.load_revision(&post, to).await?;
+
+        let tags = |revision: &PostRevision| revision.tags.clone().unwrap_or_default().join("\n");
+        let sections = [
+            ("title", old.title.clone(), new.title.clone()),
+            ("content", old.content.clone(), new.content.clone()),
+            ("code", old.code.clone().unwrap_or_default(), new.code.clone().unwrap_or_default()),
+            ("language", old.language.clone().unwrap_or_default(), new.language.clone().unwrap_or_default()),
+            ("tags", tags(&old), tags(&new)),
+        ];
+
+        Ok(RevisionDiff {
+            post_id: post.id,
+            from,
+            to,
+            diff: sections.iter().map(|(name, old, new)| unified_diff(name, old, new)).collect(),
+        })
+    }
+
+    /// Puts the text of an earlier revision back. This is itself an edit: it
+    /// adds a new revision pointing at the restored one, so nothing is lost.
+    pub async fn restore_revision(&self, post_id: &str, user_id: &str, revision: i32) -> Result<Post, AppError> {
+        let previous = self.get_post_by_id(post_id, None).await?.post;
+        if previous.user_id != user_id {
+            return Err(AppError("Unauthorized: You can only edit your own posts".to_string(), StatusCode::FORBIDDEN));
+        }
+        let restored = self.load_revision(&previous, revision).await?;
+
+        let mut post = previous.clone();
+        post.title = restored.title;
+        post.content = restored.content;
+        post.code = restored.code;
+        post.language = restored.language;
+        post.tags = restored.tags;
+
+        self.save_post(&previous, post, Some(revision)).await
+    }
+
+    async fn get_run_row(&self, post_uuid: Uuid) -> Result<RunRow, AppError> {
+        let db_client = DbClient::<Post> {
+            session: &self.session,
+            _phantom: PhantomData,
+        };
+
+        let results = db_client.query::<RunRow, _>(
+            "SELECT stdin, expected_output, run_key, success, output, error, execution_time, matches_expected, ran_at FROM post_runs WHERE post_id = ?",
+            Some((post_uuid,))
+        ).await?;
+
+        Ok(results.into_iter().next().unwrap_or_default())
+    }
+
+    /// Runs a post's code through the shared compiler pool. A run with the
+    /// author's input is what readers see on the post, so its result is
+    /// cached there and served again until the code or input changes; runs
+    /// with other input are not stored.
+    pub async fn run_post(&self, pool: &CompilerPool, post_id: &str, viewer_id: &str, request: RunPostRequest) -> Result<PostRun, PoolError> {
+        check_run_text("Input", request.stdin.as_deref())?;
+        let post = self.get_visible_post(post_id, viewer_id).await?;
+        let (language, code) = runnable(&post)?;
+        let post_uuid = Uuid::parse_str(post_id)
+            .map_err(|e| AppError(format!("Invalid post ID: {}", e), StatusCode::BAD_REQUEST))?;
+
+        let (author_stdin, expected_output, cached_key, success, output, error, execution_time, matches_expected, ran_at) =
+            self.get_run_row(post_uuid).await?;
+
+        let canonical = request.stdin.is_none() || request.stdin == author_stdin;
+        let stdin = request.stdin.or(author_stdin);
+        let key = run_key(language, code, stdin.as_deref());
+
+        if canonical && !request.force.unwrap_or(false) && cached_key.as_deref() == Some(key.as_str()) {
+            if let (Some(success), Some(ran_at)) = (success, ran_at) {
+                return Ok(PostRun {
+                    post_id: post.id,
+                    language: language.to_string(),
+                    stdin,
+                    success,
+                    output,
+                    error,
+                    execution_time: execution_time.unwrap_or_default(),
+                    expected_output,
+                    matches_expected,
+                    cached: true,
+                    ran_at: ran_at.0 / 1000,
+                });
+            }
+        }
+
+        let result = pool.run(viewer_id, CompileRequest {
+            language: language.to_string(),
+            code: code.to_string(),
+            input: stdin.clone(),
+            timeout: None,
+        }, None, |_| {}).await?;
+        let matches_expected = expected_output
+            .as_deref()
+            .map(|expected| result.success && output_matches(expected, result.output.as_deref().unwrap_or_default()));
+        let ran_at = Utc::now().timestamp_millis();
+
+        if canonical {
+            let db_client = DbClient::<Post> {
+                session: &self.session,
+                _phantom: PhantomData,
+            };
+
+            db_client.insert(
+                "UPDATE post_runs SET run_key = ?, success = ?, output = ?, error = ?, execution_time = ?, matches_expected = ?, ran_at = ? WHERE post_id = ?",
+                (
+                    &key,
+                    result.success,
+                    &result.output,
+                    &result.error,
+                    result.execution_time as i64,
+                    matches_expected,
+                    CqlTimestamp(ran_at),
+                    post_uuid,
+                )
+            ).await?;
+        }
+
+        Ok(PostRun {
+            post_id: post.id,
+            language: language.to_string(),
+            stdin,
+            success: result.success,
+            output: result.output,
+            error: result.error,
+            execution_time: result.execution_time as i64,
+            expected_output,
+            matches_expected,
+            cached: false,
+            ran_at: ran_at / 1000,
+        })
+    }
+
+    /// The cached result for the post's current code and input.
+    pub async fn latest_run(&self, post_id: &str, viewer_id: &str) -> Result<PostRun, AppError> {
+        let post = self.get_visible_post(post_id, viewer_id).await?;
+        let (language, code) = runnable(&post)?;
+        let post_uuid = Uuid::parse_str(post_id)
+            .map_err(|e| AppError(format!("Invalid post ID: {}", e), StatusCode::BAD_REQUEST))?;
+
+        let (stdin, expected_output, cached_key, success, output, error, execution_time, matches_expected, ran_at) =
+            self.get_run_row(post_uuid).await?;
+
+        match (cached_key, success, ran_at) {
+            (Some(key), Some(success), Some(ran_at)) if key == run_key(language, code, stdin.as_deref()) => Ok(PostRun {
+                post_id: post.id,
+                language: language.to_string(),
+                stdin,
+                success,
+                output,
+                error,
+                execution_time: execution_time.unwrap_or_default(),
+                expected_output,
+                matches_expected,
+                cached: true,
+                ran_at: ran_at.0 / 1000,
+            }),
+            _ => Err(AppError("This post's code has not been run since it last changed".to_string(), StatusCode::NOT_FOUND)),
+        }
+    }
+
+    /// Sets the input the post's code is run with and the output it should
+    /// print. The cached result is re-checked against the new expectation.
+    pub async fn set_run_settings(&self, post_id: &str, user_id: &str, settings: PostRunSettings) -> Result<PostRunSettings, AppError> {
+        check_run_text("Input", settings.stdin.as_deref())?;
+        check_run_text("Expected output", settings.expected_output.as_deref())?;
+        let post = self.get_post_by_id(post_id, None).await?.post;
+        if post.user_id != user_id {
+            return Err(AppError("Unauthorized: You can only edit your own posts".to_string(), StatusCode::FORBIDDEN));
+        }
+        let post_uuid = Uuid::parse_str(post_id)
+            .map_err(|e| AppError(format!("Invalid post ID: {}", e), StatusCode::BAD_REQUEST))?;
+
+        let (_, _, _, success, output, _, _, _, _) = self.get_run_row(post_uuid).await?;
+        let matches_expected = match (&settings.expected_output, success) {
+            (Some(expected), Some(success)) => Some(success && output_matches(expected, output.as_deref().unwrap_or_default())),
+            _ => None,
+        };
+
+        let db_client = DbClient::<Post> {
+            session: &self.session,
+            _phantom: PhantomData,
+        };
+
+        db_client.insert(
+            "UPDATE post_runs SET stdin = ?, expected_output = ?, matches_expected = ? WHERE post_id = ?",
+            (&settings.stdin, &settings.expected_output, matches_expected, post_uuid)
+        ).await?;
+
+        Ok(settings)
+    }
+
     async fn get_user_info(&self, user_id: &str) -> Result<PostAuthor, AppError> {
         let user_uuid = Uuid::parse_str(user_id)
             .map_err(|e| AppError(format!("Invalid user ID: {}", e), StatusCode::BAD_REQUEST))?;
@@ -303,40 +779,737 @@ impl PostsService {
         }
     }
 
-    pub async fn toggle_like_post(&self, post_id: &str, user_id: &str) -> Result<Post, AppError> {
+    /// Looks up the authors of many posts in one query.
+    pub async fn get_authors(&self, user_ids: &[Uuid]) -> Result<HashMap<String, PostAuthor>, AppError> {
+        let mut user_ids = user_ids.to_vec();
+        user_ids.sort();
+        user_ids.dedup();
+        if user_ids.is_empty() {
+            return Ok(HashMap::new());
+        }
+
+        let db_client = DbClient::<User> {
+            session: &self.session,
+            _phantom: PhantomData,
+        };
+
+        let mut authors = HashMap::with_capacity(user_ids.len());
+        for chunk in user_ids.chunks(MAX_KEYS_PER_QUERY) {
+            let results = db_client.query::<(Uuid, String), _>(
+                "SELECT id, username FROM users WHERE id IN ?",
+                Some((chunk.to_vec(),))
+            ).await?;
+            authors.extend(results
+                .into_iter()
+                .map(|(id, username)| (id.to_string(), PostAuthor { user_id: id.to_string(), username })));
+        }
+        Ok(authors)
+    }
+
+    /// Fills in like and comment totals from `post_like_counts` and
+    /// `post_comment_counts`, the last run of each post's current code from
+    /// `post_runs` and, for `viewer_id`, `liked_by_me`, with a few queries per
+    /// chunk of posts.
+    async fn apply_engagement(&self, posts: &mut [PostResponse], viewer_id: Option<&str>) -> Result<(), AppError> {
+        let viewer = viewer_id
+            .map(Uuid::parse_str)
+            .transpose()
+            .map_err(|e| AppError(format!("Invalid user ID: {}", e), StatusCode::BAD_REQUEST))?;
+        let post_ids = posts
+            .iter()
+            .map(|response| Uuid::parse_str(&response.post.id))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| AppError(format!("Invalid post ID: {}", e), StatusCode::BAD_REQUEST))?;
+
+        let db_client = DbClient::<Post> {
+            session: &self.session,
+            _phantom: PhantomData,
+        };
+
+        let mut counts: HashMap<Uuid, i64> = HashMap::new();
+        let mut comment_counts: HashMap<Uuid, i64> = HashMap::new();
+        let mut runs: HashMap<Uuid, (Option<String>, Option<String>, Option<bool>, Option<bool>, Option<CqlTimestamp>)> = HashMap::new();
+        let mut liked: Vec<Uuid> = Vec::new();
+        for chunk in post_ids.chunks(MAX_KEYS_PER_QUERY) {
+            let rows = db_client.query::<(Uuid, Counter), _>(
+                "SELECT post_id, likes FROM post_like_counts WHERE post_id IN ?",
+                Some((chunk.to_vec(),))
+            ).await?;
+            counts.extend(rows.into_iter().map(|(post_id, likes)| (post_id, likes.0)));
+
+            let rows = db_client.query::<(Uuid, Counter), _>(
+                "SELECT post_id, comments FROM post_comment_counts WHERE post_id IN ?",
+                Some((chunk.to_vec(),))
+            ).await?;
+            comment_counts.extend(rows.into_iter().map(|(post_id, comments)| (post_id, comments.0)));
+
+            let rows = db_client.query::<(Uuid, Option<String>, Option<String>, Option<bool>, Option<bool>, Option<CqlTimestamp>), _>(
+                "SELECT post_id, stdin, run_key, success, matches_expected, ran_at FROM post_runs WHERE post_id IN ?",
+                Some((chunk.to_vec(),))
+            ).await?;
+            runs.extend(rows.into_iter().map(|(post_id, stdin, key, success, matches_expected, ran_at)| {
+                (post_id, (stdin, key, success, matches_expected, ran_at))
+            }));
+
+            if let Some(viewer) = viewer {
+                let rows = db_client.query::<(Uuid,), _>(
+                    "SELECT post_id FROM post_likes WHERE post_id IN ? AND user_id = ?",
+                    Some((chunk.to_vec(), viewer))
+                ).await?;
+                liked.extend(rows.into_iter().map(|(post_id,)| post_id));
+            }
+        }
+
+        for (response, post_id) in posts.iter_mut().zip(&post_ids) {
+            response.post.likes_count = counts.get(post_id).copied().unwrap_or(0);
+            response.liked_by_me = liked.contains(post_id);
+            response.comment_count = comment_counts.get(post_id).copied().unwrap_or(0);
+            response.last_run = match (runs.remove(post_id), runnable(&response.post)) {
+                (Some((stdin, Some(key), Some(success), matches_expected, Some(ran_at))), Ok((language, code)))
+                    if key == run_key(language, code, stdin.as_deref()) =>
+                {
+                    Some(PostRunStatus { success, matches_expected, ran_at: ran_at.0 / 1000 })
+                }
+                _ => None,
+            };
+        }
+        Ok(())
+    }
+
+    /// Loads posts by id in one query and pairs them with their authors,
+    /// keeping the order of `post_ids`. Posts that were deleted, unpublished
+    /// or whose author is gone are left out.
+    async fn load_published(&self, post_ids: &[Uuid], viewer_id: Option<&str>) -> Result<Vec<PostResponse>, AppError> {
+        if post_ids.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let db_client = DbClient::<Post> {
+            session: &self.session,
+            _phantom: PhantomData,
+        };
+
+        let mut rows = Vec::with_capacity(post_ids.len());
+        for chunk in post_ids.chunks(MAX_KEYS_PER_QUERY) {
+            rows.extend(db_client.query::<PostRow, _>(
+                "SELECT id, user_id, title, content, code, language, tags, created_at, updated_at, is_published, likes_count FROM posts WHERE id IN ?",
+                Some((chunk.to_vec(),))
+            ).await?);
+        }
+
+        let author_ids: Vec<Uuid> = rows.iter().map(|row| row.1).collect();
+        let authors = self.get_authors(&author_ids).await?;
+        let mut posts: HashMap<String, Post> = rows
+            .into_iter()
+            .map(post_from_row)
+            .filter(|post| post.is_published)
+            .map(|post| (post.id.clone(), post))
+            .collect();
+
+        let mut responses: Vec<PostResponse> = post_ids
+            .iter()
+            .filter_map(|id| posts.remove(&id.to_string()))
+            .filter_map(|post| {
+                let author = authors.get(&post.user_id).cloned()?;
+                Some(PostResponse { post, author, liked_by_me: false, comment_count: 0, last_run: None })
+            })
+            .collect();
+        self.apply_engagement(&mut responses, viewer_id).await?;
+        Ok(responses)
+    }
+
+    async fn add_to_timeline(&self, post_id: Uuid, user_id: Uuid, created_at_millis: i64) -> Result<(), AppError> {
+        let db_client = DbClient::<Post> {
+            session: &self.session,
+            _phantom: PhantomData,
+        };
+
+        db_client.insert(
+            "INSERT INTO post_timeline (bucket, created_at, post_id, user_id) VALUES (?, ?, ?, ?)",
+            (bucket_of(created_at_millis), CqlTimestamp(created_at_millis), post_id, user_id)
+        ).await?;
+        db_client.insert(
+            "INSERT INTO user_post_timeline (user_id, created_at, post_id) VALUES (?, ?, ?)",
+            (user_id, CqlTimestamp(created_at_millis), post_id)
+        ).await
+    }
+
+    async fn remove_from_timeline(&self, post_id: Uuid, user_id: Uuid, created_at_millis: i64) -> Result<(), AppError> {
+        let db_client = DbClient::<Post> {
+            session: &self.session,
+            _phantom: PhantomData,
+        };
+
+        db_client.insert(
+            "DELETE FROM post_timeline WHERE bucket = ? AND created_at = ? AND post_id = ?",
+            (bucket_of(created_at_millis), CqlTimestamp(created_at_millis), post_id)
+        ).await?;
+        db_client.insert(
+            "DELETE FROM user_post_timeline WHERE user_id = ? AND created_at = ? AND post_id = ?",
+            (user_id, CqlTimestamp(created_at_millis), post_id)
+        ).await
+    }
+
+    /// Moves the tag, language and search indexes of a post from `previous` to
+    /// `current`, where `None` stands for a post that is not published. Only
+    /// the entries that differ are written.
+    async fn update_indexes(&self, previous: Option<&Post>, current: Option<&Post>) -> Result<(), AppError> {
+        let Some(post_id) = current.or(previous).map(|post| post.id.as_str()) else { return Ok(()) };
         let post_uuid = Uuid::parse_str(post_id)
             .map_err(|e| AppError(format!("Invalid post ID: {}", e), StatusCode::BAD_REQUEST))?;
 
-        let existing_post = self.get_post_by_id(post_id).await?;
-        let mut post = existing_post.post;
+        let db_client = DbClient::<Post> {
+            session: &self.session,
+            _phantom: PhantomData,
+        };
 
-        post.likes_count += 1;
+        let previous_tags = previous.map(post_tags).unwrap_or_default();
+        let current_tags = current.map(post_tags).unwrap_or_default();
+        if let Some(previous) = previous {
+            for tag in previous_tags.iter().filter(|tag| !current_tags.contains(tag)) {
+                db_client.insert(
+                    "DELETE FROM posts_by_tag WHERE tag = ? AND created_at = ? AND post_id = ?",
+                    (tag, CqlTimestamp(previous.created_at * 1000), post_uuid)
+                ).await?;
+                db_client.insert(
+                    "UPDATE post_tag_usage SET posts = posts - 1 WHERE kind = ? AND tag = ?",
+                    (POST_TAGS, tag)
+                ).await?;
+            }
+        }
+        if let Some(current) = current {
+            for tag in current_tags.iter().filter(|tag| !previous_tags.contains(tag)) {
+                db_client.insert(
+                    "INSERT INTO posts_by_tag (tag, created_at, post_id) VALUES (?, ?, ?)",
+                    (tag, CqlTimestamp(current.created_at * 1000), post_uuid)
+                ).await?;
+                db_client.insert(
+                    "UPDATE post_tag_usage SET posts = posts + 1 WHERE kind = ? AND tag = ?",
+                    (POST_TAGS, tag)
+                ).await?;
+            }
+        }
 
+        let previous_language = previous.and_then(|post| post.language.as_deref()).and_then(normalize_tag);
+        let current_language = current.and_then(|post| post.language.as_deref()).and_then(normalize_tag);
+        if previous_language != current_language {
+            if let (Some(previous), Some(language)) = (previous, &previous_language) {
+                db_client.insert(
+                    "DELETE FROM posts_by_language WHERE language = ? AND created_at = ? AND post_id = ?",
+                    (language, CqlTimestamp(previous.created_at * 1000), post_uuid)
+                ).await?;
+                db_client.insert(
+                    "UPDATE post_tag_usage SET posts = posts - 1 WHERE kind = ? AND tag = ?",
+                    (POST_LANGUAGES, language)
+                ).await?;
+            }
+            if let (Some(current), Some(language)) = (current, &current_language) {
+                db_client.insert(
+                    "INSERT INTO posts_by_language (language, created_at, post_id) VALUES (?, ?, ?)",
+                    (language, CqlTimestamp(current.created_at * 1000), post_uuid)
+                ).await?;
+                db_client.insert(
+                    "UPDATE post_tag_usage SET posts = posts + 1 WHERE kind = ? AND tag = ?",
+                    (POST_LANGUAGES, language)
+                ).await?;
+            }
+        }
+
+        let previous_terms = previous.map(post_terms).unwrap_or_default();
+        let current_terms = current.map(post_terms).unwrap_or_default();
+        for (field, term) in previous_terms.difference(&current_terms) {
+            db_client.insert(
+                "DELETE FROM post_search_terms WHERE term = ? AND post_id = ? AND field = ?",
+                (term, post_uuid, field.as_str())
+            ).await?;
+        }
+        for (field, term) in current_terms.difference(&previous_terms) {
+            db_client.insert(
+                "INSERT INTO post_search_terms (term, post_id, field) VALUES (?, ?, ?)",
+                (term, post_uuid, field.as_str())
+            ).await?;
+        }
+        Ok(())
+    }
+
+    /// Moves like totals kept in `posts.likes_count`, from before likes were
+    /// tracked per user, into `post_like_counts`. Each post's old total is
+    /// zeroed with a conditional write before it is added to the counter, so
+    /// a restart or a second instance never adds it twice; once every post
+    /// is moved this is a no-op. Runs at startup.
+    pub async fn migrate_legacy_likes(&self) -> Result<(), AppError> {
         let db_client = DbClient::<Post> {
             session: &self.session,
             _phantom: PhantomData,
         };
 
-        let user_uuid = Uuid::parse_str(&post.user_id)
+        let rows = db_client.query::<(Uuid, Option<i64>), ()>(
+            "SELECT id, likes_count FROM posts",
+            None
+        ).await?;
+
+        let mut migrated = 0;
+        for (post_id, likes_count) in rows {
+            let Some(likes_count) = likes_count.filter(|likes| *likes > 0) else { continue };
+            let claimed = db_client.apply(
+                "UPDATE posts SET likes_count = 0 WHERE id = ? IF likes_count = ?",
+                (post_id, likes_count)
+            ).await?;
+            if !claimed {
+                continue;
+            }
+            db_client.insert(
+                "UPDATE post_like_counts SET likes = likes + ? WHERE post_id = ?",
+                (Counter(likes_count), post_id)
+            ).await?;
+            migrated += 1;
+        }
+
+        if migrated > 0 {
+            info!("Moved the like totals of {} posts to post_like_counts", migrated);
+        }
+        Ok(())
+    }
+
+    /// Stores the current state of every post written before revisions
+    /// existed as its revision 1, so reading a history never has to write.
+    /// Runs at startup.
+    pub async fn seed_revisions(&self) -> Result<(), AppError> {
+        let db_client = DbClient::<Post> {
+            session: &self.session,
+            _phantom: PhantomData,
+        };
+
+        let seeded: HashSet<Uuid> = db_client.query::<(Uuid,), ()>(
+            "SELECT DISTINCT post_id FROM post_revisions",
+            None
+        ).await?
+            .into_iter()
+            .map(|(post_id,)| post_id)
+            .collect();
+
+        let rows = db_client.query::<PostRow, ()>(
+            "SELECT id, user_id, title, content, code, language, tags, created_at, updated_at, is_published, likes_count FROM posts",
+            None
+        ).await?;
+
+        let mut count = 0;
+        for row in rows.into_iter().filter(|row| !seeded.contains(&row.0)) {
+            if self.insert_revision(&post_from_row(row), 1, None).await? {
+                count += 1;
+            }
+        }
+
+        if count > 0 {
+            info!("Stored revision 1 for {} posts", count);
+        }
+        Ok(())
+    }
+
+    /// Fills the timeline tables from `posts` when they are empty, for posts
+    /// written before the timeline existed. Runs at startup.
+    pub async fn rebuild_timeline_if_empty(&self) -> Result<(), AppError> {
+        let db_client = DbClient::<Post> {
+            session: &self.session,
+            _phantom: PhantomData,
+        };
+
+        let existing = db_client.query::<(String,), ()>(
+            "SELECT bucket FROM post_timeline LIMIT 1",
+            None
+        ).await?;
+        if !existing.is_empty() {
+            return Ok(());
+        }
+
+        let posts = db_client.query::<(Uuid, Uuid, CqlTimestamp, bool), ()>(
+            "SELECT id, user_id, created_at, is_published FROM posts",
+            None
+        ).await?;
+        let published: Vec<_> = posts.into_iter().filter(|(_, _, _, is_published)| *is_published).collect();
+        if !published.is_empty() {
+            info!("Adding {} published posts to the timeline", published.len());
+        }
+        for (post_id, user_id, created_at, _) in published {
+            self.add_to_timeline(post_id, user_id, created_at.0).await?;
+        }
+        Ok(())
+    }
+
+    /// Builds the tag, language and search indexes from `posts` when they are
+    /// empty, for posts written before they existed. Runs at startup.
+    pub async fn rebuild_indexes_if_empty(&self) -> Result<(), AppError> {
+        let db_client = DbClient::<Post> {
+            session: &self.session,
+            _phantom: PhantomData,
+        };
+
+        let existing = db_client.query::<(String,), ()>(
+            "SELECT term FROM post_search_terms LIMIT 1",
+            None
+        ).await?;
+        if !existing.is_empty() {
+            return Ok(());
+        }
+
+        let rows = db_client.query::<PostRow, ()>(
+            "SELECT id, user_id, title, content, code, language, tags, created_at, updated_at, is_published, likes_count FROM posts",
+            None
+        ).await?;
+        let published: Vec<Post> = rows.into_iter().map(post_from_row).filter(|post| post.is_published).collect();
+        if !published.is_empty() {
+            info!("Indexing {} published posts for tags and search", published.len());
+        }
+        for post in &published {
+            self.update_indexes(None, Some(post)).await?;
+        }
+        Ok(())
+    }
+
+    /// Every published post, newest first. Walks the monthly timeline buckets
+    /// backwards from the cursor until the page is full or a year of empty
+    /// buckets suggests there is nothing older.
+    pub async fn latest_feed(&self, viewer_id: &str, cursor: Option<TimelineCursor>, limit: i32) -> Result<Page<PostResponse>, AppError> {
+        let db_client = DbClient::<Post> {
+            session: &self.session,
+            _phantom: PhantomData,
+        };
+
+        let mut position = cursor.unwrap_or_else(TimelineCursor::now);
+        let mut entries: Vec<(i64, Uuid)> = Vec::new();
+        let mut empty_buckets = 0;
+        let mut next_cursor = None;
+
+        for _ in 0..FEED_MAX_BUCKETS_PER_PAGE {
+            let wanted = limit - entries.len() as i32;
+            let rows = db_client.query::<(CqlTimestamp, Uuid), _>(
+                "SELECT created_at, post_id FROM post_timeline WHERE bucket = ? AND (created_at, post_id) < (?, ?) LIMIT ?",
+                Some((bucket_of(position.created_at), CqlTimestamp(position.created_at), position.post_id, wanted))
+            ).await?;
+            empty_buckets = if rows.is_empty() { empty_buckets + 1 } else { 0 };
+            entries.extend(rows.iter().map(|(created_at, post_id)| (created_at.0, *post_id)));
+
+            if entries.len() as i32 >= limit {
+                next_cursor = entries.last().map(|&(created_at, post_id)| TimelineCursor { created_at, post_id }.encode());
+                break;
+            }
+            if empty_buckets >= FEED_MAX_EMPTY_BUCKETS {
+                next_cursor = None;
+                break;
+            }
+            position = TimelineCursor::before_millis(bucket_start(position.created_at));
+            next_cursor = Some(position.encode());
+        }
+
+        let post_ids: Vec<Uuid> = entries.iter().map(|(_, post_id)| *post_id).collect();
+        Ok(Page {
+            items: self.load_published(&post_ids, Some(viewer_id)).await?,
+            next_cursor,
+        })
+    }
+
+    /// Published posts by the people `user_id` follows, and their own, newest
+    /// first. Each author's timeline is read from the cursor and the results
+    /// merged, so a page is one query per followed author.
+    pub async fn following_feed(&self, user_id: &str, cursor: Option<TimelineCursor>, limit: i32) -> Result<Page<PostResponse>, AppError> {
+        let mut author_ids = FollowService::new(self.session.clone()).following_ids(user_id).await?;
+        author_ids.truncate(FOLLOWING_FEED_MAX_AUTHORS);
+        author_ids.push(user_id.to_string());
+
+        let db_client = DbClient::<Post> {
+            session: &self.session,
+            _phantom: PhantomData,
+        };
+
+        let position = cursor.unwrap_or_else(TimelineCursor::now);
+        let mut entries: Vec<(i64, Uuid)> = Vec::new();
+        for author_id in &author_ids {
+            let author_uuid = Uuid::parse_str(author_id)
+                .map_err(|e| AppError(format!("Invalid user ID: {}", e), StatusCode::BAD_REQUEST))?;
+            let rows = db_client.query::<(CqlTimestamp, Uuid), _>(
+                "SELECT created_at, post_id FROM user_post_timeline WHERE user_id = ? AND (created_at, post_id) < (?, ?) LIMIT ?",
+                Some((author_uuid, CqlTimestamp(position.created_at), position.post_id, limit))
+            ).await?;
+            entries.extend(rows.into_iter().map(|(created_at, post_id)| (created_at.0, post_id)));
+        }
+
+        entries.sort_by(|a, b| b.cmp(a));
+        let has_more = entries.len() > limit as usize;
+        entries.truncate(limit as usize);
+        let next_cursor = if has_more {
+            entries.last().map(|&(created_at, post_id)| TimelineCursor { created_at, post_id }.encode())
+        } else {
+            None
+        };
+
+        let post_ids: Vec<Uuid> = entries.iter().map(|(_, post_id)| *post_id).collect();
+        Ok(Page {
+            items: self.load_published(&post_ids, Some(user_id)).await?,
+            next_cursor,
+        })
+    }
+
+    /// Recent posts ranked by [`trending_score`]. The ranking moves as posts
+    /// age and collect likes, so pages are addressed by offset into the
+    /// current ranking rather than by position in a timeline.
+    pub async fn trending_feed(&self, viewer_id: &str, offset: usize, limit: i32) -> Result<Page<PostResponse>, AppError> {
+        let db_client = DbClient::<Post> {
+            session: &self.session,
+            _phantom: PhantomData,
+        };
+
+        let now = Utc::now().timestamp_millis();
+        let since = now - TRENDING_WINDOW_DAYS * 24 * 3_600_000;
+        let mut post_ids: Vec<Uuid> = Vec::new();
+        let mut bucket_at = now;
+        while bucket_at >= bucket_start(since) && (post_ids.len() as i32) < TRENDING_CANDIDATES {
+            let rows = db_client.query::<(Uuid,), _>(
+                "SELECT post_id FROM post_timeline WHERE bucket = ? AND created_at >= ? LIMIT ?",
+                Some((bucket_of(bucket_at), CqlTimestamp(since), TRENDING_CANDIDATES - post_ids.len() as i32))
+            ).await?;
+            post_ids.extend(rows.into_iter().map(|(post_id,)| post_id));
+            bucket_at = bucket_start(bucket_at) - 1;
+        }
+
+        let mut ranked = self.load_published(&post_ids, Some(viewer_id)).await?;
+        ranked.sort_by(|a, b| {
+            let a_score = trending_score(a.post.likes_count, now - a.post.created_at * 1000);
+            let b_score = trending_score(b.post.likes_count, now - b.post.created_at * 1000);
+            b_score.total_cmp(&a_score).then_with(|| b.post.created_at.cmp(&a.post.created_at))
+        });
+
+        let end = offset + limit as usize;
+        let next_cursor = (ranked.len() > end).then(|| end.to_string());
+        Ok(Page {
+            items: ranked.into_iter().skip(offset).take(limit as usize).collect(),
+            next_cursor,
+        })
+    }
+
+    /// Published posts tagged `tag`, newest first.
+    pub async fn tag_feed(&self, tag: &str, viewer_id: &str, cursor: Option<TimelineCursor>, limit: i32) -> Result<Page<PostResponse>, AppError> {
+        let tag = normalize_tag(tag)
+            .ok_or_else(|| AppError(format!("Invalid tag: {:?}", tag), StatusCode::BAD_REQUEST))?;
+        self.indexed_feed(
+            "SELECT created_at, post_id FROM posts_by_tag WHERE tag = ? AND (created_at, post_id) < (?, ?) LIMIT ?",
+            &tag,
+            viewer_id,
+            cursor,
+            limit,
+        ).await
+    }
+
+    /// Published posts written in `language`, newest first.
+    pub async fn language_feed(&self, language: &str, viewer_id: &str, cursor: Option<TimelineCursor>, limit: i32) -> Result<Page<PostResponse>, AppError> {
+        let language = normalize_language(Some(language))?
+            .ok_or_else(|| AppError("Language cannot be empty".to_string(), StatusCode::BAD_REQUEST))?;
+        self.indexed_feed(
+            "SELECT created_at, post_id FROM posts_by_language WHERE language = ? AND (created_at, post_id) < (?, ?) LIMIT ?",
+            &language,
+            viewer_id,
+            cursor,
+            limit,
+        ).await
+    }
+
+    /// One page of a `posts_by_tag`-shaped index partition, read from the
+    /// cursor like a single author's timeline.
+    async fn indexed_feed(&self, query: &str, key: &str, viewer_id: &str, cursor: Option<TimelineCursor>, limit: i32) -> Result<Page<PostResponse>, AppError> {
+        let db_client = DbClient::<Post> {
+            session: &self.session,
+            _phantom: PhantomData,
+        };
+
+        let position = cursor.unwrap_or_else(TimelineCursor::now);
+        let rows = db_client.query::<(CqlTimestamp, Uuid), _>(
+            query,
+            Some((key, CqlTimestamp(position.created_at), position.post_id, limit))
+        ).await?;
+
+        let next_cursor = if rows.len() == limit as usize {
+            rows.last().map(|(created_at, post_id)| TimelineCursor { created_at: created_at.0, post_id: *post_id }.encode())
+        } else {
+            None
+        };
+
+        let post_ids: Vec<Uuid> = rows.iter().map(|(_, post_id)| *post_id).collect();
+        Ok(Page {
+            items: self.load_published(&post_ids, Some(viewer_id)).await?,
+            next_cursor,
+        })
+    }
+
+    /// The tags (or, with `kind` "language", the languages) used by the most
+    /// published posts. The usage partition is clustered by name, so all of
+    /// it is read, page by page, and ranked here.
+    pub async fn popular_tags(&self, kind: Option<&str>, limit: usize) -> Result<Vec<TagPopularity>, AppError> {
+        let kind = match kind.unwrap_or(POST_TAGS) {
+            kind @ (POST_TAGS | POST_LANGUAGES) => kind,
+            other => return Err(AppError(format!("Unknown tag kind: {}", other), StatusCode::BAD_REQUEST)),
+        };
+
+        let db_client = DbClient::<Post> {
+            session: &self.session,
+            _phantom: PhantomData,
+        };
+
+        let rows = db_client.query::<(String, Counter), _>(
+            "SELECT tag, posts FROM post_tag_usage WHERE kind = ?",
+            Some((kind,))
+        ).await?;
+
+        let mut tags: Vec<TagPopularity> = rows
+            .into_iter()
+            .filter(|(_, posts)| posts.0 > 0)
+            .map(|(tag, posts)| TagPopularity { tag, posts: posts.0 })
+            .collect();
+        tags.sort_by(|a, b| b.posts.cmp(&a.posts).then_with(|| a.tag.cmp(&b.tag)));
+        tags.truncate(limit);
+        Ok(tags)
+    }
+
+    /// Ranks published posts by how well their title, tags, content and code
+    /// match the query terms. Candidates come from the `post_search_terms`
+    /// index, one partition per term, so only the best-scoring few posts are
+    /// loaded. Like the trending feed, pages are offsets into the ranking.
+    pub async fn search(&self, viewer_id: &str, query: &PostSearchQuery, offset: usize, limit: i32) -> Result<Page<PostSearchResult>, AppError> {
+        let terms: Vec<String> = search::terms(&query.q).into_iter().take(MAX_SEARCH_TERMS).collect();
+        if terms.is_empty() {
+            return Err(AppError("Query must contain at least one search term".to_string(), StatusCode::BAD_REQUEST));
+        }
+        let tag = query.tag.as_deref()
+            .map(|tag| normalize_tag(tag).ok_or_else(|| AppError(format!("Invalid tag: {:?}", tag), StatusCode::BAD_REQUEST)))
+            .transpose()?;
+        let language = normalize_language(query.language.as_deref())?;
+
+        let db_client = DbClient::<Post> {
+            session: &self.session,
+            _phantom: PhantomData,
+        };
+
+        let mut scores: HashMap<Uuid, (u32, Vec<String>)> = HashMap::new();
+        for term in &terms {
+            let rows = db_client.query::<(Uuid, String), _>(
+                "SELECT post_id, field FROM post_search_terms WHERE term = ? LIMIT ?",
+                Some((term, SEARCH_TERM_FANOUT))
+            ).await?;
+            for (post_id, field) in rows {
+                let Some(field) = SearchField::from_column(&field) else { continue };
+                let (score, matched_terms) = scores.entry(post_id).or_default();
+                *score += field.weight();
+                if !matched_terms.contains(term) {
+                    matched_terms.push(term.clone());
+                }
+            }
+        }
+
+        if tag.is_some() || language.is_some() {
+            let post_ids: Vec<Uuid> = scores.keys().copied().collect();
+            let kept = self.filter_candidates(&post_ids, tag.as_deref(), language.as_deref()).await?;
+            scores.retain(|post_id, _| kept.contains(post_id));
+        }
+
+        let mut ranked: Vec<(Uuid, (u32, Vec<String>))> = scores.into_iter().collect();
+        ranked.sort_by(|(a_id, (a_score, a_terms)), (b_id, (b_score, b_terms))| {
+            b_score.cmp(a_score)
+                .then_with(|| b_terms.len().cmp(&a_terms.len()))
+                .then_with(|| a_id.cmp(b_id))
+        });
+        ranked.truncate(SEARCH_CANDIDATE_LIMIT);
+
+        let post_ids: Vec<Uuid> = ranked.iter().map(|(post_id, _)| *post_id).collect();
+        let mut scores: HashMap<String, (u32, Vec<String>)> = ranked
+            .into_iter()
+            .map(|(post_id, score)| (post_id.to_string(), score))
+            .collect();
+
+        let mut results: Vec<PostSearchResult> = self.load_published(&post_ids, Some(viewer_id)).await?
+            .into_iter()
+            .filter_map(|response| {
+                let (score, matched_terms) = scores.remove(&response.post.id)?;
+                Some(PostSearchResult { post: response, score, matched_terms })
+            })
+            .collect();
+        // Among equally good matches, newer posts come first.
+        results.sort_by(|a, b| {
+            b.score.cmp(&a.score)
+                .then_with(|| b.matched_terms.len().cmp(&a.matched_terms.len()))
+                .then_with(|| b.post.post.created_at.cmp(&a.post.post.created_at))
+        });
+
+        let end = offset + limit as usize;
+        let next_cursor = (results.len() > end).then(|| end.to_string());
+        Ok(Page {
+            items: results.into_iter().skip(offset).take(limit as usize).collect(),
+            next_cursor,
+        })
+    }
+
+    /// Ids among `post_ids` of published posts carrying `tag` and written in
+    /// `language`. Only those columns are read, so search can filter every
+    /// candidate before cutting the ranking down to `SEARCH_CANDIDATE_LIMIT`.
+    async fn filter_candidates(&self, post_ids: &[Uuid], tag: Option<&str>, language: Option<&str>) -> Result<HashSet<Uuid>, AppError> {
+        let db_client = DbClient::<Post> {
+            session: &self.session,
+            _phantom: PhantomData,
+        };
+
+        let mut kept = HashSet::new();
+        for chunk in post_ids.chunks(MAX_KEYS_PER_QUERY) {
+            let rows = db_client.query::<(Uuid, Option<Vec<String>>, Option<String>, bool), _>(
+                "SELECT id, tags, language, is_published FROM posts WHERE id IN ?",
+                Some((chunk.to_vec(),))
+            ).await?;
+            kept.extend(rows
+                .into_iter()
+                .filter(|(_, tags, post_language, is_published)| {
+                    *is_published
+                        && tag.is_none_or(|tag| tags.iter().flatten().filter_map(|t| normalize_tag(t)).any(|t| t == tag))
+                        && (language.is_none() || post_language.as_deref().and_then(normalize_tag).as_deref() == language)
+                })
+                .map(|(post_id, ..)| post_id));
+        }
+        Ok(kept)
+    }
+
+    /// Likes the post for `user_id`, or takes the like back if they already
+    /// liked it. The like row is written with a lightweight transaction so
+    /// concurrent toggles cannot count the same user twice, and the total is
+    /// only moved when the row actually changed.
+    pub async fn toggle_like_post(&self, post_id: &str, user_id: &str) -> Result<PostResponse, AppError> {
+        let post_uuid = Uuid::parse_str(post_id)
+            .map_err(|e| AppError(format!("Invalid post ID: {}", e), StatusCode::BAD_REQUEST))?;
+        let user_uuid = Uuid::parse_str(user_id)
             .map_err(|e| AppError(format!("Invalid user ID: {}", e), StatusCode::BAD_REQUEST))?;
 
-        db_client.insert(
-            "INSERT INTO posts (id, user_id, title, content, code, language, tags, created_at, updated_at, is_published, likes_count) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
-            (
-                post_uuid,
-                user_uuid,
-                &post.title,
-                &post.content,
-                &post.code,
-                &post.language,
-                &post.tags,
-                CqlTimestamp(post.created_at * 1000),
-                CqlTimestamp(post.updated_at * 1000),
-                post.is_published,
-                post.likes_count,
-            )
+        // Only existing posts can be liked.
+        self.get_post_by_id(post_id, None).await?;
+
+        let db_client = DbClient::<Post> {
+            session: &self.session,
+            _phantom: PhantomData,
+        };
+
+        let liked = db_client.apply(
+            "INSERT INTO post_likes (post_id, user_id, created_at) VALUES (?, ?, ?) IF NOT EXISTS",
+            (post_uuid, user_uuid, CqlTimestamp(Utc::now().timestamp_millis()))
         ).await?;
 
-        Ok(post)
+        if liked {
+            db_client.insert(
+                "UPDATE post_like_counts SET likes = likes + 1 WHERE post_id = ?",
+                (post_uuid,)
+            ).await?;
+        } else if db_client.apply(
+            "DELETE FROM post_likes WHERE post_id = ? AND user_id = ? IF EXISTS",
+            (post_uuid, user_uuid)
+        ).await? {
+            db_client.insert(
+                "UPDATE post_like_counts SET likes = likes - 1 WHERE post_id = ?",
+                (post_uuid,)
+            ).await?;
+        }
+
+        self.get_post_by_id(post_id, Some(user_id)).await
     }
 }
@@ -1,28 +1,17 @@
 use actix_web::{web, HttpRequest, HttpResponse, Responder};
 use bcrypt::verify;
-use jsonwebtoken::{encode, Header, EncodingKey};
-use chrono::{Utc, Duration};
 use scylla::client::session::Session;
-use serde_json::json;
 use crate::error::AppError;
-use crate::models::user::{NewUser, LoginRequest, AuthResponse, UpdateProfileRequest};
-use crate::utils::jwt::get_user_id_from_token;
-use serde::{Serialize, Deserialize};
+use crate::models::user::{NewUser, LoginRequest, AuthResponse, RefreshRequest, TokenResponse, UpdateProfileRequest, MatchUsersQuery, TagCatalogQuery};
+use crate::utils::jwt::{AuthenticatedUser, Authenticator};
+use crate::utils::pagination::clamp_page_size;
+use crate::utils::sessions::{DeviceInfo, SessionStore};
 use crate::users::service;
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct UserId {
-    pub id: String,
-}
-
-#[derive(Debug, Serialize, Deserialize)]
-pub struct Claims {
-    pub sub: String,
-    pub exp: i64,
-}
-
 pub async fn register(
     session: web::Data<Session>,
+    authenticator: web::Data<Authenticator>,
+    req: HttpRequest,
     new_user: web::Json<NewUser>,
 ) -> Result<impl Responder, AppError> {
     if let Some(_) = service::find_by_email(&session, &new_user.email).await? {
@@ -31,18 +20,25 @@ pub async fn register(
 
     let user = service::create(&session, new_user.into_inner()).await?;
 
-    let token = generate_token(&user.id)?;
+    let (auth_session, refresh_token) = SessionStore::new(session.clone())
+        .create(&user.id, DeviceInfo::from_request(&req, None))
+        .await?;
+    let token = authenticator.issue_access_token(&user.id, &auth_session.id)?;
     
     Ok(HttpResponse::Created().json(AuthResponse {
         token,
+        refresh_token,
         user: user,
     }))
 }
 
 pub async fn login(
     session: web::Data<Session>,
+    authenticator: web::Data<Authenticator>,
+    req: HttpRequest,
     credentials: web::Json<LoginRequest>,
 ) -> Result<impl Responder, AppError> {
+    let credentials = credentials.into_inner();
     let user = service::find_by_email(&session, &credentials.email).await?
         .ok_or_else(|| AppError("Invalid email or password".to_string(), actix_web::http::StatusCode::UNAUTHORIZED))?;
     log::info!("User: {:?}", user);
@@ -51,16 +47,48 @@ pub async fn login(
         return Err(AppError("Invalid email or password".to_string(), actix_web::http::StatusCode::UNAUTHORIZED));
     }
 
-    service::update_user_status(&session, &user.id, true).await?;
-
-    let token = generate_token(&user.id)?;
+    let (auth_session, refresh_token) = SessionStore::new(session.clone())
+        .create(&user.id, DeviceInfo::from_request(&req, credentials.device_name))
+        .await?;
+    let token = authenticator.issue_access_token(&user.id, &auth_session.id)?;
     log::info!("Token: {:?}", token);
     Ok(HttpResponse::Ok().json(AuthResponse {
         token,
+        refresh_token,
         user: user,
     }))
 }
 
+/// Trades a refresh token for a new access token and a new refresh token.
+/// The presented refresh token is spent.
+pub async fn refresh(
+    session: web::Data<Session>,
+    authenticator: web::Data<Authenticator>,
+    body: web::Json<RefreshRequest>,
+) -> Result<impl Responder, AppError> {
+    let (auth_session, refresh_token) = SessionStore::new(session.clone())
+        .rotate(&body.refresh_token)
+        .await?;
+    let token = authenticator.issue_access_token(&auth_session.user_id, &auth_session.id)?;
+
+    Ok(HttpResponse::Ok().json(TokenResponse {
+        token,
+        refresh_token,
+    }))
+}
+
+/// Revokes the session behind a refresh token; access tokens issued for it
+/// stop working immediately.
+pub async fn logout(
+    session: web::Data<Session>,
+    body: web::Json<RefreshRequest>,
+) -> Result<impl Responder, AppError> {
+    SessionStore::new(session.clone())
+        .revoke_by_refresh_token(&body.refresh_token)
+        .await?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
 pub async fn get_profile(
     session: web::Data<Session>,
     user_id: web::Path<String>
@@ -73,10 +101,10 @@ pub async fn get_profile(
 
 pub async fn update_profile(
     session: web::Data<Session>,
-    req: HttpRequest,
+    user: AuthenticatedUser,
     update_data: web::Json<UpdateProfileRequest>,
 ) -> Result<impl Responder, AppError> {
-    let user_id = get_user_id_from_token(&req)?;
+    let user_id = user.user_id;
     let updated = service::update_profile(&session, &user_id, update_data.into_inner()).await?;
     log::debug!("Updated user: {:?}", updated);
     Ok(HttpResponse::Ok().json(updated.to_profile()))
@@ -90,40 +118,31 @@ pub async fn search_users(
     Ok(HttpResponse::Ok().json(users))
 } 
 
-pub async fn logout(
+pub async fn match_users(
     session: web::Data<Session>,
-    user_id: web::Json<UserId>,  
+    user: AuthenticatedUser,
+    query: web::Query<MatchUsersQuery>,
 ) -> Result<impl Responder, AppError> {
-    service::update_user_status(&session, &user_id.id, false).await?;
-    Ok(HttpResponse::Ok().finish())
+    let limit = clamp_page_size(query.limit) as usize;
+    let matches = service::match_users(&session, &user.user_id, &query, limit).await?;
+    Ok(HttpResponse::Ok().json(matches))
 }
 
-pub async fn get_me(
+pub async fn tag_catalog(
     session: web::Data<Session>,
-    req: HttpRequest,
+    _user: AuthenticatedUser,
+    query: web::Query<TagCatalogQuery>,
 ) -> Result<impl Responder, AppError> {
-    let auth_header = req.headers()
-        .get("Authorization")
-        .ok_or_else(|| AppError("Missing Authorization header".to_string(), actix_web::http::StatusCode::UNAUTHORIZED))?
-        .to_str()
-        .map_err(|_| AppError("Invalid Authorization header".to_string(), actix_web::http::StatusCode::UNAUTHORIZED))?;
-
-    let token = auth_header
-        .strip_prefix("Bearer ")
-        .ok_or_else(|| AppError("Invalid token format".to_string(), actix_web::http::StatusCode::UNAUTHORIZED))?;
-
-    let secret = std::env::var("JWT_SECRET").unwrap_or_else(|_| "your-secret-key".to_string());
-    let validation = jsonwebtoken::Validation::default();
-    
-    let claims = jsonwebtoken::decode::<Claims>(
-        token,
-        &jsonwebtoken::DecodingKey::from_secret(secret.as_bytes()),
-        &validation,
-    )
-    .map_err(|e| AppError(format!("Invalid token: {}", e), actix_web::http::StatusCode::UNAUTHORIZED))?
-    .claims;
+    let limit = clamp_page_size(query.limit) as usize;
+    let suggestions = service::tag_catalog(&session, &query, limit).await?;
+    Ok(HttpResponse::Ok().json(suggestions))
+}
 
-    let user = service::find_by_id(&session, &claims.sub).await?
+pub async fn get_me(
+    session: web::Data<Session>,
+    user: AuthenticatedUser,
+) -> Result<impl Responder, AppError> {
+    let user = service::find_by_id(&session, &user.user_id).await?
         .ok_or_else(|| AppError("User not found".to_string(), actix_web::http::StatusCode::NOT_FOUND))?;
 
     Ok(HttpResponse::Ok().json(user.to_profile()))
@@ -136,34 +155,7 @@ pub async fn get_all_users(
     Ok(HttpResponse::Ok().json(users))
 }
 
-pub async fn set_user_online(
-    session: web::Data<Session>,
-    user_id: web::Json<UserId>,
-) -> Result<impl Responder, AppError> {
-    service::update_user_status(&session, &user_id.id, true).await?;
-    Ok(HttpResponse::Ok().json(json!({ "status": "online" })))
-}
-
 #[derive(serde::Deserialize)]
 pub struct SearchQuery {
     q: String,
 }
-
-fn generate_token(user_id: &str) -> Result<String, AppError> {
-    let expiration = Utc::now()
-        .checked_add_signed(Duration::hours(24))
-        .expect("valid timestamp")
-        .timestamp();
-
-    let claims = Claims {
-        sub: user_id.to_owned(),
-        exp: expiration,
-    };
-
-    let secret = std::env::var("JWT_SECRET").unwrap_or_else(|_| "your-secret-key".to_string());
-    encode(
-        &Header::default(),
-        &claims,
-        &EncodingKey::from_secret(secret.as_bytes()),
-    ).map_err(|e| AppError(format!("Token generation error: {}", e), actix_web::http::StatusCode::INTERNAL_SERVER_ERROR))
-}
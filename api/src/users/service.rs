@@ -3,19 +3,82 @@ use scylla::client::session::Session;
 use actix_web::http::StatusCode;
 use scylla::value::CqlTimestamp;
 use uuid::Uuid;
-use chrono::Utc;
+use chrono::{DateTime, Utc};
+use log::info;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::marker::PhantomData;
 use crate::{
     error::AppError, 
-    models::user::{NewUser, User, UserProfile},
-    utils::db_client::DbClient
+    follows::service::FollowService,
+    models::user::{Interest, MatchUsersQuery, NewUser, SkillLevel, TagCatalogQuery, TagSuggestion, User, UserMatch, UserProfile},
+    utils::db_client::DbClient,
+    utils::matching::{self, MatchField, MAX_QUERY_TERMS},
+    utils::terms::IndexField,
+    utils::tags::{normalize_tag, normalize_tags}
 };
+use scylla::value::Counter;
+
+pub const MAX_INTERESTS: usize = 20;
+pub const MAX_LANGUAGES: usize = 10;
+
+/// Tag kinds kept in the `tag_usage` catalog.
+pub const INTEREST_TAGS: &str = "interest";
+pub const LANGUAGE_TAGS: &str = "language";
+
+/// How many index entries a single query term may pull in.
+const MATCH_TERM_FANOUT: i32 = 1000;
+/// How many of the best-scoring candidates are loaded to apply the online and
+/// country filters before giving up on filling the page.
+const MATCH_CANDIDATE_LIMIT: usize = 200;
+
+/// Interests are stored as a `tag -> level` map; a tag without a level maps to
+/// an empty string because collection values cannot be null.
+fn interests_to_column(interests: &[Interest]) -> BTreeMap<String, String> {
+    interests
+        .iter()
+        .map(|interest| (interest.tag.clone(), interest.level.map(|level| level.as_str()).unwrap_or_default().to_string()))
+        .collect()
+}
+
+fn interests_from_column(column: Option<BTreeMap<String, String>>) -> Vec<Interest> {
+    column
+        .unwrap_or_default()
+        .into_iter()
+        .map(|(tag, level)| Interest { tag, level: SkillLevel::from_column(&level) })
+        .collect()
+}
+
+/// De-duplicates interests by tag, the last level given for a tag winning.
+fn dedupe_interests(interests: Vec<Interest>) -> Vec<Interest> {
+    let mut deduped: Vec<Interest> = Vec::with_capacity(interests.len());
+    for interest in interests {
+        match deduped.iter_mut().find(|existing| existing.tag == interest.tag) {
+            Some(existing) => existing.level = interest.level,
+            None => deduped.push(interest),
+        }
+    }
+    deduped
+}
+
+fn normalize_interests(interests: Vec<Interest>) -> Result<Vec<Interest>, AppError> {
+    let normalized = dedupe_interests(interests);
+    if normalized.len() > MAX_INTERESTS {
+        return Err(AppError(format!("At most {} interests are allowed", MAX_INTERESTS), StatusCode::BAD_REQUEST));
+    }
+    Ok(normalized)
+}
+
+fn interest_tags(user: &User) -> Vec<String> {
+    user.interests.iter().map(|interest| interest.tag.clone()).collect()
+}
 
 pub async fn create(session: &web::Data<Session>, new_user: NewUser) -> Result<User, AppError> {
     let id = Uuid::new_v4();
     let now = Utc::now().timestamp();
     let password_hash = bcrypt::hash(&new_user.password, bcrypt::DEFAULT_COST)
         .map_err(|e| AppError(format!("Password hashing error: {}", e), StatusCode::INTERNAL_SERVER_ERROR))?;
+    let interests = normalize_interests(new_user.interests)?;
+    let languages = normalize_tags(&new_user.languages, MAX_LANGUAGES)?;
 
     let db_client = DbClient::<User> { 
         session, 
@@ -23,7 +86,7 @@ pub async fn create(session: &web::Data<Session>, new_user: NewUser) -> Result<U
     };  
 
     db_client.insert(
-        "INSERT INTO users (id, username, email, password_hash, created_at, updated_at, last_seen_at, is_online, interests, language, profile_image_url, home_country, project_building) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+        "INSERT INTO users (id, username, email, password_hash, created_at, updated_at, last_seen_at, is_online, interest_tags, languages, profile_image_url, home_country, project_building) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
         (
             &id, 
             &new_user.username, 
@@ -33,25 +96,30 @@ pub async fn create(session: &web::Data<Session>, new_user: NewUser) -> Result<U
             CqlTimestamp(now), 
             None::<CqlTimestamp>, 
             false, 
-            &new_user.interests, 
-            &new_user.language,
+            interests_to_column(&interests), 
+            &languages,
             &new_user.profile_image_url,
             &new_user.home_country,
             &new_user.project_building
         )
     ).await?;
 
-    Ok(User::new(
+    let user = User::new(
         id.to_string(),
         new_user.username,
         new_user.email,
         password_hash,
-        new_user.interests,
-        new_user.language,
+        interests,
+        languages,
         new_user.profile_image_url,
         new_user.home_country,
         new_user.project_building
-    ))
+    );
+    index_match_terms(session, id, &HashSet::new(), &matching::profile_terms(&user)).await?;
+    update_tag_usage(session, INTEREST_TAGS, &[], &interest_tags(&user)).await?;
+    update_tag_usage(session, LANGUAGE_TAGS, &[], &user.languages).await?;
+
+    Ok(user)
 }
 
 pub async fn find_by_email(session: &web::Data<Session>, email: &str) -> Result<Option<User>, AppError> {
@@ -60,13 +128,13 @@ pub async fn find_by_email(session: &web::Data<Session>, email: &str) -> Result<
         _phantom: PhantomData 
     };
 
-    let results = db_client.query::<(Uuid, String, String, String, CqlTimestamp, CqlTimestamp, Option<CqlTimestamp>, bool, Option<String>, Option<String>, Option<String>, Option<String>, Option<String>), _>(
-        "SELECT id, username, email, password_hash, created_at, updated_at, last_seen_at, is_online, interests, language, profile_image_url, home_country, project_building FROM users WHERE email = ?",
+    let results = db_client.query::<(Uuid, String, String, String, CqlTimestamp, CqlTimestamp, Option<CqlTimestamp>, bool, Option<BTreeMap<String, String>>, Option<Vec<String>>, Option<String>, Option<String>, Option<String>), _>(
+        "SELECT id, username, email, password_hash, created_at, updated_at, last_seen_at, is_online, interest_tags, languages, profile_image_url, home_country, project_building FROM users WHERE email = ?",
         Some((email,))
     ).await?;
 
     if let Some(row) = results.first() {
-        let (id, username, email, password_hash, created_at, updated_at, last_seen_at, is_online, interests, language, profile_image_url, home_country, project_building) = row.clone();
+        let (id, username, email, password_hash, created_at, updated_at, last_seen_at, is_online, interests, languages, profile_image_url, home_country, project_building) = row.clone();
         Ok(Some(User {
             id: id.to_string(),
             username,
@@ -76,8 +144,8 @@ pub async fn find_by_email(session: &web::Data<Session>, email: &str) -> Result<
             updated_at: updated_at.0,   
             last_seen_at: last_seen_at.map(|ts| ts.0),
             is_online,
-            interests,
-            language,
+            interests: interests_from_column(interests),
+            languages: languages.unwrap_or_default(),
             profile_image_url,
             home_country,
             project_building,
@@ -95,13 +163,13 @@ pub async fn find_by_id(session: &web::Data<Session>, id: &str) -> Result<Option
         _phantom: PhantomData 
     };
 
-    let results = db_client.query::<(Uuid, String, String, String, CqlTimestamp, CqlTimestamp, Option<CqlTimestamp>, bool, Option<String>, Option<String>, Option<String>, Option<String>, Option<String>), _>(
-        "SELECT id, username, email, password_hash, created_at, updated_at, last_seen_at, is_online, interests, language, profile_image_url, home_country, project_building FROM users WHERE id = ?",
+    let results = db_client.query::<(Uuid, String, String, String, CqlTimestamp, CqlTimestamp, Option<CqlTimestamp>, bool, Option<BTreeMap<String, String>>, Option<Vec<String>>, Option<String>, Option<String>, Option<String>), _>(
+        "SELECT id, username, email, password_hash, created_at, updated_at, last_seen_at, is_online, interest_tags, languages, profile_image_url, home_country, project_building FROM users WHERE id = ?",
         Some((uuid,))
     ).await?;
 
     if let Some(row) = results.first() {
-        let (id, username, email, password_hash, created_at, updated_at, last_seen_at, is_online, interests, language, profile_image_url, home_country, project_building) = row.clone();
+        let (id, username, email, password_hash, created_at, updated_at, last_seen_at, is_online, interests, languages, profile_image_url, home_country, project_building) = row.clone();
         Ok(Some(User {
             id: id.to_string(),
             username,
@@ -111,8 +179,8 @@ pub async fn find_by_id(session: &web::Data<Session>, id: &str) -> Result<Option
             updated_at: updated_at.0,
             last_seen_at: last_seen_at.map(|ts| ts.0),
             is_online,
-            interests,
-            language,
+            interests: interests_from_column(interests),
+            languages: languages.unwrap_or_default(),
             profile_image_url,
             home_country,
             project_building,
@@ -129,6 +197,14 @@ pub async fn update_profile(session: &web::Data<Session>, id: &str, update_data:
 
     let current_user = find_by_id(session, id).await?
         .ok_or_else(|| AppError("User not found".to_string(), StatusCode::NOT_FOUND))?;
+    let interests = match update_data.interests {
+        Some(interests) => normalize_interests(interests)?,
+        None => current_user.interests.clone(),
+    };
+    let languages = match &update_data.languages {
+        Some(languages) => normalize_tags(languages, MAX_LANGUAGES)?,
+        None => current_user.languages.clone(),
+    };
 
     let db_client = DbClient::<User> { 
         session, 
@@ -136,15 +212,15 @@ pub async fn update_profile(session: &web::Data<Session>, id: &str, update_data:
     };
 
     db_client.insert(
-        "UPDATE users SET username = ?, email = ?, interests = ?, language = ?, profile_image_url = ?, home_country = ?, project_building = ?, updated_at = ? WHERE id = ?",
+        "UPDATE users SET username = ?, email = ?, interest_tags = ?, languages = ?, profile_image_url = ?, home_country = ?, project_building = ?, updated_at = ? WHERE id = ?",
         (
             update_data.username.as_ref().unwrap_or(&current_user.username),
             update_data.email.as_ref().unwrap_or(&current_user.email),
-            &update_data.interests.or(current_user.interests),
-            &update_data.language.or(current_user.language),
-            &update_data.profile_image_url.or(current_user.profile_image_url),
-            &update_data.home_country.or(current_user.home_country),
-            &update_data.project_building.or(current_user.project_building),
+            interests_to_column(&interests),
+            &languages,
+            update_data.profile_image_url.as_ref().or(current_user.profile_image_url.as_ref()),
+            update_data.home_country.as_ref().or(current_user.home_country.as_ref()),
+            update_data.project_building.as_ref().or(current_user.project_building.as_ref()),
             CqlTimestamp(now),
             uuid
         )
@@ -161,7 +237,12 @@ pub async fn update_profile(session: &web::Data<Session>, id: &str, update_data:
     }
 
     match find_by_id(session, id).await? {
-        Some(user) => Ok(user),
+        Some(user) => {
+            index_match_terms(session, uuid, &matching::profile_terms(&current_user), &matching::profile_terms(&user)).await?;
+            update_tag_usage(session, INTEREST_TAGS, &interest_tags(&current_user), &interest_tags(&user)).await?;
+            update_tag_usage(session, LANGUAGE_TAGS, &current_user.languages, &user.languages).await?;
+            Ok(user)
+        }
         None => Err(AppError("Failed to retrieve updated user".to_string(), StatusCode::INTERNAL_SERVER_ERROR)),
     }
 }
@@ -171,6 +252,9 @@ pub async fn update_user(session: &web::Data<Session>, id: &str, updated_user: N
     let now = Utc::now().timestamp();
     let password_hash = bcrypt::hash(&updated_user.password, bcrypt::DEFAULT_COST)
         .map_err(|e| AppError(format!("Password hashing error: {}", e), StatusCode::INTERNAL_SERVER_ERROR))?;
+    let previous_terms = find_by_id(session, id).await?
+        .map(|user| matching::profile_terms(&user))
+        .unwrap_or_default();
 
     let db_client = DbClient::<User> { 
         session, 
@@ -183,14 +267,23 @@ pub async fn update_user(session: &web::Data<Session>, id: &str, updated_user: N
     ).await?;
 
     match find_by_id(session, id).await? {
-        Some(user) => Ok(user),
+        Some(user) => {
+            index_match_terms(session, uuid, &previous_terms, &matching::profile_terms(&user)).await?;
+            Ok(user)
+        }
         None => Err(AppError("Failed to retrieve updated user".to_string(), StatusCode::INTERNAL_SERVER_ERROR)),
     }
 }
 
 pub async fn update_user_status(session: &web::Data<Session>, id: &str, is_online: bool) -> Result<(), AppError> {
+    update_user_status_at(session, id, is_online, Utc::now()).await
+}
+
+/// [`update_user_status`] as of `at`. The write is stamped with `at`, so when
+/// two status changes race to the database the later one wins whichever
+/// arrives first.
+pub async fn update_user_status_at(session: &web::Data<Session>, id: &str, is_online: bool, at: DateTime<Utc>) -> Result<(), AppError> {
     let uuid = Uuid::parse_str(id).map_err(|e| AppError(format!("Invalid UUID format: {}", e), StatusCode::BAD_REQUEST))?;
-    let now = Utc::now().timestamp();
 
     let db_client = DbClient::<User> { 
         session, 
@@ -198,14 +291,20 @@ pub async fn update_user_status(session: &web::Data<Session>, id: &str, is_onlin
     };
 
     db_client.insert(
-        "UPDATE users SET is_online = ?, last_seen_at = ? WHERE id = ?",
-        (is_online, CqlTimestamp(now), uuid)
+        "UPDATE users USING TIMESTAMP ? SET is_online = ?, last_seen_at = ? WHERE id = ?",
+        (at.timestamp_micros(), is_online, CqlTimestamp(at.timestamp()), uuid)
     ).await
 }
 
 pub async fn delete_user(session: &web::Data<Session>, id: &str) -> Result<(), AppError> {
     let uuid = Uuid::parse_str(id).map_err(|e| AppError(format!("Invalid UUID format: {}", e), StatusCode::BAD_REQUEST))?;
 
+    if let Some(user) = find_by_id(session, id).await? {
+        index_match_terms(session, uuid, &matching::profile_terms(&user), &HashSet::new()).await?;
+        update_tag_usage(session, INTEREST_TAGS, &interest_tags(&user), &[]).await?;
+        update_tag_usage(session, LANGUAGE_TAGS, &user.languages, &[]).await?;
+    }
+
     let db_client = DbClient::<User> { 
         session, 
         _phantom: PhantomData 
@@ -226,15 +325,15 @@ pub async fn search_users(
         _phantom: PhantomData 
     };
 
-    let results = db_client.query::<(Uuid, String, String, CqlTimestamp, Option<CqlTimestamp>, bool, Option<String>, Option<String>), _>(
-        "SELECT id, username, email, created_at, last_seen_at, is_online, interests, language FROM users",
+    let results = db_client.query::<(Uuid, String, String, CqlTimestamp, Option<CqlTimestamp>, bool, Option<BTreeMap<String, String>>, Option<Vec<String>>), _>(
+        "SELECT id, username, email, created_at, last_seen_at, is_online, interest_tags, languages FROM users",
         None::<()>
     ).await?;   
 
     let users = results
         .into_iter()
         .filter(|(_, username, email, _, _, _, _, _)| username.contains(path.as_str().clone()) || email.contains(path.as_str().clone()))
-        .map(|(id, username, email, created_at, last_seen_at, is_online, interests, language)| {
+        .map(|(id, username, email, created_at, last_seen_at, is_online, interests, languages)| {
             UserProfile {
                 id: id.to_string(),
                 username,
@@ -242,8 +341,8 @@ pub async fn search_users(
                 created_at: created_at.0,
                 last_seen_at: last_seen_at.map(|ts| ts.0),
                 is_online,
-                interests,
-                language,
+                interests: interests_from_column(interests),
+                languages: languages.unwrap_or_default(),
                 profile_image_url: None,
                 home_country: None,
                 project_building: None,
@@ -254,20 +353,280 @@ pub async fn search_users(
     Ok(users)
 }
 
+/// Brings the `user_match_terms` index from `previous` to `current`, touching
+/// only the entries that changed.
+async fn index_match_terms(
+    session: &web::Data<Session>,
+    user_id: Uuid,
+    previous: &HashSet<(MatchField, String)>,
+    current: &HashSet<(MatchField, String)>,
+) -> Result<(), AppError> {
+    let db_client = DbClient::<User> { 
+        session, 
+        _phantom: PhantomData 
+    };
+
+    for (field, term) in previous.difference(current) {
+        db_client.insert(
+            "DELETE FROM user_match_terms WHERE term = ? AND user_id = ? AND field = ?",
+            (term, user_id, field.as_str())
+        ).await?;
+    }
+    for (field, term) in current.difference(previous) {
+        db_client.insert(
+            "INSERT INTO user_match_terms (term, user_id, field) VALUES (?, ?, ?)",
+            (term, user_id, field.as_str())
+        ).await?;
+    }
+    Ok(())
+}
+
+/// Adjusts the per-tag user counts behind the tag catalog.
+async fn update_tag_usage(session: &web::Data<Session>, kind: &str, previous: &[String], current: &[String]) -> Result<(), AppError> {
+    let db_client = DbClient::<User> { 
+        session, 
+        _phantom: PhantomData 
+    };
+
+    for tag in previous.iter().filter(|tag| !current.contains(tag)) {
+        db_client.insert(
+            "UPDATE tag_usage SET users = users - 1 WHERE kind = ? AND tag = ?",
+            (kind, tag)
+        ).await?;
+    }
+    for tag in current.iter().filter(|tag| !previous.contains(tag)) {
+        db_client.insert(
+            "UPDATE tag_usage SET users = users + 1 WHERE kind = ? AND tag = ?",
+            (kind, tag)
+        ).await?;
+    }
+    Ok(())
+}
+
+/// Autocompletes interest or language tags. Tags are clustered by name within
+/// their kind's partition, so a prefix is a single range read (and an empty one
+/// the whole partition, read page by page); the matches are then ranked by how
+/// many users carry them.
+pub async fn tag_catalog(session: &web::Data<Session>, query: &TagCatalogQuery, limit: usize) -> Result<Vec<TagSuggestion>, AppError> {
+    let kind = match query.kind.as_deref().unwrap_or(INTEREST_TAGS) {
+        kind @ (INTEREST_TAGS | LANGUAGE_TAGS) => kind,
+        other => return Err(AppError(format!("Unknown tag kind: {}", other), StatusCode::BAD_REQUEST)),
+    };
+
+    let db_client = DbClient::<User> { 
+        session, 
+        _phantom: PhantomData 
+    };
+
+    let rows = if query.q.trim().is_empty() {
+        db_client.query::<(String, Counter), _>(
+            "SELECT tag, users FROM tag_usage WHERE kind = ?",
+            Some((kind,))
+        ).await?
+    } else {
+        // Normalize what has been typed so far the same way tags are stored;
+        // a trailing space still means "more words to come".
+        let prefix = normalize_tag(&query.q)
+            .map(|tag| if query.q.ends_with(char::is_whitespace) { format!("{}-", tag) } else { tag })
+            .ok_or_else(|| AppError(format!("Invalid tag: {:?}", query.q), StatusCode::BAD_REQUEST))?;
+        db_client.query::<(String, Counter), _>(
+            "SELECT tag, users FROM tag_usage WHERE kind = ? AND tag >= ? AND tag < ?",
+            Some((kind, &prefix, format!("{}{}", prefix, char::MAX)))
+        ).await?
+    };
+
+    let mut suggestions: Vec<TagSuggestion> = rows
+        .into_iter()
+        .filter(|(_, users)| users.0 > 0)
+        .map(|(tag, users)| TagSuggestion { tag, users: users.0 })
+        .collect();
+    suggestions.sort_by(|a, b| b.users.cmp(&a.users).then_with(|| a.tag.cmp(&b.tag)));
+    suggestions.truncate(limit);
+    Ok(suggestions)
+}
+
+/// Moves users off the old free-text `interests` and `language` columns:
+/// comma-separated values become normalized tags (entries like "rust:expert"
+/// keep their level, unreadable ones are dropped) and the old columns are
+/// cleared, so this is a no-op once every user has been converted.
+pub async fn migrate_legacy_interests(session: &web::Data<Session>) -> Result<(), AppError> {
+    let db_client = DbClient::<User> { 
+        session, 
+        _phantom: PhantomData 
+    };
+
+    let rows = db_client.query::<(Uuid, Option<String>, Option<String>), ()>(
+        "SELECT id, interests, language FROM users",
+        None
+    ).await?;
+
+    let mut migrated = 0;
+    for (user_id, legacy_interests, legacy_language) in rows {
+        let legacy_interests = legacy_interests.unwrap_or_default();
+        let legacy_language = legacy_language.unwrap_or_default();
+        if legacy_interests.trim().is_empty() && legacy_language.trim().is_empty() {
+            continue;
+        }
+        let Some(mut user) = find_by_id(session, &user_id.to_string()).await? else { continue };
+        let previous = user.clone();
+
+        let parsed: Vec<Interest> = legacy_interests
+            .split([',', ';'])
+            .filter(|spec| !spec.trim().is_empty())
+            .filter_map(|spec| Interest::parse(spec).ok())
+            .collect();
+        let mut interests = user.interests.clone();
+        interests.extend(parsed);
+        let mut interests = dedupe_interests(interests);
+        interests.truncate(MAX_INTERESTS);
+
+        for language in legacy_language.split([',', ';']).filter_map(normalize_tag) {
+            if !user.languages.contains(&language) && user.languages.len() < MAX_LANGUAGES {
+                user.languages.push(language);
+            }
+        }
+        user.interests = interests;
+
+        db_client.insert(
+            "UPDATE users SET interest_tags = ?, languages = ?, interests = null, language = null WHERE id = ?",
+            (interests_to_column(&user.interests), &user.languages, user_id)
+        ).await?;
+
+        // The match index may hold terms from the free text or from the
+        // tags alone, depending on when it was built; drop every one of this
+        // user's old terms and index the new ones in full.
+        let mut legacy_terms = matching::profile_terms(&previous);
+        legacy_terms.extend(matching::terms(&legacy_interests).into_iter().map(|term| (MatchField::Interests, term)));
+        legacy_terms.extend(matching::terms(&legacy_language).into_iter().map(|term| (MatchField::Language, term)));
+        index_match_terms(session, user_id, &legacy_terms, &HashSet::new()).await?;
+        index_match_terms(session, user_id, &HashSet::new(), &matching::profile_terms(&user)).await?;
+        update_tag_usage(session, INTEREST_TAGS, &interest_tags(&previous), &interest_tags(&user)).await?;
+        update_tag_usage(session, LANGUAGE_TAGS, &previous.languages, &user.languages).await?;
+        migrated += 1;
+    }
+
+    if migrated > 0 {
+        info!("Migrated interests and languages of {} users to tags", migrated);
+    }
+    Ok(())
+}
+
+/// Builds the match index for users created before it existed. Runs at
+/// startup and does nothing once the index has any entries.
+pub async fn rebuild_match_index_if_empty(session: &web::Data<Session>) -> Result<(), AppError> {
+    let db_client = DbClient::<User> { 
+        session, 
+        _phantom: PhantomData 
+    };
+
+    let existing = db_client.query::<(String,), ()>(
+        "SELECT term FROM user_match_terms LIMIT 1",
+        None
+    ).await?;
+    if !existing.is_empty() {
+        return Ok(());
+    }
+
+    let user_ids = db_client.query::<(Uuid,), ()>("SELECT id FROM users", None).await?;
+    info!("Building the match index for {} users", user_ids.len());
+    for (user_id,) in user_ids {
+        if let Some(user) = find_by_id(session, &user_id.to_string()).await? {
+            index_match_terms(session, user_id, &HashSet::new(), &matching::profile_terms(&user)).await?;
+        }
+    }
+    Ok(())
+}
+
+/// Ranks other users by how well their interests, language, project and
+/// username overlap the query terms. Candidates come from the
+/// `user_match_terms` index, one partition per term, so the `users` table is
+/// only read for the best-scoring few.
+pub async fn match_users(
+    session: &web::Data<Session>,
+    user_id: &str,
+    query: &MatchUsersQuery,
+    limit: usize,
+) -> Result<Vec<UserMatch>, AppError> {
+    let caller = Uuid::parse_str(user_id).map_err(|e| AppError(format!("Invalid UUID format: {}", e), StatusCode::BAD_REQUEST))?;
+    let terms: Vec<String> = matching::terms(&query.q).into_iter().take(MAX_QUERY_TERMS).collect();
+    if terms.is_empty() {
+        return Err(AppError("Query must contain at least one search term".to_string(), StatusCode::BAD_REQUEST));
+    }
+
+    let db_client = DbClient::<User> { 
+        session, 
+        _phantom: PhantomData 
+    };
+
+    let mut scores: HashMap<Uuid, (u32, Vec<String>)> = HashMap::new();
+    for term in &terms {
+        let rows = db_client.query::<(Uuid, String), _>(
+            "SELECT user_id, field FROM user_match_terms WHERE term = ? LIMIT ?",
+            Some((term, MATCH_TERM_FANOUT))
+        ).await?;
+        for (candidate, field) in rows {
+            let Some(field) = MatchField::from_column(&field) else { continue };
+            let (score, matched_terms) = scores.entry(candidate).or_default();
+            *score += field.weight();
+            if !matched_terms.contains(term) {
+                matched_terms.push(term.clone());
+            }
+        }
+    }
+    scores.remove(&caller);
+
+    // Among equally good matches, people the caller already follows come first.
+    let following: HashSet<String> = FollowService::new(session.clone())
+        .following_ids(user_id)
+        .await?
+        .into_iter()
+        .collect();
+
+    let mut ranked: Vec<_> = scores.into_iter().collect();
+    ranked.sort_by(|(a_id, (a_score, a_terms)), (b_id, (b_score, b_terms))| {
+        b_score.cmp(a_score)
+            .then_with(|| b_terms.len().cmp(&a_terms.len()))
+            .then_with(|| following.contains(&b_id.to_string()).cmp(&following.contains(&a_id.to_string())))
+            .then_with(|| a_id.cmp(b_id))
+    });
+
+    let country = query.country.as_deref().map(|c| c.trim().to_lowercase()).filter(|c| !c.is_empty());
+    let mut matches = Vec::new();
+    for (candidate, (score, matched_terms)) in ranked.into_iter().take(MATCH_CANDIDATE_LIMIT) {
+        if matches.len() >= limit {
+            break;
+        }
+        // Index entries can outlive a deleted user.
+        let Some(user) = find_by_id(session, &candidate.to_string()).await? else { continue };
+        if query.online.is_some_and(|online| user.is_online != online) {
+            continue;
+        }
+        if let Some(country) = &country {
+            if user.home_country.as_deref().map(|c| c.trim().to_lowercase()).as_ref() != Some(country) {
+                continue;
+            }
+        }
+        let is_following = following.contains(&user.id);
+        matches.push(UserMatch { user: user.to_profile(), score, matched_terms, is_following });
+    }
+
+    Ok(matches)
+}
+
 pub async fn get_all_users(session: &web::Data<Session>) -> Result<Vec<UserProfile>, AppError> {
     let db_client = DbClient::<UserProfile> { 
         session, 
         _phantom: PhantomData 
     };
 
-    let results = db_client.query::<(Uuid, String, String, CqlTimestamp, Option<CqlTimestamp>, bool, Option<String>, Option<String>, Option<String>, Option<String>, Option<String>), _>(
-        "SELECT id, username, email, created_at, last_seen_at, is_online, interests, language, profile_image_url, home_country, project_building FROM users",
+    let results = db_client.query::<(Uuid, String, String, CqlTimestamp, Option<CqlTimestamp>, bool, Option<BTreeMap<String, String>>, Option<Vec<String>>, Option<String>, Option<String>, Option<String>), _>(
+        "SELECT id, username, email, created_at, last_seen_at, is_online, interest_tags, languages, profile_image_url, home_country, project_building FROM users",
         None::<()>
     ).await?;
 
     let users = results
         .into_iter()
-        .map(|(id, username, email, created_at, last_seen_at, is_online, interests, language, profile_image_url, home_country, project_building)| {
+        .map(|(id, username, email, created_at, last_seen_at, is_online, interests, languages, profile_image_url, home_country, project_building)| {
             UserProfile {
                 id: id.to_string(),
                 username,
@@ -275,8 +634,8 @@ pub async fn get_all_users(session: &web::Data<Session>) -> Result<Vec<UserProfi
                 created_at: created_at.0,
                 last_seen_at: last_seen_at.map(|ts| ts.0),
                 is_online,
-                interests,
-                language,
+                interests: interests_from_column(interests),
+                languages: languages.unwrap_or_default(),
                 profile_image_url,
                 home_country,
                 project_building,
@@ -2,11 +2,14 @@ use actix_web::{body::None, http::{KeepAlive, StatusCode}, web};
 use scylla::client::session::Session;
 use scylla::statement::unprepared::Statement;
 use scylla::serialize::row::SerializeRow;
+use scylla::value::{CqlValue, Row};
 use serde::{Serialize, de::DeserializeOwned};
 use std::marker::PhantomData;
 use crate::error::AppError;
 use futures_util::stream::TryStreamExt;
 
+/// Scylla caps the partition keys in one `IN` restriction (100 by default).
+pub const MAX_KEYS_PER_QUERY: usize = 100;
 
 pub struct DbClient<'a, T> {
     pub session: &'a web::Data<Session>,
@@ -55,4 +58,21 @@ where
             Ok(items)
         }
 
+    /// Runs a lightweight transaction (`IF NOT EXISTS`, `IF EXISTS`, ...) and
+    /// reports whether it was applied.
+    pub async fn apply<V>(&self, query: &str, values: V) -> Result<bool, AppError>
+    where
+        V: SerializeRow + Send,
+    {
+        let row = self.session
+            .query_unpaged(query, values)
+            .await
+            .map_err(|e| AppError(format!("Failed to execute query: {}", e), StatusCode::INTERNAL_SERVER_ERROR))?
+            .into_rows_result()
+            .map_err(|e| AppError(format!("Failed to get rows: {}", e), StatusCode::INTERNAL_SERVER_ERROR))?
+            .first_row::<Row>()
+            .map_err(|e| AppError(format!("Failed to process results: {}", e), StatusCode::INTERNAL_SERVER_ERROR))?;
+
+        Ok(matches!(row.columns.first(), Some(Some(CqlValue::Boolean(true)))))
+    }
 }
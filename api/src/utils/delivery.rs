@@ -0,0 +1,139 @@
+use actix_web::http::StatusCode;
+use actix_web::web;
+use scylla::client::session::Session;
+use scylla::value::CqlTimeuuid;
+use std::marker::PhantomData;
+use std::sync::LazyLock;
+use std::time::{SystemTime, UNIX_EPOCH};
+use uuid::{Context, Timestamp, Uuid};
+use crate::error::AppError;
+use crate::utils::db_client::DbClient;
+
+/// Upper bound on events replayed to a single socket on connect; anything
+/// older stays queued for the next reconnect (or until its TTL expires).
+pub const MAX_REPLAY: i32 = 1000;
+
+/// Clock sequence and node id of every TIMEUUID this process makes. The
+/// counter keeps ids from the same 100ns tick apart, the random node those
+/// of different API instances.
+static TIMEUUID_SOURCE: LazyLock<(Context, [u8; 6])> = LazyLock::new(|| {
+    let mut node: [u8; 6] = rand::random();
+    // The multicast bit marks a random node id rather than a MAC address.
+    node[0] |= 0x01;
+    (Context::new(rand::random()), node)
+});
+
+/// A TIMEUUID for the given moment, unique to this call.
+pub fn timeuuid_at(seconds: u64, nanos: u32) -> Uuid {
+    let (context, node) = &*TIMEUUID_SOURCE;
+    Uuid::new_v1(Timestamp::from_unix(context, seconds, nanos), node)
+}
+
+/// Creates a TIMEUUID for messages, comments and socket events that are
+/// not backed by a new message row (edits, deletions), so they all sort
+/// together in the queue.
+pub fn new_event_id() -> Uuid {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+    timeuuid_at(now.as_secs(), now.subsec_nanos())
+}
+
+/// Persistent per-user, per-room queue of socket payloads that could not be
+/// delivered live. Rows expire through the table's default TTL.
+pub struct DeliveryQueue {
+    session: web::Data<Session>,
+}
+
+impl DeliveryQueue {
+    pub fn new(session: web::Data<Session>) -> Self {
+        Self { session }
+    }
+
+    pub async fn enqueue(
+        &self,
+        user_id: &str,
+        room_id: &str,
+        event_id: Uuid,
+        payload: &str,
+    ) -> Result<(), AppError> {
+        let (user_uuid, room_uuid) = parse_ids(user_id, room_id)?;
+
+        let db_client = DbClient::<String> {
+            session: &self.session,
+            _phantom: PhantomData,
+        };
+
+        db_client.insert(
+            "INSERT INTO pending_deliveries (user_id, room_id, message_id, payload) VALUES (?, ?, ?, ?)",
+            (user_uuid, room_uuid, CqlTimeuuid::from_bytes(*event_id.as_bytes()), payload)
+        ).await
+    }
+
+    /// Returns queued events newer than `last_seen`, oldest first.
+    pub async fn pending_after(
+        &self,
+        user_id: &str,
+        room_id: &str,
+        last_seen: Option<Uuid>,
+    ) -> Result<Vec<(Uuid, String)>, AppError> {
+        let (user_uuid, room_uuid) = parse_ids(user_id, room_id)?;
+
+        let db_client = DbClient::<String> {
+            session: &self.session,
+            _phantom: PhantomData,
+        };
+
+        let results = match last_seen {
+            Some(last_seen) => db_client.query::<(CqlTimeuuid, String), _>(
+                "SELECT message_id, payload FROM pending_deliveries WHERE user_id = ? AND room_id = ? AND message_id > ? LIMIT ?",
+                Some((user_uuid, room_uuid, CqlTimeuuid::from_bytes(*last_seen.as_bytes()), MAX_REPLAY))
+            ).await?,
+            None => db_client.query::<(CqlTimeuuid, String), _>(
+                "SELECT message_id, payload FROM pending_deliveries WHERE user_id = ? AND room_id = ? LIMIT ?",
+                Some((user_uuid, room_uuid, MAX_REPLAY))
+            ).await?,
+        };
+
+        Ok(results
+            .into_iter()
+            .map(|(event_id, payload)| (Uuid::from_bytes(*event_id.as_bytes()), payload))
+            .collect())
+    }
+
+    /// Drops every queued event up to and including `up_to`.
+    pub async fn acknowledge(&self, user_id: &str, room_id: &str, up_to: Uuid) -> Result<(), AppError> {
+        let (user_uuid, room_uuid) = parse_ids(user_id, room_id)?;
+
+        let db_client = DbClient::<String> {
+            session: &self.session,
+            _phantom: PhantomData,
+        };
+
+        db_client.insert(
+            "DELETE FROM pending_deliveries WHERE user_id = ? AND room_id = ? AND message_id <= ?",
+            (user_uuid, room_uuid, CqlTimeuuid::from_bytes(*up_to.as_bytes()))
+        ).await
+    }
+}
+
+fn parse_ids(user_id: &str, room_id: &str) -> Result<(Uuid, Uuid), AppError> {
+    let user_uuid = Uuid::parse_str(user_id)
+        .map_err(|e| AppError(format!("Invalid user ID: {}", e), StatusCode::BAD_REQUEST))?;
+    let room_uuid = Uuid::parse_str(room_id)
+        .map_err(|e| AppError(format!("Invalid room ID: {}", e), StatusCode::BAD_REQUEST))?;
+    Ok((user_uuid, room_uuid))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ids_from_the_same_tick_differ() {
+        let first = timeuuid_at(1_700_000_000, 0);
+        let second = timeuuid_at(1_700_000_000, 0);
+        assert_ne!(first, second);
+        let unix = |id: Uuid| id.get_timestamp().map(|ts| ts.to_unix());
+        assert_eq!(unix(first), unix(second));
+        assert_eq!(first.get_node_id(), second.get_node_id());
+    }
+}
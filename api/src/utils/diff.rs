@@ -0,0 +1,205 @@
+/// Unchanged lines shown around each change in a hunk.
+pub const CONTEXT_LINES: usize = 3;
+/// Beyond this many changed lines the diff stops looking for the shortest
+/// edit and shows the differing middle as removed and re-added.
+const MAX_EDIT_DISTANCE: usize = 1000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Edit<'a> {
+    Equal(&'a str),
+    Delete(&'a str),
+    Insert(&'a str),
+}
+
+/// Shortest edit script between two lists of lines (Myers' algorithm), after
+/// setting aside the lines both sides start and end with.
+fn edits<'a>(old: &[&'a str], new: &[&'a str]) -> Vec<Edit<'a>> {
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..].iter().rev().zip(new[prefix..].iter().rev()).take_while(|(a, b)| a == b).count();
+    let (old_middle, new_middle) = (&old[prefix..old.len() - suffix], &new[prefix..new.len() - suffix]);
+
+    let mut script: Vec<Edit<'a>> = old[..prefix].iter().map(|line| Edit::Equal(line)).collect();
+    script.extend(middle_edits(old_middle, new_middle));
+    script.extend(old[old.len() - suffix..].iter().map(|line| Edit::Equal(line)));
+    script
+}
+
+fn middle_edits<'a>(old: &[&'a str], new: &[&'a str]) -> Vec<Edit<'a>> {
+    let (n, m) = (old.len() as isize, new.len() as isize);
+    let max = n + m;
+    let limit = max.min(MAX_EDIT_DISTANCE as isize);
+    let index = |k: isize| (k + max) as usize;
+
+    // `v[k]` is the furthest x reached on diagonal k; `trace[d]` keeps the
+    // diagonals -d..=d as they were before step d, for walking back.
+    let mut v = vec![0isize; 2 * max as usize + 2];
+    let mut trace: Vec<Vec<isize>> = Vec::new();
+    let mut found = false;
+    'search: for d in 0..=limit {
+        trace.push(v[index(-d)..=index(d)].to_vec());
+        for k in (-d..=d).step_by(2) {
+            let mut x = if k == -d || (k != d && v[index(k - 1)] < v[index(k + 1)]) {
+                v[index(k + 1)]
+            } else {
+                v[index(k - 1)] + 1
+            };
+            let mut y = x - k;
+            while x < n && y < m && old[x as usize] == new[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            v[index(k)] = x;
+            if x >= n && y >= m {
+                found = true;
+                break 'search;
+            }
+        }
+    }
+
+    if !found {
+        return old.iter().map(|line| Edit::Delete(line))
+            .chain(new.iter().map(|line| Edit::Insert(line)))
+            .collect();
+    }
+
+    let mut script = Vec::new();
+    let (mut x, mut y) = (n, m);
+    for (d, v) in trace.iter().enumerate().skip(1).rev() {
+        let d = d as isize;
+        let at = |k: isize| v[(k + d) as usize];
+        let k = x - y;
+        let prev_k = if k == -d || (k != d && at(k - 1) < at(k + 1)) { k + 1 } else { k - 1 };
+        let prev_x = at(prev_k);
+        let prev_y = prev_x - prev_k;
+        while x > prev_x && y > prev_y {
+            script.push(Edit::Equal(old[x as usize - 1]));
+            x -= 1;
+            y -= 1;
+        }
+        if x == prev_x {
+            script.push(Edit::Insert(new[y as usize - 1]));
+        } else {
+            script.push(Edit::Delete(old[x as usize - 1]));
+        }
+        x = prev_x;
+        y = prev_y;
+    }
+    // What is left before the first edit is a run of equal lines.
+    while x > 0 && y > 0 {
+        script.push(Edit::Equal(old[x as usize - 1]));
+        x -= 1;
+        y -= 1;
+    }
+    script.reverse();
+    script
+}
+
+/// Hunk header position: 1-based, or the line before for an empty range.
+fn hunk_range(start: usize, count: usize) -> String {
+    if count == 0 {
+        format!("{},0", start)
+    } else {
+        format!("{},{}", start + 1, count)
+    }
+}
+
+/// A unified diff of two texts, labelled `name`, with `CONTEXT_LINES` of
+/// context. Empty when the texts have the same lines.
+pub fn unified_diff(name: &str, old: &str, new: &str) -> String {
+    let old_lines: Vec<&str> = old.lines().collect();
+    let new_lines: Vec<&str> = new.lines().collect();
+    let script = edits(&old_lines, &new_lines);
+
+    let changes: Vec<usize> = script
+        .iter()
+        .enumerate()
+        .filter(|(_, edit)| !matches!(edit, Edit::Equal(_)))
+        .map(|(i, _)| i)
+        .collect();
+    if changes.is_empty() {
+        return String::new();
+    }
+
+    // Changes closer together than twice the context share a hunk.
+    let mut hunks: Vec<(usize, usize)> = Vec::new();
+    for &change in &changes {
+        match hunks.last_mut() {
+            Some((_, end)) if change - *end <= 2 * CONTEXT_LINES => *end = change,
+            _ => hunks.push((change, change)),
+        }
+    }
+
+    let mut out = format!("--- a/{}\n+++ b/{}\n", name, name);
+    for (first, last) in hunks {
+        let start = first.saturating_sub(CONTEXT_LINES);
+        let end = (last + CONTEXT_LINES + 1).min(script.len());
+        let old_start = script[..start].iter().filter(|edit| !matches!(edit, Edit::Insert(_))).count();
+        let new_start = script[..start].iter().filter(|edit| !matches!(edit, Edit::Delete(_))).count();
+        let hunk = &script[start..end];
+        let old_count = hunk.iter().filter(|edit| !matches!(edit, Edit::Insert(_))).count();
+        let new_count = hunk.iter().filter(|edit| !matches!(edit, Edit::Delete(_))).count();
+
+        out.push_str(&format!("@@ -{} +{} @@\n", hunk_range(old_start, old_count), hunk_range(new_start, new_count)));
+        for edit in hunk {
+            let (marker, line) = match edit {
+                Edit::Equal(line) => (' ', line),
+                Edit::Delete(line) => ('-', line),
+                Edit::Insert(line) => ('+', line),
+            };
+            out.push(marker);
+            out.push_str(line);
+            out.push('\n');
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_the_shortest_edit() {
+        let old = ["a", "b", "c", "a", "b", "b", "a"];
+        let new = ["c", "b", "a", "b", "a", "c"];
+        let script = edits(&old, &new);
+        let changed = script.iter().filter(|edit| !matches!(edit, Edit::Equal(_))).count();
+        assert_eq!(changed, 5);
+
+        let rebuilt_old: Vec<&str> = script.iter().filter_map(|edit| match edit {
+            Edit::Equal(line) | Edit::Delete(line) => Some(*line),
+            Edit::Insert(_) => None,
+        }).collect();
+        let rebuilt_new: Vec<&str> = script.iter().filter_map(|edit| match edit {
+            Edit::Equal(line) | Edit::Insert(line) => Some(*line),
+            Edit::Delete(_) => None,
+        }).collect();
+        assert_eq!(rebuilt_old, old);
+        assert_eq!(rebuilt_new, new);
+    }
+
+    #[test]
+    fn renders_unified_hunks() {
+        assert_eq!(unified_diff("code", "same\n", "same"), "");
+
+        let old = "fn main() {\n    println!(\"hi\");\n}\n";
+        let new = "fn main() {\n    println!(\"hello\");\n}\n";
+        assert_eq!(
+            unified_diff("code", old, new),
+            "--- a/code\n+++ b/code\n@@ -1,3 +1,3 @@\n fn main() {\n-    println!(\"hi\");\n+    println!(\"hello\");\n }\n"
+        );
+
+        assert_eq!(unified_diff("title", "", "Hello"), "--- a/title\n+++ b/title\n@@ -0,0 +1,1 @@\n+Hello\n");
+    }
+
+    #[test]
+    fn splits_distant_changes_into_hunks() {
+        let old: Vec<String> = (1..=20).map(|i| i.to_string()).collect();
+        let mut new = old.clone();
+        new[1] = "two".to_string();
+        new[17] = "eighteen".to_string();
+        let diff = unified_diff("code", &old.join("\n"), &new.join("\n"));
+        let headers: Vec<&str> = diff.lines().filter(|line| line.starts_with("@@")).collect();
+        assert_eq!(headers, vec!["@@ -1,5 +1,5 @@", "@@ -15,6 +15,6 @@"]);
+    }
+}
@@ -1,64 +1,202 @@
-use actix_web::HttpRequest;
-use chrono::{Utc, Duration};
-use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, Validation, TokenData};
+use actix_web::{dev::Payload, web, FromRequest, HttpMessage, HttpRequest};
+use chrono::{Duration, Utc};
+use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, Validation};
+use scylla::client::session::Session;
 use serde::{Serialize, Deserialize};
 use std::env;
+use std::future::{ready, Ready};
+use uuid::Uuid;
 use crate::error::AppError;
+use crate::utils::sessions::SessionStore;
 use actix_web::http::StatusCode;
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Claims {
-    pub exp: usize, 
+    pub exp: usize,
+    pub iat: usize,
     pub sub: String,
+    pub sid: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub iss: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub aud: Option<String>,
+}
+
+/// Token settings, read from the environment:
+/// `JWT_SECRET` (required), `JWT_ISSUER`, `JWT_AUDIENCE`, `JWT_LEEWAY_SECS`
+/// (default 60) and `JWT_ACCESS_TOKEN_TTL_SECS` (default 86400, one day:
+/// the frontend does not refresh tokens yet, so a shorter default would log
+/// everyone out when it ran out).
+#[derive(Debug, Clone)]
+pub struct JwtConfig {
+    pub secret: String,
+    pub issuer: Option<String>,
+    pub audience: Option<String>,
+    pub leeway_secs: u64,
+    pub access_token_ttl_secs: i64,
 }
 
+impl JwtConfig {
+    pub fn from_env() -> Result<Self, String> {
+        let secret = env::var("JWT_SECRET").map_err(|_| "JWT_SECRET must be set".to_string())?;
+        if secret.trim().is_empty() {
+            return Err("JWT_SECRET must not be empty".to_string());
+        }
 
-pub fn generate_token(email: String, id: String) -> Result<String, ()> {
-    let secret = std::env::var("JWT_SECRET").unwrap_or_else(|_| "your-secret-key".to_string());
-    let claims = Claims {
-        exp: (Utc::now() + Duration::hours(1)).timestamp() as usize,
-        sub: id,    
-    };
-    let token = encode(&Header::default(), &claims, &EncodingKey::from_secret(secret.as_ref())).unwrap();
-    Ok(token)   
+        Ok(Self {
+            secret,
+            issuer: env::var("JWT_ISSUER").ok().filter(|v| !v.is_empty()),
+            audience: env::var("JWT_AUDIENCE").ok().filter(|v| !v.is_empty()),
+            leeway_secs: parse_env("JWT_LEEWAY_SECS", 60)?,
+            access_token_ttl_secs: parse_env("JWT_ACCESS_TOKEN_TTL_SECS", 24 * 60 * 60)?,
+        })
+    }
 }
 
-pub fn generate_refresh_token(email: String, id: String) -> Result<String, ()> {
-    let secret = std::env::var("JWT_SECRET").unwrap_or_else(|_| "your-secret-key".to_string());
-    let claims = Claims {
-        exp: (Utc::now() + Duration::days(30)).timestamp() as usize,
-        sub: id,
-    };
-    let token = encode(&Header::default(), &claims, &EncodingKey::from_secret(secret.as_ref())).unwrap();
-    Ok(token)
+fn parse_env<T: std::str::FromStr>(name: &str, default: T) -> Result<T, String> {
+    match env::var(name) {
+        Ok(value) => value.parse().map_err(|_| format!("{} must be a number", name)),
+        Err(_) => Ok(default),
+    }
 }
 
-pub fn verify_token(token: String) -> Result<TokenData<Claims>, ()> {
-    let secret = std::env::var("JWT_SECRET").unwrap_or_else(|_| "your-secret-key".to_string());
-    let claims = decode::<Claims>(&token, &DecodingKey::from_secret(secret.as_ref()), &Validation::default()).unwrap();
-    Ok(claims)
+/// The user behind a request, put in the request extensions by the `Auth`
+/// middleware. Handlers behind it take this as an argument.
+#[derive(Debug, Clone)]
+pub struct AuthenticatedUser {
+    pub user_id: String,
+}
+
+impl FromRequest for AuthenticatedUser {
+    type Error = AppError;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        ready(
+            req.extensions()
+                .get::<AuthenticatedUser>()
+                .cloned()
+                .ok_or_else(|| AppError("Not authenticated".to_string(), StatusCode::UNAUTHORIZED)),
+        )
+    }
+}
+
+/// Issues and checks access tokens for every HTTP and WebSocket entry point.
+pub struct Authenticator {
+    config: JwtConfig,
+    encoding_key: EncodingKey,
+    decoding_key: DecodingKey,
+    validation: Validation,
+}
+
+impl Authenticator {
+    pub fn new(config: JwtConfig) -> Self {
+        let mut validation = Validation::default();
+        validation.leeway = config.leeway_secs;
+        validation.set_required_spec_claims(&["exp", "sub"]);
+        if let Some(issuer) = &config.issuer {
+            validation.set_issuer(&[issuer]);
+        }
+        match &config.audience {
+            Some(audience) => validation.set_audience(&[audience]),
+            None => validation.validate_aud = false,
+        }
+
+        Self {
+            encoding_key: EncodingKey::from_secret(config.secret.as_bytes()),
+            decoding_key: DecodingKey::from_secret(config.secret.as_bytes()),
+            validation,
+            config,
+        }
+    }
+
+    pub fn issue_access_token(&self, user_id: &str, session_id: &str) -> Result<String, AppError> {
+        let now = Utc::now();
+        let claims = Claims {
+            exp: (now + Duration::seconds(self.config.access_token_ttl_secs)).timestamp() as usize,
+            iat: now.timestamp() as usize,
+            sub: user_id.to_owned(),
+            sid: session_id.to_owned(),
+            iss: self.config.issuer.clone(),
+            aud: self.config.audience.clone(),
+        };
+
+        encode(&Header::default(), &claims, &self.encoding_key)
+            .map_err(|e| AppError(format!("Token generation error: {}", e), StatusCode::INTERNAL_SERVER_ERROR))
+    }
+
+    /// Checks the signature, expiry, issuer and audience of an access token.
+    pub fn verify_token(&self, token: &str) -> Result<Claims, AppError> {
+        decode::<Claims>(token, &self.decoding_key, &self.validation)
+            .map(|data| data.claims)
+            .map_err(|e| AppError(format!("Invalid token: {}", e), StatusCode::UNAUTHORIZED))
+    }
+
+    /// Verifies a token and checks that its session has not been revoked.
+    pub async fn authenticate(&self, dbsession: &web::Data<Session>, token: &str) -> Result<AuthenticatedUser, AppError> {
+        let claims = self.verify_token(token)?;
+        let session_id = Uuid::parse_str(&claims.sid)
+            .map_err(|_| AppError("Invalid token: bad session id".to_string(), StatusCode::UNAUTHORIZED))?;
+
+        if !SessionStore::new(dbsession.clone()).is_active(session_id).await? {
+            return Err(AppError("Session has been revoked".to_string(), StatusCode::UNAUTHORIZED));
+        }
+
+        Ok(AuthenticatedUser { user_id: claims.sub })
+    }
 }
 
-pub fn get_user_id_from_token(req: &HttpRequest) -> Result<String, AppError> {
-    let auth_header = req.headers()
+pub fn bearer_token(req: &HttpRequest) -> Result<&str, AppError> {
+    req.headers()
         .get("Authorization")
         .ok_or_else(|| AppError("Missing Authorization header".to_string(), StatusCode::UNAUTHORIZED))?
         .to_str()
-        .map_err(|_| AppError("Invalid Authorization header".to_string(), StatusCode::UNAUTHORIZED))?;
-
-    let token = auth_header
+        .map_err(|_| AppError("Invalid Authorization header".to_string(), StatusCode::UNAUTHORIZED))?
         .strip_prefix("Bearer ")
-        .ok_or_else(|| AppError("Invalid token format".to_string(), StatusCode::UNAUTHORIZED))?;
-
-    let secret = env::var("JWT_SECRET").unwrap_or_else(|_| "your-secret-key".to_string());
-    let validation = Validation::default();
-    
-    let claims = decode::<Claims>(
-        token,
-        &DecodingKey::from_secret(secret.as_bytes()),
-        &validation,
-    )
-    .map_err(|e| AppError(format!("Invalid token: {}", e), StatusCode::UNAUTHORIZED))?
-    .claims;
-
-    Ok(claims.sub)
-}
\ No newline at end of file
+        .ok_or_else(|| AppError("Invalid token format".to_string(), StatusCode::UNAUTHORIZED))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> JwtConfig {
+        JwtConfig {
+            secret: "test-secret".to_string(),
+            issuer: Some("kerek".to_string()),
+            audience: Some("kerek-app".to_string()),
+            leeway_secs: 0,
+            access_token_ttl_secs: 3600,
+        }
+    }
+
+    #[test]
+    fn issued_token_verifies() {
+        let authenticator = Authenticator::new(config());
+        let token = authenticator.issue_access_token("user-1", "session-1").unwrap();
+        let claims = authenticator.verify_token(&token).unwrap();
+        assert_eq!(claims.sub, "user-1");
+        assert_eq!(claims.sid, "session-1");
+    }
+
+    #[test]
+    fn rejects_token_from_other_secret_issuer_or_audience() {
+        let token = Authenticator::new(config()).issue_access_token("user-1", "session-1").unwrap();
+
+        let other_secret = JwtConfig { secret: "other-secret".to_string(), ..config() };
+        let other_issuer = JwtConfig { issuer: Some("someone-else".to_string()), ..config() };
+        let other_audience = JwtConfig { audience: Some("other-app".to_string()), ..config() };
+        for config in [other_secret, other_issuer, other_audience] {
+            let err = Authenticator::new(config).verify_token(&token).unwrap_err();
+            assert_eq!(err.1, StatusCode::UNAUTHORIZED);
+        }
+    }
+
+    #[test]
+    fn rejects_expired_token() {
+        let expired = JwtConfig { access_token_ttl_secs: -120, ..config() };
+        let token = Authenticator::new(expired).issue_access_token("user-1", "session-1").unwrap();
+        assert!(Authenticator::new(config()).verify_token(&token).is_err());
+        assert!(Authenticator::new(config()).verify_token("not-a-token").is_err());
+    }
+}
@@ -0,0 +1,90 @@
+use std::collections::HashSet;
+use crate::models::user::User;
+use crate::utils::terms::{self, IndexField};
+
+/// Words that say what kind of match is wanted rather than what to match on,
+/// e.g. "rust expert, speaks English, building a compiler".
+const STOP_WORDS: &[&str] = &[
+    "a", "an", "and", "the", "of", "in", "on", "for", "with", "to", "or", "who", "is", "i", "im", "am",
+    "expert", "experts", "experienced", "speaks", "speak", "speaking", "building", "build", "builds",
+    "developer", "developers", "dev", "someone", "people", "looking", "knows", "know", "using", "uses",
+];
+
+/// Upper bound on the terms taken from one match query, so a pasted essay
+/// cannot fan out into hundreds of index lookups.
+pub const MAX_QUERY_TERMS: usize = 10;
+
+/// Profile fields that feed the match index.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MatchField {
+    Interests,
+    Language,
+    Project,
+    Username,
+}
+
+impl IndexField for MatchField {
+    const ALL: &'static [MatchField] = &[MatchField::Interests, MatchField::Language, MatchField::Project, MatchField::Username];
+
+    fn as_str(&self) -> &'static str {
+        match self {
+            MatchField::Interests => "interests",
+            MatchField::Language => "language",
+            MatchField::Project => "project",
+            MatchField::Username => "username",
+        }
+    }
+
+    fn weight(&self) -> u32 {
+        match self {
+            MatchField::Interests => 3,
+            MatchField::Language | MatchField::Project => 2,
+            MatchField::Username => 1,
+        }
+    }
+}
+
+/// Match terms of a query or a profile field.
+pub fn terms(text: &str) -> Vec<String> {
+    terms::terms(text, STOP_WORDS)
+}
+
+/// Every `(field, term)` pair a user should be findable by. Multi-word tags
+/// such as "machine-learning" are indexed word by word.
+pub fn profile_terms(user: &User) -> HashSet<(MatchField, String)> {
+    let interests = user.interests.iter().map(|interest| (MatchField::Interests, interest.tag.as_str()));
+    let languages = user.languages.iter().map(|language| (MatchField::Language, language.as_str()));
+    let fields = [
+        (MatchField::Project, user.project_building.as_deref().unwrap_or_default()),
+        (MatchField::Username, user.username.as_str()),
+    ];
+
+    interests
+        .chain(languages)
+        .chain(fields)
+        .flat_map(|(field, text)| terms(text).into_iter().map(move |term| (field, term)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_query_into_terms() {
+        assert_eq!(
+            terms("Rust expert, speaks English, building a compiler"),
+            vec!["rust", "english", "compiler"]
+        );
+        assert_eq!(terms("C++ and C#; Node.js. rust RUST"), vec!["c++", "c#", "node.js", "rust"]);
+        assert!(terms("  , an expert ").is_empty());
+    }
+
+    #[test]
+    fn round_trips_field_names() {
+        for &field in MatchField::ALL {
+            assert_eq!(MatchField::from_column(field.as_str()), Some(field));
+        }
+        assert_eq!(MatchField::from_column("email"), None);
+    }
+}
@@ -2,4 +2,16 @@ pub mod jwt;
 pub mod websocket;
 pub mod one_to_one;
 pub mod seed;
-pub mod db_client;
\ No newline at end of file
+pub mod db_client;
+pub mod pagination;
+pub mod delivery;
+pub mod protocol;
+pub mod read_markers;
+pub mod presence;
+pub mod sessions;
+pub mod tags;
+pub mod terms;
+pub mod matching;
+pub mod timeline;
+pub mod search;
+pub mod diff;
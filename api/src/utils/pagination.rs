@@ -0,0 +1,70 @@
+use actix_web::http::StatusCode;
+use scylla::value::CqlTimeuuid;
+use serde::Serialize;
+use uuid::Uuid;
+use crate::error::AppError;
+
+pub const DEFAULT_PAGE_SIZE: i32 = 50;
+pub const MAX_PAGE_SIZE: i32 = 200;
+
+/// Position in a `message_id` (TIMEUUID) clustered partition to page from.
+#[derive(Debug, Clone, Copy)]
+pub enum MessageCursor {
+    Latest,
+    Before(CqlTimeuuid),
+    After(CqlTimeuuid),
+}
+
+impl MessageCursor {
+    pub fn from_query(before: Option<&str>, after: Option<&str>) -> Result<Self, AppError> {
+        match (before, after) {
+            (Some(_), Some(_)) => Err(AppError(
+                "Only one of 'before' or 'after' can be provided".to_string(),
+                StatusCode::BAD_REQUEST,
+            )),
+            (Some(before), None) => Ok(MessageCursor::Before(parse_timeuuid(before)?)),
+            (None, Some(after)) => Ok(MessageCursor::After(parse_timeuuid(after)?)),
+            (None, None) => Ok(MessageCursor::Latest),
+        }
+    }
+}
+
+pub fn parse_timeuuid(value: &str) -> Result<CqlTimeuuid, AppError> {
+    let uuid = Uuid::parse_str(value)
+        .map_err(|e| AppError(format!("Invalid cursor: {}", e), StatusCode::BAD_REQUEST))?;
+    if uuid.get_version_num() != 1 {
+        return Err(AppError("Invalid cursor: not a TIMEUUID".to_string(), StatusCode::BAD_REQUEST));
+    }
+    Ok(CqlTimeuuid::from_bytes(*uuid.as_bytes()))
+}
+
+pub fn clamp_page_size(limit: Option<i32>) -> i32 {
+    limit.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE)
+}
+
+/// One page of message history. `Latest` and `Before` pages are newest first,
+/// `After` pages are oldest first; `next_cursor` continues in the same direction.
+#[derive(Debug, Serialize)]
+pub struct MessagePage<T> {
+    pub messages: Vec<T>,
+    pub next_cursor: Option<String>,
+}
+
+impl<T> MessagePage<T> {
+    /// Builds a page from rows fetched with `limit + 1`, using the extra row only
+    /// to detect whether another page exists.
+    pub fn from_rows(mut rows: Vec<T>, limit: i32, id_of: impl Fn(&T) -> String) -> Self {
+        let has_more = rows.len() > limit as usize;
+        rows.truncate(limit as usize);
+        let next_cursor = if has_more { rows.last().map(id_of) } else { None };
+        MessagePage { messages: rows, next_cursor }
+    }
+}
+
+/// One page of a listing that is not message history, continued by passing
+/// `next_cursor` back as `cursor`.
+#[derive(Debug, Serialize)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    pub next_cursor: Option<String>,
+}
@@ -0,0 +1,208 @@
+use actix_web::web;
+use chrono::Utc;
+use log::{debug, error, info};
+use scylla::client::session::Session;
+use std::collections::{HashMap, HashSet};
+use std::marker::PhantomData;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{mpsc, RwLock};
+use uuid::Uuid;
+use crate::error::AppError;
+use crate::models::user::User;
+use crate::users::service as user_service;
+use crate::utils::db_client::DbClient;
+use crate::utils::protocol::ServerEvent;
+
+/// How often the server pings an idle socket.
+pub const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(20);
+/// A socket that has sent nothing, not even a pong, for this long is dropped.
+pub const CLIENT_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Live socket counts per user and the presence sockets watching them. A user
+/// is online while at least one of their sockets, on any endpoint, is open.
+#[derive(Default)]
+pub struct PresenceState {
+    connections: HashMap<String, usize>,
+    watchers: HashMap<String, Vec<(Uuid, mpsc::UnboundedSender<String>)>>,
+}
+
+pub type PresenceStore = Arc<RwLock<PresenceState>>;
+
+impl PresenceState {
+    pub fn is_online(&self, user_id: &str) -> bool {
+        self.connections.contains_key(user_id)
+    }
+}
+
+fn presence_event(user_id: &str, is_online: bool, last_seen_at: Option<i64>) -> String {
+    ServerEvent::<()>::Presence { user_id: user_id.to_string(), is_online, last_seen_at }.encode(None)
+}
+
+/// Sends a presence change to everyone watching `user_id`, pruning watchers
+/// whose sockets have gone away.
+fn notify_watchers(state: &mut PresenceState, user_id: &str, payload: &str) {
+    if let Some(watchers) = state.watchers.get_mut(user_id) {
+        watchers.retain(|(_, tx)| tx.send(payload.to_string()).is_ok());
+        if watchers.is_empty() {
+            state.watchers.remove(user_id);
+        }
+    }
+}
+
+/// Registers a new socket for `user_id`. The first socket notifies the
+/// user's watchers and marks them online in the database. The database write
+/// happens after the lock is released, stamped with the time of the change,
+/// so a slow write neither holds up other sockets nor overwrites a later one.
+pub async fn connect(dbsession: &web::Data<Session>, presence: &PresenceStore, user_id: &str) {
+    let changed_at = {
+        let mut state = presence.write().await;
+        let count = state.connections.entry(user_id.to_string()).or_insert(0);
+        *count += 1;
+        debug!("User {} now has {} live sockets", user_id, count);
+        if *count > 1 {
+            return;
+        }
+        notify_watchers(&mut state, user_id, &presence_event(user_id, true, None));
+        Utc::now()
+    };
+
+    info!("User {} is online", user_id);
+    if let Err(e) = user_service::update_user_status_at(dbsession, user_id, true, changed_at).await {
+        error!("Failed to mark user {} online: {}", user_id, e);
+    }
+}
+
+/// Releases a socket for `user_id`. When the last one closes watchers are
+/// notified and the user is marked offline with `last_seen_at` stamped, again
+/// writing to the database outside the lock.
+pub async fn disconnect(dbsession: &web::Data<Session>, presence: &PresenceStore, user_id: &str) {
+    let changed_at = {
+        let mut state = presence.write().await;
+        match state.connections.get_mut(user_id) {
+            Some(count) if *count > 1 => {
+                *count -= 1;
+                debug!("User {} now has {} live sockets", user_id, count);
+                return;
+            }
+            Some(_) => {
+                state.connections.remove(user_id);
+            }
+            None => return,
+        }
+        let changed_at = Utc::now();
+        notify_watchers(&mut state, user_id, &presence_event(user_id, false, Some(changed_at.timestamp())));
+        changed_at
+    };
+
+    info!("User {} is offline", user_id);
+    if let Err(e) = user_service::update_user_status_at(dbsession, user_id, false, changed_at).await {
+        error!("Failed to mark user {} offline: {}", user_id, e);
+    }
+}
+
+/// Starts forwarding presence changes of `contact_ids` to `tx`, returning a
+/// key for [`unsubscribe`].
+pub async fn subscribe(presence: &PresenceStore, contact_ids: &[String], tx: mpsc::UnboundedSender<String>) -> Uuid {
+    let key = crate::utils::delivery::new_event_id();
+    let mut state = presence.write().await;
+    for contact_id in contact_ids {
+        state.watchers.entry(contact_id.clone()).or_default().push((key, tx.clone()));
+    }
+    key
+}
+
+pub async fn unsubscribe(presence: &PresenceStore, contact_ids: &[String], key: Uuid) {
+    let mut state = presence.write().await;
+    for contact_id in contact_ids {
+        if let Some(watchers) = state.watchers.get_mut(contact_id) {
+            watchers.retain(|(watcher_key, _)| *watcher_key != key);
+            if watchers.is_empty() {
+                state.watchers.remove(contact_id);
+            }
+        }
+    }
+}
+
+/// Current presence of each contact as protocol events, sent to a presence
+/// socket right after it subscribes.
+pub async fn snapshot(
+    dbsession: &web::Data<Session>,
+    presence: &PresenceStore,
+    contact_ids: &[String],
+) -> Result<Vec<String>, AppError> {
+    let online: Vec<bool> = {
+        let state = presence.read().await;
+        contact_ids.iter().map(|contact_id| state.is_online(contact_id)).collect()
+    };
+
+    let mut events = Vec::with_capacity(contact_ids.len());
+    for (contact_id, is_online) in contact_ids.iter().zip(online) {
+        let last_seen_at = user_service::find_by_id(dbsession, contact_id)
+            .await?
+            .and_then(|user| user.last_seen_at);
+        events.push(presence_event(contact_id, is_online, last_seen_at));
+    }
+    Ok(events)
+}
+
+/// Everyone the user shares a conversation or a group with.
+pub async fn contacts_of(dbsession: &web::Data<Session>, user_id: &str) -> Result<Vec<String>, AppError> {
+    let user_uuid = Uuid::parse_str(user_id)
+        .map_err(|e| AppError(format!("Invalid user ID: {}", e), actix_web::http::StatusCode::BAD_REQUEST))?;
+
+    let db_client = DbClient::<User> {
+        session: dbsession,
+        _phantom: PhantomData,
+    };
+
+    let mut contacts = HashSet::new();
+
+    let conversations = db_client.query::<(Uuid,), _>(
+        "SELECT conversation_id FROM user_conversations WHERE user_id = ?",
+        Some((user_uuid,))
+    ).await?;
+    for (conversation_id,) in conversations {
+        let participants = db_client.query::<(Uuid,), _>(
+            "SELECT user_id FROM conversation_participants WHERE conversation_id = ?",
+            Some((conversation_id,))
+        ).await?;
+        contacts.extend(participants.into_iter().map(|(id,)| id));
+    }
+
+    let groups = db_client.query::<(Uuid,), _>(
+        "SELECT group_id FROM group_members WHERE user_id = ? ALLOW FILTERING",
+        Some((user_uuid,))
+    ).await?;
+    for (group_id,) in groups {
+        let members = db_client.query::<(Uuid,), _>(
+            "SELECT user_id FROM group_members WHERE group_id = ?",
+            Some((group_id,))
+        ).await?;
+        contacts.extend(members.into_iter().map(|(id,)| id));
+    }
+
+    contacts.remove(&user_uuid);
+    Ok(contacts.into_iter().map(|id| id.to_string()).collect())
+}
+
+/// Presence is held in memory, so anyone still marked online at startup was
+/// left over from a previous run and is marked offline.
+pub async fn reset_online_users(dbsession: &web::Data<Session>) -> Result<(), AppError> {
+    let db_client = DbClient::<User> {
+        session: dbsession,
+        _phantom: PhantomData,
+    };
+
+    let online = db_client.query::<(Uuid,), ()>(
+        "SELECT id FROM users WHERE is_online = true ALLOW FILTERING",
+        None
+    ).await?;
+    if !online.is_empty() {
+        info!("Marking {} stale online users offline", online.len());
+    }
+    for (user_id,) in online {
+        user_service::update_user_status(dbsession, &user_id.to_string(), false).await?;
+    }
+    Ok(())
+}
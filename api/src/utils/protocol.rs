@@ -0,0 +1,259 @@
+use actix_web::http::StatusCode;
+use log::error;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+/// Version of the socket event protocol. Clients send it as `v` on every
+/// event and the server stamps it on everything it pushes.
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// A client event: `{ "v": 1, "ref": "...", "type": "message.send", "data": { ... } }`.
+/// `ref` is an optional client correlation id echoed on the matching ack or error.
+/// Sockets other than chat rooms carry their own event types in the same envelope.
+#[derive(Debug, Deserialize)]
+pub struct ClientEnvelope<E = ClientEvent> {
+    pub v: u32,
+    #[serde(rename = "ref", default)]
+    pub ref_id: Option<String>,
+    #[serde(flatten)]
+    pub event: E,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", content = "data")]
+pub enum ClientEvent {
+    #[serde(rename = "message.send")]
+    MessageSend {
+        content: String,
+        #[serde(default)]
+        room_id: Option<String>,
+        #[serde(default)]
+        sender_id: Option<String>,
+    },
+    #[serde(rename = "message.edit")]
+    MessageEdit { message_id: String, content: String },
+    #[serde(rename = "message.delete")]
+    MessageDelete { message_id: String },
+    #[serde(rename = "typing")]
+    Typing { is_typing: bool },
+    #[serde(rename = "read")]
+    Read { message_id: String },
+    #[serde(rename = "ping")]
+    Ping,
+}
+
+/// A server event, generic over the message model of the room it is sent in
+/// (`Message` for conversations, `GroupMessage` for groups).
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", content = "data")]
+pub enum ServerEvent<M> {
+    #[serde(rename = "message.new")]
+    MessageNew(M),
+    #[serde(rename = "message.ack")]
+    MessageAck(M),
+    #[serde(rename = "message.edited")]
+    MessageEdited(M),
+    #[serde(rename = "message.deleted")]
+    MessageDeleted(M),
+    #[serde(rename = "typing")]
+    Typing { user_id: String, is_typing: bool },
+    #[serde(rename = "read")]
+    Read { user_id: String, message_id: String },
+    #[serde(rename = "member.joined")]
+    MemberJoined { user_id: String },
+    #[serde(rename = "member.left")]
+    MemberLeft { user_id: String },
+    #[serde(rename = "presence")]
+    Presence { user_id: String, is_online: bool, last_seen_at: Option<i64> },
+    #[serde(rename = "error")]
+    Error { code: ErrorCode, message: String },
+    #[serde(rename = "pong")]
+    Pong,
+}
+
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    InvalidEvent,
+    UnsupportedVersion,
+    Unsupported,
+    BadRequest,
+    Forbidden,
+    NotFound,
+    TooManyRequests,
+    Internal,
+}
+
+impl ErrorCode {
+    pub fn from_status(status: StatusCode) -> Self {
+        match status {
+            StatusCode::BAD_REQUEST => ErrorCode::BadRequest,
+            StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => ErrorCode::Forbidden,
+            StatusCode::NOT_FOUND => ErrorCode::NotFound,
+            StatusCode::TOO_MANY_REQUESTS => ErrorCode::TooManyRequests,
+            _ => ErrorCode::Internal,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct ServerEnvelope<'a, E> {
+    v: u32,
+    #[serde(rename = "ref", skip_serializing_if = "Option::is_none")]
+    ref_id: Option<&'a str>,
+    #[serde(flatten)]
+    event: &'a E,
+}
+
+/// Wraps any server event in the versioned envelope.
+pub fn encode_event<E: Serialize>(event: &E, ref_id: Option<&str>) -> String {
+    let envelope = ServerEnvelope { v: PROTOCOL_VERSION, ref_id, event };
+    serde_json::to_string(&envelope).unwrap_or_else(|e| {
+        error!("Failed to serialize server event: {}", e);
+        format!(
+            r#"{{"v":{},"type":"error","data":{{"code":"internal","message":"Failed to serialize event"}}}}"#,
+            PROTOCOL_VERSION
+        )
+    })
+}
+
+impl<M: Serialize> ServerEvent<M> {
+    pub fn encode(&self, ref_id: Option<&str>) -> String {
+        encode_event(self, ref_id)
+    }
+}
+
+pub fn error_event(code: ErrorCode, message: impl Into<String>, ref_id: Option<&str>) -> String {
+    ServerEvent::<()>::Error { code, message: message.into() }.encode(ref_id)
+}
+
+impl ClientEvent {
+    /// Rejects events that claim a sender or room other than the ones the
+    /// socket was authenticated for on handshake. The claims are optional and
+    /// only checked for consistency; the server always uses the socket's own ids.
+    pub fn authorize(&self, user_id: &str, room_id: &str) -> Result<(), (ErrorCode, String)> {
+        if let ClientEvent::MessageSend { room_id: claimed_room, sender_id: claimed_sender, .. } = self {
+            if let Some(claimed_sender) = claimed_sender {
+                if !claimed_sender.eq_ignore_ascii_case(user_id) {
+                    return Err((ErrorCode::Forbidden, "sender_id does not match the authenticated user".to_string()));
+                }
+            }
+            if let Some(claimed_room) = claimed_room {
+                if !claimed_room.eq_ignore_ascii_case(room_id) {
+                    return Err((ErrorCode::Forbidden, "room_id does not match the connected room".to_string()));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Deserialize)]
+struct VersionProbe {
+    v: Option<u32>,
+}
+
+/// Parses a client frame, rejecting unknown versions before looking at the event.
+pub fn decode(text: &str) -> Result<ClientEnvelope, (ErrorCode, String)> {
+    decode_event(text)
+}
+
+/// [`decode`] for sockets with their own client event type.
+pub fn decode_event<E: DeserializeOwned>(text: &str) -> Result<ClientEnvelope<E>, (ErrorCode, String)> {
+    let probe: VersionProbe = serde_json::from_str(text)
+        .map_err(|e| (ErrorCode::InvalidEvent, format!("Malformed event: {}", e)))?;
+    match probe.v {
+        Some(PROTOCOL_VERSION) => {}
+        Some(v) => return Err((ErrorCode::UnsupportedVersion, format!("Unsupported protocol version {}, expected {}", v, PROTOCOL_VERSION))),
+        None => return Err((ErrorCode::UnsupportedVersion, format!("Missing protocol version, expected {}", PROTOCOL_VERSION))),
+    }
+    serde_json::from_str(text).map_err(|e| (ErrorCode::InvalidEvent, format!("Invalid event: {}", e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_message_send_with_ref() {
+        let envelope = decode(r#"{"v":1,"ref":"c1","type":"message.send","data":{"content":"hi"}}"#).unwrap();
+        assert_eq!(envelope.ref_id.as_deref(), Some("c1"));
+        match envelope.event {
+            ClientEvent::MessageSend { content, room_id, sender_id } => {
+                assert_eq!(content, "hi");
+                assert!(room_id.is_none());
+                assert!(sender_id.is_none());
+            }
+            other => panic!("unexpected event: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn decodes_ping_without_data() {
+        let envelope = decode(r#"{"v":1,"type":"ping"}"#).unwrap();
+        assert!(matches!(envelope.event, ClientEvent::Ping));
+    }
+
+    #[test]
+    fn rejects_unknown_version_and_type() {
+        assert_eq!(decode(r#"{"v":2,"type":"ping"}"#).unwrap_err().0, ErrorCode::UnsupportedVersion);
+        assert_eq!(decode(r#"{"type":"ping"}"#).unwrap_err().0, ErrorCode::UnsupportedVersion);
+        assert_eq!(decode(r#"{"v":1,"type":"shout"}"#).unwrap_err().0, ErrorCode::InvalidEvent);
+        assert_eq!(decode("not json").unwrap_err().0, ErrorCode::InvalidEvent);
+    }
+
+    const USER: &str = "6f1c1d52-8a59-4b5e-9a53-0d6f5b1f3c11";
+    const OTHER_USER: &str = "0b8f2f0e-2f43-4c0b-8d34-5a2c4f9e7d22";
+    const ROOM: &str = "a3e4b7c2-1d2e-4f5a-8b9c-0d1e2f3a4b5c";
+    const OTHER_ROOM: &str = "d4c3b2a1-0f9e-4d8c-9b7a-6f5e4d3c2b1a";
+
+    fn send_event(room_id: Option<&str>, sender_id: Option<&str>) -> ClientEvent {
+        ClientEvent::MessageSend {
+            content: "hi".to_string(),
+            room_id: room_id.map(str::to_string),
+            sender_id: sender_id.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn authorize_rejects_spoofed_sender() {
+        let err = send_event(None, Some(OTHER_USER)).authorize(USER, ROOM).unwrap_err();
+        assert_eq!(err.0, ErrorCode::Forbidden);
+    }
+
+    #[test]
+    fn authorize_rejects_spoofed_room() {
+        let err = send_event(Some(OTHER_ROOM), Some(USER)).authorize(USER, ROOM).unwrap_err();
+        assert_eq!(err.0, ErrorCode::Forbidden);
+    }
+
+    #[test]
+    fn authorize_accepts_own_or_missing_claims() {
+        assert!(send_event(None, None).authorize(USER, ROOM).is_ok());
+        assert!(send_event(Some(ROOM), Some(USER)).authorize(USER, ROOM).is_ok());
+        assert!(send_event(Some(&ROOM.to_uppercase()), None).authorize(USER, ROOM).is_ok());
+        assert!(ClientEvent::Ping.authorize(USER, ROOM).is_ok());
+    }
+
+    #[test]
+    fn authorize_rejects_spoofed_sender_from_wire() {
+        let frame = format!(
+            r#"{{"v":1,"type":"message.send","data":{{"content":"hi","room_id":"{}","sender_id":"{}"}}}}"#,
+            ROOM, OTHER_USER
+        );
+        let envelope = decode(&frame).unwrap();
+        assert!(envelope.event.authorize(USER, ROOM).is_err());
+    }
+
+    #[test]
+    fn encodes_envelope() {
+        let pong = ServerEvent::<()>::Pong.encode(Some("c2"));
+        assert_eq!(pong, r#"{"v":1,"ref":"c2","type":"pong"}"#);
+
+        let error = error_event(ErrorCode::Forbidden, "nope", None);
+        assert_eq!(error, r#"{"v":1,"type":"error","data":{"code":"forbidden","message":"nope"}}"#);
+
+        let presence = ServerEvent::<()>::Presence { user_id: USER.to_string(), is_online: false, last_seen_at: Some(42) }.encode(None);
+        assert_eq!(presence, format!(r#"{{"v":1,"type":"presence","data":{{"user_id":"{}","is_online":false,"last_seen_at":42}}}}"#, USER));
+    }
+}
@@ -0,0 +1,156 @@
+use actix_web::http::StatusCode;
+use actix_web::web;
+use chrono::Utc;
+use scylla::client::session::Session;
+use scylla::value::{CqlTimestamp, CqlTimeuuid};
+use std::collections::HashMap;
+use std::marker::PhantomData;
+use uuid::Uuid;
+use crate::error::AppError;
+use crate::models::message::ReadMarker;
+use crate::utils::db_client::{DbClient, MAX_KEYS_PER_QUERY};
+
+/// Unread counts are computed by scanning at most this many newer messages.
+pub const MAX_UNREAD_COUNT: i32 = 1000;
+
+/// Which kind of room a marker is kept for, and so where its messages live.
+#[derive(Debug, Clone, Copy)]
+pub enum RoomKind {
+    Conversation,
+    Group,
+}
+
+impl RoomKind {
+    fn message_query(self) -> &'static str {
+        match self {
+            RoomKind::Conversation => "SELECT message_id FROM messages WHERE conversation_id = ? AND message_id = ?",
+            RoomKind::Group => "SELECT message_id FROM group_messages WHERE group_id = ? AND message_id = ?",
+        }
+    }
+}
+
+/// Per-user "last read message" markers for conversations and groups, keyed
+/// by the room id so both kinds of room share one table.
+pub struct ReadMarkers {
+    session: web::Data<Session>,
+}
+
+impl ReadMarkers {
+    pub fn new(session: web::Data<Session>) -> Self {
+        Self { session }
+    }
+
+    pub async fn get(&self, room_id: &str, user_id: &str) -> Result<Option<CqlTimeuuid>, AppError> {
+        let room_uuid = parse_uuid(room_id, "room")?;
+        let user_uuid = parse_uuid(user_id, "user")?;
+
+        let db_client = DbClient::<ReadMarker> {
+            session: &self.session,
+            _phantom: PhantomData,
+        };
+
+        let results = db_client.query::<(CqlTimeuuid,), _>(
+            "SELECT last_read_message_id FROM read_markers WHERE room_id = ? AND user_id = ?",
+            Some((room_uuid, user_uuid))
+        ).await?;
+
+        Ok(results.first().map(|(message_id,)| *message_id))
+    }
+
+    /// The user's markers in many rooms, one query per chunk of rooms. Rooms
+    /// the user has never read are missing from the map.
+    pub async fn get_many(&self, room_ids: &[Uuid], user_id: &str) -> Result<HashMap<Uuid, CqlTimeuuid>, AppError> {
+        let user_uuid = parse_uuid(user_id, "user")?;
+
+        let db_client = DbClient::<ReadMarker> {
+            session: &self.session,
+            _phantom: PhantomData,
+        };
+
+        let mut markers = HashMap::with_capacity(room_ids.len());
+        for chunk in room_ids.chunks(MAX_KEYS_PER_QUERY) {
+            let results = db_client.query::<(Uuid, CqlTimeuuid), _>(
+                "SELECT room_id, last_read_message_id FROM read_markers WHERE room_id IN ? AND user_id = ?",
+                Some((chunk.to_vec(), user_uuid))
+            ).await?;
+            markers.extend(results);
+        }
+        Ok(markers)
+    }
+
+    pub async fn list(&self, room_id: &str) -> Result<Vec<ReadMarker>, AppError> {
+        let room_uuid = parse_uuid(room_id, "room")?;
+
+        let db_client = DbClient::<ReadMarker> {
+            session: &self.session,
+            _phantom: PhantomData,
+        };
+
+        let results = db_client.query::<(Uuid, CqlTimeuuid, CqlTimestamp), _>(
+            "SELECT user_id, last_read_message_id, updated_at FROM read_markers WHERE room_id = ?",
+            Some((room_uuid,))
+        ).await?;
+
+        Ok(results
+            .into_iter()
+            .map(|(user_id, message_id, updated_at)| ReadMarker {
+                user_id: user_id.to_string(),
+                last_read_message_id: Uuid::from_bytes(*message_id.as_bytes()).to_string(),
+                updated_at: updated_at.0,
+            })
+            .collect())
+    }
+
+    /// Moves the user's marker forward to `message_id`, which must be a
+    /// message of the room; callers have already checked the user belongs
+    /// there. Returns `false` without writing when the marker is already at or
+    /// past that message.
+    pub async fn mark_read(&self, kind: RoomKind, room_id: &str, user_id: &str, message_id: &str) -> Result<bool, AppError> {
+        let room_uuid = parse_uuid(room_id, "room")?;
+        let user_uuid = parse_uuid(user_id, "user")?;
+        let message_uuid = parse_uuid(message_id, "message")?;
+        let message_ts = timeuuid_timestamp(&message_uuid)
+            .ok_or_else(|| AppError("Invalid message ID: not a TIMEUUID".to_string(), StatusCode::BAD_REQUEST))?;
+
+        let db_client = DbClient::<ReadMarker> {
+            session: &self.session,
+            _phantom: PhantomData,
+        };
+
+        let message = db_client.query::<(CqlTimeuuid,), _>(
+            kind.message_query(),
+            Some((room_uuid, CqlTimeuuid::from_bytes(*message_uuid.as_bytes())))
+        ).await?;
+        if message.is_empty() {
+            return Err(AppError("Message not found".to_string(), StatusCode::NOT_FOUND));
+        }
+
+        if let Some(current) = self.get(room_id, user_id).await? {
+            let current = Uuid::from_bytes(*current.as_bytes());
+            if timeuuid_timestamp(&current).is_some_and(|current_ts| current_ts >= message_ts) {
+                return Ok(false);
+            }
+        }
+
+        db_client.insert(
+            "INSERT INTO read_markers (room_id, user_id, last_read_message_id, updated_at) VALUES (?, ?, ?, ?)",
+            (
+                room_uuid,
+                user_uuid,
+                CqlTimeuuid::from_bytes(*message_uuid.as_bytes()),
+                CqlTimestamp(Utc::now().timestamp_millis()),
+            )
+        ).await?;
+
+        Ok(true)
+    }
+}
+
+fn parse_uuid(value: &str, kind: &str) -> Result<Uuid, AppError> {
+    Uuid::parse_str(value)
+        .map_err(|e| AppError(format!("Invalid {} ID: {}", kind, e), StatusCode::BAD_REQUEST))
+}
+
+fn timeuuid_timestamp(uuid: &Uuid) -> Option<(u64, u32)> {
+    uuid.get_timestamp().map(|ts| ts.to_unix())
+}
@@ -0,0 +1,113 @@
+use std::collections::HashSet;
+use crate::models::post::Post;
+use crate::utils::tags::normalize_tag;
+use crate::utils::terms::{self, IndexField};
+
+/// Common English words that would match nearly every post.
+const STOP_WORDS: &[&str] = &[
+    "a", "an", "and", "are", "as", "at", "be", "by", "for", "from", "how", "i", "in", "is", "it",
+    "my", "of", "on", "or", "that", "the", "this", "to", "was", "what", "with",
+];
+
+/// Upper bound on the terms taken from one search query.
+pub const MAX_SEARCH_TERMS: usize = 10;
+/// Upper bound on the distinct terms indexed from a post's code, so a large
+/// paste does not turn into thousands of index rows.
+pub const MAX_CODE_TERMS: usize = 300;
+
+/// Parts of a post that feed the search index.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SearchField {
+    Title,
+    Tags,
+    Content,
+    Code,
+}
+
+impl IndexField for SearchField {
+    const ALL: &'static [SearchField] = &[SearchField::Title, SearchField::Tags, SearchField::Content, SearchField::Code];
+
+    fn as_str(&self) -> &'static str {
+        match self {
+            SearchField::Title => "title",
+            SearchField::Tags => "tags",
+            SearchField::Content => "content",
+            SearchField::Code => "code",
+        }
+    }
+
+    fn weight(&self) -> u32 {
+        match self {
+            SearchField::Title | SearchField::Tags => 3,
+            SearchField::Content => 2,
+            SearchField::Code => 1,
+        }
+    }
+}
+
+/// Search terms of a query or a post's text.
+pub fn terms(text: &str) -> Vec<String> {
+    terms::terms(text, STOP_WORDS)
+}
+
+/// The normalized tags of a post, skipping any that cannot be normalized.
+pub fn post_tags(post: &Post) -> Vec<String> {
+    let mut tags: Vec<String> = Vec::new();
+    for tag in post.tags.iter().flatten().filter_map(|tag| normalize_tag(tag)) {
+        if !tags.contains(&tag) {
+            tags.push(tag);
+        }
+    }
+    tags
+}
+
+/// Every `(field, term)` pair a post should be findable by. Multi-word tags
+/// such as "serde-json" are indexed word by word.
+pub fn post_terms(post: &Post) -> HashSet<(SearchField, String)> {
+    let code_terms = post.code.as_deref().map(terms).unwrap_or_default();
+    let tag_terms = post_tags(post).into_iter().flat_map(|tag| terms(&tag));
+
+    terms(&post.title).into_iter().map(|term| (SearchField::Title, term))
+        .chain(tag_terms.map(|term| (SearchField::Tags, term)))
+        .chain(terms(&post.content).into_iter().map(|term| (SearchField::Content, term)))
+        .chain(code_terms.into_iter().take(MAX_CODE_TERMS).map(|term| (SearchField::Code, term)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_identifiers_whole() {
+        assert_eq!(
+            terms("How to use fs::read_to_string in Rust?"),
+            vec!["use", "fs", "read_to_string", "rust"]
+        );
+        assert_eq!(terms("C++ vs. c# and __init__"), vec!["c++", "vs", "c#", "init"]);
+    }
+
+    #[test]
+    fn indexes_every_field() {
+        let mut post = Post::new(
+            "id".to_string(),
+            "user".to_string(),
+            "Parsing JSON".to_string(),
+            "A small serde example".to_string(),
+            Some("let v: Value = serde_json::from_str(s)?;".to_string()),
+            Some("rust".to_string()),
+            Some(vec!["Serde JSON".to_string(), "no/slashes".to_string()]),
+            true,
+        );
+        let indexed = post_terms(&post);
+        assert!(indexed.contains(&(SearchField::Title, "json".to_string())));
+        assert!(indexed.contains(&(SearchField::Tags, "serde".to_string())));
+        assert!(indexed.contains(&(SearchField::Tags, "json".to_string())));
+        assert!(indexed.contains(&(SearchField::Content, "serde".to_string())));
+        assert!(indexed.contains(&(SearchField::Code, "from_str".to_string())));
+        assert_eq!(post_tags(&post), vec!["serde-json"]);
+
+        post.code = None;
+        assert!(!post_terms(&post).iter().any(|(field, _)| *field == SearchField::Code));
+    }
+}
@@ -2,10 +2,11 @@ use actix_web::web;
 use futures::TryStreamExt;
 use scylla::{client::session::Session, statement::Statement};
 use scylla::value::{CqlTimestamp, CqlTimeuuid};
-use uuid::{NoContext, Timestamp, Uuid};
+use uuid::Uuid;
 use chrono::Utc;
 use bcrypt;
 use crate::error::AppError;
+use crate::utils::delivery::timeuuid_at;
 use actix_web::http::StatusCode;
 use serde::{Deserialize, Serialize};
 
@@ -135,10 +136,7 @@ pub async fn seed_database(session: &web::Data<Session>) -> Result<(), AppError>
             let now = Utc::now();
             let message_timestamp = now.timestamp() + (index as i64 * 60);
             
-            let message_id = Uuid::new_v1(
-                Timestamp::from_unix(&NoContext, message_timestamp as u64, 0),
-                &[1, 2, 3, 4, 5, 6],
-            );
+            let message_id = timeuuid_at(message_timestamp as u64, 0);
             
             let sender_id = if index % 2 == 0 { user_pairs[i] } else { user_pairs[i + 1] };
 
@@ -0,0 +1,200 @@
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpRequest};
+use chrono::Utc;
+use log::warn;
+use rand::Rng;
+use scylla::client::session::Session;
+use scylla::value::CqlTimestamp;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::marker::PhantomData;
+use uuid::Uuid;
+use crate::error::AppError;
+use crate::utils::db_client::DbClient;
+use crate::utils::delivery::new_event_id;
+
+/// Where a login came from, recorded on its session.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DeviceInfo {
+    pub device_name: Option<String>,
+    pub user_agent: Option<String>,
+    pub ip_address: Option<String>,
+}
+
+impl DeviceInfo {
+    pub fn from_request(req: &HttpRequest, device_name: Option<String>) -> Self {
+        Self {
+            device_name,
+            user_agent: req
+                .headers()
+                .get("User-Agent")
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_string),
+            ip_address: req.connection_info().realip_remote_addr().map(str::to_string),
+        }
+    }
+}
+
+/// A login session: one per device, identified in access tokens by the `sid`
+/// claim and renewed with single-use refresh tokens.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AuthSession {
+    pub id: String,
+    pub user_id: String,
+}
+
+fn generate_refresh_token() -> String {
+    let bytes: [u8; 32] = rand::rng().random();
+    hex(&bytes)
+}
+
+/// Refresh tokens are stored as SHA-256 digests so a leaked table cannot be
+/// replayed.
+fn hash_refresh_token(token: &str) -> String {
+    hex(&Sha256::digest(token.as_bytes()))
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn invalid_refresh_token() -> AppError {
+    AppError("Invalid refresh token".to_string(), StatusCode::UNAUTHORIZED)
+}
+
+pub struct SessionStore {
+    session: web::Data<Session>,
+}
+
+impl SessionStore {
+    pub fn new(session: web::Data<Session>) -> Self {
+        Self { session }
+    }
+
+    /// Opens a session for a fresh login and returns it with its first
+    /// refresh token.
+    pub async fn create(&self, user_id: &str, device: DeviceInfo) -> Result<(AuthSession, String), AppError> {
+        let user_uuid = Uuid::parse_str(user_id)
+            .map_err(|e| AppError(format!("Invalid user ID: {}", e), StatusCode::BAD_REQUEST))?;
+        let session_id = new_event_id();
+        let now = CqlTimestamp(Utc::now().timestamp_millis());
+
+        let db_client = DbClient::<AuthSession> {
+            session: &self.session,
+            _phantom: PhantomData,
+        };
+
+        db_client.insert(
+            "INSERT INTO auth_sessions (session_id, user_id, device_name, user_agent, ip_address, created_at, last_used_at, revoked) VALUES (?, ?, ?, ?, ?, ?, ?, ?)",
+            (session_id, user_uuid, device.device_name, device.user_agent, device.ip_address, now, now, false)
+        ).await?;
+
+        let refresh_token = self.issue_refresh_token(session_id, user_uuid).await?;
+
+        Ok((AuthSession { id: session_id.to_string(), user_id: user_id.to_string() }, refresh_token))
+    }
+
+    async fn issue_refresh_token(&self, session_id: Uuid, user_id: Uuid) -> Result<String, AppError> {
+        let refresh_token = generate_refresh_token();
+        let now = CqlTimestamp(Utc::now().timestamp_millis());
+
+        let db_client = DbClient::<AuthSession> {
+            session: &self.session,
+            _phantom: PhantomData,
+        };
+
+        db_client.insert(
+            "INSERT INTO refresh_tokens (token_hash, session_id, user_id, created_at, used) VALUES (?, ?, ?, ?, ?)",
+            (hash_refresh_token(&refresh_token), session_id, user_id, now, false)
+        ).await?;
+
+        Ok(refresh_token)
+    }
+
+    /// Exchanges a refresh token for a new one on the same session. Each token
+    /// works once; presenting a spent token means it was stolen or replayed,
+    /// so the whole session is revoked.
+    pub async fn rotate(&self, refresh_token: &str) -> Result<(AuthSession, String), AppError> {
+        let token_hash = hash_refresh_token(refresh_token);
+
+        let db_client = DbClient::<AuthSession> {
+            session: &self.session,
+            _phantom: PhantomData,
+        };
+
+        let results = db_client.query::<(Uuid, Uuid), _>(
+            "SELECT session_id, user_id FROM refresh_tokens WHERE token_hash = ?",
+            Some((token_hash.clone(),))
+        ).await?;
+        let (session_id, user_id) = *results.first().ok_or_else(invalid_refresh_token)?;
+
+        if !self.is_active(session_id).await? {
+            return Err(invalid_refresh_token());
+        }
+
+        // Spending the token is a single conditional write, so of two
+        // refreshes racing with the same token only one gets through.
+        let spent = db_client.apply(
+            "UPDATE refresh_tokens SET used = true WHERE token_hash = ? IF used = false",
+            (token_hash,)
+        ).await?;
+        if !spent {
+            warn!("Refresh token reuse detected for session {}; revoking it", session_id);
+            self.revoke(session_id).await?;
+            return Err(invalid_refresh_token());
+        }
+        db_client.insert(
+            "UPDATE auth_sessions SET last_used_at = ? WHERE session_id = ?",
+            (CqlTimestamp(Utc::now().timestamp_millis()), session_id)
+        ).await?;
+
+        let refresh_token = self.issue_refresh_token(session_id, user_id).await?;
+
+        Ok((AuthSession { id: session_id.to_string(), user_id: user_id.to_string() }, refresh_token))
+    }
+
+    /// Revokes the session a refresh token belongs to. Unknown tokens are
+    /// ignored so logging out twice is harmless.
+    pub async fn revoke_by_refresh_token(&self, refresh_token: &str) -> Result<(), AppError> {
+        let db_client = DbClient::<AuthSession> {
+            session: &self.session,
+            _phantom: PhantomData,
+        };
+
+        let results = db_client.query::<(Uuid,), _>(
+            "SELECT session_id FROM refresh_tokens WHERE token_hash = ?",
+            Some((hash_refresh_token(refresh_token),))
+        ).await?;
+
+        match results.first() {
+            Some((session_id,)) => self.revoke(*session_id).await,
+            None => Ok(()),
+        }
+    }
+
+    pub async fn revoke(&self, session_id: Uuid) -> Result<(), AppError> {
+        let db_client = DbClient::<AuthSession> {
+            session: &self.session,
+            _phantom: PhantomData,
+        };
+
+        db_client.insert(
+            "UPDATE auth_sessions SET revoked = ? WHERE session_id = ?",
+            (true, session_id)
+        ).await
+    }
+
+    pub async fn is_active(&self, session_id: Uuid) -> Result<bool, AppError> {
+        let db_client = DbClient::<AuthSession> {
+            session: &self.session,
+            _phantom: PhantomData,
+        };
+
+        let results = db_client.query::<(Option<bool>,), _>(
+            "SELECT revoked FROM auth_sessions WHERE session_id = ?",
+            Some((session_id,))
+        ).await?;
+
+        Ok(matches!(results.first(), Some((revoked,)) if !revoked.unwrap_or(false)))
+    }
+}
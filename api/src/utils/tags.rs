@@ -0,0 +1,60 @@
+use actix_web::http::StatusCode;
+use crate::error::AppError;
+
+pub const MAX_TAG_LENGTH: usize = 32;
+
+/// Lowercases a tag and turns runs of whitespace or underscores into single
+/// hyphens, so "Machine Learning" and "machine_learning" are the same tag.
+/// Returns `None` for tags that are empty or contain anything but letters,
+/// digits, hyphens, `+`, `#` and `.` after that.
+pub fn normalize_tag(tag: &str) -> Option<String> {
+    let mut normalized = String::with_capacity(tag.len());
+    for word in tag.trim().split(|c: char| c.is_whitespace() || c == '_').filter(|w| !w.is_empty()) {
+        if !normalized.is_empty() {
+            normalized.push('-');
+        }
+        normalized.push_str(&word.to_lowercase());
+    }
+
+    let valid = !normalized.is_empty()
+        && normalized.chars().count() <= MAX_TAG_LENGTH
+        && normalized.chars().all(|c| c.is_alphanumeric() || matches!(c, '-' | '+' | '#' | '.'));
+    valid.then_some(normalized)
+}
+
+/// Normalizes and de-duplicates a list of tags, keeping their order.
+pub fn normalize_tags(tags: &[String], max_tags: usize) -> Result<Vec<String>, AppError> {
+    let mut normalized: Vec<String> = Vec::with_capacity(tags.len());
+    for tag in tags {
+        let tag = normalize_tag(tag)
+            .ok_or_else(|| AppError(format!("Invalid tag: {:?}", tag), StatusCode::BAD_REQUEST))?;
+        if !normalized.contains(&tag) {
+            normalized.push(tag);
+        }
+    }
+    if normalized.len() > max_tags {
+        return Err(AppError(format!("At most {} tags are allowed", max_tags), StatusCode::BAD_REQUEST));
+    }
+    Ok(normalized)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalizes_case_and_separators() {
+        assert_eq!(normalize_tag("  Machine Learning ").as_deref(), Some("machine-learning"));
+        assert_eq!(normalize_tag("machine_learning").as_deref(), Some("machine-learning"));
+        assert_eq!(normalize_tag("C++").as_deref(), Some("c++"));
+        assert_eq!(normalize_tag("").as_deref(), None);
+        assert_eq!(normalize_tag("no/slashes").as_deref(), None);
+    }
+
+    #[test]
+    fn dedupes_and_limits() {
+        let tags = vec!["Rust".to_string(), "rust".to_string(), "Go".to_string()];
+        assert_eq!(normalize_tags(&tags, 5).unwrap(), vec!["rust", "go"]);
+        assert!(normalize_tags(&tags, 1).is_err());
+    }
+}
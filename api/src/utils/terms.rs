@@ -0,0 +1,46 @@
+use std::collections::HashSet;
+use std::hash::Hash;
+
+/// Terms longer than this are not indexed; they are hashes, base64 and the like.
+const MAX_TERM_LENGTH: usize = 40;
+
+/// A part of a record that feeds one of the term indexes (post search, user
+/// matching), with the weight a hit on it adds to the record's score.
+pub trait IndexField: Copy + Eq + Hash + 'static {
+    const ALL: &'static [Self];
+
+    fn as_str(&self) -> &'static str;
+
+    fn weight(&self) -> u32;
+
+    fn from_column(value: &str) -> Option<Self> {
+        Self::ALL.iter().copied().find(|field| field.as_str() == value)
+    }
+}
+
+/// Splits text into lowercase index terms. Identifiers stay whole, so
+/// "read_to_string" is one term, and `+`, `#` and `.` stay part of a word so
+/// "c++", "c#" and "node.js" survive; `stop_words`, overlong terms and
+/// duplicates are dropped.
+pub fn terms(text: &str, stop_words: &[&str]) -> Vec<String> {
+    let mut seen = HashSet::new();
+    text.to_lowercase()
+        .split(|c: char| !(c.is_alphanumeric() || matches!(c, '_' | '+' | '#' | '.')))
+        .map(|word| word.trim_matches(|c| c == '.' || c == '_'))
+        .filter(|word| !word.is_empty() && word.chars().count() <= MAX_TERM_LENGTH && !stop_words.contains(word))
+        .filter(|word| seen.insert(word.to_string()))
+        .map(str::to_string)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn drops_stop_words_and_overlong_terms() {
+        let hash = "a".repeat(MAX_TERM_LENGTH + 1);
+        assert_eq!(terms(&format!("The {} digest of Node.js", hash), &["the", "of"]), vec!["digest", "node.js"]);
+        assert_eq!(terms("_private __init__ ..", &[]), vec!["private", "init"]);
+    }
+}
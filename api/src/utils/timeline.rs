@@ -0,0 +1,88 @@
+use actix_web::http::StatusCode;
+use chrono::{DateTime, Datelike, TimeZone, Utc};
+use uuid::Uuid;
+use crate::error::AppError;
+
+/// Timeline partitions are calendar months (UTC), named like "2026-10".
+pub fn bucket_of(millis: i64) -> String {
+    let at = DateTime::from_timestamp_millis(millis).unwrap_or_default();
+    format!("{:04}-{:02}", at.year(), at.month())
+}
+
+/// First millisecond of the month `millis` falls in.
+pub fn bucket_start(millis: i64) -> i64 {
+    let at = DateTime::from_timestamp_millis(millis).unwrap_or_default();
+    Utc.with_ymd_and_hms(at.year(), at.month(), 1, 0, 0, 0)
+        .single()
+        .map(|start| start.timestamp_millis())
+        .unwrap_or_default()
+}
+
+/// Position in a timeline: everything strictly older than this post. Sent to
+/// clients as `"<created_at millis>:<post id>"`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimelineCursor {
+    pub created_at: i64,
+    pub post_id: Uuid,
+}
+
+impl TimelineCursor {
+    /// A cursor just past the newest possible post.
+    pub fn now() -> Self {
+        Self::before_millis(Utc::now().timestamp_millis() + 1)
+    }
+
+    /// A cursor that includes every post created before `millis`.
+    pub fn before_millis(millis: i64) -> Self {
+        Self { created_at: millis - 1, post_id: Uuid::max() }
+    }
+
+    pub fn parse(value: &str) -> Result<Self, AppError> {
+        let invalid = || AppError(format!("Invalid cursor: {:?}", value), StatusCode::BAD_REQUEST);
+        let (created_at, post_id) = value.split_once(':').ok_or_else(invalid)?;
+        Ok(Self {
+            created_at: created_at.parse().map_err(|_| invalid())?,
+            post_id: Uuid::parse_str(post_id).map_err(|_| invalid())?,
+        })
+    }
+
+    pub fn encode(&self) -> String {
+        format!("{}:{}", self.created_at, self.post_id)
+    }
+}
+
+/// Hacker News style ranking: likes decay with age, so a fresh post with a
+/// few likes can outrank an old one with many.
+pub fn trending_score(likes: i64, age_millis: i64) -> f64 {
+    let age_hours = age_millis.max(0) as f64 / 3_600_000.0;
+    (likes.max(0) as f64 + 1.0) / (age_hours + 2.0).powf(1.5)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn buckets_by_month() {
+        let millis = Utc.with_ymd_and_hms(2026, 10, 18, 12, 30, 0).unwrap().timestamp_millis();
+        assert_eq!(bucket_of(millis), "2026-10");
+        let start = bucket_start(millis);
+        assert_eq!(bucket_of(start), "2026-10");
+        assert_eq!(bucket_of(start - 1), "2026-09");
+    }
+
+    #[test]
+    fn cursor_round_trips() {
+        let cursor = TimelineCursor { created_at: 1_760_000_000_000, post_id: Uuid::nil() };
+        assert_eq!(TimelineCursor::parse(&cursor.encode()).unwrap(), cursor);
+        assert!(TimelineCursor::parse("yesterday").is_err());
+        assert!(TimelineCursor::parse("12:not-a-uuid").is_err());
+    }
+
+    #[test]
+    fn recent_likes_beat_old_likes() {
+        let hour = 3_600_000;
+        assert!(trending_score(5, hour) > trending_score(5, 48 * hour));
+        assert!(trending_score(3, hour) > trending_score(30, 7 * 24 * hour));
+    }
+}
@@ -1,45 +1,452 @@
 use actix_web::{rt, web, Error, HttpRequest, HttpResponse};
-use actix_ws::Message;
+use actix_ws::{CloseCode, CloseReason, Message};
 use futures_util::StreamExt as _;
 use scylla::client::session::Session;
 use log::{info, error, warn, debug};
-use actix_web::http::StatusCode;
-use jsonwebtoken::{decode, DecodingKey, Validation};
-use scylla::SerializeRow;
 use serde::{Deserialize, Serialize};
-use std::env;
-use std::collections::{HashMap, VecDeque};
+use std::collections::HashMap;
 use std::sync::Arc;
+use std::time::Instant;
 use tokio::sync::{RwLock, mpsc};
-use crate::models::message::NewMessage;
-use crate::models::group::NewGroupMessage;
-use crate::models::user::User;
-use crate::utils::jwt::Claims;
+use uuid::Uuid;
+use crate::models::message::{Message as ChatMessage, NewMessage, UpdateMessage};
+use crate::models::group::{GroupMessage, NewGroupMessage};
+use crate::utils::jwt::Authenticator;
 use crate::error::AppError;
-use crate::utils::db_client::DbClient;
+use crate::utils::delivery::{new_event_id, DeliveryQueue};
+use crate::utils::presence::{self, PresenceStore, CLIENT_TIMEOUT, HEARTBEAT_INTERVAL};
+use crate::utils::protocol::{self, error_event, ClientEnvelope, ClientEvent, ErrorCode, ServerEvent};
+use crate::utils::read_markers::{ReadMarkers, RoomKind};
 use crate::conversations::service as conversation_service;
 use crate::groups::service as group_service;
 
+/// A payload queued for one socket, tagged with the TIMEUUID used to persist
+/// it in the delivery queue if it cannot be written to the socket.
+pub struct OutboundEvent {
+    pub id: Uuid,
+    pub payload: String,
+}
+
+/// What a room sends down one socket: an event to write, or notice that the
+/// user was evicted and the socket should close.
+pub enum RoomSignal {
+    Event(OutboundEvent),
+    Evicted,
+}
+
+#[derive(Default)]
 pub struct RoomState {
-    pub senders: HashMap<String, Vec<mpsc::UnboundedSender<String>>>,
-    pub pending_messages: HashMap<String, VecDeque<String>>,     
+    /// Open sockets per user, each keyed so that closing one leaves the
+    /// user's other tabs connected.
+    pub senders: HashMap<String, Vec<(Uuid, mpsc::UnboundedSender<RoomSignal>)>>,
+    /// Members of a group room, read when a socket connects and kept current
+    /// by [`set_members`]; unused for conversations.
+    pub members: Vec<String>,
 }
 
 pub type RoomStore = Arc<RwLock<HashMap<String, RoomState>>>;
 
+/// Sends `payload` to every live connection of every recipient except
+/// `sender_id`, returning the recipients it could not reach.
+pub async fn broadcast_live<'a>(
+    room_store: &RoomStore,
+    room_id: &str,
+    recipient_ids: &'a [String],
+    sender_id: &str,
+    event_id: Uuid,
+    payload: &str,
+) -> Vec<&'a String> {
+    let mut undelivered = Vec::new();
+    let store = room_store.read().await;
+    let room = store.get(room_id);
+    for recipient_id in recipient_ids {
+        if recipient_id == sender_id {
+            debug!("Skipping echo to sender user {}.", sender_id);
+            continue;
+        }
+        match room.and_then(|room| room.senders.get(recipient_id)) {
+            Some(txs) => {
+                debug!("Sending message to connected user {}: {}", recipient_id, payload);
+                let mut all_failed = true;
+                for (_, tx) in txs {
+                    let event = OutboundEvent { id: event_id, payload: payload.to_string() };
+                    if let Err(e) = tx.send(RoomSignal::Event(event)) {
+                        error!("Failed to send message to user {} on one connection: {}", recipient_id, e);
+                    } else {
+                        all_failed = false;
+                    }
+                }
+                if all_failed {
+                    error!("All connections failed for user {}.", recipient_id);
+                    undelivered.push(recipient_id);
+                }
+            }
+            None => {
+                debug!("User {} not connected.", recipient_id);
+                undelivered.push(recipient_id);
+            }
+        }
+    }
+    undelivered
+}
+
+/// Replaces the member list of a group room with open sockets, after
+/// someone joined or left.
+pub async fn set_members(room_store: &RoomStore, room_id: &str, member_ids: &[String]) {
+    if let Some(room) = room_store.write().await.get_mut(room_id) {
+        room.members = member_ids.to_vec();
+    }
+}
+
+/// Registers a socket of `user_id` in a room, returning a key for [`leave`].
+async fn join(room_store: &RoomStore, room_id: &str, user_id: &str, tx: mpsc::UnboundedSender<RoomSignal>) -> Uuid {
+    let key = new_event_id();
+    let mut store = room_store.write().await;
+    let room = store.entry(room_id.to_string()).or_insert_with(|| {
+        info!("Creating new RoomState for room {}", room_id);
+        RoomState::default()
+    });
+    room.senders.entry(user_id.to_string()).or_default().push((key, tx));
+    key
+}
+
+/// Removes the one socket registered under `key`, dropping the room once
+/// nobody is connected to it.
+async fn leave(room_store: &RoomStore, room_id: &str, user_id: &str, key: Uuid) {
+    let mut store = room_store.write().await;
+    let Some(room) = store.get_mut(room_id) else {
+        warn!("RoomState for room {} not found during cleanup.", room_id);
+        return;
+    };
+    info!("Cleaning up a connection of user {} from room {}", user_id, room_id);
+    if let Some(txs) = room.senders.get_mut(user_id) {
+        txs.retain(|(tx_key, _)| *tx_key != key);
+        if txs.is_empty() {
+            room.senders.remove(user_id);
+        }
+    }
+    if room.senders.is_empty() {
+        info!("No more users in room {}. Removing RoomState.", room_id);
+        store.remove(room_id);
+    }
+}
+
+/// Closes every socket a user has open on a room; used when someone leaves
+/// or is removed from a group.
+pub async fn evict(room_store: &RoomStore, room_id: &str, user_id: &str) {
+    let mut store = room_store.write().await;
+    if let Some(room) = store.get_mut(room_id) {
+        for (_, tx) in room.senders.remove(user_id).unwrap_or_default() {
+            let _ = tx.send(RoomSignal::Evicted);
+        }
+        if room.senders.is_empty() {
+            store.remove(room_id);
+        }
+    }
+}
+
+/// Like [`broadcast_live`], but recipients that are offline, or whose
+/// connections all failed, get the payload persisted in the delivery queue
+/// under `event_id`.
+pub async fn broadcast(
+    dbsession: &web::Data<Session>,
+    room_store: &RoomStore,
+    room_id: &str,
+    recipient_ids: &[String],
+    sender_id: &str,
+    event_id: Uuid,
+    payload: &str,
+) {
+    let undelivered = broadcast_live(room_store, room_id, recipient_ids, sender_id, event_id, payload).await;
+
+    let queue = DeliveryQueue::new(dbsession.clone());
+    for recipient_id in undelivered {
+        debug!("Queuing message for user {} in room {}.", recipient_id, room_id);
+        if let Err(e) = queue.enqueue(recipient_id, room_id, event_id, payload).await {
+            error!("Failed to queue message for user {} in room {}: {}", recipient_id, room_id, e);
+        }
+    }
+}
+
+/// Encodes a protocol event and fans it out to the room under a fresh event id,
+/// used for changes to existing messages such as edits and deletions.
+pub async fn broadcast_event<M: Serialize>(
+    dbsession: &web::Data<Session>,
+    room_store: &RoomStore,
+    room_id: &str,
+    recipient_ids: &[String],
+    sender_id: &str,
+    event: &ServerEvent<M>,
+) {
+    let payload = event.encode(None);
+    broadcast(dbsession, room_store, room_id, recipient_ids, sender_id, new_event_id(), &payload).await;
+}
+
+async fn send_event<M: Serialize>(session: &mut actix_ws::Session, event: &ServerEvent<M>, ref_id: Option<&str>) {
+    if let Err(e) = session.text(event.encode(ref_id)).await {
+        error!("Failed to send event to socket: {}", e);
+    }
+}
+
+pub async fn send_error(session: &mut actix_ws::Session, code: ErrorCode, message: &str, ref_id: Option<&str>) {
+    if let Err(e) = session.text(error_event(code, message, ref_id)).await {
+        error!("Failed to send error event to socket: {}", e);
+    }
+}
+
+pub async fn send_app_error(session: &mut actix_ws::Session, err: &AppError, ref_id: Option<&str>) {
+    send_error(session, ErrorCode::from_status(err.1), &err.0, ref_id).await;
+}
+
+/// Typing indicators are only useful while they are fresh, so they go to live
+/// connections and are never queued for offline members.
+async fn relay_typing<M: Serialize>(
+    room_store: &RoomStore,
+    room_id: &str,
+    user_id: &str,
+    recipient_ids: &[String],
+    is_typing: bool,
+) {
+    let event = ServerEvent::<M>::Typing { user_id: user_id.to_string(), is_typing };
+    broadcast_live(room_store, room_id, recipient_ids, user_id, new_event_id(), &event.encode(None)).await;
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn mark_read<M: Serialize>(
+    session: &mut actix_ws::Session,
+    dbsession: &web::Data<Session>,
+    room_store: &RoomStore,
+    room_id: &str,
+    user_id: &str,
+    recipient_ids: &[String],
+    kind: RoomKind,
+    message_id: &str,
+    ref_id: Option<&str>,
+) {
+    match ReadMarkers::new(dbsession.clone()).mark_read(kind, room_id, user_id, message_id).await {
+        Ok(true) => {
+            let event = ServerEvent::<M>::Read { user_id: user_id.to_string(), message_id: message_id.to_string() };
+            broadcast(dbsession, room_store, room_id, recipient_ids, user_id, new_event_id(), &event.encode(None)).await;
+        }
+        Ok(false) => debug!("Read marker for user {} in room {} already at or past {}", user_id, room_id, message_id),
+        Err(e) => send_app_error(session, &e, ref_id).await,
+    }
+}
+
+async fn handle_conversation_event(
+    session: &mut actix_ws::Session,
+    dbsession: &web::Data<Session>,
+    room_store: &RoomStore,
+    conversation_id: &str,
+    user_id: &str,
+    participant_ids: &[String],
+    envelope: ClientEnvelope,
+) {
+    let ref_id = envelope.ref_id.as_deref();
+    let conversation_service = match conversation_service::ConversationService::new(dbsession.clone()).await {
+        Ok(service) => service,
+        Err(e) => {
+            error!("Failed to create conversation service: {}", e);
+            send_app_error(session, &e, ref_id).await;
+            return;
+        }
+    };
+
+    if let Err((code, message)) = envelope.event.authorize(user_id, conversation_id) {
+        warn!("Rejected conversation event from user {} in {}: {}", user_id, conversation_id, message);
+        send_error(session, code, &message, ref_id).await;
+        return;
+    }
+
+    match envelope.event {
+        ClientEvent::MessageSend { content, .. } => {
+            match conversation_service.send_message(conversation_id, user_id, NewMessage { content }).await {
+                Ok(saved_msg) => {
+                    info!("Message from user {} saved to DB: {:?}", user_id, saved_msg);
+                    let event_id = Uuid::parse_str(&saved_msg.id).unwrap_or_else(|_| new_event_id());
+                    let payload = ServerEvent::MessageNew(&saved_msg).encode(None);
+                    send_event(session, &ServerEvent::MessageAck(&saved_msg), ref_id).await;
+                    broadcast(dbsession, room_store, conversation_id, participant_ids, user_id, event_id, &payload).await;
+                }
+                Err(e) => {
+                    error!("Failed to save message from user {}: {}", user_id, e);
+                    send_app_error(session, &e, ref_id).await;
+                }
+            }
+        }
+        ClientEvent::MessageEdit { message_id, content } => {
+            match conversation_service.edit_message(conversation_id, &message_id, user_id, UpdateMessage { content }).await {
+                Ok(message) => {
+                    let event = ServerEvent::MessageEdited(&message);
+                    send_event(session, &event, ref_id).await;
+                    broadcast_event(dbsession, room_store, conversation_id, participant_ids, user_id, &event).await;
+                }
+                Err(e) => send_app_error(session, &e, ref_id).await,
+            }
+        }
+        ClientEvent::MessageDelete { message_id } => {
+            match conversation_service.delete_message(conversation_id, &message_id, user_id).await {
+                Ok(message) => {
+                    let event = ServerEvent::MessageDeleted(&message);
+                    send_event(session, &event, ref_id).await;
+                    broadcast_event(dbsession, room_store, conversation_id, participant_ids, user_id, &event).await;
+                }
+                Err(e) => send_app_error(session, &e, ref_id).await,
+            }
+        }
+        ClientEvent::Typing { is_typing } => {
+            relay_typing::<ChatMessage>(room_store, conversation_id, user_id, participant_ids, is_typing).await;
+        }
+        ClientEvent::Read { message_id } => {
+            mark_read::<ChatMessage>(session, dbsession, room_store, conversation_id, user_id, participant_ids, RoomKind::Conversation, &message_id, ref_id).await;
+        }
+        ClientEvent::Ping => send_event(session, &ServerEvent::<ChatMessage>::Pong, ref_id).await,
+    }
+}
+
+async fn handle_group_event(
+    session: &mut actix_ws::Session,
+    dbsession: &web::Data<Session>,
+    room_store: &RoomStore,
+    group_id: &str,
+    user_id: &str,
+    envelope: ClientEnvelope,
+) {
+    let ref_id = envelope.ref_id.as_deref();
+    let group_service = match group_service::GroupService::new(dbsession.clone()).await {
+        Ok(service) => service,
+        Err(e) => {
+            error!("Failed to create group service: {}", e);
+            send_app_error(session, &e, ref_id).await;
+            return;
+        }
+    };
+
+    if let Err((code, message)) = envelope.event.authorize(user_id, group_id) {
+        warn!("Rejected group event from user {} in {}: {}", user_id, group_id, message);
+        send_error(session, code, &message, ref_id).await;
+        return;
+    }
+
+    let member_ids = room_store.read().await
+        .get(group_id)
+        .map(|room| room.members.clone())
+        .unwrap_or_default();
+    if !member_ids.iter().any(|id| id == user_id) {
+        warn!("User {} is no longer a member of group {}", user_id, group_id);
+        send_error(session, ErrorCode::Forbidden, "You are no longer a member of this group", ref_id).await;
+        return;
+    }
+    let member_ids = member_ids.as_slice();
+
+    match envelope.event {
+        ClientEvent::MessageSend { content, .. } => {
+            match group_service.send_message(group_id, user_id, NewGroupMessage { content }).await {
+                Ok(saved_msg) => {
+                    info!("Group message from user {} saved to DB: {:?}", user_id, saved_msg);
+                    let event_id = Uuid::parse_str(&saved_msg.id).unwrap_or_else(|_| new_event_id());
+                    let payload = ServerEvent::MessageNew(&saved_msg).encode(None);
+                    send_event(session, &ServerEvent::MessageAck(&saved_msg), ref_id).await;
+                    broadcast(dbsession, room_store, group_id, member_ids, user_id, event_id, &payload).await;
+                }
+                Err(e) => {
+                    error!("Failed to save group message from user {}: {}", user_id, e);
+                    send_app_error(session, &e, ref_id).await;
+                }
+            }
+        }
+        ClientEvent::MessageEdit { .. } | ClientEvent::MessageDelete { .. } => {
+            send_error(session, ErrorCode::Unsupported, "Editing group messages is not supported", ref_id).await;
+        }
+        ClientEvent::Typing { is_typing } => {
+            relay_typing::<GroupMessage>(room_store, group_id, user_id, member_ids, is_typing).await;
+        }
+        ClientEvent::Read { message_id } => {
+            mark_read::<GroupMessage>(session, dbsession, room_store, group_id, user_id, member_ids, RoomKind::Group, &message_id, ref_id).await;
+        }
+        ClientEvent::Ping => send_event(session, &ServerEvent::<GroupMessage>::Pong, ref_id).await,
+    }
+}
+
+/// Writes queued events newer than `last_seen` to a freshly connected socket,
+/// then drops everything the client now has from the queue.
+async fn replay_pending(
+    session: &mut actix_ws::Session,
+    queue: &DeliveryQueue,
+    user_id: &str,
+    room_id: &str,
+    last_seen: Option<Uuid>,
+) {
+    let pending = match queue.pending_after(user_id, room_id, last_seen).await {
+        Ok(pending) => pending,
+        Err(e) => {
+            error!("Failed to load pending messages for user {} in room {}: {}", user_id, room_id, e);
+            return;
+        }
+    };
+    if !pending.is_empty() {
+        info!("Delivering {} pending messages to user {} in room {}", pending.len(), user_id, room_id);
+    }
+
+    let mut delivered_up_to = last_seen;
+    for (event_id, payload) in pending {
+        debug!("Sending pending message to user {}: {}", user_id, payload);
+        if let Err(e) = session.text(payload).await {
+            error!("Failed to send pending message to user {}: {}", user_id, e);
+            break;
+        }
+        delivered_up_to = Some(event_id);
+    }
+
+    if let Some(up_to) = delivered_up_to {
+        if let Err(e) = queue.acknowledge(user_id, room_id, up_to).await {
+            error!("Failed to acknowledge pending messages for user {} in room {}: {}", user_id, room_id, e);
+        }
+    }
+}
+
+/// Checks the heartbeat deadline and pings the client; returns false once the
+/// socket should be dropped.
+pub async fn heartbeat(session: &mut actix_ws::Session, last_heartbeat: Instant, user_id: &str) -> bool {
+    if last_heartbeat.elapsed() > CLIENT_TIMEOUT {
+        warn!("Heartbeat timed out for user {}. Closing connection.", user_id);
+        return false;
+    }
+    if let Err(e) = session.ping(b"").await {
+        error!("Failed to ping user {}: {}", user_id, e);
+        return false;
+    }
+    true
+}
+
+fn parse_last_seen(last_seen_id: Option<&str>) -> Option<Uuid> {
+    let last_seen_id = last_seen_id?;
+    match Uuid::parse_str(last_seen_id) {
+        Ok(id) => Some(id),
+        Err(e) => {
+            warn!("Ignoring invalid last_seen_id {}: {}", last_seen_id, e);
+            None
+        }
+    }
+}
+
+/// `last_seen_id` is the newest message id the client already has; a
+/// reconnecting socket receives every queued event after it.
 #[derive(Deserialize, Debug)]
 pub struct ConversationQuery {
     token: String,
+    last_seen_id: Option<String>,
 }
 
 #[derive(Deserialize, Debug)]
 pub struct GroupQuery {
     token: String,
+    last_seen_id: Option<String>,
 }
 
 #[derive(Deserialize, Debug)]
 pub struct OnlineQuery {
-    user_id: String,
+    token: String,
 }
 
 pub async fn echo(
@@ -49,34 +456,17 @@ pub async fn echo(
     query: web::Query<ConversationQuery>,
     dbsession: web::Data<Session>,
     room_store: web::Data<RoomStore>,
+    presence: web::Data<PresenceStore>,
+    authenticator: web::Data<Authenticator>,
 ) -> Result<HttpResponse, Error> {
     info!("WebSocket handshake initiated for path: {:?}", path);
 
     let (res, mut session, mut stream) = actix_ws::handle(&req, stream)?;
     info!("WebSocket handshake successful.");
 
-    let token: String = query.token.clone();
-    debug!("Received token: {}", token);
-
-    let secret: String = env::var("JWT_SECRET").unwrap_or_else(|_| "your-secret-key".to_string());
-    let validation: Validation = Validation::default();
-
-    let claims = match decode::<Claims>(
-        &token,
-        &DecodingKey::from_secret(secret.as_bytes()),
-        &validation,
-    ) {
-        Ok(data) => {
-            info!("JWT decoded successfully: {:?}", data.claims);
-            data.claims
-        }
-        Err(e) => {
-            error!("Invalid token: {}", e);
-            return Err(AppError(format!("Invalid token: {}", e), StatusCode::UNAUTHORIZED).into());
-        }
-    };
+    let user = authenticator.authenticate(&dbsession, &query.token).await?;
 
-    let user_id = claims.sub.clone();
+    let user_id = user.user_id.clone();
     let conversation_id = path.into_inner();
     info!("User {} is attempting to connect to conversation {}", user_id, conversation_id);
 
@@ -101,33 +491,17 @@ pub async fn echo(
         return Ok(HttpResponse::Unauthorized().finish());
     }
 
-    let (tx, mut rx) = mpsc::unbounded_channel::<String>();
+    let (tx, mut rx) = mpsc::unbounded_channel::<RoomSignal>();
     info!("Channel created for user {} in conversation {}", user_id, conversation_id);
 
-    let mut store = room_store.write().await;
-    let room = store.entry(conversation_id.clone()).or_insert_with(|| {
-        info!("Creating new RoomState for conversation {}", conversation_id);
-        RoomState {
-            senders: HashMap::new(),
-            pending_messages: HashMap::new(),
-        }
-    });
-
-    room.senders.entry(user_id.clone()).or_default().push(tx);
+    let connection_key = join(&room_store, &conversation_id, &user_id, tx).await;
     info!("User {} added to senders for conversation {}", user_id, conversation_id);
 
-    let mut pending = room.pending_messages.remove(&user_id).unwrap_or_default();
-    if !pending.is_empty() {
-        info!("Delivering {} pending messages to user {} in conversation {}", pending.len(), user_id, conversation_id);
-    }
-    drop(store);
+    presence::connect(&dbsession, &presence, &user_id).await;
 
-    for msg in pending.drain(..) {
-        debug!("Sending pending message to user {}: {}", user_id, msg);
-        if let Err(e) = session.text(msg).await {
-            error!("Failed to send pending message to user {}: {}", user_id, e);
-        }
-    }
+    let queue = DeliveryQueue::new(dbsession.clone());
+    let last_seen = parse_last_seen(query.last_seen_id.as_deref());
+    replay_pending(&mut session, &queue, &user_id, &conversation_id, last_seen).await;
 
     let participant_ids = conversation.participant_ids.clone();
     let user_id_clone = user_id.clone();
@@ -139,86 +513,27 @@ pub async fn echo(
         let user_id = user_id_clone;
         let conversation_id = conversation_id_clone;
         
+        let mut heartbeat_interval = tokio::time::interval(HEARTBEAT_INTERVAL);
+        let mut last_heartbeat = Instant::now();
+
         loop {
             tokio::select! {
-                Some(msg) = stream.next() => {
+                msg = stream.next() => {
+                    let Some(msg) = msg else {
+                        info!("WebSocket stream ended for user {}", user_id);
+                        break;
+                    };
+                    last_heartbeat = Instant::now();
                     match msg {
                         Ok(Message::Text(text)) => {
                             info!("Received message from user {}: {}", user_id, text);
-
-                            #[derive(Deserialize, Debug)]
-                            struct IncomingMessage {
-                                content: String,
-                                conversationId: String,
-                                senderId: String,
-                            }
-                            let incoming: Result<IncomingMessage, _> = serde_json::from_str(&text);
-                            match incoming {
-                                Ok(msg) => {
-                                    info!("Parsed incoming message from user {}: {:?}", user_id, msg);
-                                    let conversation_service = match conversation_service::ConversationService::new(dbsession.clone()).await {
-                                        Ok(service) => service,
-                                        Err(e) => {
-                                            error!("Failed to create conversation service: {}", e);
-                                            continue;
-                                        }
-                                    };
-                                    match conversation_service.send_message(
-                                        &msg.conversationId,
-                                        &msg.senderId,
-                                        NewMessage { content: msg.content.clone() }
-                                    ).await {
-                                        Ok(saved_msg) => {
-                                            info!("Message from user {} saved to DB: {:?}", user_id, saved_msg);
-                                            let response = match serde_json::to_string(&saved_msg) {
-                                                Ok(resp) => resp,
-                                                Err(e) => {
-                                                    error!("Failed to serialize saved message: {}", e);
-                                                    continue;
-                                                }
-                                            };
-
-                                            let mut store = room_store.write().await;
-                                            if let Some(room) = store.get_mut(&conversation_id) {
-                                                for pid in &participant_ids {
-                                                    if pid == &user_id {
-                                                        debug!("Skipping echo to sender user {}.", user_id);
-                                                        continue;
-                                                    }
-                                                    if let Some(other_txs) = room.senders.get(pid) {
-                                                        debug!("Sending message to connected user {}: {}", pid, response);
-                                                        let mut all_failed = true;
-                                                        for tx in other_txs {
-                                                            if let Err(e) = tx.send(response.clone()) {
-                                                                error!("Failed to send message to user {} on one connection: {}", pid, e);
-                                                            } else {
-                                                                all_failed = false;
-                                                            }
-                                                        }
-                                                        if all_failed {
-                                                            error!("All connections failed for user {}. Queuing for later.", pid);
-                                                            room.pending_messages.entry(pid.clone())
-                                                                .or_default()
-                                                                .push_back(response.clone());
-                                                        }
-                                                    } else {
-                                                        debug!("User {} not connected. Queuing message.", pid);
-                                                        room.pending_messages.entry(pid.clone())
-                                                            .or_default()
-                                                            .push_back(response.clone());
-                                                    }
-                                                }
-                                            } else {
-                                                warn!("RoomState for conversation {} not found while sending message.", conversation_id);
-                                            }
-                                        }
-                                        Err(e) => {
-                                            error!("Failed to save message from user {}: {}", user_id, e);
-                                        }
-                                    }
+                            match protocol::decode(&text) {
+                                Ok(envelope) => {
+                                    handle_conversation_event(&mut session, &dbsession, &room_store, &conversation_id, &user_id, &participant_ids, envelope).await;
                                 }
-                                Err(e) => {
-                                    error!("Failed to parse incoming message from user {}: {}", user_id, e);
+                                Err((code, message)) => {
+                                    warn!("Rejected event from user {}: {}", user_id, message);
+                                    send_error(&mut session, code, &message, None).await;
                                 }
                             }
                         }
@@ -230,36 +545,45 @@ pub async fn echo(
                             error!("WebSocket error for user {} in conversation {}: {}", user_id, conversation_id, e);
                             break;
                         }
+                        Ok(Message::Ping(bytes)) => {
+                            if let Err(e) = session.pong(&bytes).await {
+                                error!("Failed to answer ping from user {}: {}", user_id, e);
+                                break;
+                            }
+                        }
                         _ => {
                             debug!("Received non-text message from user {} in conversation {}", user_id, conversation_id);
                         }
                     }
                 }
 
-                Some(server_msg) = rx.recv() => {
-                    debug!("Sending server message to user {}: {}", user_id, server_msg);
-                    if let Err(e) = session.text(server_msg).await {
-                        error!("Failed to send server message to user {}: {}", user_id, e);
+                Some(signal) = rx.recv() => {
+                    let RoomSignal::Event(event) = signal else {
+                        info!("User {} was evicted from conversation {}. Closing connection.", user_id, conversation_id);
+                        let reason = CloseReason { code: CloseCode::Policy, description: Some("removed from room".to_string()) };
+                        let _ = session.close(Some(reason)).await;
+                        break;
+                    };
+                    debug!("Sending server message to user {}: {}", user_id, event.payload);
+                    if let Err(e) = session.text(event.payload.clone()).await {
+                        error!("Failed to send server message to user {}: {}. Queuing for later.", user_id, e);
+                        if let Err(e) = queue.enqueue(&user_id, &conversation_id, event.id, &event.payload).await {
+                            error!("Failed to queue message for user {}: {}", user_id, e);
+                        }
+                        break;
                     }
                 }
-                else => {
-                    info!("WebSocket event loop ending for user {} in conversation {}", user_id, conversation_id);
-                    break;
+                _ = heartbeat_interval.tick() => {
+                    if !heartbeat(&mut session, last_heartbeat, &user_id).await {
+                        break;
+                    }
                 }
             }
         }
 
-        let mut store = room_store.write().await;
-        if let Some(room) = store.get_mut(&conversation_id) {
-            info!("Cleaning up user {} from conversation {}", user_id, conversation_id);
-            room.senders.remove(&user_id);
-            if room.senders.is_empty() && room.pending_messages.is_empty() {
-                info!("No more users in conversation {}. Removing RoomState.", conversation_id);
-                store.remove(&conversation_id);
-            }
-        } else {
-            warn!("RoomState for conversation {} not found during cleanup.", conversation_id);
-        }
+        leave(&room_store, &conversation_id, &user_id, connection_key).await;
+
+        presence::disconnect(&dbsession, &presence, &user_id).await;
     });
 
     info!("WebSocket handler setup complete for user {} in conversation {}", user_id, conversation_id);
@@ -274,34 +598,17 @@ pub async fn group_echo(
     query: web::Query<GroupQuery>,
     dbsession: web::Data<Session>,
     room_store: web::Data<RoomStore>,
+    presence: web::Data<PresenceStore>,
+    authenticator: web::Data<Authenticator>,
 ) -> Result<HttpResponse, Error> {
     info!("Group WebSocket handshake initiated for path: {:?}", path);
 
     let (res, mut session, mut stream) = actix_ws::handle(&req, stream)?;
     info!("Group WebSocket handshake successful.");
 
-    let token: String = query.token.clone();
-    debug!("Received token: {}", token);
-
-    let secret: String = env::var("JWT_SECRET").unwrap_or_else(|_| "your-secret-key".to_string());
-    let validation: Validation = Validation::default();
-
-    let claims = match decode::<Claims>(
-        &token,
-        &DecodingKey::from_secret(secret.as_bytes()),
-        &validation,
-    ) {
-        Ok(data) => {
-            info!("JWT decoded successfully: {:?}", data.claims);
-            data.claims
-        }
-        Err(e) => {
-            error!("Invalid token: {}", e);
-            return Err(AppError(format!("Invalid token: {}", e), StatusCode::UNAUTHORIZED).into());
-        }
-    };
+    let user = authenticator.authenticate(&dbsession, &query.token).await?;
 
-    let user_id = claims.sub.clone();
+    let user_id = user.user_id.clone();
     let group_id = path.into_inner();
     info!("User {} is attempting to connect to group {}", user_id, group_id);
 
@@ -326,35 +633,19 @@ pub async fn group_echo(
         return Ok(HttpResponse::Unauthorized().finish());
     }
 
-    let (tx, mut rx) = mpsc::unbounded_channel::<String>();
+    let (tx, mut rx) = mpsc::unbounded_channel::<RoomSignal>();
     info!("Channel created for user {} in group {}", user_id, group_id);
 
-    let mut store = room_store.write().await;
-    let room = store.entry(group_id.clone()).or_insert_with(|| {
-        info!("Creating new RoomState for group {}", group_id);
-        RoomState {
-            senders: HashMap::new(),
-            pending_messages: HashMap::new(),
-        }
-    });
-
-    room.senders.entry(user_id.clone()).or_default().push(tx);
+    let connection_key = join(&room_store, &group_id, &user_id, tx).await;
+    set_members(&room_store, &group_id, &group.member_ids).await;
     info!("User {} added to senders for group {}", user_id, group_id);
 
-    let mut pending = room.pending_messages.remove(&user_id).unwrap_or_default();
-    if !pending.is_empty() {
-        info!("Delivering {} pending messages to user {} in group {}", pending.len(), user_id, group_id);
-    }
-    drop(store);
+    presence::connect(&dbsession, &presence, &user_id).await;
 
-    for msg in pending.drain(..) {
-        debug!("Sending pending message to user {}: {}", user_id, msg);
-        if let Err(e) = session.text(msg).await {
-            error!("Failed to send pending message to user {}: {}", user_id, e);
-        }
-    }
+    let queue = DeliveryQueue::new(dbsession.clone());
+    let last_seen = parse_last_seen(query.last_seen_id.as_deref());
+    replay_pending(&mut session, &queue, &user_id, &group_id, last_seen).await;
 
-    let member_ids = group.member_ids.clone();
     let user_id_clone = user_id.clone();
     let group_id_clone = group_id.clone();
 
@@ -364,86 +655,27 @@ pub async fn group_echo(
         let user_id = user_id_clone;
         let group_id = group_id_clone;
         
+        let mut heartbeat_interval = tokio::time::interval(HEARTBEAT_INTERVAL);
+        let mut last_heartbeat = Instant::now();
+
         loop {
             tokio::select! {
-                Some(msg) = stream.next() => {
+                msg = stream.next() => {
+                    let Some(msg) = msg else {
+                        info!("WebSocket stream ended for user {}", user_id);
+                        break;
+                    };
+                    last_heartbeat = Instant::now();
                     match msg {
                         Ok(Message::Text(text)) => {
                             info!("Received group message from user {}: {}", user_id, text);
-
-                            #[derive(Deserialize, Debug)]
-                            struct IncomingMessage {
-                                content: String,
-                                groupId: String,
-                                senderId: String,
-                            }
-                            let incoming: Result<IncomingMessage, _> = serde_json::from_str(&text);
-                            match incoming {
-                                Ok(msg) => {
-                                    info!("Parsed incoming group message from user {}: {:?}", user_id, msg);
-                                    let group_service = match group_service::GroupService::new(dbsession.clone()).await {
-                                        Ok(service) => service,
-                                        Err(e) => {
-                                            error!("Failed to create group service: {}", e);
-                                            continue;
-                                        }
-                                    };
-                                    match group_service.send_message(
-                                        &msg.groupId,
-                                        &msg.senderId,
-                                        NewGroupMessage { content: msg.content.clone() }
-                                    ).await {
-                                        Ok(saved_msg) => {
-                                            info!("Group message from user {} saved to DB: {:?}", user_id, saved_msg);
-                                            let response = match serde_json::to_string(&saved_msg) {
-                                                Ok(resp) => resp,
-                                                Err(e) => {
-                                                    error!("Failed to serialize saved group message: {}", e);
-                                                    continue;
-                                                }
-                                            };
-
-                                            let mut store = room_store.write().await;
-                                            if let Some(room) = store.get_mut(&group_id) {
-                                                for member_id in &member_ids {
-                                                    if member_id == &user_id {
-                                                        debug!("Skipping echo to sender user {}.", user_id);
-                                                        continue;
-                                                    }
-                                                    if let Some(other_txs) = room.senders.get(member_id) {
-                                                        debug!("Sending group message to connected user {}: {}", member_id, response);
-                                                        let mut all_failed = true;
-                                                        for tx in other_txs {
-                                                            if let Err(e) = tx.send(response.clone()) {
-                                                                error!("Failed to send group message to user {} on one connection: {}", member_id, e);
-                                                            } else {
-                                                                all_failed = false;
-                                                            }
-                                                        }
-                                                        if all_failed {
-                                                            error!("All connections failed for user {}. Queuing for later.", member_id);
-                                                            room.pending_messages.entry(member_id.clone())
-                                                                .or_default()
-                                                                .push_back(response.clone());
-                                                        }
-                                                    } else {
-                                                        debug!("User {} not connected. Queuing group message.", member_id);
-                                                        room.pending_messages.entry(member_id.clone())
-                                                            .or_default()
-                                                            .push_back(response.clone());
-                                                    }
-                                                }
-                                            } else {
-                                                warn!("RoomState for group {} not found while sending message.", group_id);
-                                            }
-                                        }
-                                        Err(e) => {
-                                            error!("Failed to save group message from user {}: {}", user_id, e);
-                                        }
-                                    }
+                            match protocol::decode(&text) {
+                                Ok(envelope) => {
+                                    handle_group_event(&mut session, &dbsession, &room_store, &group_id, &user_id, envelope).await;
                                 }
-                                Err(e) => {
-                                    error!("Failed to parse incoming group message from user {}: {}", user_id, e);
+                                Err((code, message)) => {
+                                    warn!("Rejected group event from user {}: {}", user_id, message);
+                                    send_error(&mut session, code, &message, None).await;
                                 }
                             }
                         }
@@ -455,36 +687,45 @@ pub async fn group_echo(
                             error!("Group WebSocket error for user {} in group {}: {}", user_id, group_id, e);
                             break;
                         }
+                        Ok(Message::Ping(bytes)) => {
+                            if let Err(e) = session.pong(&bytes).await {
+                                error!("Failed to answer ping from user {}: {}", user_id, e);
+                                break;
+                            }
+                        }
                         _ => {
                             debug!("Received non-text message from user {} in group {}", user_id, group_id);
                         }
                     }
                 }
 
-                Some(server_msg) = rx.recv() => {
-                    debug!("Sending server message to user {} in group: {}", user_id, server_msg);
-                    if let Err(e) = session.text(server_msg).await {
-                        error!("Failed to send server message to user {} in group: {}", user_id, e);
+                Some(signal) = rx.recv() => {
+                    let RoomSignal::Event(event) = signal else {
+                        info!("User {} was removed from group {}. Closing connection.", user_id, group_id);
+                        let reason = CloseReason { code: CloseCode::Policy, description: Some("removed from room".to_string()) };
+                        let _ = session.close(Some(reason)).await;
+                        break;
+                    };
+                    debug!("Sending server message to user {} in group: {}", user_id, event.payload);
+                    if let Err(e) = session.text(event.payload.clone()).await {
+                        error!("Failed to send server message to user {} in group: {}. Queuing for later.", user_id, e);
+                        if let Err(e) = queue.enqueue(&user_id, &group_id, event.id, &event.payload).await {
+                            error!("Failed to queue group message for user {}: {}", user_id, e);
+                        }
+                        break;
                     }
                 }
-                else => {
-                    info!("Group WebSocket event loop ending for user {} in group {}", user_id, group_id);
-                    break;
+                _ = heartbeat_interval.tick() => {
+                    if !heartbeat(&mut session, last_heartbeat, &user_id).await {
+                        break;
+                    }
                 }
             }
         }
 
-        let mut store = room_store.write().await;
-        if let Some(room) = store.get_mut(&group_id) {
-            info!("Cleaning up user {} from group {}", user_id, group_id);
-            room.senders.remove(&user_id);
-            if room.senders.is_empty() && room.pending_messages.is_empty() {
-                info!("No more users in group {}. Removing RoomState.", group_id);
-                store.remove(&group_id);
-            }
-        } else {
-            warn!("RoomState for group {} not found during cleanup.", group_id);
-        }
+        leave(&room_store, &group_id, &user_id, connection_key).await;
+
+        presence::disconnect(&dbsession, &presence, &user_id).await;
     });
 
     info!("Group WebSocket handler setup complete for user {} in group {}", user_id, group_id);
@@ -492,45 +733,109 @@ pub async fn group_echo(
     Ok(res)
 }
 
-#[derive(Deserialize,SerializeRow, Debug, Serialize)]
-pub struct UserOnline {
-    user_id: String,
-    online: bool,
-}
-
+/// Presence socket. It keeps the user online while open and streams presence
+/// events for everyone they share a conversation or group with, starting with
+/// a snapshot of each contact's current state.
 pub async fn online(
     req: HttpRequest,
     stream: web::Payload,
     query: web::Query<OnlineQuery>,
     dbsession: web::Data<Session>,
-    room_store: web::Data<RoomStore>,
+    presence: web::Data<PresenceStore>,
+    authenticator: web::Data<Authenticator>,
 ) -> Result<HttpResponse, Error> {
     let (res, mut session, mut stream) = actix_ws::handle(&req, stream)?;
-    let db_client = DbClient::<UserOnline> { 
-        session: &dbsession, 
-        _phantom: std::marker::PhantomData
+
+    let user = authenticator.authenticate(&dbsession, &query.token).await?;
+    let user_id = user.user_id.clone();
+
+    let contact_ids = match presence::contacts_of(&dbsession, &user_id).await {
+        Ok(contact_ids) => contact_ids,
+        Err(e) => {
+            error!("Failed to load contacts for user {}: {}", user_id, e);
+            return Err(e.into());
+        }
     };
 
-   tokio::select! {
-        Some(msg) = stream.next() => {
-            match msg {
-                Ok(Message::Text(text)) => {
-                    let user_online = UserOnline {
-                        user_id: query.user_id.clone(),
-                        online: true,
+    presence::connect(&dbsession, &presence, &user_id).await;
+
+    let (tx, mut rx) = mpsc::unbounded_channel::<String>();
+    let watcher_key = presence::subscribe(&presence, &contact_ids, tx).await;
+    info!("User {} subscribed to presence of {} contacts", user_id, contact_ids.len());
+
+    match presence::snapshot(&dbsession, &presence, &contact_ids).await {
+        Ok(events) => {
+            for event in events {
+                if let Err(e) = session.text(event).await {
+                    error!("Failed to send presence snapshot to user {}: {}", user_id, e);
+                    break;
+                }
+            }
+        }
+        Err(e) => error!("Failed to load presence snapshot for user {}: {}", user_id, e),
+    }
+
+    rt::spawn(async move {
+        let mut heartbeat_interval = tokio::time::interval(HEARTBEAT_INTERVAL);
+        let mut last_heartbeat = Instant::now();
+
+        loop {
+            tokio::select! {
+                msg = stream.next() => {
+                    let Some(msg) = msg else {
+                        info!("Presence stream ended for user {}", user_id);
+                        break;
                     };
-                    db_client.insert("UPDATE users SET online = ? WHERE id = ?", (user_online.online, user_online.user_id)).await;
-                    Ok(HttpResponse::Ok().finish())
+                    last_heartbeat = Instant::now();
+                    match msg {
+                        Ok(Message::Text(text)) => match protocol::decode(&text) {
+                            Ok(ClientEnvelope { ref_id, event: ClientEvent::Ping, .. }) => {
+                                send_event(&mut session, &ServerEvent::<()>::Pong, ref_id.as_deref()).await;
+                            }
+                            Ok(envelope) => {
+                                send_error(&mut session, ErrorCode::Unsupported, "Only ping is supported on the presence socket", envelope.ref_id.as_deref()).await;
+                            }
+                            Err((code, message)) => {
+                                warn!("Rejected presence event from user {}: {}", user_id, message);
+                                send_error(&mut session, code, &message, None).await;
+                            }
+                        },
+                        Ok(Message::Ping(bytes)) => {
+                            if let Err(e) = session.pong(&bytes).await {
+                                error!("Failed to answer ping from user {}: {}", user_id, e);
+                                break;
+                            }
+                        }
+                        Ok(Message::Close(reason)) => {
+                            info!("Presence socket closed by user {}: {:?}", user_id, reason);
+                            break;
+                        }
+                        Err(e) => {
+                            error!("Presence socket error for user {}: {}", user_id, e);
+                            break;
+                        }
+                        _ => {}
+                    }
                 }
-                Err(e) => {
-                    error!("WebSocket error: {}", e);
-                    return Err(e.into());
+
+                Some(payload) = rx.recv() => {
+                    if let Err(e) = session.text(payload).await {
+                        error!("Failed to send presence update to user {}: {}", user_id, e);
+                        break;
+                    }
                 }
-                _ => {
-                    debug!("Received non-text message from user {}", query.user_id);
-                    return Err(AppError("Invalid message".to_string(), StatusCode::BAD_REQUEST).into());
+
+                _ = heartbeat_interval.tick() => {
+                    if !heartbeat(&mut session, last_heartbeat, &user_id).await {
+                        break;
+                    }
                 }
             }
         }
-   }
-}
\ No newline at end of file
+
+        presence::unsubscribe(&presence, &contact_ids, watcher_key).await;
+        presence::disconnect(&dbsession, &presence, &user_id).await;
+    });
+
+    Ok(res)
+}
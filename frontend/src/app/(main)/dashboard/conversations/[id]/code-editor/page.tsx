@@ -9,6 +9,7 @@ import { Button } from "@/components/ui/button";
 import { Skeleton } from "@/components/ui/skeleton";
 import CodeEditor from "@/components/conversations/code-editor";
 import { toast } from "sonner";
+import { encodeEvent } from "@/lib/socket-protocol";
 
 export default function CodeEditorPage() {
   const { id: conversationId } = useParams();
@@ -55,13 +56,7 @@ export default function CodeEditorPage() {
         throw new Error("WebSocket is not in OPEN state during send attempt.");
       }
 
-      const messagePayload = {
-        content,
-        conversationId: conversationId,
-        senderId: user.id,
-      };
-
-      wsRef.current.send(JSON.stringify(messagePayload));
+      wsRef.current.send(encodeEvent("message.send", { content }));
 
       router.push(`/dashboard/conversations/${conversationId}`);
     } catch (error) {
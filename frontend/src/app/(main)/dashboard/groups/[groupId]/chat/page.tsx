@@ -5,6 +5,12 @@ import { ChatLayout, GroupHeader, GroupMessageList } from "@/components/chat";
 import MessageInput from "@/components/conversations/message-input";
 
 import { GroupMessage } from "@/types/group-message";
+import { MessagePage } from "@/types/conversation";
+import {
+  applyMessageEvent,
+  encodeEvent,
+  parseEvent,
+} from "@/lib/socket-protocol";
 
 interface Props {
   params: Promise<{ groupId: string }>;
@@ -36,8 +42,8 @@ export default function page({ params }: Props) {
       );
 
       if (response.ok) {
-        const existingMessages = await response.json();
-        setMessages(existingMessages.reverse());
+        const page: MessagePage<GroupMessage> = await response.json();
+        setMessages(page.messages.reverse());
       }
     } catch (error) {
       console.error("Failed to fetch messages:", error);
@@ -66,12 +72,15 @@ export default function page({ params }: Props) {
       };
 
       ws.onmessage = (event) => {
-        try {
-          const newMessage = JSON.parse(event.data);
-          setMessages((prevMessages) => [...prevMessages, newMessage]);
-        } catch (error) {
-          console.error("Failed to parse WebSocket message:", error);
+        const envelope = parseEvent<GroupMessage>(event.data);
+        if (!envelope) {
+          console.error("Unexpected WebSocket frame:", event.data);
+          return;
         }
+        if (envelope.type === "error") {
+          setWsError(envelope.data.message);
+        }
+        setMessages((prevMessages) => applyMessageEvent(prevMessages, envelope));
       };
 
       ws.onclose = () => {
@@ -120,16 +129,13 @@ export default function page({ params }: Props) {
         throw new Error("WebSocket is not in OPEN state during send attempt.");
       }
 
-      const messagePayload = {
-        content,
-        groupId: paramsStatic.groupId,
-        senderId: user.id,
-      };
-
-      wsRef.current.send(JSON.stringify(messagePayload));
+      // The optimistic copy's id doubles as the event's `ref`, so the ack
+      // can replace it.
+      const tempId = `temp_${Date.now().toString()}`;
+      wsRef.current.send(encodeEvent("message.send", { content }, tempId));
 
       const tempMessage: GroupMessage = {
-        id: `temp_${Date.now().toString()}`,
+        id: tempId,
         content: content,
         sender_id: user.id,
         group_id: paramsStatic.groupId,
@@ -17,12 +17,16 @@ export async function GET(
     }
 
     const { searchParams } = new URL(request.url);
-    const limit = searchParams.get("limit") || "50";
+    const query = new URLSearchParams({
+      limit: searchParams.get("limit") || "50",
+    });
+    const before = searchParams.get("before");
+    if (before) query.set("before", before);
 
     const response = await fetch(
       `${RUST_API_URL}/conversations/${
         (await params.id) as string
-      }/messages?limit=${limit}`,
+      }/messages?${query}`,
       {
         headers: {
           Authorization: authHeader,
@@ -14,7 +14,14 @@ export async function GET(request: NextRequest, {params}:{params: {groupId: stri
       );
     }
 
-    const response = await fetch(`${RUST_API_URL}/groups/${id}/messages?limit=50`, {
+    const { searchParams } = new URL(request.url);
+    const query = new URLSearchParams({
+      limit: searchParams.get("limit") || "50",
+    });
+    const before = searchParams.get("before");
+    if (before) query.set("before", before);
+
+    const response = await fetch(`${RUST_API_URL}/groups/${id}/messages?${query}`, {
       headers: {
         Authorization: authHeader,
       },
@@ -17,6 +17,11 @@ import Link from "next/link";
 import { ArrowLeftIcon } from "lucide-react";
 import { Button } from "../ui/button";
 import { ChatLayout } from "@/components/chat";
+import {
+  applyMessageEvent,
+  encodeEvent,
+  parseEvent,
+} from "@/lib/socket-protocol";
 
 export function ConversationDetail() {
   const { id: conversationId } = useParams();
@@ -33,7 +38,6 @@ export function ConversationDetail() {
     isLoadingMore,
     loadOlderMessages,
     setMessages,
-    mergeMessages,
   } = useMessages(conversationId as string);
 
   const {
@@ -73,12 +77,15 @@ export function ConversationDetail() {
       };
 
       ws.onmessage = (event) => {
-        try {
-          const message = JSON.parse(event.data) as Message;
-          mergeMessages([message]);
-        } catch (error) {
-          console.error("Failed to parse WebSocket message:", error);
+        const envelope = parseEvent<Message>(event.data);
+        if (!envelope) {
+          console.error("Unexpected WebSocket frame:", event.data);
+          return;
         }
+        if (envelope.type === "error") {
+          setWsError(envelope.data.message);
+        }
+        setMessages((prevMessages) => applyMessageEvent(prevMessages, envelope));
       };
 
       ws.onclose = () => {
@@ -106,7 +113,7 @@ export function ConversationDetail() {
         reconnectTimeoutRef.current = null;
       }
     };
-  }, [conversationId, user?.id, mergeMessages]);
+  }, [conversationId, user?.id, setMessages]);
 
   useEffect(() => {
     if (scrollRef.current) {
@@ -143,16 +150,13 @@ export function ConversationDetail() {
         throw new Error("WebSocket is not in OPEN state during send attempt.");
       }
 
-      const messagePayload = {
-        content,
-        conversationId: conversationId,
-        senderId: user.id,
-      };
-
-      wsRef.current.send(JSON.stringify(messagePayload));
+      // The optimistic copy's id doubles as the event's `ref`, so the ack
+      // can replace it.
+      const tempId = `temp_${Date.now().toString()}`;
+      wsRef.current.send(encodeEvent("message.send", { content }, tempId));
 
       const tempMessage: Message = {
-        id: `temp_${Date.now().toString()}`,
+        id: tempId,
         content: content,
         sender_id: user.id,
         created_at: new Date().toISOString(),
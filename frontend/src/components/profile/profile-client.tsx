@@ -2,6 +2,7 @@
 import React, { useEffect, useState } from "react";
 import { Card, CardContent, CardHeader, CardTitle } from "@/components/ui/card";
 import { Avatar, AvatarFallback, AvatarImage } from "@/components/ui/avatar";
+import { Badge } from "@/components/ui/badge";
 import { Button } from "@/components/ui/button";
 import {
   Calendar,
@@ -116,10 +117,10 @@ export function ProfileClient({ getProfileData }: ProfileClientProps) {
                   {user.home_country}
                 </span>
               )}
-              {user.language && (
+              {user.languages?.length > 0 && (
                 <span className="flex items-center gap-1">
                   <UserIcon className="h-4 w-4" />
-                  {user.language.toUpperCase()}
+                  {user.languages.join(", ")}
                 </span>
               )}
             </div>
@@ -131,7 +132,7 @@ export function ProfileClient({ getProfileData }: ProfileClientProps) {
         </Button>
       </div>
 
-      {(user.project_building || user.interests) && (
+      {(user.project_building || user.interests?.length > 0) && (
         <div className="grid gap-4 md:grid-cols-2">
           {user.project_building && (
             <Card>
@@ -145,14 +146,20 @@ export function ProfileClient({ getProfileData }: ProfileClientProps) {
             </Card>
           )}
 
-          {user.interests && (
+          {user.interests?.length > 0 && (
             <Card>
               <CardHeader className="flex flex-row items-center gap-2 space-y-0 pb-2">
                 <Heart className="h-5 w-5 text-primary" />
                 <CardTitle className="text-lg">Interests</CardTitle>
               </CardHeader>
               <CardContent>
-                <p className="text-muted-foreground">{user.interests}</p>
+                <div className="flex flex-wrap gap-2">
+                  {user.interests.map((interest) => (
+                    <Badge key={interest} variant="secondary">
+                      {interest}
+                    </Badge>
+                  ))}
+                </div>
               </CardContent>
             </Card>
           )}
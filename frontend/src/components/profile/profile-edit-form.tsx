@@ -24,20 +24,13 @@ interface ProfileEditFormProps {
   onProfileUpdated: (updatedUser: Partial<User>) => void;
 }
 
-const LANGUAGES = [
-  { value: "en", label: "English" },
-  { value: "es", label: "Spanish" },
-  { value: "fr", label: "French" },
-  { value: "de", label: "German" },
-  { value: "it", label: "Italian" },
-  { value: "pt", label: "Portuguese" },
-  { value: "ru", label: "Russian" },
-  { value: "zh", label: "Chinese" },
-  { value: "ja", label: "Japanese" },
-  { value: "ko", label: "Korean" },
-  { value: "ar", label: "Arabic" },
-  { value: "hi", label: "Hindi" },
-];
+// Interests and languages are edited as comma-separated lists and sent as
+// arrays of tags; the server normalizes them.
+const splitTags = (value: string) =>
+  value
+    .split(",")
+    .map((tag) => tag.trim())
+    .filter(Boolean);
 
 const COUNTRIES = [
   "United States",
@@ -89,10 +82,10 @@ export function ProfileEditForm({
   const [formData, setFormData] = useState({
     username: user.username || "",
     email: user.email || "",
-    language: user.language || "",
+    languages: (user.languages ?? []).join(", "),
     home_country: user.home_country || "",
     project_building: user.project_building || "",
-    interests: user.interests || "",
+    interests: (user.interests ?? []).join(", "),
     profile_image_url: user.profile_image_url || "",
   });
 
@@ -127,16 +120,17 @@ export function ProfileEditForm({
           id: user.id,
           username: formData.username,
           email: formData.email,
-          language: formData.language,
+          languages: splitTags(formData.languages),
           home_country: formData.home_country,
           project_building: formData.project_building,
-          interests: formData.interests,
+          interests: splitTags(formData.interests),
           profile_image_url: formData.profile_image_url,
         }),
       });
 
       if (!response.ok) {
-        throw new Error("Failed to update profile");
+        const body = await response.json().catch(() => null);
+        throw new Error(body?.error || "Failed to update profile");
       }
 
       const updatedUser = await response.json();
@@ -144,7 +138,9 @@ export function ProfileEditForm({
       toast.success("Profile updated successfully!");
     } catch (error) {
       console.error("Error updating profile:", error);
-      toast.error("Failed to update profile");
+      toast.error(
+        error instanceof Error ? error.message : "Failed to update profile"
+      );
     } finally {
       setIsLoading(false);
     }
@@ -188,22 +184,13 @@ export function ProfileEditForm({
 
           <div className="grid grid-cols-1 md:grid-cols-2 gap-4">
             <div className="space-y-2">
-              <Label>Language</Label>
-              <Select
-                value={formData.language}
-                onValueChange={(value) => handleInputChange("language", value)}
-              >
-                <SelectTrigger>
-                  <SelectValue placeholder="Select your language" />
-                </SelectTrigger>
-                <SelectContent>
-                  {LANGUAGES.map((lang) => (
-                    <SelectItem key={lang.value} value={lang.value}>
-                      {lang.label}
-                    </SelectItem>
-                  ))}
-                </SelectContent>
-              </Select>
+              <Label htmlFor="languages">Languages</Label>
+              <Input
+                id="languages"
+                value={formData.languages}
+                onChange={(e) => handleInputChange("languages", e.target.value)}
+                placeholder="english, spanish"
+              />
             </div>
 
             <div className="space-y-2">
@@ -246,9 +233,13 @@ export function ProfileEditForm({
               id="interests"
               value={formData.interests}
               onChange={(e) => handleInputChange("interests", e.target.value)}
-              placeholder="Tell us about your interests..."
+              placeholder="rust:expert, web development, databases:beginner"
               rows={4}
             />
+            <p className="text-xs text-muted-foreground">
+              Separate interests with commas. Add a skill level with a colon:
+              beginner, intermediate, advanced or expert.
+            </p>
           </div>
 
           <Button type="submit" disabled={isLoading} className="w-full gap-2">
@@ -1,10 +1,10 @@
 import { useState, useEffect, useCallback } from "react";
-import { Message } from "@/types/conversation";
+import { Message, MessagePage } from "@/types/conversation";
 
 export function useMessages(id: string) {
   const [messages, setMessages] = useState<Message[]>([]);
   const [isLoading, setIsLoading] = useState(true);
-  const [hasMore, setHasMore] = useState(true);
+  const [nextCursor, setNextCursor] = useState<string | null>(null);
   const [isLoadingMore, setIsLoadingMore] = useState(false);
 
   const mergeMessages = useCallback((newMsg: Message[]) => {
@@ -24,9 +24,9 @@ export function useMessages(id: string) {
           }
         );
         if (!response.ok) throw new Error("Failed to load messages");
-        const data = await response.json();
-        setMessages(data.reverse());
-        setHasMore(data.length === 20);
+        const data: MessagePage<Message> = await response.json();
+        setMessages(data.messages.reverse());
+        setNextCursor(data.next_cursor);
       } catch (error) {
         console.error("Failed to load messages:", error);
       } finally {
@@ -40,13 +40,12 @@ export function useMessages(id: string) {
   }, [id]);
 
   const loadOlderMessages = async () => {
-    if (isLoadingMore || !messages.length) return;
+    if (isLoadingMore || !nextCursor) return;
     setIsLoadingMore(true);
     try {
-      const oldest = messages[0];
       const response = await fetch(
         `/api/conversations/${id}/messages?limit=20&before=${encodeURIComponent(
-          oldest.created_at
+          nextCursor
         )}`,
         {
           headers: {
@@ -55,9 +54,9 @@ export function useMessages(id: string) {
         }
       );
       if (!response.ok) throw new Error("Failed to load older messages");
-      const data = await response.json();
-      if (data.length === 0) setHasMore(false);
-      mergeMessages(data.reverse());
+      const data: MessagePage<Message> = await response.json();
+      setMessages((prev) => [...data.messages.reverse(), ...prev]);
+      setNextCursor(data.next_cursor);
     } catch (error) {
       console.error("Failed to load older messages:", error);
     } finally {
@@ -68,7 +67,7 @@ export function useMessages(id: string) {
   return {
     messages,
     isLoading,
-    hasMore,
+    hasMore: nextCursor !== null,
     isLoadingMore,
     loadOlderMessages,
     setMessages,
@@ -0,0 +1,74 @@
+/**
+ * Versioned envelope of the chat sockets (`api/src/utils/protocol.rs`).
+ * Every frame is `{ v, ref?, type, data }`; `ref` is a client correlation id
+ * the server echoes on the matching ack or error.
+ */
+export const PROTOCOL_VERSION = 1;
+
+export type ServerEvent<M> =
+  | { type: "message.new"; data: M }
+  | { type: "message.ack"; data: M }
+  | { type: "message.edited"; data: M }
+  | { type: "message.deleted"; data: M }
+  | { type: "typing"; data: { user_id: string; is_typing: boolean } }
+  | { type: "read"; data: { user_id: string; message_id: string } }
+  | { type: "member.joined"; data: { user_id: string } }
+  | { type: "member.left"; data: { user_id: string } }
+  | {
+      type: "presence";
+      data: { user_id: string; is_online: boolean; last_seen_at: number | null };
+    }
+  | { type: "error"; data: { code: string; message: string } }
+  | { type: "pong" };
+
+export type ServerEnvelope<M> = ServerEvent<M> & { v: number; ref?: string };
+
+export function encodeEvent(
+  type: string,
+  data?: Record<string, unknown>,
+  ref?: string
+): string {
+  return JSON.stringify({ v: PROTOCOL_VERSION, ref, type, data });
+}
+
+/** Parses a server frame, or returns null for anything not in the envelope. */
+export function parseEvent<M>(raw: string): ServerEnvelope<M> | null {
+  try {
+    const envelope = JSON.parse(raw);
+    if (envelope?.v !== PROTOCOL_VERSION || typeof envelope.type !== "string") {
+      return null;
+    }
+    return envelope as ServerEnvelope<M>;
+  } catch {
+    return null;
+  }
+}
+
+/**
+ * Applies a message event to a list of messages: acks replace the optimistic
+ * copy sent with `ref`, new messages are appended once, and edits and
+ * deletions replace the message in place.
+ */
+export function applyMessageEvent<M extends { id: string }>(
+  messages: M[],
+  event: ServerEnvelope<M>
+): M[] {
+  switch (event.type) {
+    case "message.ack":
+      return messages.some((m) => m.id === event.ref)
+        ? messages.map((m) => (m.id === event.ref ? event.data : m))
+        : [...messages, event.data];
+    case "message.new":
+      return messages.some((m) => m.id === event.data.id)
+        ? messages
+        : [...messages, event.data];
+    case "message.edited":
+    case "message.deleted":
+      return messages.map((m) => (m.id === event.data.id ? event.data : m));
+    case "error":
+      // A message the server refused is not kept as if it were sent.
+      return messages.filter((m) => m.id !== event.ref);
+    default:
+      return messages;
+  }
+}
@@ -38,6 +38,13 @@ export interface NewConversation {
 
 export interface ListMessagesRequest {
   limit?: number;
+  before?: string;
+}
+
+/** One page of history, newest first; pass `next_cursor` as `before` for older messages. */
+export interface MessagePage<T> {
+  messages: T[];
+  next_cursor: string | null;
 }
 
 export interface ConversationWithMessages extends Conversation {
@@ -6,8 +6,9 @@ export interface User {
   updated_at: number;
   last_seen_at: number | null;
   is_online: boolean;
-  interests?: string;
-  language?: string;
+  /** Normalized tags, optionally with a skill level: "rust" or "rust:expert". */
+  interests: string[];
+  languages: string[];
   profile_image_url?: string;
   home_country?: string;
   project_building?: string;